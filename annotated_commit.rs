@@ -0,0 +1,29 @@
+use std::cast;
+use super::*;
+use ext;
+
+impl<'self> AnnotatedCommit<'self> {
+    /// Get the id of the commit
+    pub fn id<'r>(&self) -> &'r OID
+    {
+        unsafe {
+            // the id is owned by the annotated commit, which outlives the borrow
+            cast::transmute(ext::git_annotated_commit_id(self.commit))
+        }
+    }
+
+    /// Lookup the commit itself
+    pub fn commit(&self) -> Option<~Commit<'self>>
+    {
+        self.owner.lookup_commit(self.id())
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for AnnotatedCommit<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_annotated_commit_free(self.commit);
+        }
+    }
+}
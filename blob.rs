@@ -1,5 +1,5 @@
 use super::{Blob, OID};
-use std::cast;
+use std::{cast, io};
 use std::vec::raw::buf_as_slice;
 use ext;
 
@@ -41,6 +41,14 @@ impl<'self> Blob<'self> {
             ext::git_blob_is_binary(self.blob) as bool
         }
     }
+
+    /// Write the raw content of the blob to `writer`, like `git cat-file blob`
+    pub fn print(&self, writer: &io::Writer)
+    {
+        do self.rawcontent_as_slice |data| {
+            writer.write(data);
+        }
+    }
 }
 
 #[unsafe_destructor]
@@ -0,0 +1,95 @@
+use std::libc::{c_int, c_uint};
+use std::ptr;
+use ext;
+
+pub enum CheckoutStrategy {
+    /** default is a dry run, no actual updates */
+    GIT_CHECKOUT_NONE = 0,
+    /** Allow safe updates that cannot overwrite uncommitted data */
+    GIT_CHECKOUT_SAFE = (1 << 0),
+    /** Allow all updates to force working directory to look like index */
+    GIT_CHECKOUT_FORCE = (1 << 1),
+    /** Allow checkout to recreate missing files */
+    GIT_CHECKOUT_RECREATE_MISSING = (1 << 2),
+    /** Allow checkout to make safe updates even if conflicts are found */
+    GIT_CHECKOUT_ALLOW_CONFLICTS = (1 << 4),
+    /** Remove untracked files not in index (that are not ignored) */
+    GIT_CHECKOUT_REMOVE_UNTRACKED = (1 << 5),
+    /** Remove ignored files not in index */
+    GIT_CHECKOUT_REMOVE_IGNORED = (1 << 6),
+    /** Only update existing files, don't create new ones */
+    GIT_CHECKOUT_UPDATE_ONLY = (1 << 7),
+    /** Normally checkout updates index entries as it goes; this stops that */
+    GIT_CHECKOUT_DONT_UPDATE_INDEX = (1 << 8),
+    /** Don't refresh index/config/etc before doing checkout */
+    GIT_CHECKOUT_NO_REFRESH = (1 << 9),
+    /** Treat pathspec as simple list of exact match file paths */
+    GIT_CHECKOUT_DISABLE_PATHSPEC_MATCH = (1 << 13),
+    /** Ignore directories in use, they will be left empty */
+    GIT_CHECKOUT_SKIP_LOCKED_DIRECTORIES = (1 << 18),
+    /** Write conflicting files with the standard merge markers */
+    GIT_CHECKOUT_CONFLICT_STYLE_MERGE = (1 << 20),
+    /** Write conflicting files with diff3 markers, including the base version */
+    GIT_CHECKOUT_CONFLICT_STYLE_DIFF3 = (1 << 21),
+}
+
+pub struct CheckoutOption {
+    strategy: ~[CheckoutStrategy],
+    disable_filters: bool,
+    dir_mode: uint,     // 0 means the default 0755
+    file_mode: uint,    // 0 means the default 0644 or 0755 as dictated by blob
+    paths: ~[~str],
+}
+
+impl CheckoutOption {
+    pub fn new() -> CheckoutOption {
+        CheckoutOption {
+            strategy: ~[GIT_CHECKOUT_SAFE, GIT_CHECKOUT_RECREATE_MISSING],
+            disable_filters: false,
+            dir_mode: 0,
+            file_mode: 0,
+            paths: ~[],
+        }
+    }
+}
+
+/// Convert the options to the libgit2 representation, and pass it to the closure.
+pub fn with_c_opts<T>(opts: &CheckoutOption, f: &fn(&ext::git_checkout_options) -> T) -> T
+{
+    let strategy = do opts.strategy.iter().fold(0u) |strategy, &s| {
+        strategy | (s as uint)
+    };
+
+    let paths = do opts.paths.map |path| {
+        do path.as_c_str |c_path| { c_path }
+    };
+
+    unsafe {
+        let c_opts = ext::git_checkout_options {
+            version: 1,     // GIT_CHECKOUT_OPTIONS_VERSION
+            checkout_strategy: strategy as c_uint,
+            disable_filters: opts.disable_filters as c_int,
+            dir_mode: opts.dir_mode as c_uint,
+            file_mode: opts.file_mode as c_uint,
+            file_open_flags: 0,
+            notify_flags: 0,
+            notify_cb: ptr::null(),
+            notify_payload: ptr::null(),
+            progress_cb: ptr::null(),
+            progress_payload: ptr::null(),
+            paths: ext::git_strarray {
+                strings: std::vec::raw::to_ptr(paths),
+                count: paths.len() as u64,
+            },
+            baseline: ptr::null(),
+            baseline_index: ptr::null(),
+            target_directory: ptr::null(),
+            ancestor_label: ptr::null(),
+            our_label: ptr::null(),
+            their_label: ptr::null(),
+            perfdata_cb: ptr::null(),
+            perfdata_payload: ptr::null(),
+        };
+        f(&c_opts)
+    }
+}
@@ -0,0 +1,268 @@
+use std::hashmap::HashSet;
+use std::vec;
+use super::*;
+use diff;
+use odb;
+use revwalk;
+
+/// A commit of the head branch, as reported by `cherry`
+pub struct CherryCommit {
+    id: OID,
+    /// true if an equivalent change already exists in upstream
+    upstream: bool,
+}
+
+/// Compute the patch id of a commit: a hash of the changes introduced by the commit
+/// against its parent, ignoring whitespace and line numbers.
+///
+/// Two commits applying the same change to different bases will have the same patch id.
+/// The hash is computed by this library, and does not match the output of `git patch-id`.
+///
+/// Returns None for merge commits, and an error if the trees of the commit or of its
+/// parent cannot be read.
+pub fn patch_id(repo: &Repository, commit: &Commit) -> Result<Option<OID>, (~str, GitError)>
+{
+    let parents = commit.parents_oid();
+    if parents.len() > 1 {
+        return Ok(None);
+    }
+
+    let tree = match commit.tree() {
+        Ok(tree) => tree,
+        Err(e) => return Err(e),
+    };
+    let opts = diff::DiffOption::new();
+    let difflist = if parents.is_empty() {
+        repo.diff_trees(None, Some(tree), &opts)
+    } else {
+        match repo.try_lookup_commit(&*parents[0]) {
+            Ok(parent) => match parent.tree() {
+                Ok(parent_tree) => repo.diff_trees(Some(parent_tree), Some(tree), &opts),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e.to_error(&*parents[0])),
+        }
+    };
+    let difflist = match difflist {
+        Ok(d) => d,
+        Err(e) => return Err(e),
+    };
+
+    let mut data: ~[u8] = ~[];
+    let mut last_path = ~"";
+    for difflist.each_line |path, origin, content| {
+        if path != last_path {
+            data.push_all(path.as_bytes());
+            data.push('\n' as u8);
+            last_path = path.to_owned();
+        }
+        if origin == '+' || origin == '-' {
+            data.push(origin as u8);
+            for content.iter().advance |&b| {
+                if b != (' ' as u8) && b != ('\t' as u8) && b != ('\n' as u8) &&
+                        b != ('\r' as u8) {
+                    data.push(b);
+                }
+            }
+        }
+    };
+
+    match odb::hash(data, GIT_OBJ_BLOB) {
+        Ok(oid) => Ok(Some(oid)),
+        Err(e) => Err(e),
+    }
+}
+
+/// the patch id of the commit `id`
+fn patch_id_of(repo: &Repository, id: &OID) -> Result<Option<OID>, (~str, GitError)>
+{
+    match repo.try_lookup_commit(id) {
+        Ok(commit) => patch_id(repo, commit),
+        Err(e) => Err(e.to_error(id)),
+    }
+}
+
+/// commits reachable from `from` but not from `hide`, oldest first
+fn range(repo: &Repository, from: &OID, hide: &OID) -> Result<~[OID], (~str, GitError)>
+{
+    match repo.revwalk() {
+        Ok(walk) => {
+            walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL, revwalk::GIT_SORT_REVERSE]);
+            walk.push(from);
+            walk.hide(hide);
+            let mut walk = walk;
+            Ok( walk.collect() )
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Find the commits of `head` which are not in `upstream`, like `git cherry`,
+/// and mark those whose change was already applied in `upstream` with an equivalent patch.
+///
+/// The commits are returned oldest first.
+pub fn cherry(repo: &Repository, upstream: &OID, head: &OID)
+    -> Result<~[CherryCommit], (~str, GitError)>
+{
+    let upstream_ids = match range(repo, upstream, head) {
+        Ok(ids) => ids,
+        Err(e) => return Err(e),
+    };
+    let head_ids = match range(repo, head, upstream) {
+        Ok(ids) => ids,
+        Err(e) => return Err(e),
+    };
+
+    let mut upstream_patches: HashSet<OID> = HashSet::new();
+    for upstream_ids.iter().advance |oid| {
+        match patch_id_of(repo, oid) {
+            Ok(Some(id)) => { upstream_patches.insert(id); },
+            Ok(None) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut result: ~[CherryCommit] = ~[];
+    for head_ids.iter().advance |oid| {
+        let applied = match patch_id_of(repo, oid) {
+            Ok(Some(id)) => upstream_patches.contains(&id),
+            Ok(None) => false,
+            Err(e) => return Err(e),
+        };
+        result.push(CherryCommit { id: *oid, upstream: applied });
+    }
+    Ok(result)
+}
+
+/// How a commit changed between two versions of a branch, see `range_diff`
+#[deriving(Eq)]
+pub enum RangeDiffStatus {
+    /// the commit applies the same patch in both versions
+    RangeUnchanged,
+    /// the commit has the same summary line, but its patch changed
+    RangeModified,
+    /// the commit is only in the new version
+    RangeAdded,
+    /// the commit is only in the old version
+    RangeDropped,
+}
+
+/// A pair of matching commits from the two versions of a branch; `old` is None for
+/// added commits and `new` is None for dropped ones
+pub struct RangeDiffEntry {
+    status: RangeDiffStatus,
+    old: Option<OID>,
+    new: Option<OID>,
+}
+
+/// the patch id and summary line of each of the commits
+fn patch_keys(repo: &Repository, ids: &[OID])
+    -> Result<~[(Option<OID>, ~str)], (~str, GitError)>
+{
+    let mut keys = ~[];
+    for ids.iter().advance |oid| {
+        let commit = match repo.try_lookup_commit(oid) {
+            Ok(commit) => commit,
+            Err(e) => return Err(e.to_error(oid)),
+        };
+        let patch = match patch_id(repo, commit) {
+            Ok(patch) => patch,
+            Err(e) => return Err(e),
+        };
+        let message = commit.message();
+        let summary = message.line_iter().next().get_or_default("").to_owned();
+        keys.push((patch, summary));
+    }
+    Ok(keys)
+}
+
+/// the first index which is not used yet and satisfies `pred`
+fn find_unused(used: &[bool], pred: &fn(uint) -> bool) -> Option<uint>
+{
+    let mut i = 0;
+    while i < used.len() {
+        if !used[i] && pred(i) {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Match up the commits of two versions of a branch, e.g. before and after a rebase,
+/// like `git range-diff`.
+///
+/// Each range is given as (base, tip), for the commits reachable from tip but not
+/// from base. Commits are first matched by patch id, then by summary line. The entries
+/// are in the order of the new version, with each dropped commit listed before the
+/// first entry matching a later commit of the old version.
+pub fn range_diff(repo: &Repository, (old_base, old_tip): (&OID, &OID),
+                  (new_base, new_tip): (&OID, &OID))
+    -> Result<~[RangeDiffEntry], (~str, GitError)>
+{
+    let old_ids = match range(repo, old_tip, old_base) {
+        Ok(ids) => ids,
+        Err(e) => return Err(e),
+    };
+    let new_ids = match range(repo, new_tip, new_base) {
+        Ok(ids) => ids,
+        Err(e) => return Err(e),
+    };
+    let old_keys = match patch_keys(repo, old_ids) {
+        Ok(keys) => keys,
+        Err(e) => return Err(e),
+    };
+    let new_keys = match patch_keys(repo, new_ids) {
+        Ok(keys) => keys,
+        Err(e) => return Err(e),
+    };
+
+    let mut used = vec::from_elem(old_ids.len(), false);
+    let mut matches: ~[(RangeDiffStatus, Option<uint>)] = ~[];
+    for new_keys.iter().advance |&(ref new_patch, ref new_summary)| {
+        let m = {
+            let same_patch = if new_patch.is_none() {
+                None
+            } else {
+                find_unused(used, |i| { let (ref p, _) = old_keys[i]; p == new_patch })
+            };
+            match same_patch {
+                Some(i) => (RangeUnchanged, Some(i)),
+                None => match find_unused(used, |i| {
+                    let (_, ref s) = old_keys[i];
+                    s == new_summary
+                }) {
+                    Some(i) => (RangeModified, Some(i)),
+                    None => (RangeAdded, None),
+                },
+            }
+        };
+        match m {
+            (_, Some(i)) => used[i] = true,
+            _ => (),
+        }
+        matches.push(m);
+    }
+
+    let mut result: ~[RangeDiffEntry] = ~[];
+    let mut next_dropped = 0u;
+    let push_dropped = |result: &mut ~[RangeDiffEntry], next: &mut uint, until: uint| {
+        while *next < until {
+            if !used[*next] {
+                result.push(RangeDiffEntry { status: RangeDropped, old: Some(old_ids[*next]),
+                                            new: None });
+            }
+            *next += 1;
+        }
+    };
+    for matches.iter().enumerate().advance |(n, &(status, old))| {
+        match old {
+            Some(i) => push_dropped(&mut result, &mut next_dropped, i),
+            None => (),
+        }
+        result.push(RangeDiffEntry { status: status, old: old.map(|&i| old_ids[i]),
+                                    new: Some(new_ids[n]) });
+    }
+    push_dropped(&mut result, &mut next_dropped, old_ids.len());
+    Ok(result)
+}
@@ -0,0 +1,255 @@
+use std::{io, uint, vec};
+use std::io::Reader;
+use super::*;
+use odb;
+
+/// how much `write` reads at once
+static READ_BLOCK_SIZE: uint = 64 * 1024;
+
+/// How `write` splits the content into chunks
+pub enum ChunkMode {
+    /// chunks of exactly `chunk_size` bytes, except the last one
+    FixedChunks,
+    /// chunk boundaries chosen from the content with a rolling hash, so that inserting
+    /// or removing bytes only changes the chunks around the edit; `chunk_size` is the
+    /// average size, and the chunks are kept between a quarter and four times that size
+    RollingChunks,
+}
+
+/// Options of `write`
+pub struct ChunkOption {
+    /// size of the chunks in bytes; see `ChunkMode`
+    chunk_size: uint,
+    mode: ChunkMode,
+    /// reference pointed to the manifest, replacing its previous target;
+    /// "refs/chunks/<manifest id>" when None
+    refname: Option<~str>,
+}
+
+impl ChunkOption {
+    pub fn new() -> ChunkOption {
+        ChunkOption {
+            chunk_size: 1024 * 1024,
+            mode: RollingChunks,
+            refname: None,
+        }
+    }
+}
+
+/// Result of a `write`
+pub struct ChunkedBlob {
+    /// id of the manifest tree listing the chunks
+    manifest: OID,
+    /// the reference pointing to the manifest, which keeps the content from being pruned
+    refname: ~str,
+    /// size of the whole content, in bytes
+    size: u64,
+    /// number of chunks of the content
+    chunks: uint,
+    /// number of chunks which were not already in the object database
+    new_chunks: uint,
+}
+
+/// Finds the chunk boundaries, with a gear hash of the last 64 bytes for `RollingChunks`
+struct Chunker {
+    /// random value of each byte; empty for `FixedChunks`
+    gear: ~[u64],
+    /// bits of the hash which must be zero at a boundary
+    mask: u64,
+    min_size: uint,
+    max_size: uint,
+    hash: u64,
+}
+
+impl Chunker {
+    fn new(opts: &ChunkOption) -> Chunker {
+        match opts.mode {
+            FixedChunks => Chunker {
+                gear: ~[],
+                mask: 0,
+                min_size: opts.chunk_size,
+                max_size: opts.chunk_size,
+                hash: 0,
+            },
+            RollingChunks => {
+                // a boundary is found every 2^bits bytes on average
+                let mut bits = 0;
+                while (1u << (bits + 1)) <= opts.chunk_size {
+                    bits += 1;
+                }
+                Chunker {
+                    gear: gear_table(),
+                    mask: if bits == 0 { 0 } else { !0u64 << (64 - bits) },
+                    min_size: uint::max(opts.chunk_size / 4, 1),
+                    max_size: opts.chunk_size * 4,
+                    hash: 0,
+                }
+            },
+        }
+    }
+
+    /// Whether the chunk ends after `byte`, which made it `len` bytes long
+    fn is_boundary(&mut self, byte: u8, len: uint) -> bool {
+        if self.gear.is_empty() {
+            return len >= self.max_size;
+        }
+        self.hash = (self.hash << 1) + self.gear[byte as uint];
+        if len >= self.max_size || (len >= self.min_size && self.hash & self.mask == 0) {
+            self.hash = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A fixed pseudo-random value for each byte, so that the boundaries of a content
+/// are the same on every run
+fn gear_table() -> ~[u64] {
+    let mut state = 0x9e3779b97f4a7c15u64;
+    do vec::from_fn(256) |_| {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
+
+/// Name of the manifest entry of the `index`th chunk; the fixed width keeps the
+/// entries of the tree in the order of the content
+fn chunk_name(index: uint) -> ~str {
+    fmt!("%08x", index)
+}
+
+/// Split the content of `reader` into chunks, each written as a blob, and write a
+/// manifest tree listing the chunks in order, for contents too large to be handled
+/// as a single blob.
+///
+/// Identical chunks are stored only once, within the content and across contents,
+/// so that storing a slightly modified version of a large file mostly reuses the
+/// chunks of the previous one, in particular with `RollingChunks`.
+/// The content can be read back with `read`.
+///
+/// Nothing else refers to the manifest and the chunks, so `write` points a reference
+/// to the manifest (see `ChunkOption::refname`): without it, the next `git gc` or
+/// `maintenance::repack` would delete the content. Deleting the reference, or
+/// pointing it to another manifest, lets them delete it.
+pub fn write(repo: &Repository, reader: &Reader, opts: &ChunkOption)
+    -> Result<ChunkedBlob, (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    if opts.chunk_size == 0 {
+        return Err( (~"the chunk size must not be 0", GITERR_INVALID) );
+    }
+    let odb = match repo.odb() {
+        Ok(odb) => odb,
+        Err(e) => return Err(e),
+    };
+    let builder = match TreeBuilder::new(repo) {
+        Ok(builder) => builder,
+        Err(e) => return Err(e),
+    };
+    let mut result = ChunkedBlob {
+        manifest: OID { id: [0, ..20] },
+        refname: ~"",
+        size: 0,
+        chunks: 0,
+        new_chunks: 0,
+    };
+
+    let mut chunker = Chunker::new(opts);
+    let mut chunk = vec::with_capacity(chunker.max_size);
+    let mut block = vec::from_elem(READ_BLOCK_SIZE, 0u8);
+    while !reader.eof() {
+        let read = reader.read(block, READ_BLOCK_SIZE);
+        if read == 0 {
+            break;
+        }
+        for block.slice(0, read).iter().advance |&byte| {
+            chunk.push(byte);
+            if chunker.is_boundary(byte, chunk.len()) {
+                match write_chunk(repo, odb, &builder, chunk, &mut result) {
+                    Ok(()) => (),
+                    Err(e) => return Err(e),
+                }
+                chunk.truncate(0);
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        match write_chunk(repo, odb, &builder, chunk, &mut result) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut error = None;
+    do git_error::cond.trap(|e| error = Some(e)).in {
+        result.manifest = builder.write(repo);
+    }
+    match error {
+        Some(e) => return Err(e),
+        None => (),
+    }
+
+    result.refname = match opts.refname {
+        Some(ref name) => copy *name,
+        None => ~"refs/chunks/" + result.manifest.to_str(),
+    };
+    match repo.reference_create(result.refname, &result.manifest, true, None) {
+        Ok(_) => Ok(result),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_chunk(repo: &Repository, odb: &Odb, builder: &TreeBuilder, chunk: &[u8],
+               result: &mut ChunkedBlob) -> Result<(), (~str, GitError)>
+{
+    let id = match odb::hash(chunk, GIT_OBJ_BLOB) {
+        Ok(id) => id,
+        Err(e) => return Err(e),
+    };
+    if !odb.exists(&id) {
+        match repo.blob_write(chunk) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        }
+        result.new_chunks += 1;
+    }
+    match builder.insert(chunk_name(result.chunks), &id, GIT_FILEMODE_BLOB) {
+        Ok(_) => (),
+        Err(e) => return Err(e),
+    }
+    result.chunks += 1;
+    result.size += chunk.len() as u64;
+    Ok(())
+}
+
+/// Write the content stored by `write` with the manifest tree `manifest` to `writer`.
+///
+/// Fails with GITERR_INVALID if `manifest` is not a manifest tree.
+pub fn read(repo: &Repository, manifest: &OID, writer: &io::Writer)
+    -> Result<(), (~str, GitError)>
+{
+    let tree = match repo.lookup_tree(manifest) {
+        Some(tree) => tree,
+        None => return Err( (fmt!("manifest tree %s not found", manifest.to_str()),
+                             GITERR_INVALID) ),
+    };
+    let entries = tree.collect_paths();
+    for entries.iter().enumerate().advance |(index, &(ref name, ref id, mode))| {
+        if *name != chunk_name(index) || mode as int != GIT_FILEMODE_BLOB as int {
+            return Err( (fmt!("%s is not a chunk manifest: unexpected entry %s",
+                              manifest.to_str(), *name), GITERR_INVALID) );
+        }
+        match repo.blob_lookup(id) {
+            Some(blob) => do blob.rawcontent_as_slice |content| { writer.write(content) },
+            None => return Err( (fmt!("chunk %s not found", id.to_str()), GITERR_INVALID) ),
+        }
+    }
+    Ok(())
+}
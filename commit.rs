@@ -1,8 +1,10 @@
 use std::libc::c_uint;
-use std::ptr;
-use std::str::raw::from_c_str;
+use std::{cast, ptr};
+use std::str::raw::{from_c_str, from_buf_len};
 use ext;
 use signature;
+use cherry;
+use dag;
 use super::*;
 
 impl<'self> Commit<'self> {
@@ -12,7 +14,7 @@ impl<'self> Commit<'self> {
         unsafe {
             // OID pointer returned by git_commit_id is const pointer
             // so it's safe to use as long as self is alive
-            ext::git_commit_id(self.commit)
+            cast::transmute(ext::git_commit_id(self.commit))
         }
     }
 
@@ -40,6 +42,32 @@ impl<'self> Commit<'self> {
         }
     }
 
+    /// Get the value of the header `field` of the commit, e.g. "gpgsig", "mergetag"
+    /// or a custom header, or None if the commit has no such header.
+    ///
+    /// The continuation lines of multi-line values are joined with newlines, without
+    /// their leading space. If the header appears several times, the first value is
+    /// returned.
+    ///
+    /// raises git_error on error
+    pub fn header_field(&self, field: &str) -> Option<~str>
+    {
+        do field.as_c_str |c_field| {
+            unsafe {
+                let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+                match ext::git_commit_header_field(&mut buf, self.commit, c_field) {
+                    0 => {
+                        let value = from_buf_len(buf.ptr as *u8, buf.size as uint);
+                        ext::git_buf_dispose(&mut buf);
+                        Some(value)
+                    },
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
+                }
+            }
+        }
+    }
+
     /// Get the committer of a commit
     pub fn committer(&self) -> Signature
     {
@@ -59,14 +87,14 @@ impl<'self> Commit<'self> {
     }
 
     /// Get the tree pointed to by a commit.
-    pub fn tree<'r>(&'r self) -> ~Tree<'r>
+    pub fn tree<'r>(&'r self) -> Result<~Tree<'r>, (~str, GitError)>
     {
         unsafe {
             let mut tree:*ext::git_tree = ptr::null();
             if ext::git_commit_tree(&mut tree, self.commit) == 0 {
-                ~Tree { tree: tree, owner: self.owner }
+                Ok( ~Tree { tree: tree, owner: self.owner } )
             } else {
-                fail!(~"failed to retrieve tree")
+                Err( last_error() )
             }
         }
     }
@@ -119,6 +147,15 @@ impl<'self> Commit<'self> {
         }
     }
 
+    /// Compute the patch id of the commit, which identifies the change it introduces
+    /// independently of its base.
+    ///
+    /// See `cherry::patch_id`. Returns None for merge commits.
+    pub fn patch_id(&self) -> Result<Option<OID>, (~str, GitError)>
+    {
+        cherry::patch_id(self.owner, self)
+    }
+
     /// Get the oid of parents for the commit. This is different from
     /// parents(&self), which will attempt to load the parent commit from the ODB.
     pub fn parents_oid(&self) -> ~[~OID]
@@ -147,6 +184,14 @@ impl<'self> Commit<'self> {
             }
         }
     }
+
+    /// Get the commits of `index` having this commit as an ancestor.
+    ///
+    /// See `dag::ChildrenIndex::descendants`.
+    pub fn descendants(&self, index: &dag::ChildrenIndex) -> ~[OID]
+    {
+        index.descendants(self.id())
+    }
 }
 
 #[unsafe_destructor]
@@ -0,0 +1,388 @@
+use std::libc;
+use std::libc::{c_int, c_void, size_t};
+use std::{os, ptr, rand, cast, vec};
+use std::str::raw::{from_buf_len, from_c_str};
+use super::*;
+use ext;
+
+/// Open the global, XDG and system configuration files
+pub fn open_default() -> Result<~Config, (~str, GitError)>
+{
+    unsafe {
+        let mut cfg: *ext::git_config = ptr::null();
+        if ext::git_config_open_default(&mut cfg) == 0 {
+            Ok( ~Config { cfg: cfg, tmpdir: None } )
+        } else {
+            Err( last_error() )
+        }
+    }
+}
+
+/// Parse configuration text, e.g. the content of a config file stored in a database,
+/// with the same rules as git.
+///
+/// The text is stored in a temporary directory which only the user can read, as it
+/// may hold credentials; the directory is removed when the Config is dropped.
+/// Changes made with the `set_*` methods can be read back with `to_buffer`.
+pub fn from_buffer(text: &str) -> Result<~Config, (~str, GitError)>
+{
+    // creating the directory fails if the name is taken, e.g. by a symbolic link;
+    // the files libgit2 writes there on `set_*` get the default mode
+    let dir = os::tmpdir().push(fmt!("git2-rs-config-%u", rand::random::<uint>()));
+    if !os::make_dir(&dir, 0o700) {
+        return Err( (fmt!("cannot create %s: %s", dir.to_str(), os::last_os_error()),
+                     GITERR_OS) );
+    }
+    let path = dir.push("config");
+    match write_new_file(&path, text.as_bytes()) {
+        Ok(()) => (),
+        Err(e) => {
+            remove_tmpdir(&dir);
+            return Err(e);
+        },
+    }
+    unsafe {
+        let mut cfg: *ext::git_config = ptr::null();
+        let res = do path.to_str().as_c_str |c_path| {
+            ext::git_config_open_ondisk(&mut cfg, c_path)
+        };
+        if res == 0 {
+            Ok( ~Config { cfg: cfg, tmpdir: Some(dir) } )
+        } else {
+            let err = last_error();
+            remove_tmpdir(&dir);
+            Err(err)
+        }
+    }
+}
+
+/// create `path`, which must not exist yet, readable by the user only, with `data`
+fn write_new_file(path: &Path, data: &[u8]) -> Result<(), (~str, GitError)>
+{
+    unsafe {
+        let fd = do path.to_str().as_c_str |c_path| {
+            libc::open(c_path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o600)
+        };
+        if fd < 0 {
+            return Err( (fmt!("cannot create %s: %s", path.to_str(), os::last_os_error()),
+                         GITERR_OS) );
+        }
+        let mut written = 0u;
+        while written < data.len() {
+            let res = do vec::as_imm_buf(data.slice_from(written)) |buf, len| {
+                libc::write(fd, buf as *c_void, len as size_t)
+            };
+            if res <= 0 {
+                let err = os::last_os_error();
+                libc::close(fd);
+                return Err( (fmt!("cannot write %s: %s", path.to_str(), err), GITERR_OS) );
+            }
+            written += res as uint;
+        }
+        if libc::close(fd) != 0 {
+            return Err( (fmt!("cannot write %s: %s", path.to_str(), os::last_os_error()),
+                         GITERR_OS) );
+        }
+        Ok(())
+    }
+}
+
+/// remove the directory of a configuration created from a buffer, with the config
+/// file and the lock file libgit2 may have left
+fn remove_tmpdir(dir: &Path)
+{
+    for os::list_dir(dir).iter().advance |name| {
+        os::remove_file(&dir.push(*name));
+    }
+    os::remove_dir(dir);
+}
+
+/// quote a value if git would not read it back as is
+fn quote_value(value: &str) -> ~str
+{
+    let needs_quotes = value.is_empty() || value.starts_with(" ") || value.ends_with(" ") ||
+        value.iter().any(|c| c == ';' || c == '#');
+    let mut out = ~"";
+    if needs_quotes {
+        out.push_char('"');
+    }
+    for value.iter().advance |c| {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push_char(c),
+        }
+    }
+    if needs_quotes {
+        out.push_char('"');
+    }
+    out
+}
+
+impl Config {
+    /// Render all the variables of the configuration as config file text.
+    ///
+    /// Variables are grouped by section, in the order they are read; comments and
+    /// formatting of the original files are not preserved.
+    pub fn to_buffer(&self) -> ~str
+    {
+        let mut out = ~"";
+        let mut current: Option<~str> = None;
+        for self.foreach_match(".*") |name, value| {
+            let first = name.find('.');
+            let last = name.rfind('.');
+            match (first, last) {
+                (Some(first), Some(last)) => {
+                    let header = if first == last {
+                        fmt!("[%s]", name.slice(0, first))
+                    } else {
+                        let subsection = name.slice(first + 1, last)
+                                            .replace("\\", "\\\\").replace("\"", "\\\"");
+                        fmt!("[%s \"%s\"]", name.slice(0, first), subsection)
+                    };
+                    if current != Some(copy header) {
+                        out.push_str(header);
+                        out.push_char('\n');
+                        current = Some(header);
+                    }
+                    out.push_str(fmt!("\t%s = %s\n", name.slice_from(last + 1),
+                                        quote_value(value)));
+                },
+                _ => (),
+            }
+        };
+        out
+    }
+
+    /// Create a snapshot of the configuration
+    ///
+    /// Create a snapshot of the current state of a configuration,
+    /// which allows you to look into a consistent view of the configuration
+    /// for looking up complex values (e.g. a remote, submodule).
+    pub fn snapshot(&self) -> Result<~Config, (~str, GitError)>
+    {
+        unsafe {
+            let mut snap: *ext::git_config = ptr::null();
+            if ext::git_config_snapshot(&mut snap, self.cfg) == 0 {
+                Ok( ~Config { cfg: snap, tmpdir: None } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Get the value of a string config variable.
+    ///
+    /// All config files will be looked into, in the order of their
+    /// defined level. A higher level means a higher priority. The
+    /// first occurrence of the variable will be returned here.
+    ///
+    /// Returns None if the variable is not set; raises git_error on other errors
+    pub fn get_str(&self, name: &str) -> Option<~str>
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+                match ext::git_config_get_string_buf(&mut buf, self.cfg, c_name) {
+                    0 => {
+                        let value = from_buf_len(buf.ptr as *u8, buf.size as uint);
+                        ext::git_buf_dispose(&mut buf);
+                        Some(value)
+                    },
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
+                }
+            }
+        }
+    }
+
+    /// Get the value of a boolean config variable.
+    ///
+    /// This function uses the usual C convention of 0 being false and
+    /// anything else true.
+    ///
+    /// Returns None if the variable is not set; raises git_error on other errors
+    pub fn get_bool(&self, name: &str) -> Option<bool>
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                let mut value: c_int = 0;
+                match ext::git_config_get_bool(&mut value, self.cfg, c_name) {
+                    0 => Some(value != 0),
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
+                }
+            }
+        }
+    }
+
+    /// Get the value of an integer config variable.
+    ///
+    /// Returns None if the variable is not set; raises git_error on other errors
+    pub fn get_i32(&self, name: &str) -> Option<i32>
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                let mut value: i32 = 0;
+                match ext::git_config_get_int32(&mut value, self.cfg, c_name) {
+                    0 => Some(value),
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
+                }
+            }
+        }
+    }
+
+    /// Set the value of a string config variable in the config file
+    /// with the highest level (usually the local one).
+    ///
+    /// raises git_error on error
+    pub fn set_str(&self, name: &str, value: &str)
+    {
+        do name.as_c_str |c_name| {
+            do value.as_c_str |c_value| {
+                unsafe {
+                    if ext::git_config_set_string(self.cfg, c_name, c_value) != 0 {
+                        raise()
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set the value of a boolean config variable in the config file
+    /// with the highest level (usually the local one).
+    ///
+    /// raises git_error on error
+    pub fn set_bool(&self, name: &str, value: bool)
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                if ext::git_config_set_bool(self.cfg, c_name, value as c_int) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Set the value of an integer config variable in the config file
+    /// with the highest level (usually the local one).
+    ///
+    /// raises git_error on error
+    pub fn set_i32(&self, name: &str, value: i32)
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                if ext::git_config_set_int32(self.cfg, c_name, value) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Delete a config variable from the config file
+    /// with the highest level (usually the local one).
+    ///
+    /// return true if successful, false if the variable does not exist
+    pub fn delete(&self, name: &str) -> bool
+    {
+        do name.as_c_str |c_name| {
+            unsafe {
+                match ext::git_config_delete_entry(self.cfg, c_name) {
+                    0 => true,
+                    ext::GIT_ENOTFOUND => false,
+                    _ => { raise(); false },
+                }
+            }
+        }
+    }
+
+    /// Perform an operation on each config variable whose name matches the regular
+    /// expression `regexp`, with the name and the value of the variable.
+    ///
+    /// Variables with several values are visited once per value.
+    /// If the callback returns false, this function will stop looping
+    ///
+    /// return values:
+    ///   true: the loop finished successfully
+    ///   false: the callback returned false
+    /// raises git_error on other errors
+    pub fn foreach_match(&self, regexp: &str, op: &fn(name: &str, value: &str) -> bool) -> bool
+    {
+        do regexp.as_c_str |c_regexp| {
+            unsafe {
+                let payload: *c_void = cast::transmute(&op);
+                match ext::git_config_foreach_match(self.cfg, c_regexp, git_config_foreach_cb,
+                                                    payload) {
+                    0 => true,
+                    // the nonzero value returned by the callback to stop
+                    res if res > 0 => false,
+                    _ => { raise(); false },
+                }
+            }
+        }
+    }
+
+    /// Rewrite a remote URL with the `url.<base>.insteadOf` rules, like git does
+    /// when it connects to a remote.
+    ///
+    /// When `push` is true, the `url.<base>.pushInsteadOf` rules are tried first.
+    /// The longest matching prefix wins; the URL is returned unchanged if no rule matches.
+    pub fn rewrite_url(&self, url: &str, push: bool) -> ~str
+    {
+        if push {
+            match self.longest_rewrite(url, "pushinsteadof") {
+                Some(rewritten) => return rewritten,
+                None => (),
+            }
+        }
+        match self.longest_rewrite(url, "insteadof") {
+            Some(rewritten) => rewritten,
+            None => url.to_owned(),
+        }
+    }
+
+    fn longest_rewrite(&self, url: &str, key: &str) -> Option<~str>
+    {
+        let mut best: Option<(~str, uint)> = None;
+        let suffix = ~"." + key;
+        for self.foreach_match(~"^url\\..*\\." + key + "$") |name, prefix| {
+            let base = name.slice("url.".len(), name.len() - suffix.len());
+            let longer = match best {
+                Some((_, len)) => prefix.len() > len,
+                None => true,
+            };
+            if url.starts_with(prefix) && longer {
+                best = Some((base.to_owned() + url.slice_from(prefix.len()), prefix.len()));
+            }
+        };
+        best.map(|&(ref rewritten, _)| copy *rewritten)
+    }
+}
+
+extern fn git_config_foreach_cb(entry: *ext::git_config_entry, payload: *c_void) -> c_int
+{
+    unsafe {
+        let op_ptr: *&fn(&str, &str) -> bool = cast::transmute(payload);
+        let op = *op_ptr;
+        if op(from_c_str((*entry).name), from_c_str((*entry).value)) {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl Drop for Config {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_config_free(self.cfg);
+        }
+        match self.tmpdir {
+            Some(ref dir) => remove_tmpdir(dir),
+            None => (),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::hashmap::{HashMap, HashSet};
+use super::*;
+use revwalk;
+use ext;
+
+/// set of tips, one bit per tip
+type TipSet = ~[u64];
+
+fn tipset_new(ntips: uint) -> TipSet {
+    std::vec::from_elem((ntips + 63) / 64, 0u64)
+}
+
+#[inline]
+fn tipset_get(set: &[u64], idx: uint) -> bool {
+    set[idx / 64] & (1u64 << (idx % 64)) != 0
+}
+
+#[inline]
+fn tipset_set(set: &mut [u64], idx: uint) {
+    set[idx / 64] |= 1u64 << (idx % 64);
+}
+
+fn tipset_union(dst: &mut [u64], src: &[u64]) {
+    for src.iter().enumerate().advance |(i, &word)| {
+        dst[i] |= word;
+    }
+}
+
+/// Count ahead/behind commits for many pairs of commits at once.
+///
+/// For each pair (local, upstream), `ahead` is the number of commits reachable
+/// from local but not from upstream, and `behind` the number of commits reachable
+/// from upstream but not from local.
+///
+/// The history is walked once for all the pairs: every commit is marked with the set of
+/// tips which can reach it, and the counts are then computed from these marks.
+pub fn ahead_behind_matrix(repo: &Repository, pairs: &[(OID, OID)])
+    -> Result<~[(uint, uint)], (~str, GitError)>
+{
+    let mut walk = match repo.revwalk() {
+        Ok(walk) => walk,
+        Err(e) => return Err(e),
+    };
+    walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL]);
+
+    // every distinct tip gets a bit
+    let mut tips: HashMap<OID, ~[uint]> = HashMap::new();
+    let mut ntips = 0u;
+    for pairs.iter().advance |&(ref local, ref upstream)| {
+        for [local, upstream].iter().advance |oid| {
+            let bits = tips.find_or_insert(*oid, ~[]);
+            bits.push(ntips);
+            walk.push(*oid);
+            ntips += 1;
+        }
+    }
+
+    // children come before their parents, so the marks of a commit are complete
+    // when it is visited
+    let mut marks: HashMap<OID, TipSet> = HashMap::new();
+    let mut counts = std::vec::from_elem(pairs.len(), (0u, 0u));
+    for walk.advance |oid| {
+        let mut mark = match marks.pop(&oid) {
+            Some(mark) => mark,
+            None => tipset_new(ntips),
+        };
+        match tips.find(&oid) {
+            Some(bits) => for bits.iter().advance |&bit| { tipset_set(mark, bit); },
+            None => (),
+        }
+
+        for counts.mut_iter().enumerate().advance |(i, count)| {
+            let local = tipset_get(mark, 2 * i);
+            let upstream = tipset_get(mark, 2 * i + 1);
+            let (ahead, behind) = *count;
+            if local && !upstream {
+                *count = (ahead + 1, behind);
+            } else if upstream && !local {
+                *count = (ahead, behind + 1);
+            }
+        }
+
+        match repo.lookup_commit(&oid) {
+            Some(commit) => {
+                for commit.parents_oid().iter().advance |parent| {
+                    let parent_mark = marks.find_or_insert(*parent, tipset_new(ntips));
+                    tipset_union(*parent_mark, mark);
+                }
+            },
+            None => (),
+        }
+    }
+    Ok(counts)
+}
+
+/// Reachability index of the commits of a repository.
+///
+/// Every commit reachable from a reference gets a generation number: 1 for root commits,
+/// and one more than the highest generation of its parents otherwise. An ancestor always
+/// has a lower generation than its descendants, which allows to cut the search early.
+///
+/// The index is not updated when new commits are created; queries involving commits
+/// unknown to the index fall back to a plain graph walk.
+pub struct Reachability<'self> {
+    priv owner: &'self Repository,
+    priv index: HashMap<OID, uint>,
+    priv generations: ~[uint],
+    priv parents: ~[~[uint]],
+    /// the query in which each commit was last visited, so that the visited set is
+    /// reused without being cleared
+    priv visited: ~[uint],
+    priv query: uint,
+}
+
+/// Build the reachability index of all the commits reachable from the references and HEAD.
+pub fn reachability<'r>(repo: &'r Repository) -> Result<Reachability<'r>, (~str, GitError)>
+{
+    let mut walk = match repo.revwalk() {
+        Ok(walk) => walk,
+        Err(e) => return Err(e),
+    };
+    // parents come before their children
+    walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL, revwalk::GIT_SORT_REVERSE]);
+    for repo.reference_names().iter().advance |name| {
+        // references to other objects than commits are skipped
+        match repo.lookup(*name) {
+            Some(reference) => match repo.lookup_commit(&reference.resolve()) {
+                Some(commit) => walk.push(commit.id()),
+                None => (),
+            },
+            None => (),
+        }
+    }
+    match repo.head() {
+        Some(head) => walk.push(&head.resolve()),
+        None => (),
+    }
+
+    let mut reach = Reachability { owner: repo, index: HashMap::new(), generations: ~[],
+                                    parents: ~[], visited: ~[], query: 0 };
+    for walk.advance |oid| {
+        let parent_ids = match repo.lookup_commit(&oid) {
+            Some(commit) => commit.parents_oid(),
+            None => ~[],
+        };
+        let mut parents = ~[];
+        let mut generation = 1u;
+        for parent_ids.iter().advance |parent| {
+            match reach.index.find(parent) {
+                Some(&idx) => {
+                    parents.push(idx);
+                    generation = std::uint::max(generation, reach.generations[idx] + 1);
+                },
+                None => (),
+            }
+        }
+        reach.index.insert(oid, reach.generations.len());
+        reach.generations.push(generation);
+        reach.parents.push(parents);
+        reach.visited.push(0);
+    }
+    Ok(reach)
+}
+
+impl<'self> Reachability<'self> {
+    /// Number of commits in the index
+    pub fn len(&self) -> uint {
+        self.generations.len()
+    }
+
+    /// Generation number of a commit, or None if the commit is not in the index
+    pub fn generation(&self, id: &OID) -> Option<uint> {
+        match self.index.find(id) {
+            Some(&idx) => Some(self.generations[idx]),
+            None => None,
+        }
+    }
+
+    /// Determine if `ancestor` is reachable from `commit`.
+    ///
+    /// A commit is considered to be its own ancestor.
+    ///
+    /// Only the commits with a higher generation than `ancestor` are visited.
+    pub fn is_ancestor(&mut self, ancestor: &OID, commit: &OID) -> bool {
+        let (a, c) = match (self.index.find(ancestor), self.index.find(commit)) {
+            (Some(&a), Some(&c)) => (a, c),
+            _ => unsafe {
+                if ancestor == commit {
+                    return true;
+                }
+                return match ext::git_graph_descendant_of(self.owner.repo, commit, ancestor) {
+                    1 => true,
+                    0 => false,
+                    _ => { raise(); false },
+                };
+            },
+        };
+
+        let min_generation = self.generations[a];
+        self.query += 1;
+        let mut stack = ~[c];
+        while !stack.is_empty() {
+            let idx = stack.pop();
+            if idx == a {
+                return true;
+            }
+            if self.visited[idx] == self.query || self.generations[idx] <= min_generation {
+                loop;
+            }
+            self.visited[idx] = self.query;
+            for self.parents[idx].iter().advance |&p| {
+                stack.push(p);
+            }
+        }
+        false
+    }
+}
+
+/// Reverse index of the commit graph, mapping each commit to its children.
+///
+/// Commits only point to their parents; this index answers "which commits are
+/// based on X" for the commits visited by the walk it was built from.
+pub struct ChildrenIndex {
+    priv children: HashMap<OID, ~[OID]>,
+    priv len: uint,
+}
+
+/// Build the children index of the commits produced by `walk`.
+///
+/// The parents of the walked commits are keys of the index even when they were
+/// not walked themselves, e.g. the boundary of a range `a..b` knows its children.
+/// Children are listed in the order of the walk.
+pub fn children_index(repo: &Repository, walk: &mut Revwalk) -> ChildrenIndex
+{
+    let mut index = ChildrenIndex { children: HashMap::new(), len: 0 };
+    for walk.advance |oid| {
+        index.len += 1;
+        match repo.lookup_commit(&oid) {
+            Some(commit) => {
+                for commit.parents_oid().iter().advance |parent| {
+                    let children = index.children.find_or_insert(*parent, ~[]);
+                    children.push(oid);
+                }
+            },
+            None => (),
+        }
+    }
+    index
+}
+
+impl ChildrenIndex {
+    /// Number of commits walked to build the index
+    pub fn len(&self) -> uint {
+        self.len
+    }
+
+    /// The commits having `id` as a parent
+    pub fn children(&self, id: &OID) -> ~[OID] {
+        match self.children.find(id) {
+            Some(children) => copy *children,
+            None => ~[],
+        }
+    }
+
+    /// All the commits having `id` as an ancestor, `id` excluded, in breadth-first order
+    pub fn descendants(&self, id: &OID) -> ~[OID] {
+        let mut seen: HashSet<OID> = HashSet::new();
+        let mut result = ~[];
+        let mut next = 0u;
+        let mut current = *id;
+        loop {
+            match self.children.find(&current) {
+                Some(children) => for children.iter().advance |child| {
+                    if seen.insert(*child) {
+                        result.push(*child);
+                    }
+                },
+                None => (),
+            }
+            if next == result.len() {
+                break;
+            }
+            current = result[next];
+            next += 1;
+        }
+        result
+    }
+}
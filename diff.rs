@@ -1,5 +1,11 @@
+use std::libc::{c_char, c_int, c_void, size_t};
+use std::{comm, io, ptr, cast, task, uint, util, vec};
+use std::vec::raw::buf_as_slice;
+use std::str::raw::{from_c_str, from_buf_len};
+use std::to_str::ToStr;
 use super::*;
 use ext;
+use trace;
 
 pub enum DiffFlag {
     /** Reverse the sides of the diff */
@@ -101,21 +107,850 @@ impl DiffOption {
             max_size: 1 << 29,
         }
     }
+
+    /// Same as `new`: 3 lines of context, "a" and "b" prefixes, no pathspec,
+    /// and a maximum blob size of 512MB.
+    pub fn default() -> DiffOption {
+        DiffOption::new()
+    }
+
+    /// Set the number of unchanged lines that define the boundary of a hunk
+    pub fn context_lines(self, lines: u16) -> DiffOption {
+        let mut opts = self;
+        opts.context_lines = lines;
+        opts
+    }
+
+    /// Set the maximum number of unchanged lines between hunk boundaries
+    /// before the hunks will be merged into one
+    pub fn interhunk_lines(self, lines: u16) -> DiffOption {
+        let mut opts = self;
+        opts.interhunk_lines = lines;
+        opts
+    }
+
+    /// Restrict the diff to the paths matching one of the patterns
+    pub fn pathspec(self, patterns: &[&str]) -> DiffOption {
+        let mut opts = self;
+        opts.pathspec = patterns.map(|p| p.to_owned());
+        opts
+    }
+
+    /// Set the prefixes of the old and new paths in the patch headers
+    pub fn prefixes(self, old_prefix: &str, new_prefix: &str) -> DiffOption {
+        let mut opts = self;
+        opts.old_prefix = old_prefix.to_owned();
+        opts.new_prefix = new_prefix.to_owned();
+        opts
+    }
+
+    /// Set the size (in bytes) above which a blob will be marked as binary
+    pub fn max_size(self, size: i64) -> DiffOption {
+        let mut opts = self;
+        opts.max_size = size;
+        opts
+    }
+
+    /// Add a flag
+    pub fn flag(self, flag: DiffFlag) -> DiffOption {
+        let mut opts = self;
+        opts.flags.push(flag);
+        opts
+    }
+
+    /// Reverse the sides of the diff
+    pub fn reverse(self) -> DiffOption {
+        self.flag(GIT_DIFF_REVERSE)
+    }
+
+    /// Ignore all whitespace
+    pub fn ignore_whitespace(self) -> DiffOption {
+        self.flag(GIT_DIFF_IGNORE_WHITESPACE)
+    }
+
+    /// Ignore the changes of the file modes, like the executable bit
+    ///
+    /// Like git, diffs between trees and the index report mode changes whatever
+    /// `core.filemode` is; this is the way to leave them out.
+    pub fn ignore_filemode(self) -> DiffOption {
+        self.flag(GIT_DIFF_IGNORE_FILEMODE)
+    }
+
+    /// Include untracked files in the diff list
+    pub fn include_untracked(self) -> DiffOption {
+        self.flag(GIT_DIFF_INCLUDE_UNTRACKED)
+    }
+
+    /// Check that the options are valid: the maximum blob size must be positive,
+    /// and the prefixes must not contain line breaks or NUL characters.
+    pub fn validate(&self) -> Result<(), (~str, GitError)> {
+        if self.max_size <= 0 {
+            return Err( (fmt!("invalid max_size: %?", self.max_size), GITERR_INVALID) );
+        }
+        for [&self.old_prefix, &self.new_prefix].iter().advance |prefix| {
+            if prefix.iter().any(|c| c == '\n' || c == '\r' || c == '\x00') {
+                return Err( (fmt!("invalid prefix: %?", *prefix), GITERR_INVALID) );
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate the options, and return them
+    pub fn build(self) -> Result<DiffOption, (~str, GitError)> {
+        match self.validate() {
+            Ok(()) => Ok(self),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Convert the options to the libgit2 representation, and pass it to the closure.
+pub fn with_c_opts<T>(opts: &DiffOption, f: &fn(&ext::git_diff_options) -> T) -> T
+{
+    let flags = do opts.flags.iter().fold(0u32) |flags, &f| {
+        flags | (f as u32)
+    };
+
+    let pathspec = do opts.pathspec.map |path| {
+        do path.as_c_str |c_path| { c_path }
+    };
+
+    unsafe {
+        let c_opts = ext::git_diff_options {
+            version: 1,     // GIT_DIFF_OPTIONS_VERSION
+            flags: flags,
+            ignore_submodules: 0,   // GIT_SUBMODULE_IGNORE_UNSPECIFIED
+            pathspec: ext::git_strarray {
+                strings: std::vec::raw::to_ptr(pathspec),
+                count: pathspec.len() as u64,
+            },
+            notify_cb: ptr::null(),
+            progress_cb: ptr::null(),
+            payload: ptr::null(),
+            context_lines: opts.context_lines as u32,
+            interhunk_lines: opts.interhunk_lines as u32,
+            id_abbrev: 0,
+            max_size: opts.max_size,
+            old_prefix: do opts.old_prefix.as_c_str |c_pref| { c_pref },
+            new_prefix: do opts.new_prefix.as_c_str |c_pref| { c_pref },
+        };
+        f(&c_opts)
+    }
+}
+
+pub enum DiffFindFlag {
+    /** look for renames? (`--find-renames`) */
+    GIT_DIFF_FIND_RENAMES = (1 << 0),
+    /** consider old side of modified for renames? (`--break-rewrites=N`) */
+    GIT_DIFF_FIND_RENAMES_FROM_REWRITES = (1 << 1),
+    /** look for copies? (a la `--find-copies`) */
+    GIT_DIFF_FIND_COPIES = (1 << 2),
+    /** consider unmodified as copy sources? (`--find-copies-harder`) */
+    GIT_DIFF_FIND_COPIES_FROM_UNMODIFIED = (1 << 3),
+    /** split large rewrites into delete/add pairs (`--break-rewrites=/M`) */
+    GIT_DIFF_FIND_AND_BREAK_REWRITES = (1 << 4),
+    /** measure similarity ignoring all whitespace */
+    GIT_DIFF_FIND_IGNORE_WHITESPACE = (1 << 12),
+    /** measure similarity including all data */
+    GIT_DIFF_FIND_DONT_IGNORE_WHITESPACE = (1 << 13),
+}
+
+pub struct FindOption {
+    flags: ~[DiffFindFlag],
+    rename_threshold: u16,
+    rename_from_rewrite_threshold: u16,
+    copy_threshold: u16,
+    break_rewrite_threshold: u16,
+    target_limit: u16,
+}
+
+impl FindOption {
+    pub fn new() -> FindOption {
+        FindOption {
+            flags: ~[GIT_DIFF_FIND_RENAMES],
+            rename_threshold: 50,
+            rename_from_rewrite_threshold: 50,
+            copy_threshold: 50,
+            break_rewrite_threshold: 60,
+            target_limit: 200,
+        }
+    }
+}
+
+/// A custom content similarity measure for `DiffList::find_similar_with`, e.g. one
+/// comparing tokens for generated files
+///
+/// A signature summarises the content of a file as a list of feature hashes; it is
+/// computed once per file, then compared to the signatures of the candidates.
+pub struct SimilarityMetric<'self> {
+    /// compute the signature of the content of the file at `path`, or None to
+    /// exclude the file from the similarity detection
+    signature: &'self fn(path: &str, data: &[u8]) -> Option<~[u64]>,
+    /// score the similarity of two signatures, from 0 (different) to 100 (identical)
+    similarity: &'self fn(a: &[u64], b: &[u64]) -> uint,
 }
 
 pub struct DiffFile {
     oid: OID,
+    /// invalid UTF-8 sequences are replaced with U+FFFD
     path: ~str,
+    /// the path as stored by git
+    path_bytes: ~[u8],
     size: i64,
     flags: u32,
     mode: u16,
 }
 
+impl DiffFile {
+    /// Check if the file exists on this side of the diff; the old side of an added
+    /// file and the new side of a deleted file do not.
+    pub fn exists(&self) -> bool {
+        self.flags & ext::GIT_DIFF_FLAG_EXISTS != 0
+    }
+
+    /// Check if the content of the file was found to be binary.
+    ///
+    /// The content is only inspected when needed, e.g. to produce a patch, so this can
+    /// be false while `is_not_binary` is false too.
+    pub fn is_binary(&self) -> bool {
+        self.flags & ext::GIT_DIFF_FLAG_BINARY != 0
+    }
+
+    /// Check if the content of the file was found to be text
+    pub fn is_not_binary(&self) -> bool {
+        self.flags & ext::GIT_DIFF_FLAG_NOT_BINARY != 0
+    }
+
+    /// Check if `oid` is known. For files of the working directory, the id is only
+    /// computed when needed, and is zero otherwise.
+    pub fn is_valid_oid(&self) -> bool {
+        self.flags & ext::GIT_DIFF_FLAG_VALID_ID != 0
+    }
+}
+
+/// Description of changes to one entry.
+///
+/// When iterating over a diff list, this will be passed to most callbacks and
+/// you can use the contents to understand exactly what has changed.
+///
+/// The `old_file` represents the "from" side of the diff and the `new_file`
+/// represents to "to" side of the diff.
+pub struct Delta {
+    status: DiffDelta,
+    similarity: uint,
+    old_file: DiffFile,
+    new_file: DiffFile,
+}
+
+impl ToStr for Delta {
+    /// Format the delta like `git diff --name-status`: the status letter and the path,
+    /// with the similarity score and both paths for renames and copies
+    fn to_str(&self) -> ~str {
+        let code = match self.status {
+            GIT_DELTA_UNMODIFIED => ' ',
+            GIT_DELTA_ADDED => 'A',
+            GIT_DELTA_DELETED => 'D',
+            GIT_DELTA_MODIFIED => 'M',
+            GIT_DELTA_RENAMED => 'R',
+            GIT_DELTA_COPIED => 'C',
+            GIT_DELTA_IGNORED => '!',
+            GIT_DELTA_UNTRACKED => '?',
+            GIT_DELTA_TYPECHANGE => 'T',
+        };
+        match self.status {
+            GIT_DELTA_RENAMED | GIT_DELTA_COPIED =>
+                fmt!("%c%03u\t%s\t%s", code, self.similarity, self.old_file.path,
+                    self.new_file.path),
+            GIT_DELTA_DELETED => fmt!("%c\t%s", code, self.old_file.path),
+            _ => fmt!("%c\t%s", code, self.new_file.path),
+        }
+    }
+}
+
+unsafe fn from_c_file(file: &ext::git_diff_file) -> DiffFile {
+    let path_bytes = if file.path == ptr::null() { ~[] } else { from_c_bytes(file.path) };
+    DiffFile {
+        oid: file.oid,
+        path: lossy_utf8(path_bytes),
+        path_bytes: path_bytes,
+        size: file.size,
+        flags: file.flags,
+        mode: file.mode,
+    }
+}
+
+/// Parse a patch in the format of `git diff`, e.g. the output of `DiffList::to_patch`
+pub fn from_patch(patch: &str) -> Result<~DiffList, (~str, GitError)>
+{
+    unsafe {
+        let mut difflist: *ext::git_diff = ptr::null();
+        let res = do patch.as_c_str |c_patch| {
+            ext::git_diff_from_buffer(&mut difflist, c_patch, patch.len() as size_t)
+        };
+        if res == 0 {
+            Ok( ~DiffList { difflist: difflist } )
+        } else {
+            Err( last_error() )
+        }
+    }
+}
+
+/// "a/x" -> "x"; "/dev/null" is kept
+fn strip_side(path: &str) -> ~str {
+    if path.starts_with("a/") || path.starts_with("b/") {
+        path.slice_from(2).to_owned()
+    } else {
+        path.to_owned()
+    }
+}
+
+/// "a/x" and "b/y" -> "a/y" and "b/x"
+fn swap_sides(old: &str, new: &str) -> (~str, ~str) {
+    let side = |prefix: &str, path: &str| {
+        if path == "/dev/null" { path.to_owned() } else { fmt!("%s%s", prefix, strip_side(path)) }
+    };
+    (side("a/", new), side("b/", old))
+}
+
+/// "-1,2 +3,4" -> "-3,4 +1,2"
+fn reverse_hunk_header(line: &str) -> Option<~str> {
+    let end = match line.slice_from(2).find_str(" @@") {
+        Some(end) => end + 2,
+        None => return None,
+    };
+    let ranges: ~[&str] = line.slice(3, end).split_iter(' ').collect();
+    if ranges.len() != 2 || !ranges[0].starts_with("-") || !ranges[1].starts_with("+") {
+        return None;
+    }
+    Some(fmt!("@@ -%s +%s%s", ranges[1].slice_from(1), ranges[0].slice_from(1),
+                line.slice_from(end)))
+}
+
+/// Append a reversed change block to `out`: the removed lines come before the added
+/// ones, like in the patches git writes
+fn flush_block(out: &mut ~str, removed: &mut ~[~str], added: &mut ~[~str])
+{
+    for removed.consume_iter().advance |line| {
+        out.push_str(line);
+        out.push_char('\n');
+    }
+    for added.consume_iter().advance |line| {
+        out.push_str(line);
+        out.push_char('\n');
+    }
+}
+
+/// Reverse a patch in the format of `git diff`, like `git diff -R`
+fn reverse_patch(patch: &str) -> Result<~str, (~str, GitError)>
+{
+    let mut out = ~"";
+    let mut in_hunk = false;
+    // the lines of the current change block, once reversed
+    let mut removed: ~[~str] = ~[];
+    let mut added: ~[~str] = ~[];
+    // whether the last line of the block went to `added`
+    let mut last_added = false;
+    // the first line of the pairs of headers which are swapped
+    let mut minus: Option<~str> = None;
+    let mut old_mode: Option<~str> = None;
+    let mut rename_from: Option<~str> = None;
+    for patch.line_iter().advance |line| {
+        if in_hunk {
+            if line.starts_with("+") {
+                removed.push(~"-" + line.slice_from(1));
+                last_added = false;
+                loop;
+            } else if line.starts_with("-") {
+                added.push(~"+" + line.slice_from(1));
+                last_added = true;
+                loop;
+            } else if line.starts_with("\\") {
+                // "\ No newline at end of file" belongs to the line before it
+                if last_added { added.push(line.to_owned()) } else { removed.push(line.to_owned()) }
+                loop;
+            }
+        }
+        flush_block(&mut out, &mut removed, &mut added);
+        let reversed = if in_hunk && !line.starts_with("diff --git ") {
+            if line.starts_with("@@ ") {
+                match reverse_hunk_header(line) {
+                    Some(header) => header,
+                    None => return Err( (fmt!("invalid hunk header: %s", line), GITERR_INVALID) ),
+                }
+            } else {
+                line.to_owned()
+            }
+        } else if line.starts_with("diff --git ") {
+            in_hunk = false;
+            // the paths may contain " b/" too, the last one starts the new path
+            let mut new_start = None;
+            for line.matches_index_iter(" b/").advance |(start, _)| {
+                new_start = Some(start);
+            }
+            match new_start {
+                Some(pos) => {
+                    let (old, new) = swap_sides(line.slice(11, pos), line.slice_from(pos + 1));
+                    fmt!("diff --git %s %s", old, new)
+                },
+                None => return Err( (fmt!("invalid diff header: %s", line), GITERR_INVALID) ),
+            }
+        } else if line.starts_with("@@ ") {
+            in_hunk = true;
+            match reverse_hunk_header(line) {
+                Some(header) => header,
+                None => return Err( (fmt!("invalid hunk header: %s", line), GITERR_INVALID) ),
+            }
+        } else if line.starts_with("--- ") {
+            minus = Some(line.slice_from(4).to_owned());
+            loop;
+        } else if line.starts_with("+++ ") {
+            let old = util::replace(&mut minus, None).get_or_default(~"/dev/null");
+            let (old, new) = swap_sides(old, line.slice_from(4));
+            fmt!("--- %s\n+++ %s", old, new)
+        } else if line.starts_with("old mode ") {
+            old_mode = Some(line.slice_from(9).to_owned());
+            loop;
+        } else if line.starts_with("new mode ") {
+            fmt!("old mode %s\nnew mode %s", line.slice_from(9),
+                    util::replace(&mut old_mode, None).get_or_default(~""))
+        } else if line.starts_with("rename from ") {
+            rename_from = Some(line.slice_from(12).to_owned());
+            loop;
+        } else if line.starts_with("rename to ") {
+            fmt!("rename from %s\nrename to %s", line.slice_from(10),
+                    util::replace(&mut rename_from, None).get_or_default(~""))
+        } else if line.starts_with("new file mode ") {
+            ~"deleted file mode " + line.slice_from(14)
+        } else if line.starts_with("deleted file mode ") {
+            ~"new file mode " + line.slice_from(18)
+        } else if line.starts_with("index ") {
+            let rest = line.slice_from(6);
+            let ids_end = rest.find(' ').get_or_default(rest.len());
+            match rest.slice_to(ids_end).find_str("..") {
+                Some(dots) => fmt!("index %s..%s%s", rest.slice(dots + 2, ids_end),
+                                    rest.slice_to(dots), rest.slice_from(ids_end)),
+                None => return Err( (fmt!("invalid index line: %s", line), GITERR_INVALID) ),
+            }
+        } else if line.starts_with("Binary files ") || line.starts_with("GIT binary patch") ||
+                line.starts_with("copy from ") {
+            return Err( (~"binary changes and copies cannot be reversed", GITERR_INVALID) );
+        } else {
+            line.to_owned()
+        };
+        out.push_str(reversed);
+        out.push_char('\n');
+    }
+    flush_block(&mut out, &mut removed, &mut added);
+    Ok(out)
+}
+
+impl DiffList {
+    /// Query how many diff records are there in a diff list.
+    pub fn len(&self) -> uint
+    {
+        unsafe {
+            ext::git_diff_num_deltas(self.difflist) as uint
+        }
+    }
+
+    /// Return the diff delta for an entry in the diff list.
+    pub fn delta(&self, idx: uint) -> Option<Delta>
+    {
+        unsafe {
+            let c_delta = ext::git_diff_get_delta(self.difflist, idx as size_t);
+            if c_delta == ptr::null() {
+                return None;
+            }
+            Some(Delta {
+                status: (*c_delta).status,
+                similarity: (*c_delta).similarity as uint,
+                old_file: from_c_file(&(*c_delta).old_file),
+                new_file: from_c_file(&(*c_delta).new_file),
+            })
+        }
+    }
+
+    /// Return all the deltas of the diff list.
+    pub fn deltas(&self) -> ~[Delta]
+    {
+        let len = self.len();
+        let mut deltas = std::vec::with_capacity(len);
+        let mut idx = 0u;
+        while idx < len {
+            match self.delta(idx) {
+                Some(d) => deltas.push(d),
+                None => (),
+            }
+            idx += 1;
+        }
+        deltas
+    }
+
+    /// Loop over every changed line of the diff list, and issue a callback for each one.
+    ///
+    /// The callback is passed the path of the file, the origin of the line
+    /// (' ' for context, '+' for additions and '-' for deletions) and the content
+    /// of the line. If the callback returns false, this function will stop looping
+    ///
+    /// return values:
+    ///   true: the loop finished successfully
+    ///   false: the callback returned false
+    /// raises git_error on other errors
+    pub fn each_line(&self, op: &fn(path: &str, origin: char, content: &[u8]) -> bool) -> bool
+    {
+        unsafe {
+            let payload: *c_void = cast::transmute(&op);
+            let res = ext::git_diff_foreach(self.difflist, ptr::null(), ptr::null(), ptr::null(),
+                                            git_diff_line_cb, payload);
+            if res == 0 {
+                true
+            } else if res > 0 {
+                // the callback stopped the loop
+                false
+            } else {
+                raise();
+                false
+            }
+        }
+    }
+
+    /// Format the diff list as a patch, like `git diff`
+    pub fn to_patch(&self) -> Result<~str, (~str, GitError)>
+    {
+        unsafe {
+            let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+            if ext::git_diff_to_buf(&mut buf, self.difflist, ext::GIT_DIFF_FORMAT_PATCH) != 0 {
+                return Err( last_error() );
+            }
+            let patch = from_buf_len(buf.ptr as *u8, buf.size as uint);
+            ext::git_buf_dispose(&mut buf);
+            Ok(patch)
+        }
+    }
+
+    /// Compute the inverse diff list, which undoes the changes of this one, like
+    /// `git diff -R`.
+    ///
+    /// The reversed diff list is parsed from a patch, so its deltas have no file sizes,
+    /// and the ids of the files are abbreviated. Binary changes and copies cannot be
+    /// reversed.
+    pub fn reverse(&self) -> Result<~DiffList, (~str, GitError)>
+    {
+        let patch = match self.to_patch() {
+            Ok(patch) => patch,
+            Err(e) => return Err(e),
+        };
+        match reverse_patch(patch) {
+            Ok(reversed) => from_patch(reversed),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Transform a diff list marking file renames, copies, etc.
+    ///
+    /// This modifies a diff list in place, replacing old entries that look
+    /// like renames or copies with new entries reflecting those changes.
+    /// This also will, if requested, break modified files into add/remove
+    /// pairs if the amount of change is above a threshold.
+    ///
+    /// raises git_error on error
+    pub fn find_similar(&self, opts: &FindOption)
+    {
+        self.find_similar_metric(opts, ptr::null())
+    }
+
+    /// Like `find_similar`, measuring the similarity of files with `metric`
+    /// instead of the default line-based measure.
+    ///
+    /// raises git_error on error
+    pub fn find_similar_with(&self, opts: &FindOption, metric: &SimilarityMetric)
+    {
+        unsafe {
+            let c_metric = ext::git_diff_similarity_metric {
+                file_signature: file_signature_cb,
+                buffer_signature: buffer_signature_cb,
+                free_signature: free_signature_cb,
+                similarity: similarity_cb,
+                payload: cast::transmute(metric),
+            };
+            self.find_similar_metric(opts, &c_metric)
+        }
+    }
+
+    fn find_similar_metric(&self, opts: &FindOption, metric: *ext::git_diff_similarity_metric)
+    {
+        let flags = do opts.flags.iter().fold(0u32) |flags, &f| {
+            flags | (f as u32)
+        };
+        let c_opts = ext::git_diff_find_options {
+            version: 1,     // GIT_DIFF_FIND_OPTIONS_VERSION
+            flags: flags,
+            rename_threshold: opts.rename_threshold,
+            rename_from_rewrite_threshold: opts.rename_from_rewrite_threshold,
+            copy_threshold: opts.copy_threshold,
+            break_rewrite_threshold: opts.break_rewrite_threshold,
+            rename_limit: opts.target_limit as size_t,
+            metric: metric,
+        };
+        unsafe {
+            if ext::git_diff_find_similar(self.difflist, &c_opts) != 0 {
+                raise()
+            }
+        }
+    }
+}
+
+/// Store the signature computed by the metric in `out`; libgit2 frees it with
+/// free_signature_cb. Without a signature, the file is skipped.
+unsafe fn store_signature(out: *mut *c_void, metric: &SimilarityMetric, path: &str,
+                          data: &[u8]) -> c_int
+{
+    match (metric.signature)(path, data) {
+        Some(sig) => {
+            let sig: *c_void = cast::transmute(~sig);
+            *out = sig;
+        },
+        None => *out = ptr::null(),
+    }
+    0
+}
+
+extern fn file_signature_cb(out: *mut *c_void, file: *ext::git_diff_file, fullpath: *c_char,
+                            payload: *c_void) -> c_int
+{
+    unsafe {
+        let metric: &SimilarityMetric = cast::transmute(payload);
+        match io::read_whole_file(&Path(from_c_str(fullpath))) {
+            Ok(data) => store_signature(out, metric, from_c_str((*file).path), data),
+            // the file cannot be read: skip it rather than failing the whole diff
+            Err(_) => { *out = ptr::null(); 0 },
+        }
+    }
+}
+
+extern fn buffer_signature_cb(out: *mut *c_void, file: *ext::git_diff_file, buf: *c_char,
+                              buflen: size_t, payload: *c_void) -> c_int
+{
+    unsafe {
+        let metric: &SimilarityMetric = cast::transmute(payload);
+        do buf_as_slice(buf as *u8, buflen as uint) |data| {
+            store_signature(out, metric, from_c_str((*file).path), data)
+        }
+    }
+}
+
+extern fn free_signature_cb(sig: *c_void, _payload: *c_void)
+{
+    if sig != ptr::null() {
+        unsafe {
+            let _sig: ~~[u64] = cast::transmute(sig);
+        }
+    }
+}
+
+extern fn similarity_cb(score: *mut c_int, siga: *c_void, sigb: *c_void,
+                        payload: *c_void) -> c_int
+{
+    unsafe {
+        let metric: &SimilarityMetric = cast::transmute(payload);
+        if siga == ptr::null() || sigb == ptr::null() {
+            *score = 0;
+            return 0;
+        }
+        let a: &~[u64] = cast::transmute(siga);
+        let b: &~[u64] = cast::transmute(sigb);
+        *score = uint::min((metric.similarity)(*a, *b), 100) as c_int;
+        0
+    }
+}
+
+/// A range of lines which changed between two versions of a file.
+/// Line numbers start at 1.
+pub struct Hunk {
+    old_start: uint,
+    old_lines: uint,
+    new_start: uint,
+    new_lines: uint,
+}
+
+/// The changes made by a commit, from `diff_commits_parallel`
+pub struct CommitDeltas {
+    commit: OID,
+    /// changes between the first parent (or the empty tree) and the commit
+    deltas: ~[Delta],
+}
+
+/// the changes of a commit against its first parent
+fn commit_deltas(snap: &Snapshot, id: &OID, opts: &DiffOption)
+    -> Result<CommitDeltas, (~str, GitError)>
+{
+    let commit = match snap.lookup_commit(id) {
+        Some(commit) => commit,
+        None => return Err( (fmt!("commit %s not found", id.to_str()), GITERR_INVALID) ),
+    };
+    let tree = match commit.tree() {
+        Ok(tree) => tree,
+        Err(e) => return Err(e),
+    };
+    let parents = commit.parents();
+    let difflist = if parents.is_empty() {
+        snap.diff_trees(None, Some(tree), opts)
+    } else {
+        match parents[0].tree() {
+            Ok(parent_tree) => snap.diff_trees(Some(parent_tree), Some(tree), opts),
+            Err(e) => Err(e),
+        }
+    };
+    match difflist {
+        Ok(difflist) => Ok( CommitDeltas { commit: *id, deltas: difflist.deltas() } ),
+        Err(e) => Err(e),
+    }
+}
+
+/// Diff each commit of `commits` against its first parent, distributing the commits
+/// over `n_tasks` tasks.
+///
+/// Each task is given its own Snapshot of the repository, opened with
+/// `Repository::snapshot`, as repository handles must not be shared between tasks.
+/// The results are in the order of `commits`; if any diff fails, the first error is
+/// returned.
+pub fn diff_commits_parallel(repo: &Repository, commits: &[OID], opts: &DiffOption,
+                             n_tasks: uint) -> Result<~[CommitDeltas], (~str, GitError)>
+{
+    let n_tasks = uint::max(1, uint::min(n_tasks, commits.len()));
+    // open all the handles first, so that no task is left running on an error
+    let mut snaps = ~[];
+    while snaps.len() < n_tasks {
+        match repo.snapshot() {
+            Ok(snap) => snaps.push(snap),
+            Err(e) => return Err(e),
+        }
+    }
+    let (port, chan) = comm::stream();
+    let chan = comm::SharedChan::new(chan);
+    let mut n = 0u;
+    for snaps.consume_iter().advance |snap| {
+        // task n handles the commits n, n + n_tasks, n + 2 * n_tasks...
+        let mut ids = ~[];
+        let mut idx = n;
+        while idx < commits.len() {
+            ids.push((idx, commits[idx]));
+            idx += n_tasks;
+        }
+        let chan = chan.clone();
+        let opts = copy *opts;
+        do task::spawn {
+            let mut results = ~[];
+            let mut error = None;
+            for ids.iter().advance |&(idx, id)| {
+                match commit_deltas(&snap, &id, &opts) {
+                    Ok(deltas) => results.push((idx, deltas)),
+                    Err(e) => { error = Some(e); break; },
+                }
+            }
+            let result = match error {
+                Some(e) => Err(e),
+                None => Ok(results),
+            };
+            chan.send(result);
+        }
+        n += 1;
+    }
+
+    let mut slots: ~[Option<CommitDeltas>] = vec::from_fn(commits.len(), |_| None);
+    let mut error = None;
+    n = 0;
+    while n < n_tasks {
+        match port.recv() {
+            Ok(results) => {
+                for results.consume_iter().advance |(idx, deltas)| {
+                    slots[idx] = Some(deltas);
+                }
+            },
+            Err(e) => if error.is_none() { error = Some(e) },
+        }
+        n += 1;
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok( slots.consume_iter().transform(|slot| slot.unwrap()).collect() ),
+    }
+}
+
+/// Compute the changed hunks between two blobs.
+///
+/// None is treated as an empty blob.
+pub fn blob_hunks(old_blob: Option<&Blob>, new_blob: Option<&Blob>, opts: &DiffOption)
+    -> Result<~[Hunk], (~str, GitError)>
+{
+    let old_b = match old_blob {
+        None => ptr::null(),
+        Some(b) => b.blob,
+    };
+    let new_b = match new_blob {
+        None => ptr::null(),
+        Some(b) => b.blob,
+    };
+
+    let mut hunks: ~[Hunk] = ~[];
+    do with_c_opts(opts) |c_opts| {
+        unsafe {
+            let payload: *c_void = cast::transmute(&mut hunks);
+            let res = do trace::timed("git_diff_blobs") {
+                ext::git_diff_blobs(old_b, ptr::null(), new_b, ptr::null(), c_opts, ptr::null(),
+                                    ptr::null(), git_diff_hunk_cb, ptr::null(), payload)
+            };
+            if res == 0 {
+                Ok( copy hunks )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+}
+
+extern fn git_diff_hunk_cb(_delta: *ext::git_diff_delta, hunk: *ext::git_diff_hunk,
+    payload: *c_void) -> c_int
+{
+    unsafe {
+        let hunks: &mut ~[Hunk] = cast::transmute(payload);
+        hunks.push(Hunk {
+            old_start: (*hunk).old_start as uint,
+            old_lines: (*hunk).old_lines as uint,
+            new_start: (*hunk).new_start as uint,
+            new_lines: (*hunk).new_lines as uint,
+        });
+        0
+    }
+}
+
+extern fn git_diff_line_cb(delta: *ext::git_diff_delta, _hunk: *ext::git_diff_hunk,
+    line: *ext::git_diff_line, payload: *c_void) -> c_int
+{
+    unsafe {
+        let op_ptr: *&fn(&str, char, &[u8]) -> bool = cast::transmute(payload);
+        let op = *op_ptr;
+        let file = if (*delta).new_file.path == ptr::null() {
+            &(*delta).old_file
+        } else {
+            &(*delta).new_file
+        };
+        let path = from_c_str(file.path);
+        do buf_as_slice((*line).content as *u8, (*line).content_len as uint) |v| {
+            if op(path, (*line).origin as u8 as char, v) {
+                0
+            } else {
+                1
+            }
+        }
+    }
+}
+
 #[unsafe_destructor]
 impl Drop for DiffList {
     fn finalize(&self) {
         unsafe {
-            ext::git_diff_list_free(self.difflist);
+            ext::git_diff_free(self.difflist);
         }
     }
 }
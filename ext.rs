@@ -1,4 +1,4 @@
-use std::libc::{c_char, c_void, c_int, c_uint, size_t};
+use std::libc::{c_char, c_void, c_int, c_uint, c_ulong, c_ushort, size_t};
 
 /* from <git2/errors.h> */
 pub struct git_error {
@@ -15,11 +15,12 @@ pub static GIT_EAMBIGUOUS:c_int = -5;
 pub static GIT_EBUFS:c_int = -6;
 pub static GIT_EUSER:c_int = -7;
 pub static GIT_EBAREREPO:c_int = -8;
-pub static GIT_EORPHANEDHEAD:c_int = -9;
+pub static GIT_EUNBORNBRANCH:c_int = -9;
 pub static GIT_EUNMERGED:c_int = -10;
 pub static GIT_ENONFASTFORWARD:c_int = -11;
 pub static GIT_EINVALIDSPEC:c_int = -12;
 pub static GIT_EMERGECONFLICT:c_int = -13;
+pub static GIT_ELOCKED:c_int = -14;
 
 pub static GIT_PASSTHROUGH:c_int = -30;
 pub static GIT_ITEROVER:c_int = -31;
@@ -47,6 +48,45 @@ pub struct git_transport {
     free: callback_t,
 }
 
+/* from <git2/remote.h> */
+pub struct git_remote_callbacks {
+    version: c_uint,
+    sideband_progress: callback_t,
+    completion: callback_t,
+    credentials: callback_t,
+    certificate_check: callback_t,
+    transfer_progress: callback_t,
+    update_tips: callback_t,
+    pack_progress: callback_t,
+    push_transfer_progress: callback_t,
+    push_update_reference: callback_t,
+    push_negotiation: callback_t,
+    transport: callback_t,
+    payload: *c_void,
+    resolve_url: callback_t,
+}
+
+/* from <git2/proxy.h> */
+pub struct git_proxy_options {
+    version: c_uint,
+    proxy_type: c_int,
+    url: *c_char,
+    credentials: callback_t,
+    certificate_check: callback_t,
+    payload: *c_void,
+}
+
+/* from <git2/remote.h> */
+pub struct git_fetch_options {
+    version: c_int,
+    callbacks: git_remote_callbacks,
+    prune: c_int,
+    update_fetchhead: c_int,
+    download_tags: git_remote_autotag_option_t,
+    proxy_opts: git_proxy_options,
+    custom_headers: git_strarray,
+}
+
 /* from <git2/strarray.h> */
 pub struct git_strarray {
     strings: **c_char,
@@ -54,81 +94,17 @@ pub struct git_strarray {
 }
 
 /* from <git2/checkout.h> */
-type git_checkout_strategy_t = uint;
-
-/** default is a dry run, no actual updates */
-static GIT_CHECKOUT_NONE:git_checkout_strategy_t = 0;
-
-/** Allow safe updates that cannot overwrite uncommitted data */
-static GIT_CHECKOUT_SAFE:git_checkout_strategy_t = (1u << 0);
-
-/** Allow safe updates plus creation of missing files */
-static GIT_CHECKOUT_SAFE_CREATE:git_checkout_strategy_t = (1u << 1);
-
-/** Allow all updates to force working directory to look like index */
-static GIT_CHECKOUT_FORCE:git_checkout_strategy_t = (1u << 2);
-
-/** Allow checkout to make safe updates even if conflicts are found */
-static GIT_CHECKOUT_ALLOW_CONFLICTS:git_checkout_strategy_t = (1u << 4);
-
-/** Remove untracked files not in index (that are not ignored) */
-static GIT_CHECKOUT_REMOVE_UNTRACKED:git_checkout_strategy_t = (1u << 5);
-
-/** Remove ignored files not in index */
-static GIT_CHECKOUT_REMOVE_IGNORED:git_checkout_strategy_t = (1u << 6);
-
-/** Only update existing files, don't create new ones */
-static GIT_CHECKOUT_UPDATE_ONLY:git_checkout_strategy_t = (1u << 7);
-
-/** Normally checkout updates index entries as it goes; this stops that */
-static GIT_CHECKOUT_DONT_UPDATE_INDEX:git_checkout_strategy_t = (1u << 8);
-
-/** Don't refresh index/config/etc before doing checkout */
-static GIT_CHECKOUT_NO_REFRESH:git_checkout_strategy_t = (1u << 9);
-
-/** Treat pathspec as simple list of exact match file paths */
-static GIT_CHECKOUT_DISABLE_PATHSPEC_MATCH:git_checkout_strategy_t = (1u << 13);
-
-/** Ignore directories in use, they will be left empty */
-static GIT_CHECKOUT_SKIP_LOCKED_DIRECTORIES:git_checkout_strategy_t = (1u << 18);
-
-/**
- * THE FOLLOWING OPTIONS ARE NOT YET IMPLEMENTED
- */
-
-/** Allow checkout to skip unmerged files (NOT IMPLEMENTED) */
-static GIT_CHECKOUT_SKIP_UNMERGED:git_checkout_strategy_t = (1u << 10);
-/** For unmerged files, checkout stage 2 from index (NOT IMPLEMENTED) */
-static GIT_CHECKOUT_USE_OURS:git_checkout_strategy_t = (1u << 11);
-/** For unmerged files, checkout stage 3 from index (NOT IMPLEMENTED) */
-static GIT_CHECKOUT_USE_THEIRS:git_checkout_strategy_t = (1u << 12);
-
-/** Recursively checkout submodules with same options (NOT IMPLEMENTED) */
-static GIT_CHECKOUT_UPDATE_SUBMODULES:git_checkout_strategy_t = (1u << 16);
-/** Recursively checkout submodules if HEAD moved in super repo (NOT IMPLEMENTED) */
-static GIT_CHECKOUT_UPDATE_SUBMODULES_IF_CHANGED:git_checkout_strategy_t = (1u << 17);
-
-type git_checkout_notify_t = uint;
-
-static GIT_CHECKOUT_NOTIFY_NONE:git_checkout_notify_t       = 0;
-static GIT_CHECKOUT_NOTIFY_CONFLICT:git_checkout_notify_t   = (1u << 0);
-static GIT_CHECKOUT_NOTIFY_DIRTY:git_checkout_notify_t      = (1u << 1);
-static GIT_CHECKOUT_NOTIFY_UPDATED:git_checkout_notify_t    = (1u << 2);
-static GIT_CHECKOUT_NOTIFY_UNTRACKED:git_checkout_notify_t  = (1u << 3);
-static GIT_CHECKOUT_NOTIFY_IGNORED:git_checkout_notify_t    = (1u << 4);
-
-/* from <git2/checkout.h> */
-pub struct git_checkout_opts {
+pub struct git_checkout_options {
     version: c_uint,
 
-    checkout_strategy: git_checkout_strategy_t,
+    checkout_strategy: c_uint,
 
     disable_filters: c_int,
     dir_mode: c_uint,
     file_mode: c_uint,
     file_open_flags: c_int,
 
-    notify_flags: git_checkout_notify_t,
+    notify_flags: c_uint,
     notify_cb: callback_t,
     notify_payload: *c_void,
 
@@ -138,29 +114,61 @@ pub struct git_checkout_opts {
     paths: git_strarray,
 
     baseline: *git_tree,
+    baseline_index: *git_index,
+
+    target_directory: *c_char,
+
+    ancestor_label: *c_char,
+    our_label: *c_char,
+    their_label: *c_char,
+
+    perfdata_cb: callback_t,
+    perfdata_payload: *c_void,
 }
 
 /* from <git2/clone.h> */
 pub struct git_clone_options {
     version: c_uint,
 
-    checkout_opts: git_checkout_opts,
+    checkout_opts: git_checkout_options,
+    fetch_opts: git_fetch_options,
+
     bare: c_int,
-    fetch_progress_cb: callback_t,
-    fetch_progress_payload: *c_void,
-
-    remote_name: *c_char,
-    pushurl: *c_char,
-    fetch_spec: *c_char,
-    push_spec: *c_char,
-    cred_acquire_cb: callback_t,
-    cred_acquire_payload: *c_void,
-    transport: *git_transport,
-    remote_callbacks: callback_t,
-    remote_autotag: git_remote_autotag_option_t,
+    local: c_int,
     checkout_branch: *c_char,
+
+    repository_cb: callback_t,
+    repository_cb_payload: *c_void,
+    remote_cb: callback_t,
+    remote_cb_payload: *c_void,
+}
+
+/* from <git2/repository.h> */
+pub static GIT_REPOSITORY_INIT_BARE:u32 = 1 << 0;
+pub static GIT_REPOSITORY_INIT_NO_REINIT:u32 = 1 << 1;
+pub static GIT_REPOSITORY_INIT_MKPATH:u32 = 1 << 4;
+pub static GIT_REPOSITORY_INIT_EXTERNAL_TEMPLATE:u32 = 1 << 5;
+
+pub static GIT_REPOSITORY_OPEN_NO_SEARCH:c_uint = 1 << 0;
+pub static GIT_REPOSITORY_OPEN_CROSS_FS:c_uint = 1 << 1;
+pub static GIT_REPOSITORY_OPEN_FROM_ENV:c_uint = 1 << 4;
+
+pub struct git_repository_init_options {
+    version: c_uint,
+    flags: u32,
+    mode: u32,
+    workdir_path: *c_char,
+    description: *c_char,
+    template_path: *c_char,
+    initial_head: *c_char,
+    origin_url: *c_char,
 }
 
+/* from <git2/common.h> */
+pub static GIT_FEATURE_THREADS:c_int = 1 << 0;
+pub static GIT_FEATURE_HTTPS:c_int = 1 << 1;
+pub static GIT_FEATURE_SSH:c_int = 1 << 2;
+
 /* from <git2/status.h> */
 pub static GIT_STATUS_INDEX_NEW:c_uint        = (1u << 0) as c_uint;
 pub static GIT_STATUS_INDEX_MODIFIED:c_uint   = (1u << 1) as c_uint;
@@ -172,10 +180,79 @@ pub static GIT_STATUS_WT_NEW:c_uint           = (1u << 7) as c_uint;
 pub static GIT_STATUS_WT_MODIFIED:c_uint      = (1u << 8) as c_uint;
 pub static GIT_STATUS_WT_DELETED:c_uint       = (1u << 9) as c_uint;
 pub static GIT_STATUS_WT_TYPECHANGE:c_uint    = (1u << 10) as c_uint;
+pub static GIT_STATUS_WT_RENAMED:c_uint       = (1u << 11) as c_uint;
+pub static GIT_STATUS_WT_UNREADABLE:c_uint    = (1u << 12) as c_uint;
 
 pub static GIT_STATUS_IGNORED:c_uint          = (1u << 14) as c_uint;
+pub static GIT_STATUS_CONFLICTED:c_uint       = (1u << 15) as c_uint;
+
+pub static GIT_STATUS_OPT_INCLUDE_UNTRACKED:c_uint      = (1u << 0) as c_uint;
+pub static GIT_STATUS_OPT_INCLUDE_IGNORED:c_uint        = (1u << 1) as c_uint;
+pub static GIT_STATUS_OPT_INCLUDE_UNMODIFIED:c_uint     = (1u << 2) as c_uint;
+pub static GIT_STATUS_OPT_RECURSE_UNTRACKED_DIRS:c_uint = (1u << 4) as c_uint;
+
+pub struct git_status_options {
+    version: c_uint,
+    show: c_int,
+    flags: c_uint,
+    pathspec: git_strarray,
+    baseline: *git_tree,
+}
+
+/* from <git2/diff.h> */
+pub static GIT_DIFF_FORMAT_PATCH:c_int = 1;
+
+pub static GIT_DIFF_FLAG_BINARY:u32 = 1 << 0;
+pub static GIT_DIFF_FLAG_NOT_BINARY:u32 = 1 << 1;
+pub static GIT_DIFF_FLAG_VALID_ID:u32 = 1 << 2;
+pub static GIT_DIFF_FLAG_EXISTS:u32 = 1 << 3;
+
+/* from <git2/merge.h> */
+pub static GIT_MERGE_ANALYSIS_NORMAL:c_int = 1 << 0;
+pub static GIT_MERGE_ANALYSIS_UP_TO_DATE:c_int = 1 << 1;
+pub static GIT_MERGE_ANALYSIS_FASTFORWARD:c_int = 1 << 2;
+pub static GIT_MERGE_ANALYSIS_UNBORN:c_int = 1 << 3;
+
+pub static GIT_MERGE_PREFERENCE_NO_FASTFORWARD:c_int = 1 << 0;
+pub static GIT_MERGE_PREFERENCE_FASTFORWARD_ONLY:c_int = 1 << 1;
+
+pub static GIT_MERGE_FIND_RENAMES:u32 = 1 << 0;
+
+pub static GIT_MERGE_FILE_STYLE_MERGE:u32 = 1 << 0;
+pub static GIT_MERGE_FILE_STYLE_DIFF3:u32 = 1 << 1;
+
+pub struct git_merge_options {
+    version: c_uint,
+    flags: u32,
+    rename_threshold: c_uint,
+    target_limit: c_uint,
+    metric: *git_diff_similarity_metric,
+    recursion_limit: c_uint,
+    default_driver: *c_char,
+    file_favor: c_int,
+    file_flags: u32,
+}
+
+/* from <git2/apply.h> */
+pub static GIT_APPLY_LOCATION_WORKDIR:c_int = 0;
+pub static GIT_APPLY_LOCATION_INDEX:c_int = 1;
+pub static GIT_APPLY_LOCATION_BOTH:c_int = 2;
+
+/* from <git2/filter.h> */
+pub static GIT_FILTER_TO_ODB:c_int = 1;
+pub static GIT_FILTER_DEFAULT:u32 = 0;
 
 /* from <git2/tree.h> */
+pub static GIT_TREE_UPDATE_UPSERT:c_int = 0;
+pub static GIT_TREE_UPDATE_REMOVE:c_int = 1;
+
+pub struct git_tree_update {
+    action: c_int,
+    id: super::OID,
+    filemode: c_int,
+    path: *c_char,
+}
+
 pub enum git_treewalk_mode {
 	GIT_TREEWALK_PRE = 0, /* Pre-order */
 	GIT_TREEWALK_POST = 1, /* Post-order */
@@ -183,10 +260,17 @@ pub enum git_treewalk_mode {
 
 /* from <git2/types.h> */
 
+pub type git_ref_t = c_int;
+
+pub static GIT_REF_INVALID: git_ref_t = 0;
+pub static GIT_REF_OID: git_ref_t = 1;
+pub static GIT_REF_SYMBOLIC: git_ref_t = 2;
+
 pub type git_branch_t = c_uint;
 
 pub static GIT_BRANCH_LOCAL: git_branch_t = 1;
 pub static GIT_BRANCH_REMOTE: git_branch_t = 2;
+pub static GIT_BRANCH_ALL: git_branch_t = 3;
 
 // the storage size of these types are unknown
 pub struct git_repository;
@@ -195,7 +279,24 @@ pub struct git_tree_entry;
 pub struct git_treebuilder;
 pub struct git_index;
 pub struct git_object;
-pub struct git_diff_list;
+pub struct git_diff;
+pub struct git_odb;
+pub struct git_odb_object;
+pub struct git_odb_backend;
+pub struct git_odb_stream;
+pub struct git_refdb;
+pub struct git_annotated_commit;
+pub struct git_note;
+pub struct git_indexer;
+pub struct git_revwalk;
+pub struct git_config;
+pub struct git_reference_iterator;
+pub struct git_branch_iterator;
+pub struct git_filter_list;
+pub struct git_packbuilder;
+pub struct git_reflog;
+pub struct git_reflog_entry;
+pub struct git_remote;
 pub type git_tree = git_object;
 pub type git_commit = git_object;
 pub type git_blob = git_object;
@@ -214,6 +315,36 @@ pub type git_off_t = i64;
 pub struct git_time {
     time: git_time_t,
     offset: c_int,
+    sign: c_char,
+}
+
+pub struct git_index_time {
+    seconds: i32,
+    nanoseconds: u32,
+}
+
+pub struct git_index_entry {
+    ctime: git_index_time,
+    mtime: git_index_time,
+    dev: c_uint,
+    ino: c_uint,
+    mode: c_uint,
+    uid: c_uint,
+    gid: c_uint,
+    file_size: u32,
+    oid: super::OID,
+    flags: c_ushort,
+    flags_extended: c_ushort,
+    path: *c_char,
+}
+
+pub struct git_config_entry {
+    name: *c_char,
+    value: *c_char,
+    include_depth: c_uint,
+    level: c_int,
+    free: callback_t,
+    payload: *c_void,
 }
 
 pub struct git_signature {
@@ -225,14 +356,99 @@ pub struct git_signature {
 pub struct git_diff_options {
     version: c_uint,
     flags: u32,
-    context_lines: u16,
-    interhunk_lines: u16,
-    old_prefix: *c_char,
-    new_prefix: *c_char,
+    ignore_submodules: c_int,
     pathspec: git_strarray,
-    max_size: git_off_t,
     notify_cb: callback_t,
-    notify_payload: *c_void,
+    progress_cb: callback_t,
+    payload: *c_void,
+    context_lines: u32,
+    interhunk_lines: u32,
+    id_abbrev: u16,
+    max_size: git_off_t,
+    old_prefix: *c_char,
+    new_prefix: *c_char,
+}
+
+/* from <git2/buffer.h> */
+pub struct git_buf {
+    pub ptr: *c_char,
+    pub asize: size_t,
+    pub size: size_t,
+}
+
+/* from <git2/indexer.h> */
+pub struct git_indexer_options {
+    version: c_uint,
+    progress_cb: callback_t,
+    progress_cb_payload: *c_void,
+    verify: u8,
+}
+
+/* from <git2/indexer.h> */
+pub struct git_transfer_progress {
+    pub total_objects: c_uint,
+    pub indexed_objects: c_uint,
+    pub received_objects: c_uint,
+    pub local_objects: c_uint,
+    pub total_deltas: c_uint,
+    pub indexed_deltas: c_uint,
+    pub received_bytes: size_t,
+}
+
+pub struct git_diff_file {
+    pub oid: super::OID,
+    pub path: *c_char,
+    pub size: git_off_t,
+    pub flags: u32,
+    pub mode: u16,
+    pub id_abbrev: u16,
+}
+
+pub struct git_diff_delta {
+    pub status: super::DiffDelta,
+    pub flags: u32,
+    pub similarity: u16,
+    pub nfiles: u16,
+    pub old_file: git_diff_file,
+    pub new_file: git_diff_file,
+}
+
+pub struct git_diff_hunk {
+    pub old_start: c_int,
+    pub old_lines: c_int,
+    pub new_start: c_int,
+    pub new_lines: c_int,
+    pub header_len: size_t,
+    pub header: [c_char, ..128],
+}
+
+pub struct git_diff_line {
+    pub origin: c_char,
+    pub old_lineno: c_int,
+    pub new_lineno: c_int,
+    pub num_lines: c_int,
+    pub content_len: size_t,
+    pub content_offset: git_off_t,
+    pub content: *c_char,
+}
+
+pub struct git_diff_find_options {
+    version: c_uint,
+    flags: u32,
+    rename_threshold: u16,
+    rename_from_rewrite_threshold: u16,
+    copy_threshold: u16,
+    break_rewrite_threshold: u16,
+    rename_limit: size_t,
+    metric: *git_diff_similarity_metric,
+}
+
+pub struct git_diff_similarity_metric {
+    file_signature: callback_t,
+    buffer_signature: callback_t,
+    free_signature: callback_t,
+    similarity: callback_t,
+    payload: *c_void,
 }
 
 // value type of 'crust' functions is *u8
@@ -245,46 +461,109 @@ pub extern {
 
     /* from <git2/repository.h> */
     pub fn git_repository_open(out: &mut *git_repository, path: *c_char) -> c_int;
+    pub fn git_repository_open_ext(out: &mut *git_repository, path: *c_char, flags: c_uint,
+        ceiling_dirs: *c_char) -> c_int;
     pub fn git_repository_free(repo: *git_repository) -> c_void;
-    pub fn git_repository_discover(path_out: *mut c_char, path_size: size_t,
-                            start_path: *c_char, across_fs: c_int,
-                            ceiling_dirs: *c_char) -> c_int;
+    pub fn git_repository_discover(out: &mut git_buf, start_path: *c_char, across_fs: c_int,
+        ceiling_dirs: *c_char) -> c_int;
     pub fn git_repository_path(repo: *git_repository) -> *c_char;
     pub fn git_repository_workdir(repo: *git_repository) -> *c_char;
+    pub fn git_repository_set_workdir(repo: *git_repository, workdir: *c_char,
+        update_gitlink: c_int) -> c_int;
+    pub fn git_repository_hashfile(out: &mut super::OID, repo: *git_repository, path: *c_char,
+        otype: super::OType, as_path: *c_char) -> c_int;
     pub fn git_repository_init(out: &mut *git_repository, path: *c_char, is_bare: c_uint) -> c_int;
+    pub fn git_repository_init_ext(out: &mut *git_repository, repo_path: *c_char,
+        opts: *git_repository_init_options) -> c_int;
     pub fn git_repository_head(out: &mut *git_reference, repo: *git_repository) -> c_int;
     pub fn git_repository_is_empty(repo: *git_repository) -> c_int;
     pub fn git_repository_is_bare(repo: *git_repository) -> c_int;
+    pub fn git_repository_is_shallow(repo: *git_repository) -> c_int;
+    pub fn git_repository_is_worktree(repo: *git_repository) -> c_int;
+    pub fn git_repository_state_cleanup(repo: *git_repository) -> c_int;
     pub fn git_repository_index(out: &mut *git_index, repo: *git_repository) -> c_int;
+    pub fn git_repository_set_head(repo: *git_repository, refname: *c_char) -> c_int;
+    pub fn git_repository_head_detached(repo: *git_repository) -> c_int;
 
     /* from <git2/refs.h> */
     pub fn git_reference_free(c_ref: *git_reference) -> c_void;
     pub fn git_reference_lookup(out: &mut *git_reference, repo: *git_repository,
                                 name: *c_char) -> c_int;
     pub fn git_reference_resolve(out: &mut *git_reference, c_ref: *git_reference) -> c_int;
+    pub fn git_reference_create(out: &mut *git_reference, repo: *git_repository,
+                                name: *c_char, id: &super::OID, force: c_int,
+                                log_message: *c_char) -> c_int;
+    pub fn git_reference_set_target(out: &mut *git_reference, c_ref: *git_reference,
+                                    id: &super::OID, log_message: *c_char) -> c_int;
+    pub fn git_reference_delete(c_ref: *git_reference) -> c_int;
     pub fn git_reference_target(c_ref: *git_reference) -> *super::OID;
-
-    /* from <git2/threads.h> */
-    pub fn git_threads_init() -> c_void;
-    pub fn git_threads_shutdown() -> c_void;
+    pub fn git_reference_name(c_ref: *git_reference) -> *c_char;
+    pub fn git_reference_list(array: &mut git_strarray, repo: *git_repository) -> c_int;
+    pub fn git_reference_peel(out: &mut *git_object, c_ref: *git_reference,
+        otype: super::OType) -> c_int;
+    pub fn git_reference_type(c_ref: *git_reference) -> git_ref_t;
+    pub fn git_reference_symbolic_target(c_ref: *git_reference) -> *c_char;
+    pub fn git_reference_iterator_new(out: &mut *git_reference_iterator,
+        repo: *git_repository) -> c_int;
+    pub fn git_reference_next(out: &mut *git_reference, iter: *git_reference_iterator) -> c_int;
+    pub fn git_reference_iterator_free(iter: *git_reference_iterator) -> c_void;
+
+    /* from <git2/reflog.h> */
+    pub fn git_reflog_read(out: &mut *git_reflog, repo: *git_repository,
+        name: *c_char) -> c_int;
+    pub fn git_reflog_write(reflog: *git_reflog) -> c_int;
+    pub fn git_reflog_entrycount(reflog: *git_reflog) -> size_t;
+    pub fn git_reflog_entry_byindex(reflog: *git_reflog, idx: size_t) -> *git_reflog_entry;
+    pub fn git_reflog_drop(reflog: *git_reflog, idx: size_t,
+        rewrite_previous_entry: c_int) -> c_int;
+    pub fn git_reflog_entry_id_old(entry: *git_reflog_entry) -> *super::OID;
+    pub fn git_reflog_entry_id_new(entry: *git_reflog_entry) -> *super::OID;
+    pub fn git_reflog_entry_committer(entry: *git_reflog_entry) -> *git_signature;
+    pub fn git_reflog_entry_message(entry: *git_reflog_entry) -> *c_char;
+    pub fn git_reflog_free(reflog: *git_reflog) -> c_void;
+
+    /* from <git2/strarray.h> */
+    pub fn git_strarray_free(array: &mut git_strarray) -> c_void;
+
+    /* from <git2/common.h> */
+    pub fn git_libgit2_version(major: &mut c_int, minor: &mut c_int, rev: &mut c_int) -> c_void;
+    pub fn git_libgit2_features() -> c_int;
+
+    /* from <git2/global.h> */
+    pub fn git_libgit2_init() -> c_int;
+    pub fn git_libgit2_shutdown() -> c_int;
 
     /* from <git2/clone.h> */
     pub fn git_clone(out: &mut *git_repository, url: *c_char, local_path: *c_char,
                     options: *git_clone_options) -> c_int;
 
     /* from <git2/checkout.h> */
-    pub fn git_checkout_head(repo: *git_repository, opts: *git_checkout_opts) -> c_int;
+    pub fn git_checkout_head(repo: *git_repository, opts: *git_checkout_options) -> c_int;
+    pub fn git_checkout_tree(repo: *git_repository, treeish: *git_object,
+        opts: *git_checkout_options) -> c_int;
+    pub fn git_checkout_index(repo: *git_repository, index: *git_index,
+        opts: *git_checkout_options) -> c_int;
 
     /* from <git2/index.h> */
     pub fn git_index_free(index: *git_index) -> c_void;
     pub fn git_index_write(index: *git_index) -> c_int;
     pub fn git_index_write_tree(out: &mut super::OID, index: *git_index) -> c_int;
+    pub fn git_index_write_tree_to(out: &mut super::OID, index: *git_index,
+        repo: *git_repository) -> c_int;
+    pub fn git_index_has_conflicts(index: *git_index) -> c_int;
     pub fn git_index_add_bypath(index: *git_index, path: *c_char) -> c_int;
     pub fn git_index_remove_bypath(index: *git_index, path: *c_char) -> c_int;
     pub fn git_index_read_tree(index: *git_index, tree: *git_tree) -> c_int;
-    pub fn git_index_clear(index: *git_index) -> c_void;
+    pub fn git_index_clear(index: *git_index) -> c_int;
+    pub fn git_index_entrycount(index: *git_index) -> size_t;
+    pub fn git_index_add(index: *git_index, source_entry: &git_index_entry) -> c_int;
+    pub fn git_index_get_byindex(index: *git_index, n: size_t) -> *git_index_entry;
+    pub fn git_index_get_bypath(index: *git_index, path: *c_char,
+        stage: c_int) -> *git_index_entry;
 
     /* from <git2/status.h> */
+    pub fn git_status_foreach_ext(repo: *git_repository, opts: *git_status_options,
+        callback: callback_t, payload: *c_void) -> c_int;
     pub fn git_status_foreach(repo: *git_repository, callback: callback_t,
                                 payload: *c_void) -> c_int;
 
@@ -293,20 +572,31 @@ pub extern {
 
     /* from <git2/object.h> */
     pub fn git_object_free(object: *git_object) -> c_void;
-    pub fn git_object_id(obj: *git_object) -> &super::OID;
+    pub fn git_object_id(obj: *git_object) -> *super::OID;
+    pub fn git_object_type(obj: *git_object) -> super::OType;
     pub fn git_object_lookup(out: &mut *git_object, repo: *git_repository, id: &super::OID,
         otype: super::OType) -> c_int;
+    pub fn git_object_peel(peeled: &mut *git_object, object: *git_object,
+        target_type: super::OType) -> c_int;
+
+    /* from <git2/revparse.h> */
+    pub fn git_revparse_single(out: &mut *git_object, repo: *git_repository,
+        spec: *c_char) -> c_int;
 
     /* from <git2/oid.h> */
     pub fn git_oid_fromstr(out: &mut super::OID, c_str: *c_char) -> c_int;
-    pub fn git_oid_fmt(out: *mut c_char, oid: &super::OID) -> c_int;
+    pub fn git_oid_fmt(out: *mut c_char, oid: &super::OID) -> c_void;
 
     /* from <git2/commit.h> */
     pub fn git_commit_message_encoding(commit: *git_commit) -> *c_char;
     pub fn git_commit_message(commit: *git_commit) -> *c_char;
     pub fn git_commit_committer(commit: *git_commit) -> *git_signature;
+    pub fn git_commit_time(commit: *git_commit) -> i64;
     pub fn git_commit_author(commit: *git_commit) -> *git_signature;
+    pub fn git_commit_header_field(out: &mut git_buf, commit: *git_commit, field: *c_char)
+        -> c_int;
     pub fn git_commit_tree(tree_out: &mut *git_tree, commit: *git_commit) -> c_int;
+    pub fn git_commit_tree_id(commit: *git_commit) -> *super::OID;
     pub fn git_commit_parentcount(commit: *git_commit) -> c_uint;
     pub fn git_commit_parent(out: &mut *git_commit, commit: *git_commit, n: c_uint) -> c_int;
     pub fn git_commit_parent_id(commit: *git_commit, n: c_uint) -> *super::OID;
@@ -315,11 +605,19 @@ pub extern {
     pub fn git_commit_create(id: &mut super::OID, repo: *git_repository,
         update_ref: *c_char, author: &git_signature, committer: &git_signature,
         message_encoding: *c_char, message: *c_char, tree: *git_tree,
-        parent_count: c_int, parents: *const *git_commit) -> c_int;
+        parent_count: c_int, parents: **git_commit) -> c_int;
+    pub fn git_commit_create_buffer(out: &mut git_buf, repo: *git_repository,
+        author: &git_signature, committer: &git_signature, message_encoding: *c_char,
+        message: *c_char, tree: *git_tree, parent_count: size_t, parents: **git_commit)
+        -> c_int;
+    pub fn git_commit_create_with_signature(out: &mut super::OID, repo: *git_repository,
+        commit_content: *c_char, signature: *c_char, signature_field: *c_char) -> c_int;
 
     /* from <git2/tree.h> */
     pub fn git_tree_id(tree: *git_tree) -> *super::OID;
     pub fn git_tree_entrycount(tree: *git_tree) -> size_t;
+    pub fn git_tree_create_updated(out: &mut super::OID, repo: *git_repository,
+        baseline: *git_tree, nupdates: size_t, updates: *git_tree_update) -> c_int;
     pub fn git_tree_entry_byname(tree: *git_tree, filename: *c_char) -> *git_tree_entry;
     pub fn git_tree_entry_byindex(tree: *git_tree, idx: size_t) -> *git_tree_entry;
     pub fn git_tree_entry_byoid(tree: *git_tree, oid: &super::OID) -> *git_tree_entry;
@@ -332,9 +630,10 @@ pub extern {
     pub fn git_tree_entry_type(entry: *git_tree_entry) -> super::OType;
     pub fn git_tree_entry_filemode(entry: *git_tree_entry) -> super::FileMode;
     pub fn git_tree_entry_cmp(e1: *git_tree_entry, e2: *git_tree_entry) -> c_int;
-    pub fn git_treebuilder_create(out: &mut *git_treebuilder, source: *git_tree) -> c_int;
+    pub fn git_treebuilder_new(out: &mut *git_treebuilder, repo: *git_repository,
+        source: *git_tree) -> c_int;
     pub fn git_treebuilder_clear(bld: *git_treebuilder) -> c_void;
-    pub fn git_treebuilder_entrycount(bld: *git_treebuilder) -> c_uint;
+    pub fn git_treebuilder_entrycount(bld: *git_treebuilder) -> size_t;
     pub fn git_treebuilder_free(bld: *git_treebuilder) -> c_void;
     pub fn git_treebuilder_get(bld: *git_treebuilder, filename: *c_char) -> *git_tree_entry;
     pub fn git_treebuilder_insert(out: &mut *git_tree_entry, bld: *git_treebuilder,
@@ -342,8 +641,7 @@ pub extern {
     pub fn git_treebuilder_remove(bld: *git_treebuilder, filename: *c_char) -> c_int;
     pub fn git_treebuilder_filter(bld: *git_treebuilder, filter: callback_t,
         payload: *c_void) -> c_void;
-    pub fn git_treebuilder_write(id: &mut super::OID, repo: *git_repository,
-        bld: *git_treebuilder) -> c_int;
+    pub fn git_treebuilder_write(id: &mut super::OID, bld: *git_treebuilder) -> c_int;
     pub fn git_tree_walk(tree: *git_tree, mode: git_treewalk_mode, callback: callback_t,
         payload: *c_void) -> c_int;
 
@@ -354,34 +652,300 @@ pub extern {
         relative_path: *c_char) -> c_int;
     pub fn git_blob_create_fromdisk(id: &mut super::OID, repo: *git_repository,
         path: *c_char) -> c_int;
-    pub fn git_blob_create_fromchunks(id: &mut super::OID, repo: *git_repository,
-        hintpath: *c_char, callback: callback_t, payload: *c_void) -> c_int;
     pub fn git_blob_create_frombuffer(oid: &mut super::OID, repo: *git_repository,
         buffer: *c_void, len: size_t) -> c_int;
     pub fn git_blob_is_binary(blob: *git_blob) -> c_int;
 
+    /* from <git2/filter.h> */
+    pub fn git_filter_list_load(filters: &mut *git_filter_list, repo: *git_repository,
+        blob: *git_blob, path: *c_char, mode: c_int, flags: u32) -> c_int;
+    pub fn git_filter_list_apply_to_file(out: &mut git_buf, filters: *git_filter_list,
+        repo: *git_repository, path: *c_char) -> c_int;
+    pub fn git_filter_list_free(filters: *git_filter_list) -> c_void;
+
     /* from <git2/branch.h> */
     pub fn git_branch_create(out: &mut *git_reference, repo: *git_repository,
         branch_name: *c_char, target: *git_commit, force: c_int) -> c_int;
+    pub fn git_branch_create_from_annotated(out: &mut *git_reference, repo: *git_repository,
+        branch_name: *c_char, commit: *git_annotated_commit, force: c_int) -> c_int;
     pub fn git_branch_delete(branch: *git_reference) -> c_int;
-    pub fn git_branch_foreach(repo: *git_repository, list_flags: c_uint, branch_cb: callback_t,
-        payload: *c_void) -> c_int;
+    pub fn git_branch_iterator_new(out: &mut *git_branch_iterator, repo: *git_repository,
+        list_flags: git_branch_t) -> c_int;
+    pub fn git_branch_next(out: &mut *git_reference, out_type: &mut git_branch_t,
+        iter: *git_branch_iterator) -> c_int;
+    pub fn git_branch_iterator_free(iter: *git_branch_iterator) -> c_void;
     pub fn git_branch_move(out: &mut *git_reference, branch: *git_reference,
         new_branch_name: *c_char, force: c_int) -> c_int;
     pub fn git_branch_lookup(out: &mut *git_reference, repo: *git_repository, 
         branch_name: *c_char, branch_type: git_branch_t) -> c_int;
     pub fn git_branch_upstream(out: &mut *git_reference, branch: *git_reference) -> c_int;
     pub fn git_branch_set_upstream(branch: *git_reference, upstream_name: *c_char) -> c_int;
-    pub fn git_branch_upstream_name(tracking_branch_name_out: *mut c_char, buffer_size: size_t,
-        repo: *git_repository, canonical_branch_name: *c_char) -> c_int;
+    pub fn git_branch_upstream_name(out: &mut git_buf, repo: *git_repository,
+        refname: *c_char) -> c_int;
     pub fn git_branch_is_head(branch: *git_reference) -> c_int;
-    pub fn git_branch_remote_name(remote_name_out: *mut c_char, buffer_size: size_t, 
-        repo: *git_repository, canonical_branch_name: *c_char) -> c_int;
+    pub fn git_branch_remote_name(out: &mut git_buf, repo: *git_repository,
+        refname: *c_char) -> c_int;
 
     /* from <git2/diff.h> */
-    pub fn git_diff_list_free(diff: *git_diff_list) -> c_void;
-    pub fn git_diff_tree_to_tree(diff: &mut *git_diff_list, repo: *git_repository,
+    pub fn git_diff_free(diff: *git_diff) -> c_void;
+    pub fn git_diff_tree_to_tree(diff: &mut *git_diff, repo: *git_repository,
         old_tree: *git_tree, new_tree: *git_tree, opts: *git_diff_options) -> c_int;
+    pub fn git_diff_tree_to_index(diff: &mut *git_diff, repo: *git_repository,
+        old_tree: *git_tree, index: *git_index, opts: *git_diff_options) -> c_int;
+    pub fn git_diff_num_deltas(diff: *git_diff) -> size_t;
+    pub fn git_diff_get_delta(diff: *git_diff, idx: size_t) -> *git_diff_delta;
+    pub fn git_diff_find_similar(diff: *git_diff, options: *git_diff_find_options) -> c_int;
+    pub fn git_diff_foreach(diff: *git_diff, file_cb: callback_t, binary_cb: callback_t,
+        hunk_cb: callback_t, line_cb: callback_t, payload: *c_void) -> c_int;
+    pub fn git_diff_from_buffer(out: &mut *git_diff, content: *c_char,
+        content_len: size_t) -> c_int;
+    pub fn git_diff_to_buf(out: &mut git_buf, diff: *git_diff, format: c_int) -> c_int;
+    pub fn git_diff_blobs(old_blob: *git_blob, old_as_path: *c_char, new_blob: *git_blob,
+        new_as_path: *c_char, options: *git_diff_options, file_cb: callback_t,
+        binary_cb: callback_t, hunk_cb: callback_t, line_cb: callback_t,
+        payload: *c_void) -> c_int;
+
+    /* from <git2/apply.h> */
+    pub fn git_apply(repo: *git_repository, diff: *git_diff, location: c_int,
+        options: *c_void) -> c_int;
+
+    /* from <git2/graph.h> */
+    pub fn git_graph_ahead_behind(ahead: &mut size_t, behind: &mut size_t,
+        repo: *git_repository, local: &super::OID, upstream: &super::OID) -> c_int;
+    pub fn git_graph_descendant_of(repo: *git_repository, commit: &super::OID,
+        ancestor: &super::OID) -> c_int;
+
+    /* from <git2/remote.h> */
+    pub fn git_remote_list(out: &mut git_strarray, repo: *git_repository) -> c_int;
+    pub fn git_remote_lookup(out: &mut *git_remote, repo: *git_repository,
+        name: *c_char) -> c_int;
+    pub fn git_remote_name(remote: *git_remote) -> *c_char;
+    pub fn git_remote_url(remote: *git_remote) -> *c_char;
+    pub fn git_remote_pushurl(remote: *git_remote) -> *c_char;
+    pub fn git_remote_fetch(remote: *git_remote, refspecs: *git_strarray,
+        opts: *git_fetch_options, reflog_message: *c_char) -> c_int;
+    pub fn git_remote_free(remote: *git_remote) -> c_void;
+
+    /* from <git2/merge.h> */
+    pub fn git_merge_base(out: &mut super::OID, repo: *git_repository, one: &super::OID,
+        two: &super::OID) -> c_int;
+    pub fn git_merge_commits(out: &mut *git_index, repo: *git_repository,
+        our_commit: *git_commit, their_commit: *git_commit, opts: *git_merge_options) -> c_int;
+    pub fn git_merge_analysis(analysis_out: &mut c_int, preference_out: &mut c_int,
+        repo: *git_repository, their_heads: **git_annotated_commit, their_heads_len: size_t)
+        -> c_int;
+    pub fn git_merge(repo: *git_repository, their_heads: **git_annotated_commit,
+        their_heads_len: size_t, merge_opts: *git_merge_options,
+        checkout_opts: *git_checkout_options) -> c_int;
+
+    /* from <git2/cherrypick.h> */
+    pub fn git_cherrypick_commit(out: &mut *git_index, repo: *git_repository,
+        cherrypick_commit: *git_commit, our_commit: *git_commit, mainline: c_uint,
+        merge_options: *git_merge_options) -> c_int;
+
+    /* from <git2/revert.h> */
+    pub fn git_revert_commit(out: &mut *git_index, repo: *git_repository,
+        revert_commit: *git_commit, our_commit: *git_commit, mainline: c_uint,
+        merge_options: *git_merge_options) -> c_int;
+
+    /* from <git2/annotated_commit.h> */
+    pub fn git_annotated_commit_lookup(out: &mut *git_annotated_commit, repo: *git_repository,
+        id: &super::OID) -> c_int;
+    pub fn git_annotated_commit_from_ref(out: &mut *git_annotated_commit, repo: *git_repository,
+        reference: *git_reference) -> c_int;
+    pub fn git_annotated_commit_from_fetchhead(out: &mut *git_annotated_commit,
+        repo: *git_repository, branch_name: *c_char, remote_url: *c_char, id: &super::OID)
+        -> c_int;
+    pub fn git_annotated_commit_from_revspec(out: &mut *git_annotated_commit,
+        repo: *git_repository, revspec: *c_char) -> c_int;
+    pub fn git_annotated_commit_id(commit: *git_annotated_commit) -> *super::OID;
+    pub fn git_annotated_commit_free(commit: *git_annotated_commit) -> c_void;
+
+    /* from <git2/buffer.h> */
+    pub fn git_buf_dispose(buffer: &mut git_buf) -> c_void;
+
+    /* from <git2/odb.h> */
+    pub fn git_repository_odb(out: &mut *git_odb, repo: *git_repository) -> c_int;
+    pub fn git_odb_free(db: *git_odb) -> c_void;
+    pub fn git_odb_read(out: &mut *git_odb_object, db: *git_odb, id: &super::OID) -> c_int;
+    pub fn git_odb_exists_prefix(out: &mut super::OID, db: *git_odb, short_id: &super::OID,
+        len: size_t) -> c_int;
+    pub fn git_odb_exists(db: *git_odb, id: &super::OID) -> c_int;
+    pub fn git_odb_refresh(db: *git_odb) -> c_int;
+    pub fn git_odb_read_header(len_out: &mut size_t, type_out: &mut super::OType, db: *git_odb,
+        id: &super::OID) -> c_int;
+    pub fn git_odb_write(out: &mut super::OID, odb: *git_odb, data: *c_void, len: size_t,
+        otype: super::OType) -> c_int;
+    pub fn git_odb_hash(out: &mut super::OID, data: *c_void, len: size_t,
+        otype: super::OType) -> c_int;
+    pub fn git_odb_add_backend(odb: *git_odb, backend: *git_odb_backend,
+        priority: c_int) -> c_int;
+    pub fn git_odb_add_disk_alternate(odb: *git_odb, path: *c_char) -> c_int;
+    pub fn git_odb_foreach(db: *git_odb, cb: callback_t, payload: *c_void) -> c_int;
+    pub fn git_odb_open_rstream(out: &mut *git_odb_stream, len: &mut size_t,
+        otype: &mut super::OType, db: *git_odb, id: &super::OID) -> c_int;
+    pub fn git_odb_stream_read(stream: *git_odb_stream, buffer: *mut c_char, len: size_t)
+        -> c_int;
+    pub fn git_odb_stream_free(stream: *git_odb_stream) -> c_void;
+    pub fn git_odb_object_free(object: *git_odb_object) -> c_void;
+    pub fn git_odb_object_id(object: *git_odb_object) -> *super::OID;
+    pub fn git_odb_object_data(object: *git_odb_object) -> *c_void;
+    pub fn git_odb_object_size(object: *git_odb_object) -> size_t;
+    pub fn git_odb_object_type(object: *git_odb_object) -> super::OType;
+
+    /* from <git2/odb_backend.h> */
+    pub fn git_odb_backend_loose(out: &mut *git_odb_backend, objects_dir: *c_char,
+        compression_level: c_int, do_fsync: c_int, dir_mode: c_uint, file_mode: c_uint) -> c_int;
+    pub fn git_odb_backend_pack(out: &mut *git_odb_backend, objects_dir: *c_char) -> c_int;
+
+    /* from <git2/pack.h> */
+    pub fn git_packbuilder_new(out: &mut *git_packbuilder, repo: *git_repository) -> c_int;
+    pub fn git_packbuilder_set_threads(pb: *git_packbuilder, n: c_uint) -> c_uint;
+    pub fn git_packbuilder_insert(pb: *git_packbuilder, id: &super::OID,
+        name: *c_char) -> c_int;
+    pub fn git_packbuilder_insert_tree(pb: *git_packbuilder, id: &super::OID) -> c_int;
+    pub fn git_packbuilder_insert_commit(pb: *git_packbuilder, id: &super::OID) -> c_int;
+    pub fn git_packbuilder_insert_walk(pb: *git_packbuilder, walk: *git_revwalk) -> c_int;
+    pub fn git_packbuilder_write(pb: *git_packbuilder, path: *c_char, mode: c_uint,
+        progress_cb: callback_t, progress_cb_payload: *c_void) -> c_int;
+    pub fn git_packbuilder_hash(pb: *git_packbuilder) -> *super::OID;
+    pub fn git_packbuilder_object_count(pb: *git_packbuilder) -> size_t;
+    pub fn git_packbuilder_written(pb: *git_packbuilder) -> size_t;
+    pub fn git_packbuilder_free(pb: *git_packbuilder) -> c_void;
+
+    /* from <git2/sys/repository.h> */
+    pub fn git_repository_new(out: &mut *git_repository) -> c_int;
+    pub fn git_repository_set_odb(repo: *git_repository, odb: *git_odb) -> c_void;
+    pub fn git_repository_set_refdb(repo: *git_repository, refdb: *git_refdb) -> c_void;
+
+    /* from <git2/sys/odb_backend.h> */
+    pub fn git_odb_new(out: &mut *git_odb) -> c_int;
+
+    /* from <git2/sys/refdb_backend.h> */
+    pub fn git_refdb_new(out: &mut *git_refdb, repo: *git_repository) -> c_int;
+    pub fn git_refdb_free(refdb: *git_refdb) -> c_void;
+
+    /* from <git2/sys/mempack.h> */
+    pub fn git_mempack_new(out: &mut *git_odb_backend) -> c_int;
+    pub fn git_mempack_dump(pack: &mut git_buf, repo: *git_repository,
+        backend: *git_odb_backend) -> c_int;
+    pub fn git_mempack_reset(backend: *git_odb_backend) -> c_void;
+
+    /* from <git2/indexer.h> */
+    pub fn git_indexer_new(out: &mut *git_indexer, path: *c_char, mode: c_uint,
+        odb: *git_odb, opts: *git_indexer_options) -> c_int;
+    pub fn git_indexer_append(idx: *git_indexer, data: *c_void, size: size_t,
+        stats: &mut git_transfer_progress) -> c_int;
+    pub fn git_indexer_commit(idx: *git_indexer, stats: &mut git_transfer_progress) -> c_int;
+    pub fn git_indexer_free(idx: *git_indexer) -> c_void;
+
+    /* from <git2/revwalk.h> */
+    pub fn git_revwalk_new(out: &mut *git_revwalk, repo: *git_repository) -> c_int;
+    pub fn git_revwalk_free(walk: *git_revwalk) -> c_void;
+    pub fn git_revwalk_reset(walk: *git_revwalk) -> c_void;
+    pub fn git_revwalk_push(walk: *git_revwalk, id: &super::OID) -> c_int;
+    pub fn git_revwalk_push_head(walk: *git_revwalk) -> c_int;
+    pub fn git_revwalk_push_ref(walk: *git_revwalk, refname: *c_char) -> c_int;
+    pub fn git_revwalk_push_glob(walk: *git_revwalk, glob: *c_char) -> c_int;
+    pub fn git_revwalk_hide(walk: *git_revwalk, id: &super::OID) -> c_int;
+    pub fn git_revwalk_hide_ref(walk: *git_revwalk, refname: *c_char) -> c_int;
+    pub fn git_revwalk_next(out: &mut super::OID, walk: *git_revwalk) -> c_int;
+    pub fn git_revwalk_sorting(walk: *git_revwalk, sort_mode: c_uint) -> c_void;
+    pub fn git_revwalk_simplify_first_parent(walk: *git_revwalk) -> c_int;
+    pub fn git_revwalk_add_hide_cb(walk: *git_revwalk, hide_cb: callback_t, payload: *c_void)
+        -> c_int;
+
+    /* from <git2/config.h> */
+    pub fn git_repository_config(out: &mut *git_config, repo: *git_repository) -> c_int;
+    pub fn git_repository_config_snapshot(out: &mut *git_config, repo: *git_repository)
+        -> c_int;
+    pub fn git_config_free(cfg: *git_config) -> c_void;
+    pub fn git_config_snapshot(out: &mut *git_config, config: *git_config) -> c_int;
+    pub fn git_config_get_string_buf(out: &mut git_buf, cfg: *git_config,
+        name: *c_char) -> c_int;
+    pub fn git_config_get_bool(out: &mut c_int, cfg: *git_config, name: *c_char) -> c_int;
+    pub fn git_config_get_int32(out: &mut i32, cfg: *git_config, name: *c_char) -> c_int;
+    pub fn git_config_set_string(cfg: *git_config, name: *c_char, value: *c_char) -> c_int;
+    pub fn git_config_set_bool(cfg: *git_config, name: *c_char, value: c_int) -> c_int;
+    pub fn git_config_set_int32(cfg: *git_config, name: *c_char, value: i32) -> c_int;
+    pub fn git_config_delete_entry(cfg: *git_config, name: *c_char) -> c_int;
+    pub fn git_config_open_default(out: &mut *git_config) -> c_int;
+    pub fn git_config_open_ondisk(out: &mut *git_config, path: *c_char) -> c_int;
+    pub fn git_config_foreach_match(cfg: *git_config, regexp: *c_char, callback: callback_t,
+        payload: *c_void) -> c_int;
+
+    /* from <git2/notes.h> */
+    pub fn git_note_read(out: &mut *git_note, repo: *git_repository, notes_ref: *c_char,
+        oid: &super::OID) -> c_int;
+    pub fn git_note_create(out: &mut super::OID, repo: *git_repository, notes_ref: *c_char,
+        author: &git_signature, committer: &git_signature, oid: &super::OID, note: *c_char,
+        force: c_int) -> c_int;
+    pub fn git_note_remove(repo: *git_repository, notes_ref: *c_char, author: &git_signature,
+        committer: &git_signature, oid: &super::OID) -> c_int;
+    pub fn git_note_message(note: *git_note) -> *c_char;
+    pub fn git_note_id(note: *git_note) -> *super::OID;
+    pub fn git_note_free(note: *git_note) -> c_void;
+
+    /* from <git2/trace.h> */
+    pub fn git_trace_set(level: c_int, cb: callback_t) -> c_int;
+
+    /* from <git2/signature.h> */
+    pub fn git_signature_now(out: &mut *git_signature, name: *c_char, email: *c_char) -> c_int;
+    pub fn git_signature_default(out: &mut *git_signature, repo: *git_repository) -> c_int;
+    pub fn git_signature_free(sig: *git_signature) -> c_void;
+}
+
+/* from <regex.h>, in the C library */
+pub static REG_EXTENDED:c_int = 1;
+pub static REG_NOMATCH:c_int = 1;
+
+/// regex_t is opaque and its size depends on the C library: this is large enough
+/// for glibc, musl and the BSDs
+pub type regex_t = [u64, ..32];
+
+pub extern {
+    pub fn regcomp(preg: *mut regex_t, pattern: *c_char, cflags: c_int) -> c_int;
+    pub fn regexec(preg: *regex_t, string: *c_char, nmatch: size_t, pmatch: *c_void,
+        eflags: c_int) -> c_int;
+    pub fn regerror(errcode: c_int, preg: *regex_t, errbuf: *mut c_char, errbuf_size: size_t)
+        -> size_t;
+    pub fn regfree(preg: *mut regex_t) -> c_void;
+}
+
+/* from <fnmatch.h>, in the C library */
+pub extern {
+    pub fn fnmatch(pattern: *c_char, string: *c_char, flags: c_int) -> c_int;
+}
+
+/* from <zlib.h>, which libgit2 links to as well */
+pub static Z_OK:c_int = 0;
+pub static Z_STREAM_END:c_int = 1;
+pub static Z_NO_FLUSH:c_int = 0;
+/// checked by inflateInit_: only the major version has to match
+pub static ZLIB_VERSION:&'static str = "1.2.11";
+
+pub struct z_stream {
+    next_in: *u8,
+    avail_in: c_uint,
+    total_in: c_ulong,
+    next_out: *mut u8,
+    avail_out: c_uint,
+    total_out: c_ulong,
+    msg: *c_char,
+    state: *c_void,
+    zalloc: callback_t,
+    zfree: callback_t,
+    opaque: *c_void,
+    data_type: c_int,
+    adler: c_ulong,
+    reserved: c_ulong,
+}
+
+#[link_args = "-lz"]
+pub extern {
+    pub fn inflateInit_(strm: *mut z_stream, version: *c_char, stream_size: c_int) -> c_int;
+    pub fn inflate(strm: *mut z_stream, flush: c_int) -> c_int;
+    pub fn inflateEnd(strm: *mut z_stream) -> c_int;
 }
 
 /* from <git2/commit.h> */
@@ -399,7 +963,7 @@ pub unsafe fn git_commit_free(commit: *git_commit) -> c_void
 }
 
 #[inline]
-pub unsafe fn git_commit_id(commit: *git_commit) -> &super::OID
+pub unsafe fn git_commit_id(commit: *git_commit) -> *super::OID
 {
     git_object_id(commit)
 }
@@ -419,7 +983,7 @@ pub unsafe fn git_tree_lookup(out: &mut *git_tree, repo: *git_repository, id: &s
 
 /* from <git2/blob.h> */
 #[inline]
-pub unsafe fn git_blob_id(blob: *git_blob) -> &super::OID
+pub unsafe fn git_blob_id(blob: *git_blob) -> *super::OID
 {
     git_object_id(blob)
 }
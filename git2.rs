@@ -7,6 +7,13 @@
 
 #[crate_type = "lib"];
 
+extern mod extra;
+
+/// Raw libgit2 bindings, grouped by the header they come from.
+/// This is the only binding module: every wrapper calls libgit2 through it.
+///
+/// The pointers libgit2 takes or returns as `const` are declared as `*T` or `&T`,
+/// and the ones it writes through as `*mut T` or `&mut T`.
 pub mod ext;
 pub mod repository;
 pub mod reference;
@@ -17,6 +24,33 @@ pub mod commit;
 pub mod signature;
 pub mod oid;
 pub mod diff;
+pub mod odb;
+pub mod revwalk;
+pub mod graph;
+pub mod config;
+pub mod checkout;
+pub mod history;
+pub mod cherry;
+pub mod mailbox;
+pub mod dag;
+pub mod snapshot;
+pub mod packbuilder;
+pub mod maintenance;
+pub mod reflog;
+pub mod json;
+pub mod test;
+pub mod replace;
+pub mod stats;
+pub mod merge;
+pub mod remote;
+pub mod lock;
+pub mod watch;
+pub mod notes;
+pub mod policy;
+pub mod trace;
+pub mod annotated_commit;
+pub mod chunked;
+pub mod regex;
 
 condition! {
     git_error: (~str, super::GitError) -> ();
@@ -33,6 +67,91 @@ pub unsafe fn last_error() -> (~str, GitError) {
     (message, klass)
 }
 
+/// Version of the linked libgit2, as (major, minor, revision)
+pub fn version() -> (int, int, int) {
+    let mut major = 0;
+    let mut minor = 0;
+    let mut rev = 0;
+    unsafe {
+        ext::git_libgit2_version(&mut major, &mut minor, &mut rev);
+    }
+    (major as int, minor as int, rev as int)
+}
+
+/// Optional features the linked libgit2 was built with
+pub struct Features {
+    /// libgit2 is thread-aware
+    threads: bool,
+    /// the https transport is available
+    https: bool,
+    /// the ssh transport is available
+    ssh: bool,
+}
+
+/// Features of the linked libgit2
+pub fn features() -> Features {
+    let flags = unsafe { ext::git_libgit2_features() };
+    Features {
+        threads: flags & ext::GIT_FEATURE_THREADS != 0,
+        https: flags & ext::GIT_FEATURE_HTTPS != 0,
+        ssh: flags & ext::GIT_FEATURE_SSH != 0,
+    }
+}
+
+/// Check that the linked libgit2 is at least version `major.minor`.
+///
+/// `what` names the operation which requires it, for the error message.
+pub fn require_version(major: int, minor: int, what: &str) -> Result<(), (~str, GitError)> {
+    let (cur_major, cur_minor, cur_rev) = version();
+    if cur_major > major || (cur_major == major && cur_minor >= minor) {
+        Ok(())
+    } else {
+        Err( (fmt!("%s requires libgit2 %d.%d or later, but %d.%d.%d is linked",
+                    what, major, minor, cur_major, cur_minor, cur_rev), GITERR_INVALID) )
+    }
+}
+
+/// Convert a git_strarray into a vector of owned strings.
+/// The strarray is not freed.
+pub unsafe fn from_c_strarray(arr: &ext::git_strarray) -> ~[~str] {
+    let mut strs: ~[~str] = std::vec::with_capacity(arr.count as uint);
+    let mut idx = 0u;
+    while idx < arr.count as uint {
+        let c_str = *std::ptr::offset(arr.strings, idx);
+        strs.push(std::str::raw::from_c_str(c_str));
+        idx += 1;
+    }
+    strs
+}
+
+/// Copy the bytes of a NUL-terminated C string, which need not be valid UTF-8:
+/// git stores paths as raw bytes.
+pub unsafe fn from_c_bytes(c_str: *std::libc::c_char) -> ~[u8] {
+    let len = std::libc::strlen(c_str) as uint;
+    std::vec::raw::from_buf_raw(c_str as *u8, len)
+}
+
+/// Convert bytes to a string, replacing each invalid UTF-8 sequence with U+FFFD,
+/// for display; use the byte variants of the path accessors to get the exact names.
+pub fn lossy_utf8(bytes: &[u8]) -> ~str {
+    if std::str::is_utf8(bytes) {
+        return std::str::from_bytes(bytes);
+    }
+    let mut s = std::str::with_capacity(bytes.len());
+    let mut i = 0u;
+    while i < bytes.len() {
+        let width = std::str::utf8_char_width(bytes[i]);
+        if width > 0 && i + width <= bytes.len() && std::str::is_utf8(bytes.slice(i, i + width)) {
+            s.push_str(std::str::from_bytes(bytes.slice(i, i + width)));
+            i += width;
+        } else {
+            s.push_char('\uFFFD');
+            i += 1;
+        }
+    }
+    s
+}
+
 /** Error classes */
 pub enum GitError {
     GITERR_NOMEMORY,
@@ -59,8 +178,112 @@ pub enum GitError {
     GITERR_MERGE,
 }
 
+/// Why an object lookup failed
+pub enum LookupError {
+    /// there is no object with this id
+    NotFound,
+    /// the object exists, but is of another type
+    WrongType(OType),
+    /// the lookup failed for another reason, e.g. an I/O error
+    LookupFailed(~str, GitError),
+}
+
+impl LookupError {
+    /// The failure to look up `id`, as the error of the other results
+    pub fn to_error(&self, id: &OID) -> (~str, GitError) {
+        match *self {
+            NotFound => (fmt!("object %s not found", id.to_str()), GITERR_ODB),
+            WrongType(_) => (fmt!("object %s has the wrong type", id.to_str()), GITERR_INVALID),
+            LookupFailed(ref msg, klass) => (copy *msg, klass),
+        }
+    }
+}
+
+/// Step of a composite operation, like `merge::pull`
+pub enum Step {
+    StepFetch,
+    /// finding out what has to be done, e.g. whether a fast-forward is possible
+    StepAnalysis,
+    StepMerge,
+    StepCommit,
+    StepCheckout,
+    StepUpdateRef,
+}
+
+impl std::to_str::ToStr for Step {
+    fn to_str(&self) -> ~str {
+        match *self {
+            StepFetch => ~"fetch",
+            StepAnalysis => ~"analysis",
+            StepMerge => ~"merge",
+            StepCommit => ~"commit",
+            StepCheckout => ~"checkout",
+            StepUpdateRef => ~"reference update",
+        }
+    }
+}
+
+/// Error of a composite operation: the step which failed, with its error
+pub struct StepError {
+    step: Step,
+    message: ~str,
+    klass: GitError,
+}
+
+impl std::to_str::ToStr for StepError {
+    /// e.g. "fetch failed: failed to resolve address"
+    fn to_str(&self) -> ~str {
+        fmt!("%s failed: %s", self.step.to_str(), self.message)
+    }
+}
+
+/// Attach the step it comes from to the error of a result
+pub fn at_step<T>(step: Step, res: Result<T, (~str, GitError)>) -> Result<T, StepError> {
+    match res {
+        Ok(value) => Ok(value),
+        Err((message, klass)) => Err( StepError { step: step, message: message, klass: klass } ),
+    }
+}
+
+/// Why a write protected by a lock file failed
+pub enum LockError {
+    /// the lock file is held by another process, and was not released before the timeout
+    Busy,
+    /// the write failed for another reason
+    LockFailed(~str, GitError),
+}
+
+/// Callbacks run after the writes made through this crate, see `Repository::on_ref_updated`
+pub struct Observers {
+    priv ref_updated: ~[~fn(name: &str, id: &OID)],
+    priv ref_deleted: ~[~fn(name: &str)],
+    priv index_written: ~[~fn()],
+    priv commit_created: ~[~fn(id: &OID)],
+}
+
 pub struct Repository {
     priv repo: *ext::git_repository,
+    priv observers: Observers,
+    /// refuse every write; see `Repository::is_read_only`
+    priv read_only: bool,
+    /// signs the commits when `commit.gpgsign` is set; see `Repository::set_commit_signer`
+    priv signer: Option<~fn(content: &str) -> Result<~str, (~str, GitError)>>,
+}
+
+/// A read-only, point-in-time view of a repository.
+///
+/// A Snapshot uses its own repository handle, so it can be sent to another task
+/// while the Repository it was created from keeps being used.
+/// The references and the configuration are captured when the snapshot is taken,
+/// and later updates are not visible; only read accessors are available, and the
+/// handle refuses every write.
+pub struct Snapshot {
+    priv repo: Repository,
+    priv config: ~Config,
+    priv head: Option<(~str, OID)>,
+    /// resolved references, sorted by name
+    priv refs: ~[(~str, OID)],
+    priv summary: reference::RefSummary,
 }
 
 pub struct Reference<'self> {
@@ -93,6 +316,25 @@ pub enum WalkMode {
     WalkStop = -1,
 }
 
+/// How the tree walks handle gitlink entries, which point to a commit of a submodule
+pub enum GitlinkMode {
+    /// pass gitlink entries to the callback, with the id of the submodule commit
+    GitlinkReport,
+    /// leave gitlink entries out of the walk
+    GitlinkSkip,
+    /// pass gitlink entries to the callback, then walk the tree of the submodule commit
+    /// when the repository of the submodule is available
+    GitlinkDescend,
+}
+
+/// A change applied by `Tree::update`; paths are relative to the root of the tree
+pub enum TreeUpdate {
+    /// add or replace the entry at the path, creating the missing parent trees
+    Upsert(~str, OID, FileMode),
+    /// remove the entry at the path; trees left empty are removed as well
+    Remove(~str),
+}
+
 pub enum DiffDelta {
     GIT_DELTA_UNMODIFIED = 0, // no changes
     GIT_DELTA_ADDED = 1,      // entry does not exist in old version
@@ -106,36 +348,36 @@ pub enum DiffDelta {
 }
 
 pub struct DiffList {
-    priv difflist: *ext::git_diff_list,
+    priv difflist: *ext::git_diff,
 }
 
 impl TreeBuilder {
     /// Create a new tree builder.
     /// The tree builder can be used to create or modify trees in memory and
-    /// write them as tree objects to the database.
+    /// write them as tree objects to the database of `repo`.
     /// The tree builder will start with no entries and will have to be filled manually.
-    pub fn new() -> TreeBuilder
+    pub fn new(repo: &Repository) -> Result<TreeBuilder, (~str, GitError)>
     {
         let mut bld:*ext::git_treebuilder = std::ptr::null();
         unsafe {
-            if ext::git_treebuilder_create(&mut bld, std::ptr::null()) == 0 {
-                TreeBuilder { bld: bld }
+            if ext::git_treebuilder_new(&mut bld, repo.repo, std::ptr::null()) == 0 {
+                Ok( TreeBuilder { bld: bld } )
             } else {
-                fail!(~"failed to create treebuilder")
+                Err( last_error() )
             }
         }
     }
 
     /// Create a new tree builder.
     /// The tree builder will be initialized with the entries of the given tree.
-    pub fn from_tree(tree: &Tree) -> TreeBuilder
+    pub fn from_tree(tree: &Tree) -> Result<TreeBuilder, (~str, GitError)>
     {
         let mut bld:*ext::git_treebuilder = std::ptr::null();
         unsafe {
-            if ext::git_treebuilder_create(&mut bld, tree.tree) == 0 {
-                TreeBuilder { bld: bld }
+            if ext::git_treebuilder_new(&mut bld, tree.owner.repo, tree.tree) == 0 {
+                Ok( TreeBuilder { bld: bld } )
             } else {
-                fail!(~"failed to create treebuilder")
+                Err( last_error() )
             }
         }
     }
@@ -151,6 +393,92 @@ pub struct Commit<'self> {
     priv owner: &'self Repository,
 }
 
+/// A commit along with the way it was found: the reference, fetch head or revision
+/// string it comes from, which the operations taking it use in their reflog messages
+pub struct AnnotatedCommit<'self> {
+    priv commit: *ext::git_annotated_commit,
+    priv owner: &'self Repository,
+}
+
+/// Objects loaded by `Repository::prefetch`, which stay in memory as long as this lives
+pub struct Prefetched<'self> {
+    priv objects: std::hashmap::HashMap<OID, *ext::git_object>,
+    priv owner: &'self Repository,
+}
+
+pub struct Odb<'self> {
+    priv odb: *ext::git_odb,
+    priv owner: &'self Repository,
+}
+
+pub struct OdbObject {
+    priv obj: *ext::git_odb_object,
+}
+
+/// Streaming reader over the content of an object of an Odb, see `Odb::reader`.
+pub struct OdbReader<'self> {
+    /// stream opened by the backend, or null when reading from `obj` or `pack`
+    priv stream: *ext::git_odb_stream,
+    /// whole object, read when the backends cannot stream it
+    priv obj: Option<~OdbObject>,
+    /// undeltified object inflated straight from its packfile
+    priv pack: Option<@mut PackStream>,
+    priv size: uint,
+    priv pos: @mut uint,
+    priv owner: &'self Repository,
+}
+
+/// The compressed data of an undeltified packed object, see `OdbReader`.
+/// Kept in a box: zlib refers to `zs` by address once it is initialized.
+struct PackStream {
+    file: @std::io::Reader,
+    zs: ext::z_stream,
+    /// compressed data read from `file` and not inflated yet
+    input: ~[u8],
+}
+
+/// In-memory object backend attached to an Odb.
+/// The backend is owned by the Odb it was added to, and freed together with it.
+pub struct Mempack<'self> {
+    priv backend: *ext::git_odb_backend,
+    priv owner: &'self Repository,
+}
+
+pub struct Revwalk<'self> {
+    priv walk: *ext::git_revwalk,
+    priv owner: &'self Repository,
+    /// boxed, as libgit2 keeps a pointer to it once `since` is called
+    priv bounds: ~revwalk::TimeBounds,
+}
+
+pub struct Config {
+    priv cfg: *ext::git_config,
+    /// private directory holding the file of a configuration created from a buffer,
+    /// removed on drop
+    priv tmpdir: Option<Path>,
+}
+
+/// Keeps the configuration of a repository open to create its default signatures.
+pub struct SignatureCache<'self> {
+    priv owner: &'self Repository,
+    priv config: Option<~Config>,
+}
+
+pub struct PackBuilder<'self> {
+    priv pb: *ext::git_packbuilder,
+    priv owner: &'self Repository,
+}
+
+pub struct Reflog<'self> {
+    priv reflog: *ext::git_reflog,
+    priv owner: &'self Repository,
+}
+
+pub struct Remote<'self> {
+    priv remote: *ext::git_remote,
+    priv owner: &'self Repository,
+}
+
 pub struct Time {
     pub time: i64,      /* time in seconds from epoch */
     pub offset: int,    /* timezone offset, in minutes */
@@ -185,8 +513,13 @@ pub struct Status {
     pub wt_modified: bool,
     pub wt_deleted: bool,
     pub wt_typechange: bool,
+    pub wt_renamed: bool,
+    /// the file cannot be read, e.g. because of its permissions
+    pub wt_unreadable: bool,
 
     pub ignored: bool,
+    /// the file has unresolved conflicts in the index, during a merge
+    pub conflicted: bool,
 }
 
 impl Status {
@@ -203,12 +536,119 @@ impl Status {
             wt_modified: false,
             wt_deleted: false,
             wt_typechange: false,
+            wt_renamed: false,
+            wt_unreadable: false,
 
             ignored: false,
+            conflicted: false,
+        }
+    }
+
+    /// Whether no flag is set: the file is tracked and unchanged. Such entries are
+    /// only reported when `StatusOption::include_unmodified` is set.
+    pub fn is_unmodified(&self) -> bool {
+        !(self.index_new || self.index_modified || self.index_deleted ||
+          self.index_renamed || self.index_typechange ||
+          self.wt_new || self.wt_modified || self.wt_deleted || self.wt_typechange ||
+          self.wt_renamed || self.wt_unreadable || self.ignored || self.conflicted)
+    }
+}
+
+/// Which comparisons a status run makes
+pub enum StatusShow {
+    /// HEAD to index, and index to working directory, like `git status`
+    StatusShowIndexAndWorkdir = 0,
+    /// HEAD to index only: only the `index_*` flags are set
+    StatusShowIndexOnly = 1,
+    /// index to working directory only: only the `wt_*` flags are set
+    StatusShowWorkdirOnly = 2,
+}
+
+/// Options of `Repository::status_with`
+pub struct StatusOption {
+    show: StatusShow,
+    /// report untracked files
+    include_untracked: bool,
+    /// report ignored files
+    include_ignored: bool,
+    /// report tracked files without changes as well, with no flag set
+    include_unmodified: bool,
+    /// report the files of untracked directories, instead of the directories only
+    recurse_untracked_dirs: bool,
+    /// limit the status to the files matching these patterns
+    pathspec: ~[~str],
+    /// ignore the changes of the executable bit, even when `core.filemode` is true
+    ///
+    /// `core.filemode` and `core.symlinks` are always honored: with `core.filemode`
+    /// false, mode changes are never reported. Repositories copied from another
+    /// filesystem often keep `core.filemode` true where the executable bit is
+    /// meaningless; this overrides the configuration for them.
+    ignore_filemode: bool,
+}
+
+impl StatusOption {
+    /// The defaults of `git status`: both comparisons, untracked files without their
+    /// content, no ignored nor unmodified files.
+    pub fn new() -> StatusOption {
+        StatusOption {
+            show: StatusShowIndexAndWorkdir,
+            include_untracked: true,
+            include_ignored: false,
+            include_unmodified: false,
+            recurse_untracked_dirs: false,
+            pathspec: ~[],
+            ignore_filemode: false,
         }
     }
 }
 
+/// What a modification reported by the status changes in the content of a file,
+/// see `Repository::status_classified`
+#[deriving(Eq)]
+pub enum ChangeKind {
+    /// the content is the same, only the mode changed
+    ChangeModeOnly,
+    /// only the line endings changed, e.g. LF to CRLF
+    ChangeEolOnly,
+    /// only whitespace changed, the line endings included
+    ChangeWhitespaceOnly,
+    /// anything else
+    ChangeContent,
+}
+
+impl std::to_str::ToStr for Status {
+    /// Two letter status code, like the short format of `git status`:
+    /// e.g. "M " for a change added to the index, " M" for a change in the working
+    /// directory, "??" for an untracked file, "!!" for an ignored file and "UU"
+    /// for a file with conflicts.
+    fn to_str(&self) -> ~str {
+        if self.ignored {
+            return ~"!!";
+        }
+        if self.conflicted {
+            return ~"UU";
+        }
+        let index = if self.index_new { 'A' }
+            else if self.index_modified { 'M' }
+            else if self.index_deleted { 'D' }
+            else if self.index_renamed { 'R' }
+            else if self.index_typechange { 'T' }
+            else { ' ' };
+        if self.wt_new && index == ' ' {
+            return ~"??";
+        }
+        let wt = if self.wt_modified { 'M' }
+            else if self.wt_deleted { 'D' }
+            else if self.wt_typechange { 'T' }
+            else if self.wt_renamed { 'R' }
+            else { ' ' };
+        let mut code = ~"";
+        code.push_char(index);
+        code.push_char(wt);
+        code
+    }
+}
+
 /// Valid modes for index and tree entries.
 pub enum FileMode {
     GIT_FILEMODE_NEW                    = 0x0000,   // 0000000
@@ -234,19 +674,38 @@ pub enum OType {
 }
 
 
-// FIXME: there should be better ways to do this...
-// if you call this library in multiple tasks,
-// this function must be called before calling any other functions in library
-pub fn threads_init() {
+/// The libgit2 release this crate works with, as (major, minor).
+///
+/// The structures declared in `ext` are laid out as in the headers of this release;
+/// other releases, older or newer, add or move fields, so `init` refuses them.
+pub static LIBGIT2_VERSION: (int, int) = (0, 28);
+
+/// Check that the linked libgit2 is the release of `LIBGIT2_VERSION`, and initialize it.
+///
+/// Opening or creating a Repository does this, and the Repository releases libgit2
+/// when it is dropped; the functions which do not take a repository, such as
+/// `odb::hash` or `config::open_default`, need `init` to be called first.
+/// Every successful call must be balanced by a call to `shutdown`.
+pub fn init() -> Result<(), (~str, GitError)> {
+    let (major, minor) = LIBGIT2_VERSION;
+    let (cur_major, cur_minor, cur_rev) = version();
+    if cur_major != major || cur_minor != minor {
+        return Err( (fmt!("git2-rs requires libgit2 %d.%d, whose structures it mirrors, \
+                           but %d.%d.%d is linked",
+                          major, minor, cur_major, cur_minor, cur_rev), GITERR_INVALID) );
+    }
     unsafe {
-        ext::git_threads_init();
+        if ext::git_libgit2_init() < 0 {
+            Err( (~"cannot initialize libgit2", GITERR_OS) )
+        } else {
+            Ok(())
+        }
     }
 }
 
-// if you call this library in multiple tasks,
-// this function must be called before shutting down the library
-pub fn threads_shutdown() {
+/// Release libgit2, once every `init` has been balanced by a `shutdown`
+pub fn shutdown() {
     unsafe {
-        ext::git_threads_shutdown();
+        ext::git_libgit2_shutdown();
     }
 }
@@ -1,5 +1,40 @@
+use std::libc::{c_char, c_int, c_uint, c_ushort, size_t};
+use std::{cast, ptr, vec};
 use super::*;
 use ext;
+use lock;
+
+/// An entry of the index
+pub struct IndexEntry {
+    /// path relative to the working directory; invalid UTF-8 sequences are replaced
+    /// with U+FFFD
+    path: ~str,
+    /// path relative to the working directory, as stored in the index
+    path_bytes: ~[u8],
+    /// id of the blob
+    id: OID,
+    mode: uint,
+    file_size: i64,
+    /// conflict stage: 0 for a normal entry, 1 to 3 for the sides of a conflict
+    stage: uint,
+}
+
+/// bits of the index entry flags holding the stage
+static GIT_IDXENTRY_STAGEMASK: u16 = 0x3000;
+static GIT_IDXENTRY_STAGESHIFT: u16 = 12;
+
+unsafe fn from_c_entry(entry: *ext::git_index_entry) -> IndexEntry {
+    let path_bytes = from_c_bytes((*entry).path);
+    IndexEntry {
+        path: lossy_utf8(path_bytes),
+        path_bytes: path_bytes,
+        id: (*entry).oid,
+        mode: (*entry).mode as uint,
+        file_size: (*entry).file_size as i64,
+        stage: (((*entry).flags as u16 & GIT_IDXENTRY_STAGEMASK) >> GIT_IDXENTRY_STAGESHIFT)
+                as uint,
+    }
+}
 
 impl<'self> GitIndex<'self> {
     /// Add or update an index entry from a file on disk
@@ -19,11 +54,24 @@ impl<'self> GitIndex<'self> {
     ///
     /// raises git_error on error
     pub fn add_bypath(&self, path: &str) {
+        self.add_bypath_bytes(path.as_bytes())
+    }
+
+    /// Add or update an index entry from a file on disk, like `add_bypath`, with the
+    /// path as bytes, e.g. for a file name which is not valid UTF-8
+    ///
+    /// raises git_error on error
+    pub fn add_bypath_bytes(&self, path: &[u8]) {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return; },
+        }
+        let mut c_path = path.to_owned();
+        c_path.push(0);
         unsafe {
-            do path.as_c_str |c_path| {
-                if ext::git_index_add_bypath(self.index, c_path) != 0 {
-                    raise()
-                }
+            let c_path: *c_char = cast::transmute(vec::raw::to_ptr(c_path));
+            if ext::git_index_add_bypath(self.index, c_path) != 0 {
+                raise()
             }
         }
     }
@@ -59,14 +107,97 @@ impl<'self> GitIndex<'self> {
         }
     }
 
+    /// Read the entries of a tree selected by a predicate into the index
+    ///
+    /// The current index contents will be replaced by the selected entries.
+    /// The predicate is called with the full path of each entry, relative to the root
+    /// of the tree, and the entry itself; when it returns false for a subtree, the whole
+    /// subtree is skipped without being read.
+    ///
+    /// raises git_error on error
+    pub fn read_tree_filtered(&self, tree: &Tree, pred: &fn(&str, &TreeEntry) -> bool) {
+        self.clear();
+        do tree.walk_preorder |root, entry| {
+            let path = entry.path_in(root);
+            if !pred(path, entry) {
+                WalkSkip
+            } else {
+                match entry.otype() {
+                    GIT_OBJ_TREE => (),
+                    _ => self.add_entry(path, entry.id(), entry.filemode() as uint),
+                }
+                WalkPass
+            }
+        };
+    }
+
+    /// add an entry without stat data, like git_index_read_tree does
+    fn add_entry(&self, path: &str, id: &OID, mode: uint) {
+        do path.as_c_str |c_path| {
+            let time = ext::git_index_time { seconds: 0, nanoseconds: 0 };
+            let entry = ext::git_index_entry {
+                ctime: time,
+                mtime: time,
+                dev: 0,
+                ino: 0,
+                mode: mode as c_uint,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                oid: *id,
+                // the low bits of the flags hold the length of the path
+                flags: std::uint::min(path.len(), 0xfff) as c_ushort,
+                flags_extended: 0,
+                path: c_path,
+            };
+            unsafe {
+                if ext::git_index_add(self.index, &entry) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
     /// Write an existing index object from memory back to disk using an atomic file lock.
     ///
+    /// libgit2 takes care of the racily clean entries, whose file was modified in the
+    /// same second as the index: their content is compared instead of their stat data.
+    ///
     /// raises git_error on error
     pub fn write(&self)
     {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return; },
+        }
         unsafe {
             if ext::git_index_write(self.index) != 0 {
                 raise()
+            } else {
+                self.owner.notify_index_written();
+            }
+        }
+    }
+
+    /// Write the index back to disk like `write`, waiting for the index lock held by
+    /// another process to be released, for at most `timeout_ms` milliseconds.
+    ///
+    /// See `lock::retry`.
+    pub fn write_locked(&self, timeout_ms: uint) -> Result<(), LockError>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err((msg, klass)) => return Err( LockFailed(msg, klass) ),
+        }
+        do lock::retry(timeout_ms) {
+            unsafe {
+                match ext::git_index_write(self.index) {
+                    0 => {
+                        self.owner.notify_index_written();
+                        Ok(())
+                    },
+                    res => Err(lock::error(res)),
+                }
             }
         }
     }
@@ -83,6 +214,10 @@ impl<'self> GitIndex<'self> {
     ///
     /// The index must not contain any file in conflict.
     pub fn write_tree<'r>(&'r self) -> Result<~Tree<'r>, (~str, GitError)> {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
         unsafe {
             let mut oid = OID { id: [0, .. 20] };
             if ext::git_index_write_tree(&mut oid, self.index) == 0 {
@@ -101,10 +236,83 @@ impl<'self> GitIndex<'self> {
     /// Clear the contents (all the entries) of an index object.
     /// This clears the index object in memory; changes must be manually
     /// written to disk for them to take effect.
+    ///
+    /// raises git_error on error
     pub fn clear(&self) {
         unsafe {
-            ext::git_index_clear(self.index);
+            if ext::git_index_clear(self.index) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Get the count of entries currently in the index
+    pub fn len(&self) -> uint {
+        unsafe {
+            ext::git_index_entrycount(self.index) as uint
+        }
+    }
+
+    /// Whether the index contains conflicting entries
+    pub fn has_conflicts(&self) -> bool {
+        unsafe {
+            ext::git_index_has_conflicts(self.index) == 1
+        }
+    }
+
+    /// Get the id of the blob of the n-th entry in the index,
+    /// or None if `n` is out of bounds
+    pub fn entry_id(&self, n: uint) -> Option<OID> {
+        unsafe {
+            let entry = ext::git_index_get_byindex(self.index, n as size_t);
+            if entry == ptr::null() {
+                None
+            } else {
+                Some((*entry).oid)
+            }
+        }
+    }
+
+    /// Get the n-th entry in the index, or None if `n` is out of bounds
+    pub fn get(&self, n: uint) -> Option<IndexEntry> {
+        unsafe {
+            let entry = ext::git_index_get_byindex(self.index, n as size_t);
+            if entry == ptr::null() {
+                None
+            } else {
+                Some(from_c_entry(entry))
+            }
+        }
+    }
+
+    /// Get the entry of `path` at the given stage, or None if there is no such entry
+    ///
+    /// The stage is 0 for a normal entry, and 1 to 3 for the sides of a conflict.
+    pub fn get_bypath(&self, path: &str, stage: uint) -> Option<IndexEntry> {
+        do path.as_c_str |c_path| {
+            unsafe {
+                let entry = ext::git_index_get_bypath(self.index, c_path, stage as c_int);
+                if entry == ptr::null() {
+                    None
+                } else {
+                    Some(from_c_entry(entry))
+                }
+            }
+        }
+    }
+
+    /// Get all the entries of the index, in index order
+    pub fn entries(&self) -> ~[IndexEntry] {
+        let mut entries = std::vec::with_capacity(self.len());
+        let mut n = 0u;
+        while n < self.len() {
+            match self.get(n) {
+                Some(entry) => entries.push(entry),
+                None => break,
+            }
+            n += 1;
         }
+        entries
     }
 }
 
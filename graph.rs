@@ -0,0 +1,107 @@
+use std::ptr;
+use std::hashmap::HashMap;
+use extra::json::{List, ToJson};
+use super::*;
+use ext;
+
+/// A commit in an exported history graph
+pub struct CommitNode {
+    pub id: OID,
+    pub parents: ~[OID],
+    pub refs: ~[~str],
+}
+
+/// Walk the history configured on `walk`, and collect every visited commit
+/// with its parents and the names of the references pointing at it.
+///
+/// Annotated tags are peeled, so a tag is listed on the commit it points to.
+pub fn commit_graph(repo: &Repository, walk: &mut Revwalk) -> ~[CommitNode]
+{
+    let mut refs_by_id: HashMap<OID, ~[~str]> = HashMap::new();
+    for repo.reference_names().iter().advance |name| {
+        match peel_to_commit(repo, *name) {
+            Some(oid) => {
+                let names = refs_by_id.find_or_insert(oid, ~[]);
+                names.push(copy *name);
+            },
+            None => (),
+        }
+    }
+
+    let mut nodes: ~[CommitNode] = ~[];
+    for walk.advance |oid| {
+        let parents = match repo.lookup_commit(&oid) {
+            Some(commit) => commit.parents_oid().map(|p| **p),
+            None => ~[],
+        };
+        let refs = match refs_by_id.find(&oid) {
+            Some(names) => copy *names,
+            None => ~[],
+        };
+        nodes.push(CommitNode { id: oid, parents: parents, refs: refs });
+    }
+    nodes
+}
+
+fn peel_to_commit(repo: &Repository, refname: &str) -> Option<OID>
+{
+    match repo.lookup(refname) {
+        None => None,
+        Some(reference) => unsafe {
+            let mut obj: *ext::git_object = ptr::null();
+            if ext::git_reference_peel(&mut obj, reference.c_ref, GIT_OBJ_COMMIT) == 0 {
+                let oid = *ext::git_object_id(obj);
+                ext::git_object_free(obj);
+                Some(oid)
+            } else {
+                None
+            }
+        },
+    }
+}
+
+/// Render the graph in the graphviz DOT language.
+///
+/// Edges point from a commit to its parents, and references are added as labels.
+pub fn to_dot(nodes: &[CommitNode]) -> ~str
+{
+    let mut out = ~"digraph commits {\n";
+    for nodes.iter().advance |node| {
+        let id = node.id.to_str();
+        let mut label = id.slice(0, 7).to_owned();
+        for node.refs.iter().advance |name| {
+            label.push_str("\\n");
+            label.push_str(escape(*name));
+        }
+        out.push_str(fmt!("    \"%s\" [label=\"%s\"];\n", id, label));
+        for node.parents.iter().advance |parent| {
+            out.push_str(fmt!("    \"%s\" -> \"%s\";\n", id, parent.to_str()));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Render the graph as a JSON adjacency list:
+/// `[{"id": "...", "parents": ["..."], "refs": ["..."]}, ...]`
+pub fn to_json(nodes: &[CommitNode]) -> ~str
+{
+    List(nodes.map(|node| node.to_json())).to_str()
+}
+
+/// escape a string so that it can be placed between double quotes in DOT, where
+/// only the quote needs an escape; backslashes are doubled and newlines written as
+/// `\n`, as graphviz interprets both in labels
+fn escape(s: &str) -> ~str
+{
+    let mut out = ~"";
+    for s.iter().advance |c| {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push_char(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,360 @@
+use std::iterator::Iterator;
+use super::*;
+use diff;
+use revwalk;
+
+/// Iterator over the commits which modified a single path.
+///
+/// Each step yields the commit id, and the path of the file in that commit;
+/// the path changes when the history crosses a rename.
+/// An error, e.g. a missing commit or tree, ends the iteration; it is then returned by
+/// `error`.
+pub struct FileHistory<'self> {
+    priv repo: &'self Repository,
+    priv walk: ~Revwalk<'self>,
+    priv path: Option<~str>,
+    priv error: Option<(~str, GitError)>,
+}
+
+/// Create an iterator over the commits reachable from `start` which modified `path`,
+/// like `git log --follow -- <path>`.
+///
+/// The first parent of each commit is used to decide whether the path was modified;
+/// the commits deleting the path are included.
+/// When the path was added in a commit, renames are looked up in that commit,
+/// and the history continues with the old name.
+pub fn file_history<'r>(repo: &'r Repository, path: &str, start: &OID)
+    -> Result<FileHistory<'r>, (~str, GitError)>
+{
+    match repo.revwalk() {
+        Ok(walk) => {
+            walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL, revwalk::GIT_SORT_TIME]);
+            walk.push(start);
+            Ok( FileHistory { repo: repo, walk: walk, path: Some(path.to_owned()),
+                               error: None } )
+        },
+        Err(e) => Err(e),
+    }
+}
+
+fn entry_id(tree: &Tree, path: &str) -> Option<OID>
+{
+    match tree.entry_bypath(path) {
+        Some(entry) => Some(*entry.id()),
+        None => None,
+    }
+}
+
+/// name of the file `path` was renamed from between `old` and `new`, if any
+pub fn renamed_from(repo: &Repository, old: &Tree, new: &Tree, path: &str)
+    -> Result<Option<~str>, (~str, GitError)>
+{
+    let opts = diff::DiffOption::new();
+    match repo.diff_trees(Some(old), Some(new), &opts) {
+        Ok(difflist) => {
+            difflist.find_similar(&diff::FindOption::new());
+            for difflist.deltas().iter().advance |delta| {
+                match delta.status {
+                    GIT_DELTA_RENAMED if delta.new_file.path.as_slice() == path => {
+                        return Ok(Some(copy delta.old_file.path));
+                    },
+                    _ => (),
+                }
+            }
+            Ok(None)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// the commit `id`, and its first parent unless it is a root commit
+fn commit_and_parent<'r>(repo: &'r Repository, id: &OID)
+    -> Result<(~Commit<'r>, Option<~Commit<'r>>), (~str, GitError)>
+{
+    let commit = match repo.try_lookup_commit(id) {
+        Ok(commit) => commit,
+        Err(e) => return Err(e.to_error(id)),
+    };
+    let parents = commit.parents_oid();
+    if parents.is_empty() {
+        return Ok((commit, None));
+    }
+    match repo.try_lookup_commit(&*parents[0]) {
+        Ok(parent) => Ok((commit, Some(parent))),
+        Err(e) => Err(e.to_error(&*parents[0])),
+    }
+}
+
+impl<'self> FileHistory<'self> {
+    /// The error which ended the iteration, or None if the history was walked to its end
+    pub fn error(&self) -> Option<(~str, GitError)>
+    {
+        copy self.error
+    }
+
+    /// the next commit which modified the path, if any
+    fn step(&mut self) -> Result<Option<(OID, ~str)>, (~str, GitError)>
+    {
+        loop {
+            let path = match self.path {
+                Some(ref p) => copy *p,
+                None => return Ok(None),
+            };
+            let oid = match self.walk.next() {
+                Some(oid) => oid,
+                None => return Ok(None),
+            };
+            let (commit, parent) = match commit_and_parent(self.repo, &oid) {
+                Ok(pair) => pair,
+                Err(e) => return Err(e),
+            };
+            let tree = match commit.tree() {
+                Ok(tree) => tree,
+                Err(e) => return Err(e),
+            };
+            let current = entry_id(tree, path);
+
+            let parent = match parent {
+                Some(parent) => parent,
+                None => {
+                    if current.is_none() {
+                        return Ok(None);
+                    }
+                    // the path was created in a root commit
+                    self.path = None;
+                    return Ok(Some((oid, path)));
+                },
+            };
+            let parent_tree = match parent.tree() {
+                Ok(tree) => tree,
+                Err(e) => return Err(e),
+            };
+            let previous = entry_id(parent_tree, path);
+            if previous == current {
+                // unchanged, or missing on both sides
+                loop;
+            }
+            if current.is_none() {
+                // the path was deleted
+                return Ok(Some((oid, path)));
+            }
+            if previous.is_none() {
+                // the path was added; keep following it if it was renamed
+                self.path = match renamed_from(self.repo, parent_tree, tree, path) {
+                    Ok(old_path) => old_path,
+                    Err(e) => return Err(e),
+                };
+            }
+            return Ok(Some((oid, path)));
+        }
+    }
+}
+
+impl<'self> Iterator<(OID, ~str)> for FileHistory<'self> {
+    fn next(&mut self) -> Option<(OID, ~str)>
+    {
+        match self.step() {
+            Ok(item) => item,
+            Err(e) => {
+                self.path = None;
+                self.error = Some(e);
+                None
+            },
+        }
+    }
+}
+
+/// A commit which modified a range of lines, with the position of the range in that commit.
+/// Line numbers start at 1, and `end` is inclusive.
+pub struct LineChange {
+    id: OID,
+    path: ~str,
+    start: uint,
+    end: uint,
+}
+
+/// Iterator over the commits which modified a range of lines, like `git log -L`.
+///
+/// As with `FileHistory`, an error ends the iteration and is returned by `error`.
+pub struct LineHistory<'self> {
+    priv repo: &'self Repository,
+    priv files: FileHistory<'self>,
+    priv start: uint,
+    priv end: uint,
+    priv error: Option<(~str, GitError)>,
+}
+
+/// Create an iterator over the commits reachable from `start` which modified the lines
+/// `start_line` to `end_line` (inclusive) of `path`, as they are in the commit `start`.
+///
+/// The range is moved along the history as lines are added and removed above it,
+/// and the iteration stops at the commit where all the lines of the range were added.
+pub fn line_history<'r>(repo: &'r Repository, path: &str, start_line: uint, end_line: uint,
+    start: &OID) -> Result<LineHistory<'r>, (~str, GitError)>
+{
+    match file_history(repo, path, start) {
+        Ok(files) => Ok( LineHistory { repo: repo, files: files, start: start_line,
+                                        end: end_line, error: None } ),
+        Err(e) => Err(e),
+    }
+}
+
+fn hunk_touches(h: &diff::Hunk, start: uint, end: uint) -> bool
+{
+    if h.new_lines == 0 {
+        // lines were removed right after new_start
+        h.new_start >= start && h.new_start < end
+    } else {
+        h.new_start <= end && h.new_start + h.new_lines - 1 >= start
+    }
+}
+
+/// position of the line `n` of the new side on the old side,
+/// or None if the line was added
+fn map_line(hunks: &[diff::Hunk], n: uint) -> Option<uint>
+{
+    let mut offset = 0i;
+    for hunks.iter().advance |h| {
+        let after = if h.new_lines == 0 {
+            n > h.new_start
+        } else {
+            n >= h.new_start + h.new_lines
+        };
+        if after {
+            offset += (h.old_lines as int) - (h.new_lines as int);
+        } else if n >= h.new_start {
+            return if h.old_lines == 0 { None } else { Some(h.old_start) };
+        } else {
+            break;
+        }
+    }
+    Some(((n as int) + offset) as uint)
+}
+
+fn blob_at<'r>(repo: &'r Repository, tree: &Tree, path: &str)
+    -> Result<Option<~Blob<'r>>, (~str, GitError)>
+{
+    match entry_id(tree, path) {
+        Some(oid) => match repo.try_lookup_blob(&oid) {
+            Ok(blob) => Ok(Some(blob)),
+            Err(e) => Err(e.to_error(&oid)),
+        },
+        None => Ok(None),
+    }
+}
+
+impl<'self> LineHistory<'self> {
+    /// The error which ended the iteration, or None if the history was walked to its end
+    pub fn error(&self) -> Option<(~str, GitError)>
+    {
+        match self.error {
+            Some(ref e) => Some(copy *e),
+            None => self.files.error(),
+        }
+    }
+
+    /// the next commit which modified the range, if any
+    fn step(&mut self) -> Result<Option<LineChange>, (~str, GitError)>
+    {
+        loop {
+            if self.start == 0 || self.end < self.start {
+                return Ok(None);
+            }
+            let (oid, path) = match self.files.next() {
+                Some(item) => item,
+                None => return Ok(None),
+            };
+            let (commit, parent) = match commit_and_parent(self.repo, &oid) {
+                Ok(pair) => pair,
+                Err(e) => return Err(e),
+            };
+            let tree = match commit.tree() {
+                Ok(tree) => tree,
+                Err(e) => return Err(e),
+            };
+            let new_blob = match blob_at(self.repo, tree, path) {
+                Ok(Some(blob)) => blob,
+                Ok(None) => {
+                    // the path was deleted; no line of the range is in this commit
+                    loop;
+                },
+                Err(e) => return Err(e),
+            };
+
+            let old_blob = match parent {
+                None => None,
+                Some(parent) => {
+                    let parent_tree = match parent.tree() {
+                        Ok(tree) => tree,
+                        Err(e) => return Err(e),
+                    };
+                    let old_path = match entry_id(parent_tree, path) {
+                        Some(_) => Some(copy path),
+                        None => match renamed_from(self.repo, parent_tree, tree, path) {
+                            Ok(old_path) => old_path,
+                            Err(e) => return Err(e),
+                        },
+                    };
+                    match old_path {
+                        Some(old_path) => match blob_at(self.repo, parent_tree, old_path) {
+                            Ok(blob) => blob,
+                            Err(e) => return Err(e),
+                        },
+                        None => None,
+                    }
+                },
+            };
+
+            // without context lines, the hunks only cover the changed lines
+            let opts = diff::DiffOption::new().context_lines(0);
+            let hunks = match diff::blob_hunks(old_blob.map(|b| &**b), Some(&*new_blob),
+                                                &opts) {
+                Ok(hunks) => hunks,
+                Err(e) => return Err(e),
+            };
+
+            let (start, end) = (self.start, self.end);
+            let touched = hunks.iter().any(|h| hunk_touches(h, start, end));
+
+            // move the range to the parent side; stop when the whole range was added
+            let mut old_start = None;
+            let mut old_end = None;
+            let mut n = start;
+            while n <= end {
+                match map_line(hunks, n) {
+                    Some(m) => {
+                        if old_start.is_none() { old_start = Some(m); }
+                        old_end = Some(m);
+                    },
+                    None => (),
+                }
+                n += 1;
+            }
+            match (old_start, old_end) {
+                (Some(s), Some(e)) if old_blob.is_some() => {
+                    self.start = s;
+                    self.end = e;
+                },
+                _ => { self.start = 0; },
+            }
+
+            if touched {
+                return Ok(Some(LineChange { id: oid, path: path, start: start, end: end }));
+            }
+        }
+    }
+}
+
+impl<'self> Iterator<LineChange> for LineHistory<'self> {
+    fn next(&mut self) -> Option<LineChange>
+    {
+        match self.step() {
+            Ok(item) => item,
+            Err(e) => {
+                self.start = 0;
+                self.error = Some(e);
+                None
+            },
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::hashmap::HashMap;
+use std::to_str::ToStr;
+use extra::json::{Json, ToJson, Object, List, String, Number, Boolean, Null};
+use super::*;
+use diff;
+use graph;
+
+fn object(fields: ~[(&str, Json)]) -> Json
+{
+    let mut obj = ~HashMap::new();
+    for fields.consume_iter().advance |(key, value)| {
+        obj.insert(key.to_owned(), value);
+    }
+    Object(obj)
+}
+
+impl ToJson for OID {
+    fn to_json(&self) -> Json {
+        String(self.to_str())
+    }
+}
+
+impl ToJson for Time {
+    /// `{"time": <seconds since the epoch>, "offset": <minutes>}`
+    fn to_json(&self) -> Json {
+        object(~[("time", Number(self.time as float)),
+                 ("offset", Number(self.offset as float))])
+    }
+}
+
+impl ToJson for Signature {
+    fn to_json(&self) -> Json {
+        object(~[("name", String(copy self.name)),
+                 ("email", String(copy self.email)),
+                 ("when", self.when.to_json())])
+    }
+}
+
+impl<'self> ToJson for Commit<'self> {
+    /// The metadata of the commit: id, tree, parents, author, committer and message
+    fn to_json(&self) -> Json {
+        let parents = self.parents_oid().map(|p| p.to_json());
+        let tree = match self.tree() {
+            Ok(tree) => tree.id().to_json(),
+            Err(_) => Null,
+        };
+        object(~[("id", self.id().to_json()),
+                 ("tree", tree),
+                 ("parents", List(parents)),
+                 ("author", self.author().to_json()),
+                 ("committer", self.committer().to_json()),
+                 ("message", String(self.message()))])
+    }
+}
+
+impl ToJson for TreeEntry {
+    fn to_json(&self) -> Json {
+        let otype = match self.otype() {
+            GIT_OBJ_COMMIT => ~"commit",
+            GIT_OBJ_TREE => ~"tree",
+            GIT_OBJ_BLOB => ~"blob",
+            GIT_OBJ_TAG => ~"tag",
+            _ => ~"unknown",
+        };
+        object(~[("name", String(self.name())),
+                 ("id", self.id().to_json()),
+                 ("type", String(otype)),
+                 ("mode", Number((self.filemode() as int) as float))])
+    }
+}
+
+impl<'self> ToJson for Tree<'self> {
+    /// The list of the entries of the tree; subtrees are not expanded
+    fn to_json(&self) -> Json {
+        let mut entries = ~[];
+        for self.each |entry| {
+            entries.push(entry.to_json());
+        }
+        List(entries)
+    }
+}
+
+impl ToJson for Status {
+    /// The short status code, and the individual flags
+    fn to_json(&self) -> Json {
+        object(~[("code", String(self.to_str())),
+                 ("index_new", Boolean(self.index_new)),
+                 ("index_modified", Boolean(self.index_modified)),
+                 ("index_deleted", Boolean(self.index_deleted)),
+                 ("index_renamed", Boolean(self.index_renamed)),
+                 ("index_typechange", Boolean(self.index_typechange)),
+                 ("wt_new", Boolean(self.wt_new)),
+                 ("wt_modified", Boolean(self.wt_modified)),
+                 ("wt_deleted", Boolean(self.wt_deleted)),
+                 ("wt_typechange", Boolean(self.wt_typechange)),
+                 ("wt_renamed", Boolean(self.wt_renamed)),
+                 ("wt_unreadable", Boolean(self.wt_unreadable)),
+                 ("ignored", Boolean(self.ignored)),
+                 ("conflicted", Boolean(self.conflicted))])
+    }
+}
+
+/// Convert the result of `Repository::status` to a JSON list of
+/// `{"path": ..., "status": ...}` objects
+pub fn status_to_json(statuses: &[(~str, ~Status)]) -> Json
+{
+    List(do statuses.map |&(ref path, ref status)| {
+        object(~[("path", String(copy *path)), ("status", status.to_json())])
+    })
+}
+
+impl ToJson for diff::DiffFile {
+    fn to_json(&self) -> Json {
+        object(~[("id", self.oid.to_json()),
+                 ("path", String(copy self.path)),
+                 ("size", Number(self.size as float)),
+                 ("mode", Number(self.mode as float))])
+    }
+}
+
+impl ToJson for diff::Delta {
+    fn to_json(&self) -> Json {
+        let line = self.to_str();
+        object(~[("status", String(line.slice(0, 1).to_owned())),
+                 ("similarity", Number(self.similarity as float)),
+                 ("old_file", self.old_file.to_json()),
+                 ("new_file", self.new_file.to_json())])
+    }
+}
+
+impl ToJson for graph::CommitNode {
+    fn to_json(&self) -> Json {
+        object(~[("id", self.id.to_json()),
+                 ("parents", List(self.parents.map(|p| p.to_json()))),
+                 ("refs", self.refs.to_json())])
+    }
+}
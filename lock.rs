@@ -0,0 +1,49 @@
+use std::libc::c_int;
+use std::uint;
+use extra::time::precise_time_ns;
+use extra::timer::sleep;
+use extra::uv::global_loop;
+use super::*;
+use ext;
+
+/// delay before the second attempt, in milliseconds; doubled after each attempt
+static FIRST_DELAY_MS: uint = 10;
+/// longest delay between two attempts, in milliseconds
+static MAX_DELAY_MS: uint = 500;
+
+/// Run `op` until it does not fail because of a lock file held by another process,
+/// or until `timeout_ms` milliseconds have passed.
+///
+/// `op` returns Busy when the lock file already exists, see `error`; the other
+/// errors are returned as they are. The delay between two attempts grows exponentially.
+/// Returns Busy if the lock could not be taken before the timeout.
+pub fn retry<T>(timeout_ms: uint, op: &fn() -> Result<T, LockError>) -> Result<T, LockError>
+{
+    let start = precise_time_ns();
+    let mut delay = FIRST_DELAY_MS;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(Busy) => {
+                let elapsed = ((precise_time_ns() - start) / 1000000) as uint;
+                if elapsed >= timeout_ms {
+                    return Err(Busy);
+                }
+                sleep(&global_loop::get(), uint::min(delay, timeout_ms - elapsed));
+                delay = uint::min(delay * 2, MAX_DELAY_MS);
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The error of the libgit2 call which just returned `res`: Busy for GIT_ELOCKED,
+/// the last libgit2 error otherwise
+pub unsafe fn error(res: c_int) -> LockError {
+    if res == ext::GIT_ELOCKED {
+        Busy
+    } else {
+        let (msg, klass) = last_error();
+        LockFailed(msg, klass)
+    }
+}
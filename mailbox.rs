@@ -0,0 +1,296 @@
+use std::libc::size_t;
+use std::ptr;
+use super::*;
+use ext;
+use trace;
+
+/// A patch read from an email, as produced by `git format-patch`
+pub struct MailPatch {
+    author: Signature,
+    /// the subject, without the "[PATCH n/m]" prefix
+    subject: ~str,
+    /// the full commit message: subject, blank line and body
+    message: ~str,
+    /// the unified diff
+    diff: ~str,
+}
+
+static MONTHS: [&'static str, ..12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+fn invalid<T>(msg: &str) -> Result<T, (~str, GitError)> {
+    Err( (msg.to_owned(), GITERR_INVALID) )
+}
+
+static DAYS: [&'static str, ..7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Whether `line` separates two messages: "From <address> <date>", with the date
+/// in the asctime format, e.g. "From 21002f5d Mon Sep 17 00:00:00 2001"
+fn is_separator(line: &str) -> bool
+{
+    if !line.starts_with("From ") {
+        return false;
+    }
+    let words: ~[&str] = line.word_iter().collect();
+    if words.len() < 7 {
+        return false;
+    }
+    DAYS.iter().any(|d| *d == words[2]) && MONTHS.iter().any(|m| *m == words[3]) &&
+        std::uint::from_str(words[4]).is_some() && words[5].contains_char(':') &&
+        std::uint::from_str(words[words.len() - 1]).is_some()
+}
+
+/// Split a mailbox in the mbox format into single messages.
+///
+/// Only the "From <address> <date>" lines separate messages; the body lines quoted
+/// as ">From ", ">>From ", ... are unquoted once, as in the mboxrd format.
+pub fn split_mbox(mbox: &str) -> ~[~str]
+{
+    let mut messages: ~[~str] = ~[];
+    let mut current = ~"";
+    for mbox.line_iter().advance |line| {
+        if is_separator(line) {
+            if !current.is_empty() {
+                messages.push(current);
+            }
+            current = ~"";
+            loop;
+        }
+        if line.starts_with(">") && line.trim_left_chars(&'>').starts_with("From ") {
+            current.push_str(line.slice_from(1));
+        } else {
+            current.push_str(line);
+        }
+        current.push_char('\n');
+    }
+    if !current.trim().is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+/// days since 1970-01-01 of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64
+{
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parse a RFC 2822 date, e.g. "Tue, 11 Jun 2013 19:14:48 +0900"
+pub fn parse_date(date: &str) -> Option<Time>
+{
+    let mut words: ~[&str] = date.word_iter().collect();
+    if words.len() > 0 && words[0].ends_with(",") {
+        words.shift();
+    }
+    if words.len() < 5 {
+        return None;
+    }
+
+    let day = match std::int::from_str(words[0]) { Some(d) => d, None => return None };
+    let month = match MONTHS.iter().position(|m| *m == words[1]) {
+        Some(m) => (m + 1) as int,
+        None => return None,
+    };
+    let year = match std::int::from_str(words[2]) { Some(y) => y, None => return None };
+
+    let hms: ~[&str] = words[3].split_iter(':').collect();
+    if hms.len() < 2 || hms.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0i;
+    for hms.iter().enumerate().advance |(i, part)| {
+        match std::int::from_str(*part) {
+            Some(v) => seconds += v * [3600, 60, 1][i],
+            None => return None,
+        }
+    }
+
+    // "+hhmm" or "-hhmm"; read as bytes, as the zone may hold any character
+    let zone = words[4].as_bytes();
+    if zone.len() != 5 || !zone.slice_from(1).iter().all(|b| (*b as char).is_digit()) {
+        return None;
+    }
+    let sign = match zone[0] as char { '+' => 1, '-' => -1, _ => return None };
+    let digit = |i: uint| (zone[i] - '0' as u8) as int;
+    let offset = sign * ((digit(1) * 10 + digit(2)) * 60 + digit(3) * 10 + digit(4));
+
+    let local = days_from_civil(year as i64, month as i64, day as i64) * 86400 + seconds as i64;
+    Some(Time { time: local - (offset as i64) * 60, offset: offset })
+}
+
+/// Parse "Name <email>", with an optionally quoted name
+fn parse_address(from: &str) -> Option<(~str, ~str)>
+{
+    match (from.find('<'), from.rfind('>')) {
+        (Some(lt), Some(gt)) if lt < gt => {
+            let name = from.slice(0, lt).trim().trim_chars(&'"');
+            let email = from.slice(lt + 1, gt).trim();
+            Some((name.to_owned(), email.to_owned()))
+        },
+        _ => None,
+    }
+}
+
+/// Remove the "[PATCH ...]" prefix of a subject
+fn strip_subject(subject: &str) -> ~str
+{
+    let subject = subject.trim();
+    if subject.starts_with("[") {
+        match subject.find(']') {
+            Some(end) => return subject.slice_from(end + 1).trim().to_owned(),
+            None => (),
+        }
+    }
+    subject.to_owned()
+}
+
+/// Parse a single patch email.
+pub fn parse(mail: &str) -> Result<MailPatch, (~str, GitError)>
+{
+    let lines: ~[&str] = mail.line_iter().collect();
+
+    // headers, with folded lines joined
+    let mut headers: ~[(~str, ~str)] = ~[];
+    let mut idx = 0u;
+    while idx < lines.len() && !lines[idx].trim().is_empty() {
+        let line = lines[idx];
+        if (line.starts_with(" ") || line.starts_with("\t")) && !headers.is_empty() {
+            let (_, ref mut value) = headers[headers.len() - 1];
+            value.push_char(' ');
+            value.push_str(line.trim());
+        } else {
+            match line.find(':') {
+                Some(colon) => headers.push((line.slice(0, colon).to_ascii_lower(),
+                                            line.slice_from(colon + 1).trim().to_owned())),
+                None => (),
+            }
+        }
+        idx += 1;
+    }
+
+    let header = |name: &str| -> Option<~str> {
+        match headers.iter().find_(|&&(ref k, _)| k.as_slice() == name) {
+            Some(&(_, ref v)) => Some(copy *v),
+            None => None,
+        }
+    };
+
+    let (name, email) = match header("from") {
+        Some(from) => match parse_address(from) {
+            Some(addr) => addr,
+            None => return invalid("invalid From header"),
+        },
+        None => return invalid("missing From header"),
+    };
+    let when = match header("date") {
+        Some(date) => match parse_date(date) {
+            Some(t) => t,
+            None => return invalid("invalid Date header"),
+        },
+        None => return invalid("missing Date header"),
+    };
+    let subject = match header("subject") {
+        Some(s) => strip_subject(s),
+        None => return invalid("missing Subject header"),
+    };
+
+    // the body ends at the "---" separator, or at the beginning of the diff
+    let mut body: ~[&str] = ~[];
+    while idx < lines.len() && lines[idx] != "---" && !lines[idx].starts_with("diff --git ") {
+        body.push(lines[idx]);
+        idx += 1;
+    }
+
+    let mut diff = ~"";
+    let mut in_diff = false;
+    while idx < lines.len() {
+        let line = lines[idx];
+        if line == "-- " {
+            // signature of format-patch
+            break;
+        }
+        if line.starts_with("diff --git ") {
+            in_diff = true;
+        }
+        if in_diff {
+            diff.push_str(line);
+            diff.push_char('\n');
+        }
+        idx += 1;
+    }
+    if diff.is_empty() {
+        return invalid("no diff found in patch");
+    }
+
+    let body = body.connect("\n");
+    let body = body.trim();
+    let message = if body.is_empty() {
+        subject + "\n"
+    } else {
+        fmt!("%s\n\n%s\n", subject, body)
+    };
+
+    Ok(MailPatch {
+        author: Signature { name: name, email: email, when: when },
+        subject: subject,
+        message: message,
+        diff: diff,
+    })
+}
+
+/// Apply a patch to the index and the working directory, and commit it on HEAD
+/// with the author of the email, like `git am`.
+///
+/// The committer is the default signature of the repository.
+/// Returns the id of the new commit.
+pub fn apply(repo: &Repository, patch: &MailPatch) -> Result<OID, (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    unsafe {
+        let mut difflist: *ext::git_diff = ptr::null();
+        let res = do patch.diff.as_c_str |c_diff| {
+            ext::git_diff_from_buffer(&mut difflist, c_diff, patch.diff.len() as size_t)
+        };
+        if res != 0 {
+            return Err( last_error() );
+        }
+        let difflist = DiffList { difflist: difflist };
+        let res = do trace::timed("git_apply") {
+            ext::git_apply(repo.repo, difflist.difflist, ext::GIT_APPLY_LOCATION_BOTH, ptr::null())
+        };
+        if res != 0 {
+            return Err( last_error() );
+        }
+    }
+
+    let committer = match repo.default_signature() {
+        Ok(sig) => sig,
+        Err(e) => return Err(e),
+    };
+    let index = match repo.index() {
+        Ok(index) => index,
+        Err(e) => return Err(e),
+    };
+    let tree = match index.write_tree() {
+        Ok(tree) => tree,
+        Err(e) => return Err(e),
+    };
+    let parents = match repo.try_head() {
+        Ok(Some(head)) => match repo.lookup_commit(&head.resolve()) {
+            Some(commit) => ~[commit],
+            None => ~[],
+        },
+        Ok(None) => ~[],
+        Err(e) => return Err(e),
+    };
+    repo.try_commit(Some("HEAD"), &patch.author, &committer, None, patch.message, tree, parents)
+}
@@ -0,0 +1,549 @@
+use std::{io, os, ptr, str};
+use std::libc::c_char;
+use std::from_str::FromStr;
+use std::hashmap::HashSet;
+use extra::sort::{quick_sort, quick_sort3};
+use super::*;
+use odb;
+use revwalk;
+use ext;
+
+/// Options for `repack`
+pub struct RepackOption {
+    /// remove the packs which existed before the repack, like `git repack -d`
+    delete_old_packs: bool,
+    /// remove the loose objects which were written into the new pack
+    delete_loose: bool,
+    /// number of threads used to compute deltas; 0 autodetects the number of CPUs
+    threads: uint,
+}
+
+impl RepackOption {
+    /// Nothing is removed: the new pack is written next to the existing objects
+    pub fn new() -> RepackOption {
+        RepackOption {
+            delete_old_packs: false,
+            delete_loose: false,
+            threads: 1,
+        }
+    }
+}
+
+/// Result of a `repack`
+pub struct RepackStats {
+    /// path of the new packfile
+    pack: ~str,
+    /// number of objects written into the new pack
+    objects: uint,
+    /// number of packfiles removed
+    removed_packs: uint,
+    /// number of loose objects removed
+    removed_loose: uint,
+}
+
+/// names of the packfiles of the repository, without extension
+fn pack_names(pack_dir: &Path) -> ~[~str]
+{
+    let mut names = ~[];
+    for os::list_dir(pack_dir).iter().advance |name| {
+        if name.ends_with(".pack") {
+            names.push(name.slice(0, name.len() - 5).to_owned());
+        }
+    }
+    names
+}
+
+/// Call `op` with the id and the path of every loose object of the repository.
+pub fn each_loose_object(repo: &Repository, op: &fn(id: &OID, path: &Path) -> bool) -> bool
+{
+    // an in-memory repository has no loose object
+    let objects = match repo.path() {
+        Some(path) => Path(path).push("objects"),
+        None => return true,
+    };
+    for os::list_dir(&objects).iter().advance |dir| {
+        if dir.len() != 2 {
+            loop;
+        }
+        let dir_path = objects.push(*dir);
+        for os::list_dir(&dir_path).iter().advance |name| {
+            if name.len() != 38 {
+                loop;
+            }
+            match FromStr::from_str(*dir + *name) {
+                Some(oid) => {
+                    let oid: OID = oid;
+                    if !op(&oid, &dir_path.push(*name)) {
+                        return false;
+                    }
+                },
+                None => (),
+            }
+        }
+    }
+    true
+}
+
+/// Write all the objects reachable from the references, their reflogs, HEAD and the
+/// index into a single new pack, like `git repack -a`.
+///
+/// Depending on `opts`, the packs which existed before and the loose objects now stored
+/// in the new pack are removed afterwards, like `git repack -a -d`; packs with a .keep
+/// file are kept. Objects reachable from nothing listed above, e.g. the chunks of
+/// a `chunked::write` whose manifest is not referenced, are lost when old packs
+/// or loose objects are removed.
+pub fn repack(repo: &Repository, opts: &RepackOption) -> Result<RepackStats, (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let pack_dir = match repo.disk_path() {
+        Ok(path) => Path(path).push_many(["objects", "pack"]),
+        Err(e) => return Err(e),
+    };
+    let pb = match repo.packbuilder() {
+        Ok(pb) => pb,
+        Err(e) => return Err(e),
+    };
+    pb.set_threads(opts.threads);
+    let (reachable, tips) = match reachable_with_tips(repo) {
+        Ok(found) => found,
+        Err(e) => return Err(e),
+    };
+    // like git, the commits go first, newest first, followed by their trees and blobs,
+    // so that the pack is laid out in the order history is read
+    let walk = match repo.revwalk() {
+        Ok(walk) => walk,
+        Err(e) => return Err(e),
+    };
+    walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL, revwalk::GIT_SORT_TIME]);
+    // then the other objects, e.g. the tags and the blobs of the index; libgit2
+    // skips those already inserted
+    let mut ids = ~[];
+    for reachable.iter().advance |id| {
+        ids.push(*id);
+    }
+    quick_sort3(ids);
+    let mut error = None;
+    do git_error::cond.trap(|e| error = Some(e)).in {
+        for tips.iter().advance |id| {
+            walk.push(id);
+        }
+        pb.insert_walk(&*walk);
+        for ids.iter().advance |id| {
+            pb.insert(id, None);
+        }
+    }
+    match error {
+        Some(e) => return Err(e),
+        None => (),
+    }
+
+    let old_packs = pack_names(&pack_dir);
+    match pb.write(pack_dir.to_str()) {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+
+    let new_pack = ~"pack-" + pb.hash().to_str();
+    let mut stats = RepackStats {
+        pack: pack_dir.push(new_pack + ".pack").to_str(),
+        objects: pb.written(),
+        removed_packs: 0,
+        removed_loose: 0,
+    };
+
+    if opts.delete_old_packs {
+        for old_packs.iter().advance |name| {
+            // packs marked with a .keep file are never removed
+            if *name == new_pack || os::path_exists(&pack_dir.push(*name + ".keep")) {
+                loop;
+            }
+            // the index goes first, so that a pack is never left without it
+            for [".idx", ".pack"].iter().advance |ext| {
+                match remove(&pack_dir.push(*name + *ext)) {
+                    Ok(()) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+            stats.removed_packs += 1;
+        }
+    }
+
+    if opts.delete_loose {
+        let idx = match io::read_whole_file(&pack_dir.push(new_pack + ".idx")) {
+            Ok(idx) => idx,
+            Err(e) => return Err( (e, GITERR_OS) ),
+        };
+        let mut error = None;
+        for each_loose_object(repo) |oid, path| {
+            if odb::pack_index_offset(idx, oid).is_some() {
+                match remove(path) {
+                    Ok(()) => stats.removed_loose += 1,
+                    Err(e) => error = Some(e),
+                }
+            }
+            error.is_none()
+        };
+        match error {
+            Some(e) => return Err(e),
+            None => (),
+        }
+    }
+    Ok(stats)
+}
+
+/// Remove a file, failing with GITERR_OS
+fn remove(path: &Path) -> Result<(), (~str, GitError)>
+{
+    if os::remove_file(path) {
+        Ok(())
+    } else {
+        Err( (fmt!("cannot remove %s", path.to_str()), GITERR_OS) )
+    }
+}
+
+/// Collect the ids of all the objects reachable from the references, their reflogs,
+/// HEAD and the index.
+///
+/// Annotated tags are peeled until a non-tag object is found, commits are followed
+/// through their parents and trees are traversed, skipping the subtrees already seen.
+/// Fails if any of these objects cannot be read, since a missing object would make
+/// the set incomplete, and `prune_loose` would then remove live objects.
+/// The reflog entries pointing to objects which no longer exist are ignored.
+pub fn reachable_objects(repo: &Repository) -> Result<HashSet<OID>, (~str, GitError)>
+{
+    match reachable_with_tips(repo) {
+        Ok((reachable, _)) => Ok(reachable),
+        Err(e) => Err(e),
+    }
+}
+
+/// `reachable_objects`, and the commits the references, reflogs and tags point to
+fn reachable_with_tips(repo: &Repository) -> Result<(HashSet<OID>, ~[OID]), (~str, GitError)>
+{
+    let odb = match repo.odb() {
+        Ok(odb) => odb,
+        Err(e) => return Err(e),
+    };
+    let mut reachable = HashSet::new();
+    let mut commits = ~[];
+    let mut trees = ~[];
+
+    let mut refnames = repo.reference_names();
+    refnames.push(~"HEAD");
+    for refnames.iter().advance |name| {
+        match repo.lookup(*name) {
+            Some(reference) => {
+                let chain = match reference.resolve_chain() {
+                    Ok(chain) => chain,
+                    Err(e) => return Err(e),
+                };
+                match chain.target {
+                    Some(target) => {
+                        match mark_tip(odb, &target, &mut reachable, &mut commits, &mut trees) {
+                            Ok(()) => (),
+                            Err(e) => return Err(e),
+                        }
+                    },
+                    // an unborn branch
+                    None => (),
+                }
+            },
+            None => (),
+        }
+        match repo.reflog(*name) {
+            Ok(reflog) => {
+                for reflog.entries().iter().advance |entry| {
+                    for [entry.id_old, entry.id_new].iter().advance |oid| {
+                        if !odb.exists(oid) {
+                            // the zero id of a creation, or an expired entry
+                            loop;
+                        }
+                        match mark_tip(odb, oid, &mut reachable, &mut commits, &mut trees) {
+                            Ok(()) => (),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            },
+            // no reflog for this reference
+            Err(_) => (),
+        }
+    }
+
+    let tips = copy commits;
+    while !commits.is_empty() {
+        let oid = commits.pop();
+        let commit = match repo.lookup_commit(&oid) {
+            Some(commit) => commit,
+            None => return Err( missing_object("commit", &oid) ),
+        };
+        match commit.tree() {
+            Ok(tree) => trees.push(*tree.id()),
+            Err(e) => return Err(e),
+        }
+        for commit.parents_oid().iter().advance |parent| {
+            if reachable.insert(**parent) {
+                commits.push(**parent);
+            }
+        }
+    }
+
+    match mark_trees(repo, trees, &mut reachable) {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+
+    let index = match repo.index() {
+        Ok(index) => index,
+        Err(e) => return Err(e),
+    };
+    let mut n = 0u;
+    while n < index.len() {
+        match index.entry_id(n) {
+            Some(oid) => { reachable.insert(oid); },
+            None => return Err( (fmt!("cannot read index entry %u", n), GITERR_INDEX) ),
+        }
+        n += 1;
+    }
+    Ok((reachable, tips))
+}
+
+fn missing_object(kind: &str, id: &OID) -> (~str, GitError) {
+    (fmt!("%s %s not found", kind, id.to_str()), GITERR_ODB)
+}
+
+/// Mark `id` and, for an annotated tag, the chain of objects it points to; the
+/// commits and trees found are queued to be traversed
+fn mark_tip(odb: &Odb, id: &OID, reachable: &mut HashSet<OID>, commits: &mut ~[OID],
+            trees: &mut ~[OID]) -> Result<(), (~str, GitError)>
+{
+    let mut id = *id;
+    loop {
+        if reachable.contains(&id) {
+            return Ok(());
+        }
+        let otype = match odb.read_header(&id) {
+            Ok((_, otype)) => otype,
+            Err(e) => return Err(e),
+        };
+        match otype {
+            // marked by mark_trees along with its entries
+            GIT_OBJ_TREE => {
+                trees.push(id);
+                return Ok(());
+            },
+            GIT_OBJ_TAG => {
+                reachable.insert(id);
+                id = match tag_target(odb, &id) {
+                    Ok(target) => target,
+                    Err(e) => return Err(e),
+                };
+            },
+            GIT_OBJ_COMMIT => {
+                reachable.insert(id);
+                commits.push(id);
+                return Ok(());
+            },
+            _ => {
+                reachable.insert(id);
+                return Ok(());
+            },
+        }
+    }
+}
+
+/// The object an annotated tag points to, from the first line of the tag
+fn tag_target(odb: &Odb, id: &OID) -> Result<OID, (~str, GitError)>
+{
+    let obj = match odb.read(id) {
+        Ok(obj) => obj,
+        Err(e) => return Err(e),
+    };
+    let target = do obj.data_as_slice |data| {
+        if data.len() >= 47 && data.slice(0, 7) == "object ".as_bytes() {
+            FromStr::from_str(str::from_bytes(data.slice(7, 47)))
+        } else {
+            None
+        }
+    };
+    match target {
+        Some(target) => Ok(target),
+        None => Err( (fmt!("malformed tag %s", id.to_str()), GITERR_OBJECT) ),
+    }
+}
+
+/// Mark the trees of `trees` and everything they contain
+fn mark_trees(repo: &Repository, trees: ~[OID], reachable: &mut HashSet<OID>)
+    -> Result<(), (~str, GitError)>
+{
+    let mut trees = trees;
+    while !trees.is_empty() {
+        let oid = trees.pop();
+        if !reachable.insert(oid) {
+            // a tree already seen: all its entries are known
+            loop;
+        }
+        let tree = match repo.lookup_tree(&oid) {
+            Some(tree) => tree,
+            None => return Err( missing_object("tree", &oid) ),
+        };
+        for tree.each |entry| {
+            match entry.otype() {
+                GIT_OBJ_TREE => trees.push(*entry.id()),
+                // submodule commits live in another repository
+                GIT_OBJ_COMMIT => (),
+                _ => { reachable.insert(*entry.id()); },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove the unreachable loose objects last modified before `older_than`
+/// (seconds since the epoch), like `git prune --expire`.
+///
+/// An object is reachable if it can be reached from a reference, a reflog entry,
+/// HEAD or the index. With `dry_run`, no object is removed.
+/// Returns the ids of the removed objects.
+pub fn prune_loose(repo: &Repository, older_than: i64, dry_run: bool)
+    -> Result<~[OID], (~str, GitError)>
+{
+    if !dry_run {
+        match repo.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+    }
+    let reachable = match reachable_objects(repo) {
+        Ok(reachable) => reachable,
+        Err(e) => return Err(e),
+    };
+
+    let mut pruned = ~[];
+    for each_loose_object(repo) |oid, path| {
+        let expired = match path.get_mtime() {
+            Some((mtime, _)) => mtime < older_than,
+            None => false,
+        };
+        if expired && !reachable.contains(oid) {
+            if dry_run || os::remove_file(path) {
+                pruned.push(*oid);
+            }
+        }
+    };
+    Ok(pruned)
+}
+
+/// What `prune_gone_branches` did with a branch whose upstream is gone
+#[deriving(Eq)]
+pub enum PruneAction {
+    /// the branch was deleted
+    PruneDeleted,
+    /// the branch would have been deleted, but this was a dry run
+    PruneDryRun,
+    /// the branch is checked out, and was kept
+    PruneKeptHead,
+    /// the branch has commits which are not merged into HEAD, and was kept
+    PruneKeptUnmerged,
+    /// deleting the branch failed with this error
+    PruneFailed(~str),
+}
+
+/// A local branch whose upstream branch no longer exists
+pub struct GoneBranch {
+    /// short name of the local branch, e.g. "feature"
+    name: ~str,
+    /// full name of the missing upstream, e.g. "refs/remotes/origin/feature"
+    upstream: ~str,
+    action: PruneAction,
+}
+
+/// Delete the local branches whose upstream branch is gone, like `git branch -d` on
+/// the branches `git branch -vv` marks as "gone".
+///
+/// The upstream is gone when the branch has one configured, but the remote-tracking
+/// branch does not exist anymore, typically because a fetch with pruning removed it
+/// after the branch was deleted on the remote. Like `git branch -d`, a branch is only
+/// deleted when it is merged into HEAD, its upstream being gone, and the checked out
+/// branch is never deleted. With `dry_run`, no branch is deleted.
+/// Returns the branches found, sorted by name, with what was done with each of them.
+///
+/// raises git_error when the branches cannot be listed
+pub fn prune_gone_branches(repo: &Repository, dry_run: bool) -> ~[GoneBranch]
+{
+    if !dry_run {
+        match repo.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return ~[]; },
+        }
+    }
+    let mut branches = ~[];
+    unsafe {
+        let mut iter: *ext::git_branch_iterator = ptr::null();
+        if ext::git_branch_iterator_new(&mut iter, repo.repo, ext::GIT_BRANCH_LOCAL) != 0 {
+            raise();
+            return ~[];
+        }
+        loop {
+            let mut c_ref: *ext::git_reference = ptr::null();
+            let mut branch_type: ext::git_branch_t = 0;
+            match ext::git_branch_next(&mut c_ref, &mut branch_type, iter) {
+                0 => (),
+                ext::GIT_ITEROVER => break,
+                _ => { raise(); break; },
+            }
+            let branch = ~Reference { c_ref: c_ref, owner: repo };
+            let mut c_name: *c_char = ptr::null();
+            if ext::git_branch_name(&mut c_name, branch.c_ref) != 0 {
+                raise();
+                break;
+            }
+            branches.push((str::raw::from_c_str(c_name), branch));
+        }
+        ext::git_branch_iterator_free(iter);
+    }
+    quick_sort(branches, |&(ref a, _), &(ref b, _)| *a <= *b);
+
+    let head = match repo.head() {
+        Some(head) => Some(head.resolve()),
+        None => None,
+    };
+    let mut gone = ~[];
+    for branches.iter().advance |&(ref name, ref branch)| {
+        let upstream = match repo.upstream_name(branch.name()) {
+            Some(upstream) => upstream,
+            None => loop,
+        };
+        if repo.lookup(upstream).is_some() {
+            loop;
+        }
+        let tip = branch.resolve();
+        let merged = match head {
+            Some(ref head) => *head == tip || unsafe {
+                ext::git_graph_descendant_of(repo.repo, head, &tip) == 1
+            },
+            None => false,
+        };
+        let action = if branch.is_head() {
+            PruneKeptHead
+        } else if !merged {
+            PruneKeptUnmerged
+        } else if dry_run {
+            PruneDryRun
+        } else {
+            let mut error = None;
+            do git_error::cond.trap(|(msg, _)| error = Some(msg)).in {
+                branch.delete();
+            }
+            match error {
+                Some(msg) => PruneFailed(msg),
+                None => PruneDeleted,
+            }
+        };
+        gone.push(GoneBranch { name: copy *name, upstream: upstream, action: action });
+    }
+    gone
+}
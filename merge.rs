@@ -0,0 +1,600 @@
+use std::{io, os, ptr};
+use std::vec;
+use std::from_str::FromStr;
+use std::libc::{c_int, c_uint, size_t};
+use super::*;
+use checkout;
+use reflog;
+use remote;
+use trace;
+use ext;
+
+/// Why a fast-forward was refused or failed
+pub enum FastForwardError {
+    /// the target is not a descendant of the current commit of the branch
+    NonFastForward,
+    /// HEAD is detached, and fast-forwarding it would lose track of the branch
+    DetachedHead,
+    /// the update failed, e.g. the checkout would overwrite local changes
+    FastForwardFailed(~str, GitError),
+}
+
+fn failed<T>((msg, klass): (~str, GitError)) -> Result<T, FastForwardError> {
+    Err( FastForwardFailed(msg, klass) )
+}
+
+/// Find a merge base between two commits, or None if they have no common ancestor
+pub fn merge_base(repo: &Repository, one: &OID, two: &OID) -> Option<OID>
+{
+    let mut base = OID { id: [0, ..20] };
+    unsafe {
+        match ext::git_merge_base(&mut base, repo.repo, one, two) {
+            0 => Some(base),
+            ext::GIT_ENOTFOUND => None,
+            _ => { raise(); None },
+        }
+    }
+}
+
+/// Move the branch `branch_ref` forward to the commit `target`.
+///
+/// The update is refused with NonFastForward unless the current commit of the branch
+/// is an ancestor of `target`. When the branch is checked out, the index and the
+/// working directory are updated as well; the update fails instead of overwriting
+/// local changes. Passing "HEAD" while HEAD is detached is refused with DetachedHead.
+///
+/// Returns the updated branch.
+pub fn fast_forward<'r>(repo: &'r Repository, branch_ref: &str, target: &OID)
+    -> Result<~Reference<'r>, FastForwardError>
+{
+    let msg = reflog::fast_forward_message(target.to_str());
+    fast_forward_logged(repo, branch_ref, target, msg)
+}
+
+/// `fast_forward`, with `log_message` in the reflogs
+fn fast_forward_logged<'r>(repo: &'r Repository, branch_ref: &str, target: &OID,
+                           log_message: &str) -> Result<~Reference<'r>, FastForwardError>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return failed(e),
+    }
+    if branch_ref == "HEAD" && unsafe { ext::git_repository_head_detached(repo.repo) == 1 } {
+        return Err(DetachedHead);
+    }
+    let branch = if branch_ref == "HEAD" {
+        // the branch HEAD points to
+        match repo.head() {
+            Some(head) => head,
+            None => return failed( (~"HEAD is unborn", GITERR_REFERENCE) ),
+        }
+    } else {
+        match repo.lookup(branch_ref) {
+            Some(branch) => branch,
+            None => return failed( (fmt!("no reference %s", branch_ref), GITERR_REFERENCE) ),
+        }
+    };
+    let current = branch.resolve();
+    if current == *target {
+        return Ok(branch);
+    }
+    match merge_base(repo, &current, target) {
+        Some(ref base) if *base == current => (),
+        _ => return Err(NonFastForward),
+    }
+
+    let commit = match repo.lookup_commit(target) {
+        Some(commit) => commit,
+        None => return failed( (fmt!("%s is not a commit", target.to_str()), GITERR_OBJECT) ),
+    };
+    if !repo.is_bare() && branch.is_head() {
+        let tree = match commit.tree() {
+            Ok(tree) => tree,
+            Err(e) => return failed(e),
+        };
+        let mut opts = checkout::CheckoutOption::new();
+        opts.strategy = ~[checkout::GIT_CHECKOUT_SAFE];
+        let res = unsafe {
+            do checkout::with_c_opts(&opts) |c_opts| {
+                do trace::timed("git_checkout_tree") {
+                    ext::git_checkout_tree(repo.repo, tree.tree as *ext::git_object, c_opts)
+                }
+            }
+        };
+        if res != 0 {
+            return failed( unsafe { last_error() } );
+        }
+    }
+
+    match branch.set_target(target, Some(log_message)) {
+        Ok(updated) => Ok(updated),
+        Err(e) => failed(e),
+    }
+}
+
+/// How the user prefers merges to be made, from the `merge.ff` setting
+#[deriving(Eq)]
+pub enum MergePreference {
+    /// no preference: fast-forward when possible, merge otherwise
+    MergePreferenceNone,
+    /// `merge.ff = false`: always create a merge commit
+    MergePreferenceNoFastForward,
+    /// `merge.ff = only`: only fast-forward
+    MergePreferenceFastForwardOnly,
+}
+
+/// What merging some commits into HEAD would take, see `merge_analysis`
+pub struct MergeAnalysis {
+    /// the commits can be merged with a merge commit
+    normal: bool,
+    /// HEAD already contains all the commits
+    up_to_date: bool,
+    /// HEAD can be fast-forwarded to the commit
+    fast_forward: bool,
+    /// HEAD is unborn: the commit can simply be checked out
+    unborn: bool,
+    /// the merge strategy configured by the user
+    preference: MergePreference,
+}
+
+impl MergeAnalysis {
+    /// The PullStrategy matching the preference of the user
+    pub fn pull_strategy(&self) -> PullStrategy {
+        match self.preference {
+            MergePreferenceNone => PullMerge,
+            MergePreferenceNoFastForward => PullNoFastForward,
+            MergePreferenceFastForwardOnly => PullFastForwardOnly,
+        }
+    }
+}
+
+/// Analyze what merging `their_heads` into HEAD would take, and which merge strategy
+/// the configuration prefers.
+pub fn merge_analysis(repo: &Repository, their_heads: &[OID])
+    -> Result<MergeAnalysis, (~str, GitError)>
+{
+    unsafe {
+        let mut heads: ~[*ext::git_annotated_commit] = vec::with_capacity(their_heads.len());
+        let mut res = 0;
+        for their_heads.iter().advance |id| {
+            let mut head: *ext::git_annotated_commit = ptr::null();
+            res = ext::git_annotated_commit_lookup(&mut head, repo.repo, id);
+            if res != 0 {
+                break;
+            }
+            heads.push(head);
+        }
+        let mut analysis: c_int = 0;
+        let mut preference: c_int = 0;
+        if res == 0 {
+            res = ext::git_merge_analysis(&mut analysis, &mut preference, repo.repo,
+                                        vec::raw::to_ptr(heads), heads.len() as size_t);
+        }
+        // keep the error of the failed call before freeing
+        let err = if res != 0 { Some(last_error()) } else { None };
+        for heads.iter().advance |head| {
+            ext::git_annotated_commit_free(*head);
+        }
+        match err {
+            Some(e) => return Err(e),
+            None => (),
+        }
+        let preference = if preference & ext::GIT_MERGE_PREFERENCE_FASTFORWARD_ONLY != 0 {
+            MergePreferenceFastForwardOnly
+        } else if preference & ext::GIT_MERGE_PREFERENCE_NO_FASTFORWARD != 0 {
+            MergePreferenceNoFastForward
+        } else {
+            MergePreferenceNone
+        };
+        Ok( MergeAnalysis {
+            normal: analysis & ext::GIT_MERGE_ANALYSIS_NORMAL != 0,
+            up_to_date: analysis & ext::GIT_MERGE_ANALYSIS_UP_TO_DATE != 0,
+            fast_forward: analysis & ext::GIT_MERGE_ANALYSIS_FASTFORWARD != 0,
+            unborn: analysis & ext::GIT_MERGE_ANALYSIS_UNBORN != 0,
+            preference: preference,
+        } )
+    }
+}
+
+/// How the conflicting hunks of a file are resolved, like `git merge -X`
+pub enum MergeFavor {
+    /// leave the conflicts in the index
+    FavorNormal,
+    /// take our side of the conflicting hunks, like `-X ours`
+    FavorOurs,
+    /// take their side of the conflicting hunks, like `-X theirs`
+    FavorTheirs,
+    /// keep both sides of the conflicting hunks, like `-X union`
+    FavorUnion,
+}
+
+/// The style of the conflict markers, like `merge.conflictStyle`
+pub enum ConflictStyle {
+    /// "<<<<<<<", "=======" and ">>>>>>>"
+    ConflictMerge,
+    /// the markers of ConflictMerge, plus the base version after "|||||||"
+    ConflictDiff3,
+}
+
+/// Options of `merge_commits`, `cherrypick_commit`, `revert_commit`, `merge` and `pull`
+pub struct MergeOption {
+    favor: MergeFavor,
+    /// used when the conflicting files are written, by `merge` unless its checkout
+    /// options choose a style; when checking out a conflicted index yourself, pass the
+    /// matching `checkout::GIT_CHECKOUT_CONFLICT_STYLE_*` strategy
+    conflict_style: ConflictStyle,
+    /// detect renames between the base and each side
+    find_renames: bool,
+    /// similarity, in percent, from which a file is considered renamed
+    rename_threshold: uint,
+}
+
+impl MergeOption {
+    /// The defaults of git: conflicts are kept, with the merge style, and renames detected
+    pub fn new() -> MergeOption {
+        MergeOption {
+            favor: FavorNormal,
+            conflict_style: ConflictMerge,
+            find_renames: true,
+            rename_threshold: 50,
+        }
+    }
+}
+
+fn with_merge_opts<T>(opts: &MergeOption, f: &fn(&ext::git_merge_options) -> T) -> T
+{
+    let c_opts = ext::git_merge_options {
+        version: 1,     // GIT_MERGE_OPTIONS_VERSION
+        flags: if opts.find_renames { ext::GIT_MERGE_FIND_RENAMES } else { 0 },
+        rename_threshold: opts.rename_threshold as c_uint,
+        target_limit: 200,
+        metric: ptr::null(),
+        recursion_limit: 0,
+        default_driver: ptr::null(),
+        file_favor: match opts.favor {
+            FavorNormal => 0,
+            FavorOurs => 1,
+            FavorTheirs => 2,
+            FavorUnion => 3,
+        },
+        file_flags: match opts.conflict_style {
+            ConflictMerge => ext::GIT_MERGE_FILE_STYLE_MERGE,
+            ConflictDiff3 => ext::GIT_MERGE_FILE_STYLE_DIFF3,
+        },
+    };
+    f(&c_opts)
+}
+
+/// the index produced by a merge function of libgit2
+fn merged_index<'r>(repo: &'r Repository, merge: &fn(&mut *ext::git_index) -> c_int)
+    -> Result<~GitIndex<'r>, (~str, GitError)>
+{
+    let mut c_index: *ext::git_index = ptr::null();
+    if merge(&mut c_index) == 0 {
+        Ok( ~GitIndex { index: c_index, owner: repo } )
+    } else {
+        unsafe { Err( last_error() ) }
+    }
+}
+
+/// Merge `theirs` into `ours` in memory, and return the resulting index.
+///
+/// Nothing is written to the repository: check `GitIndex::has_conflicts`, then write
+/// the index as a tree, or check it out.
+pub fn merge_commits<'r>(repo: &'r Repository, ours: &Commit, theirs: &Commit,
+                         opts: &MergeOption) -> Result<~GitIndex<'r>, (~str, GitError)>
+{
+    do merged_index(repo) |out| {
+        do with_merge_opts(opts) |c_opts| {
+            do trace::timed("git_merge_commits") {
+                unsafe {
+                    ext::git_merge_commits(out, repo.repo, ours.commit, theirs.commit, c_opts)
+                }
+            }
+        }
+    }
+}
+
+/// Apply the changes of `commit` onto `ours` in memory, like `git cherry-pick`, and
+/// return the resulting index.
+///
+/// For a merge commit, `mainline` is the number of the parent, starting at 1, whose
+/// changes are picked; it is 0 for other commits.
+pub fn cherrypick_commit<'r>(repo: &'r Repository, commit: &Commit, ours: &Commit,
+                             mainline: uint, opts: &MergeOption)
+    -> Result<~GitIndex<'r>, (~str, GitError)>
+{
+    do merged_index(repo) |out| {
+        do with_merge_opts(opts) |c_opts| {
+            do trace::timed("git_cherrypick_commit") {
+                unsafe {
+                    ext::git_cherrypick_commit(out, repo.repo, commit.commit, ours.commit,
+                                                mainline as c_uint, c_opts)
+                }
+            }
+        }
+    }
+}
+
+/// Undo the changes of `commit` on top of `ours` in memory, like `git revert`, and
+/// return the resulting index.
+///
+/// `mainline` is used like in `cherrypick_commit`.
+pub fn revert_commit<'r>(repo: &'r Repository, commit: &Commit, ours: &Commit,
+                         mainline: uint, opts: &MergeOption)
+    -> Result<~GitIndex<'r>, (~str, GitError)>
+{
+    do merged_index(repo) |out| {
+        do with_merge_opts(opts) |c_opts| {
+            do trace::timed("git_revert_commit") {
+                unsafe {
+                    ext::git_revert_commit(out, repo.repo, commit.commit, ours.commit,
+                                            mainline as c_uint, c_opts)
+                }
+            }
+        }
+    }
+}
+
+/// Merge `their_heads` into HEAD in the index and the working directory, like
+/// `git merge --no-commit`, and record the merge in MERGE_HEAD and MERGE_MSG.
+///
+/// The conflicts are left in the index and, with markers, in the working directory:
+/// check `GitIndex::has_conflicts`. Once they are resolved and the index written,
+/// a commit with HEAD and the `merge_heads` as parents, followed by `cleanup_state`,
+/// completes the merge, like `git commit` after a conflicted `git merge`.
+/// The checkout fails instead of overwriting local changes unless `checkout_opts`
+/// allows it.
+pub fn merge(repo: &Repository, their_heads: &[~AnnotatedCommit], opts: &MergeOption,
+             checkout_opts: &checkout::CheckoutOption) -> Result<(), (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let heads = do their_heads.map |head| { head.commit };
+    let mut checkout_opts = copy *checkout_opts;
+    let has_style = do checkout_opts.strategy.iter().any |&s| {
+        s as int == checkout::GIT_CHECKOUT_CONFLICT_STYLE_MERGE as int ||
+            s as int == checkout::GIT_CHECKOUT_CONFLICT_STYLE_DIFF3 as int
+    };
+    if !has_style {
+        checkout_opts.strategy.push(match opts.conflict_style {
+            ConflictMerge => checkout::GIT_CHECKOUT_CONFLICT_STYLE_MERGE,
+            ConflictDiff3 => checkout::GIT_CHECKOUT_CONFLICT_STYLE_DIFF3,
+        });
+    }
+    let res = do with_merge_opts(opts) |c_opts| {
+        do checkout::with_c_opts(&checkout_opts) |c_checkout_opts| {
+            do trace::timed("git_merge") {
+                unsafe {
+                    ext::git_merge(repo.repo, vec::raw::to_ptr(heads), heads.len() as size_t,
+                                   c_opts, c_checkout_opts)
+                }
+            }
+        }
+    };
+    if res == 0 {
+        repo.notify_index_written();
+        Ok(())
+    } else {
+        unsafe { Err( last_error() ) }
+    }
+}
+
+/// The commits being merged, read from MERGE_HEAD; empty when no merge is in progress
+pub fn merge_heads(repo: &Repository) -> Result<~[OID], (~str, GitError)>
+{
+    // an in-memory repository has no merge in progress
+    let path = match repo.path() {
+        Some(path) => Path(path).push("MERGE_HEAD"),
+        None => return Ok(~[]),
+    };
+    if !os::path_exists(&path) {
+        return Ok(~[]);
+    }
+    let content = match io::read_whole_file_str(&path) {
+        Ok(content) => content,
+        Err(msg) => return Err( (msg, GITERR_OS) ),
+    };
+    let mut heads = ~[];
+    for content.line_iter().advance |line| {
+        match FromStr::from_str(line.trim()) {
+            Some(id) => heads.push(id),
+            None => return Err( (fmt!("invalid MERGE_HEAD line: %s", line), GITERR_REPOSITORY) ),
+        }
+    }
+    Ok(heads)
+}
+
+/// The message prepared for the merge commit, read from MERGE_MSG, if any
+pub fn merge_message(repo: &Repository) -> Option<~str>
+{
+    let path = match repo.path() {
+        Some(path) => Path(path).push("MERGE_MSG"),
+        None => return None,
+    };
+    match io::read_whole_file_str(&path) {
+        Ok(message) => Some(message),
+        Err(_) => None,
+    }
+}
+
+/// Remove the files recording an operation in progress, like MERGE_HEAD and MERGE_MSG
+/// for a merge, once it is completed or aborted
+pub fn cleanup_state(repo: &Repository) -> Result<(), (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    unsafe {
+        if ext::git_repository_state_cleanup(repo.repo) == 0 {
+            Ok(())
+        } else {
+            Err( last_error() )
+        }
+    }
+}
+
+/// How `pull` integrates the fetched branch
+pub enum PullStrategy {
+    /// only fast-forward; fail if the branches diverged, like `git pull --ff-only`
+    PullFastForwardOnly,
+    /// fast-forward when possible, create a merge commit otherwise
+    PullMerge,
+    /// always create a merge commit, like `git pull --no-ff`
+    PullNoFastForward,
+}
+
+/// What `pull` did
+pub enum PullOutcome {
+    /// the current branch already contains the fetched commit
+    UpToDate,
+    /// the current branch was fast-forwarded to this commit
+    FastForwarded(OID),
+    /// this merge commit was created, and the current branch updated to it
+    Merged(OID),
+    /// the merge has conflicts in these paths; nothing was changed
+    Conflicts(~[~str]),
+}
+
+/// Fetch `branch` from `remote`, and integrate it into the branch HEAD points to,
+/// like `git pull <remote> <branch>`.
+///
+/// The merge is computed in memory: when it has conflicts, the repository is left
+/// untouched and the conflicting paths are reported. Otherwise the working directory
+/// is updated safely, and the update fails instead of overwriting local changes.
+/// The merge follows `opts`, like `merge_commits`.
+/// Errors tell which step of the pull failed.
+pub fn pull(repo: &Repository, remote: &str, branch: &str, strategy: PullStrategy,
+            opts: &MergeOption) -> Result<PullOutcome, StepError>
+{
+    let url = match repo.remote_lookup(remote) {
+        Some(r) => {
+            match at_step(StepFetch, r.fetch([], None)) {
+                Ok(()) => (),
+                Err(e) => return Err(e),
+            }
+            r.url()
+        },
+        None => return at_step(StepFetch, Err( (fmt!("no remote %s", remote), GITERR_NET) )),
+    };
+
+    // the remote-tracking branch the fetch refspecs of the remote map the branch to
+    let specs = match at_step(StepAnalysis, remote::default_fetch_refspecs(repo, remote)) {
+        Ok(specs) => specs,
+        Err(e) => return Err(e),
+    };
+    let remote_ref = ~"refs/heads/" + branch;
+    let mut tracking = None;
+    for specs.iter().advance |spec| {
+        tracking = remote::transform_refspec(*spec, remote_ref);
+        if tracking.is_some() {
+            break;
+        }
+    }
+    let fetched = match tracking.chain(|name| repo.lookup(name)) {
+        Some(r) => r.resolve(),
+        None => return at_step(StepAnalysis,
+                    Err( (fmt!("%s has no branch %s", remote, branch), GITERR_REFERENCE) )),
+    };
+
+    let analysis = match at_step(StepAnalysis, merge_analysis(repo, [fetched])) {
+        Ok(analysis) => analysis,
+        Err(e) => return Err(e),
+    };
+    if analysis.up_to_date {
+        return Ok(UpToDate);
+    }
+    if analysis.unborn {
+        return at_step(StepAnalysis, Err( (~"HEAD is unborn", GITERR_REFERENCE) ));
+    }
+    let head = match at_step(StepAnalysis, repo.try_head()) {
+        Ok(Some(head)) => head,
+        Ok(None) => return at_step(StepAnalysis, Err( (~"HEAD is unborn", GITERR_REFERENCE) )),
+        Err(e) => return Err(e),
+    };
+    let current = head.resolve();
+
+    let can_ff = analysis.fast_forward;
+    match strategy {
+        PullFastForwardOnly if !can_ff =>
+            return at_step(StepAnalysis, Err( (~"not possible to fast-forward", GITERR_MERGE) )),
+        PullFastForwardOnly | PullMerge if can_ff => {
+            let msg = reflog::pull_fast_forward_message();
+            let res = match fast_forward_logged(repo, head.name(), &fetched, msg) {
+                Ok(_) => Ok(FastForwarded(fetched)),
+                Err(NonFastForward) => Err( (~"not possible to fast-forward", GITERR_MERGE) ),
+                Err(DetachedHead) => Err( (~"HEAD is detached", GITERR_REFERENCE) ),
+                Err(FastForwardFailed(msg, klass)) => Err( (msg, klass) ),
+            };
+            return at_step(StepUpdateRef, res);
+        },
+        _ => (),
+    }
+
+    let ours = match repo.lookup_commit(&current) {
+        Some(c) => c,
+        None => return at_step(StepMerge,
+                    Err( (fmt!("%s is not a commit", current.to_str()), GITERR_OBJECT) )),
+    };
+    let theirs = match repo.lookup_commit(&fetched) {
+        Some(c) => c,
+        None => return at_step(StepMerge,
+                    Err( (fmt!("%s is not a commit", fetched.to_str()), GITERR_OBJECT) )),
+    };
+
+    let index = match at_step(StepMerge, merge_commits(repo, ours, theirs, opts)) {
+        Ok(index) => index,
+        Err(e) => return Err(e),
+    };
+    let mut tree_id = OID { id: [0, ..20] };
+    unsafe {
+        if ext::git_index_has_conflicts(index.index) == 1 {
+            let mut paths: ~[~str] = ~[];
+            for index.entries().iter().advance |entry| {
+                if entry.stage > 0 && !paths.contains(&entry.path) {
+                    paths.push(copy entry.path);
+                }
+            }
+            return Ok(Conflicts(paths));
+        }
+        if ext::git_index_write_tree_to(&mut tree_id, index.index, repo.repo) != 0 {
+            return at_step(StepMerge, Err( last_error() ));
+        }
+    }
+    let tree = match repo.lookup_tree(&tree_id) {
+        Some(tree) => tree,
+        None => return at_step(StepMerge, Err( (~"cannot read the merged tree", GITERR_TREE) )),
+    };
+
+    let sig = match at_step(StepCommit, repo.default_signature()) {
+        Ok(sig) => sig,
+        Err(e) => return Err(e),
+    };
+    let message = fmt!("Merge branch '%s' of %s\n", branch, url);
+    let merge_id = match at_step(StepCommit, repo.try_commit(None, &sig, &sig, None, message,
+                                                             tree, [ours, theirs])) {
+        Ok(id) => id,
+        Err(e) => return Err(e),
+    };
+
+    let mut opts = checkout::CheckoutOption::new();
+    opts.strategy = ~[checkout::GIT_CHECKOUT_SAFE];
+    let res = unsafe {
+        do checkout::with_c_opts(&opts) |c_opts| {
+            do trace::timed("git_checkout_tree") {
+                ext::git_checkout_tree(repo.repo, tree.tree as *ext::git_object, c_opts)
+            }
+        }
+    };
+    if res != 0 {
+        return at_step(StepCheckout, unsafe { Err( last_error() ) });
+    }
+    let msg = reflog::pull_merge_message("recursive");
+    match at_step(StepUpdateRef, head.set_target(&merge_id, Some(msg.as_slice()))) {
+        Ok(_) => Ok(Merged(merge_id)),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,155 @@
+use std::libc::c_int;
+use std::ptr;
+use std::str::raw::from_c_str;
+use extra::sort::quick_sort3;
+use super::*;
+use signature;
+use ext;
+
+/// The notes reference used when none is given, unless `core.notesRef` is set
+pub static DEFAULT_NOTES_REF: &'static str = "refs/notes/commits";
+
+/// A note attached to an object
+pub struct Note {
+    /// full name of the notes reference holding the note, e.g. "refs/notes/commits"
+    notes_ref: ~str,
+    message: ~str,
+    /// id of the blob holding the message
+    id: OID,
+}
+
+/// Read the note attached to `target` in `notes_ref`, or in the default notes reference
+/// if it is None.
+///
+/// Returns None if the object has no note there.
+pub fn read(repo: &Repository, notes_ref: Option<&str>, target: &OID) -> Option<Note>
+{
+    let name = match notes_ref {
+        Some(name) => name.to_owned(),
+        None => default_ref(repo),
+    };
+    do name.as_c_str |c_ref| {
+        unsafe {
+            let mut note: *ext::git_note = ptr::null();
+            match ext::git_note_read(&mut note, repo.repo, c_ref, target) {
+                0 => {
+                    let res = Note {
+                        notes_ref: copy name,
+                        message: from_c_str(ext::git_note_message(note)),
+                        id: *ext::git_note_id(note),
+                    };
+                    ext::git_note_free(note);
+                    Some(res)
+                },
+                ext::GIT_ENOTFOUND => None,
+                _ => { raise(); None },
+            }
+        }
+    }
+}
+
+/// Attach a note with `message` to any object `target`, like `git notes add`.
+///
+/// The note is committed to `notes_ref`, or to the default notes reference if it is None.
+/// Fails if the object already has a note there, unless `force` is true.
+/// Returns the id of the note.
+pub fn create(repo: &Repository, notes_ref: Option<&str>, author: &Signature,
+              committer: &Signature, target: &OID, message: &str, force: bool)
+    -> Result<OID, (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let c_author = signature::to_c_sig(author);
+    let c_committer = signature::to_c_sig(committer);
+    let c_ref = match notes_ref {
+        None => ptr::null(),
+        Some(name) => name.as_c_str(|ptr| {ptr}),
+    };
+    do message.as_c_str |c_message| {
+        unsafe {
+            let mut oid = OID { id: [0, .. 20] };
+            if ext::git_note_create(&mut oid, repo.repo, c_ref, &c_author, &c_committer,
+                                    target, c_message, force as c_int) == 0 {
+                Ok(oid)
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+}
+
+/// Remove the note attached to `target` in `notes_ref`, or in the default notes
+/// reference if it is None.
+///
+/// Returns false if the object had no note there.
+pub fn remove(repo: &Repository, notes_ref: Option<&str>, author: &Signature,
+              committer: &Signature, target: &OID) -> bool
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => { git_error::cond.raise(e); return false; },
+    }
+    let c_author = signature::to_c_sig(author);
+    let c_committer = signature::to_c_sig(committer);
+    let c_ref = match notes_ref {
+        None => ptr::null(),
+        Some(name) => name.as_c_str(|ptr| {ptr}),
+    };
+    unsafe {
+        match ext::git_note_remove(repo.repo, c_ref, &c_author, &c_committer, target) {
+            0 => true,
+            ext::GIT_ENOTFOUND => false,
+            _ => { raise(); false },
+        }
+    }
+}
+
+/// The notes references of the repository matching the shell glob `glob`, e.g.
+/// "refs/notes/review/*", sorted by name.
+pub fn notes_refs(repo: &Repository, glob: &str) -> ~[~str]
+{
+    let mut names = ~[];
+    do glob.as_c_str |c_glob| {
+        for repo.reference_names().iter().advance |name| {
+            if !name.starts_with("refs/notes/") {
+                loop;
+            }
+            let matched = do name.as_c_str |c_name| {
+                unsafe { ext::fnmatch(c_glob, c_name, 0) == 0 }
+            };
+            if matched {
+                names.push(copy *name);
+            }
+        }
+    }
+    quick_sort3(names);
+    names
+}
+
+/// All the notes attached to `target` in the notes references matching `glob`,
+/// sorted by notes reference.
+///
+/// Several tools can keep their notes in separate references, e.g.
+/// "refs/notes/ci" and "refs/notes/review"; "refs/notes/*" reads them all at once.
+pub fn notes_for(repo: &Repository, target: &OID, glob: &str) -> ~[Note]
+{
+    let mut notes = ~[];
+    for notes_refs(repo, glob).iter().advance |name| {
+        match read(repo, Some(name.as_slice()), target) {
+            Some(note) => notes.push(note),
+            None => (),
+        }
+    }
+    notes
+}
+
+/// `core.notesRef`, or "refs/notes/commits"
+fn default_ref(repo: &Repository) -> ~str
+{
+    match repo.config() {
+        Ok(cfg) => cfg.get_str("core.notesRef").get_or_default(DEFAULT_NOTES_REF.to_owned()),
+        Err(_) => DEFAULT_NOTES_REF.to_owned(),
+    }
+}
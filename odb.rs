@@ -0,0 +1,824 @@
+use std::libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::{io, os, ptr, cast, sys, uint};
+use std::vec::raw::buf_as_slice;
+use std::vec;
+use std::vec::as_imm_buf;
+use super::*;
+use ext;
+use trace;
+
+/// priority of the mempack backend; higher than any default backend,
+/// so that every write lands in memory first
+static MEMPACK_PRIORITY: c_int = 1000;
+
+/// priority of a loose backend added by `add_loose_backend`; higher than the default
+/// backends, but lower than a mempack
+static LOOSE_WRITE_PRIORITY: c_int = 100;
+
+/// priority of the packs in the default object database
+static PACK_PRIORITY: c_int = 2;
+
+/// Where an object is stored in the object database
+pub enum ObjectLocation {
+    /// the object is stored in this loose object file
+    Loose(~str),
+    /// the object is stored in this packfile, at this offset
+    Packed(~str, u64),
+}
+
+#[inline]
+fn be32(data: &[u8], pos: uint) -> u32 {
+    (data[pos] as u32 << 24) | (data[pos + 1] as u32 << 16) |
+        (data[pos + 2] as u32 << 8) | (data[pos + 3] as u32)
+}
+
+/// Look up an object in a pack index, version 1 or 2, and return its offset in the pack
+pub fn pack_index_offset(idx: &[u8], id: &OID) -> Option<u64> {
+    let key = id.id.map(|&b| b as u8);
+    // version 2 starts with "\377tOc" and the version; version 1 directly with the fan-out
+    if idx.len() >= 8 && idx[0] == 0xff && idx[1] == ('t' as u8) && idx[2] == ('O' as u8) &&
+            idx[3] == ('c' as u8) {
+        if be32(idx, 4) != 2 {
+            return None;
+        }
+        pack_index_v2_offset(idx, key)
+    } else {
+        pack_index_v1_offset(idx, key)
+    }
+}
+
+/// Search `key` in `count` sorted names of 20 bytes, starting at `names` and `stride`
+/// bytes apart, between the bounds given by the fan-out table at `fanout`; returns
+/// the position of the name
+fn pack_index_find(idx: &[u8], fanout: uint, names: uint, stride: uint, key: &[u8])
+    -> Option<uint>
+{
+    let first = key[0] as uint;
+    let mut lo = if first == 0 { 0 } else { be32(idx, fanout + (first - 1) * 4) as uint };
+    let mut hi = be32(idx, fanout + first * 4) as uint;
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let name = idx.slice(names + mid * stride, names + mid * stride + 20);
+        match name.cmp(&key) {
+            Less => lo = mid + 1,
+            Greater => hi = mid,
+            Equal => return Some(mid),
+        }
+    }
+    None
+}
+
+/// version 1: the fan-out, then a 4-byte offset and the name of each object
+fn pack_index_v1_offset(idx: &[u8], key: &[u8]) -> Option<u64> {
+    let fanout = 0;
+    if idx.len() < fanout + 256 * 4 {
+        return None;
+    }
+    let count = be32(idx, fanout + 255 * 4) as uint;
+    let entries = fanout + 256 * 4;
+    if idx.len() < entries + count * 24 {
+        return None;
+    }
+    match pack_index_find(idx, fanout, entries + 4, 24, key) {
+        Some(pos) => Some(be32(idx, entries + pos * 24) as u64),
+        None => None,
+    }
+}
+
+/// version 2: the header and fan-out, then the names, CRCs and 4-byte offsets of the
+/// objects, and the 8-byte offsets of the objects beyond 2GB
+fn pack_index_v2_offset(idx: &[u8], key: &[u8]) -> Option<u64> {
+    let fanout = 8;
+    if idx.len() < fanout + 256 * 4 {
+        return None;
+    }
+    let count = be32(idx, fanout + 255 * 4) as uint;
+    let names = fanout + 256 * 4;
+    let offsets = names + count * 20 + count * 4;
+    let large_offsets = offsets + count * 4;
+    if idx.len() < large_offsets {
+        return None;
+    }
+    let pos = match pack_index_find(idx, fanout, names, 20, key) {
+        Some(pos) => pos,
+        None => return None,
+    };
+    let offset = be32(idx, offsets + pos * 4);
+    if offset & 0x80000000 == 0 {
+        return Some(offset as u64);
+    }
+    let large = large_offsets + ((offset & 0x7fffffff) as uint) * 8;
+    if idx.len() < large + 8 {
+        return None;
+    }
+    Some(((be32(idx, large) as u64) << 32) | (be32(idx, large + 4) as u64))
+}
+
+/// Compute the id an object with this content and type would have in a database,
+/// without writing it, like `git hash-object`.
+pub fn hash(data: &[u8], otype: OType) -> Result<OID, (~str, GitError)>
+{
+    let mut oid = OID { id: [0, ..20] };
+    do as_imm_buf(data) |v, len| {
+        unsafe {
+            let buf: *c_void = cast::transmute(v);
+            if ext::git_odb_hash(&mut oid, buf, len as size_t, otype) == 0 {
+                Ok(oid)
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+}
+
+impl<'self> Odb<'self> {
+    /// Find where an object is stored: in a loose object file, or in a packfile.
+    ///
+    /// Only the objects directory of the repository is searched;
+    /// objects of alternate databases are not located.
+    /// Returns None if the object is not found, and for an in-memory repository.
+    pub fn locate(&self, id: &OID) -> Option<ObjectLocation>
+    {
+        let objects = match self.owner.path() {
+            Some(path) => Path(path).push("objects"),
+            None => return None,
+        };
+        let hex = id.to_str();
+        let loose = objects.push(hex.slice(0, 2)).push(hex.slice_from(2));
+        if os::path_exists(&loose) {
+            return Some(Loose(loose.to_str()));
+        }
+
+        let pack_dir = objects.push("pack");
+        for os::list_dir(&pack_dir).iter().advance |name| {
+            if !name.ends_with(".idx") {
+                loop;
+            }
+            let idx_path = pack_dir.push(*name);
+            match io::read_whole_file(&idx_path) {
+                Ok(idx) => match pack_index_offset(idx, id) {
+                    Some(offset) => {
+                        let pack = pack_dir.push(name.slice(0, name.len() - 4) + ".pack");
+                        return Some(Packed(pack.to_str(), offset));
+                    },
+                    None => (),
+                },
+                Err(_) => (),
+            }
+        }
+        None
+    }
+
+    /// Read an object from the database.
+    ///
+    /// This method queries all available ODB backends
+    /// trying to read the given OID.
+    pub fn read(&self, id: &OID) -> Result<~OdbObject, (~str, GitError)>
+    {
+        unsafe {
+            let mut obj: *ext::git_odb_object = ptr::null();
+            if ext::git_odb_read(&mut obj, self.odb, id) == 0 {
+                Ok( ~OdbObject { obj: obj } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Open a stream to read the content of an object, without loading it in memory.
+    ///
+    /// Returns the reader, along with the size and the type of the object.
+    /// Loose objects are streamed by libgit2, and undeltified packed objects, which
+    /// include the blobs larger than `core.bigFileThreshold`, are inflated straight
+    /// from their packfile. Deltified objects and objects of other backends are read
+    /// whole and then served from memory.
+    pub fn reader(&self, id: &OID) -> Result<(~OdbReader<'self>, uint, OType), (~str, GitError)>
+    {
+        unsafe {
+            let mut stream: *ext::git_odb_stream = ptr::null();
+            let mut len: size_t = 0;
+            let mut otype = GIT_OBJ_ANY;
+            if ext::git_odb_open_rstream(&mut stream, &mut len, &mut otype, self.odb, id) == 0 {
+                let reader = ~OdbReader {
+                    stream: stream,
+                    obj: None,
+                    pack: None,
+                    size: len as uint,
+                    pos: @mut 0,
+                    owner: self.owner,
+                };
+                return Ok( (reader, len as uint, otype) );
+            }
+        }
+        match self.locate(id) {
+            Some(Packed(path, offset)) => match open_pack_stream(path, offset) {
+                Some((pack, size, otype)) => {
+                    let reader = ~OdbReader {
+                        stream: ptr::null(),
+                        obj: None,
+                        pack: Some(pack),
+                        size: size,
+                        pos: @mut 0,
+                        owner: self.owner,
+                    };
+                    return Ok( (reader, size, otype) );
+                },
+                None => (),
+            },
+            _ => (),
+        }
+        match self.read(id) {
+            Ok(obj) => {
+                let size = obj.size();
+                let otype = obj.otype();
+                let reader = ~OdbReader {
+                    stream: ptr::null(),
+                    obj: Some(obj),
+                    pack: None,
+                    size: size,
+                    pos: @mut 0,
+                    owner: self.owner,
+                };
+                Ok( (reader, size, otype) )
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read the size and type of an object without reading its content.
+    ///
+    /// For packed deltified objects, the delta chain may still have to be read.
+    pub fn read_header(&self, id: &OID) -> Result<(uint, OType), (~str, GitError)>
+    {
+        let mut len: size_t = 0;
+        let mut otype = GIT_OBJ_ANY;
+        unsafe {
+            if ext::git_odb_read_header(&mut len, &mut otype, self.odb, id) == 0 {
+                Ok( (len as uint, otype) )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Determine if the given object can be found in the object database.
+    pub fn exists(&self, id: &OID) -> bool
+    {
+        unsafe {
+            ext::git_odb_exists(self.odb, id) as bool
+        }
+    }
+
+    /// Rescan the object database for packfiles written since it was opened, or
+    /// last refreshed, e.g. by another process running `git gc` or receiving a push.
+    ///
+    /// The reads, lookups and existence checks of objects already refresh the database
+    /// once when they miss, so this is only needed to pick up new packs eagerly.
+    pub fn refresh(&self) -> Result<(), (~str, GitError)>
+    {
+        unsafe {
+            if ext::git_odb_refresh(self.odb) == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Check whether the first `len` hexadecimal digits of `id` identify a single object.
+    ///
+    /// Returns Ok(true) if exactly one object of the database starts with this prefix,
+    /// Ok(false) if several do.
+    pub fn is_unique_prefix(&self, id: &OID, len: uint) -> Result<bool, (~str, GitError)>
+    {
+        unsafe {
+            let mut found = OID { id: [0, ..20] };
+            match ext::git_odb_exists_prefix(&mut found, self.odb, id, len as size_t) {
+                0 => Ok(true),
+                ext::GIT_EAMBIGUOUS => Ok(false),
+                _ => Err( last_error() ),
+            }
+        }
+    }
+
+    /// Write an object directly into the ODB
+    ///
+    /// This method writes a full object straight into the ODB.
+    /// The object is written to the backend with the highest priority
+    /// which supports writing; if a Mempack was added, this is the Mempack.
+    pub fn write(&self, data: &[u8], otype: OType) -> Result<OID, (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let mut oid = OID { id: [0, ..20] };
+        do as_imm_buf(data) |v, len| {
+            unsafe {
+                let buf: *c_void = cast::transmute(v);
+                if ext::git_odb_write(&mut oid, self.odb, buf, len as size_t, otype) == 0 {
+                    Ok(oid)
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// List all objects available in the database
+    ///
+    /// The callback will be called for each object available in the
+    /// database. Note that the objects are likely to be returned in the index
+    /// order, which would make accessing the objects in that order inefficient.
+    /// If the callback returns false, this function will stop looping
+    ///
+    /// return values:
+    ///   true: the loop finished successfully
+    ///   false: the callback returned false
+    /// raises git_error on other errors
+    pub fn foreach(&self, op: &fn(id: &OID) -> bool) -> bool
+    {
+        unsafe {
+            let payload: *c_void = cast::transmute(&op);
+            let res = do trace::timed("git_odb_foreach") {
+                ext::git_odb_foreach(self.odb, git_odb_foreach_cb, payload)
+            };
+            if res == 0 {
+                true
+            } else if res > 0 {
+                // the callback stopped the loop
+                false
+            } else {
+                raise();
+                false
+            }
+        }
+    }
+
+    /// Add an in-memory backend to the database.
+    ///
+    /// All objects written to the repository after this call will be kept in memory
+    /// until the Mempack is flushed or reset. This allows to write a set of objects
+    /// and then either store all of them as a single pack, or drop all of them.
+    pub fn add_mempack(&self) -> Result<~Mempack<'self>, (~str, GitError)>
+    {
+        unsafe {
+            let mut backend: *ext::git_odb_backend = ptr::null();
+            if ext::git_mempack_new(&mut backend) != 0 {
+                return Err( last_error() );
+            }
+            if ext::git_odb_add_backend(self.odb, backend, MEMPACK_PRIORITY) == 0 {
+                Ok( ~Mempack { backend: backend, owner: self.owner } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Add a backend writing loose objects compressed with the zlib level
+    /// `compression_level`: from 0 (no compression) to 9 (best compression),
+    /// or -1 for the zlib default.
+    ///
+    /// Every object written to the repository after this call goes through this
+    /// backend; a low level trades disk space for speed when importing many objects.
+    /// The objects are read as before. Each call adds another backend with the same
+    /// priority, and which one gets the writes is then unspecified:
+    /// `Repository::set_compression_level` replaces the database instead.
+    pub fn add_loose_backend(&self, compression_level: int) -> Result<(), (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        if compression_level < -1 || compression_level > 9 {
+            return Err( (fmt!("invalid zlib compression level: %d", compression_level),
+                         GITERR_INVALID) );
+        }
+        let objects_dir = match self.owner.disk_path() {
+            Ok(path) => Path(path).push("objects").to_str(),
+            Err(e) => return Err(e),
+        };
+        unsafe {
+            let mut backend: *ext::git_odb_backend = ptr::null();
+            let res = do objects_dir.as_c_str |c_dir| {
+                ext::git_odb_backend_loose(&mut backend, c_dir, compression_level as c_int,
+                                           0, 0, 0)
+            };
+            if res != 0 {
+                return Err( last_error() );
+            }
+            if ext::git_odb_add_backend(self.odb, backend, LOOSE_WRITE_PRIORITY) == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Add a backend reading the packs of the objects directory `objects_dir`,
+    /// like the one of the default object database.
+    pub fn add_pack_backend(&self, objects_dir: &str) -> Result<(), (~str, GitError)>
+    {
+        unsafe {
+            let mut backend: *ext::git_odb_backend = ptr::null();
+            let res = do objects_dir.as_c_str |c_dir| {
+                ext::git_odb_backend_pack(&mut backend, c_dir)
+            };
+            if res != 0 {
+                return Err( last_error() );
+            }
+            if ext::git_odb_add_backend(self.odb, backend, PACK_PRIORITY) == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Read the objects of the objects directory `path` as well, loose or packed,
+    /// like an entry of objects/info/alternates: nothing is written there.
+    pub fn add_disk_alternate(&self, path: &str) -> Result<(), (~str, GitError)>
+    {
+        unsafe {
+            let res = do path.as_c_str |c_path| {
+                ext::git_odb_add_disk_alternate(self.odb, c_path)
+            };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+}
+
+extern fn git_odb_foreach_cb(id: *OID, payload: *c_void) -> c_int
+{
+    unsafe {
+        let op_ptr: *&fn(&OID) -> bool = cast::transmute(payload);
+        let op = *op_ptr;
+        if op(&*id) {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for Odb<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_odb_free(self.odb);
+        }
+    }
+}
+
+impl OdbObject {
+    /// Return the OID of an ODB object
+    pub fn id<'r>(&self) -> &'r OID
+    {
+        unsafe {
+            cast::transmute(ext::git_odb_object_id(self.obj))
+        }
+    }
+
+    /// Return the size of an ODB object
+    ///
+    /// This is the real size of the `data` buffer, not the
+    /// actual size of the object.
+    pub fn size(&self) -> uint
+    {
+        unsafe {
+            ext::git_odb_object_size(self.obj) as uint
+        }
+    }
+
+    /// Return the type of an ODB object
+    pub fn otype(&self) -> OType
+    {
+        unsafe {
+            ext::git_odb_object_type(self.obj)
+        }
+    }
+
+    /// Get a read-only buffer with the uncompressed data of an ODB object.
+    ///
+    /// A reference to the data is transferred to closure
+    pub fn data_as_slice<T>(&self, f: &fn(v: &[u8]) -> T) -> T
+    {
+        unsafe {
+            let ptr: *u8 = cast::transmute(ext::git_odb_object_data(self.obj));
+            let size = ext::git_odb_object_size(self.obj);
+            buf_as_slice(ptr, size as uint, f)
+        }
+    }
+}
+
+impl Drop for OdbObject {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_odb_object_free(self.obj);
+        }
+    }
+}
+
+impl<'self> Reader for OdbReader<'self> {
+    /// raises git_error if the stream fails
+    fn read(&self, bytes: &mut [u8], len: uint) -> uint
+    {
+        let len = uint::min(uint::min(len, bytes.len()), self.size - *self.pos);
+        if len == 0 {
+            return 0;
+        }
+        let count = match (&self.obj, self.pack) {
+            (&Some(ref obj), _) => do obj.data_as_slice |data| {
+                vec::bytes::copy_memory(bytes, data.slice(*self.pos, *self.pos + len), len);
+                len
+            },
+            (_, Some(pack)) => match pack.inflate(bytes.mut_slice(0, len)) {
+                Ok(count) => count,
+                Err(e) => {
+                    git_error::cond.raise(e);
+                    0
+                },
+            },
+            _ => unsafe {
+                let buf: *mut c_char = cast::transmute(vec::raw::to_mut_ptr(bytes));
+                let res = ext::git_odb_stream_read(self.stream, buf, len as size_t);
+                if res < 0 {
+                    raise();
+                    0
+                } else {
+                    res as uint
+                }
+            },
+        };
+        *self.pos += count;
+        count
+    }
+
+    fn read_byte(&self) -> int
+    {
+        let mut byte = [0u8];
+        if self.read(byte, 1) == 1 {
+            byte[0] as int
+        } else {
+            -1
+        }
+    }
+
+    fn eof(&self) -> bool
+    {
+        *self.pos >= self.size
+    }
+
+    /// Objects read whole can be seeked anywhere, streams only forward.
+    /// raises git_error with GITERR_INVALID if the position cannot be reached
+    fn seek(&self, position: int, style: io::SeekStyle)
+    {
+        let target = match style {
+            io::SeekSet => position,
+            io::SeekCur => *self.pos as int + position,
+            io::SeekEnd => self.size as int + position,
+        };
+        if target < 0 || target as uint > self.size {
+            git_error::cond.raise( (fmt!("cannot seek to %d, outside of the object", target),
+                                    GITERR_INVALID) );
+            return;
+        }
+        let target = target as uint;
+        if self.obj.is_some() {
+            *self.pos = target;
+            return;
+        }
+        if target < *self.pos {
+            git_error::cond.raise( (~"cannot seek backwards in a streamed object",
+                                    GITERR_INVALID) );
+            return;
+        }
+        let mut skipped = [0u8, ..4096];
+        while *self.pos < target {
+            if self.read(skipped, target - *self.pos) == 0 {
+                return;
+            }
+        }
+    }
+
+    fn tell(&self) -> uint
+    {
+        *self.pos
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for OdbReader<'self> {
+    fn finalize(&self) {
+        if self.stream != ptr::null() {
+            unsafe {
+                ext::git_odb_stream_free(self.stream);
+            }
+        }
+    }
+}
+
+/// Open the packed object at `offset` of the packfile `path` for streaming, if it is
+/// stored undeltified; returns it with its size and type.
+fn open_pack_stream(path: &str, offset: u64) -> Option<(@mut PackStream, uint, OType)>
+{
+    let file = match io::file_reader(&Path(path)) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    file.seek(offset as int, io::SeekSet);
+    // type in bits 4-6 of the first byte, then the size in little-endian groups of
+    // 7 bits, 4 of them in the first byte; each byte but the last has bit 7 set
+    let mut byte = file.read_byte();
+    if byte < 0 {
+        return None;
+    }
+    let otype = match (byte >> 4) & 7 {
+        1 => GIT_OBJ_COMMIT,
+        2 => GIT_OBJ_TREE,
+        3 => GIT_OBJ_BLOB,
+        4 => GIT_OBJ_TAG,
+        // OFS_DELTA and REF_DELTA need their base
+        _ => return None,
+    };
+    let mut size = (byte & 15) as uint;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = file.read_byte();
+        if byte < 0 {
+            return None;
+        }
+        size |= ((byte & 0x7f) as uint) << shift;
+        shift += 7;
+    }
+
+    let pack = @mut PackStream {
+        file: file,
+        zs: ext::z_stream {
+            next_in: ptr::null(),
+            avail_in: 0,
+            total_in: 0,
+            next_out: ptr::mut_null(),
+            avail_out: 0,
+            total_out: 0,
+            msg: ptr::null(),
+            state: ptr::null(),
+            zalloc: ptr::null(),
+            zfree: ptr::null(),
+            opaque: ptr::null(),
+            data_type: 0,
+            adler: 0,
+            reserved: 0,
+        },
+        input: vec::from_elem(PACK_READ_SIZE, 0u8),
+    };
+    let res = do ext::ZLIB_VERSION.as_c_str |c_version| {
+        unsafe {
+            ext::inflateInit_(&mut pack.zs, c_version, sys::size_of::<ext::z_stream>() as c_int)
+        }
+    };
+    if res != ext::Z_OK {
+        // inflateEnd must not be called on a stream which was not initialized
+        pack.zs.state = ptr::null();
+        return None;
+    }
+    Some( (pack, size, otype) )
+}
+
+/// how much of a packfile `PackStream` reads at once
+static PACK_READ_SIZE: uint = 64 * 1024;
+
+impl PackStream {
+    /// Inflate into `out`; returns the number of bytes written, which is only 0 at the
+    /// end of the object
+    fn inflate(&mut self, out: &mut [u8]) -> Result<uint, (~str, GitError)>
+    {
+        self.zs.next_out = vec::raw::to_mut_ptr(out);
+        self.zs.avail_out = out.len() as c_uint;
+        while self.zs.avail_out as uint == out.len() {
+            if self.zs.avail_in == 0 {
+                let read = self.file.read(self.input, self.input.len());
+                if read == 0 {
+                    return Err( (~"truncated packfile", GITERR_ZLIB) );
+                }
+                self.zs.next_in = vec::raw::to_ptr(self.input);
+                self.zs.avail_in = read as c_uint;
+            }
+            match unsafe { ext::inflate(&mut self.zs, ext::Z_NO_FLUSH) } {
+                ext::Z_OK => (),
+                ext::Z_STREAM_END => break,
+                res => return Err( (fmt!("cannot inflate the packed object: error %d", res as int),
+                                    GITERR_ZLIB) ),
+            }
+        }
+        Ok( out.len() - self.zs.avail_out as uint )
+    }
+}
+
+impl Drop for PackStream {
+    fn finalize(&self) {
+        if self.zs.state != ptr::null() {
+            unsafe {
+                let zs: *mut ext::z_stream = cast::transmute(&self.zs);
+                ext::inflateEnd(zs);
+            }
+        }
+    }
+}
+
+impl<'self> Mempack<'self> {
+    /// Dump all the objects held in memory as a single packfile
+    pub fn dump(&self) -> Result<~[u8], (~str, GitError)>
+    {
+        unsafe {
+            let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+            if ext::git_mempack_dump(&mut buf, self.owner.repo, self.backend) == 0 {
+                let data = do buf_as_slice(buf.ptr as *u8, buf.size as uint) |v| {
+                    v.to_owned()
+                };
+                ext::git_buf_dispose(&mut buf);
+                Ok(data)
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Write all the objects held in memory to the repository as a single pack,
+    /// and clear the memory backend.
+    ///
+    /// Either all objects are written, or none of them.
+    pub fn flush(&self) -> Result<(), (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let pack_dir = match self.owner.disk_path() {
+            Ok(path) => path + "objects/pack",
+            Err(e) => return Err(e),
+        };
+        let pack = match self.dump() {
+            Ok(pack) => pack,
+            Err(e) => return Err(e),
+        };
+
+        unsafe {
+            let mut odb: *ext::git_odb = ptr::null();
+            if ext::git_repository_odb(&mut odb, self.owner.repo) != 0 {
+                return Err( last_error() );
+            }
+            let mut idx: *ext::git_indexer = ptr::null();
+            let res = do pack_dir.as_c_str |c_dir| {
+                // without options: no progress callback, no connectivity check
+                ext::git_indexer_new(&mut idx, c_dir, 0 as c_uint, odb, ptr::null())
+            };
+            if res != 0 {
+                let err = last_error();
+                ext::git_odb_free(odb);
+                return Err(err);
+            }
+
+            let mut stats = ext::git_transfer_progress {
+                total_objects: 0,
+                indexed_objects: 0,
+                received_objects: 0,
+                local_objects: 0,
+                total_deltas: 0,
+                indexed_deltas: 0,
+                received_bytes: 0,
+            };
+            let res = do as_imm_buf(pack) |v, len| {
+                let data: *c_void = cast::transmute(v);
+                do trace::timed("git_indexer_append") {
+                    if ext::git_indexer_append(idx, data, len as size_t, &mut stats) == 0 {
+                        ext::git_indexer_commit(idx, &mut stats)
+                    } else {
+                        -1
+                    }
+                }
+            };
+            let result = if res == 0 {
+                ext::git_mempack_reset(self.backend);
+                Ok(())
+            } else {
+                Err( last_error() )
+            };
+            ext::git_indexer_free(idx);
+            ext::git_odb_free(odb);
+            result
+        }
+    }
+
+    /// Drop all the objects held in memory, without writing them.
+    pub fn reset(&self)
+    {
+        unsafe {
+            ext::git_mempack_reset(self.backend);
+        }
+    }
+}
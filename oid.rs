@@ -1,9 +1,27 @@
-use std::libc::c_char;
-use std::{from_str, to_str};
-use std::{vec, cast};
+use std::{cast, from_str, to_str, io, str};
+use std::to_bytes::{IterBytes, Cb};
 use super::{OID, raise};
 use ext;
 
+static HEX_DIGITS: &'static str = "0123456789abcdef";
+
+/// id of the empty tree, which exists in every repository for git,
+/// but not necessarily in its object database
+pub static EMPTY_TREE_ID: &'static str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// id of the empty blob
+pub static EMPTY_BLOB_ID: &'static str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// The id of the empty tree
+pub fn empty_tree() -> OID {
+    from_str(EMPTY_TREE_ID)
+}
+
+/// The id of the empty blob
+pub fn empty_blob() -> OID {
+    from_str(EMPTY_BLOB_ID)
+}
+
 fn from_str(s: &str) -> OID {
     unsafe {
         let mut oid = OID { id: [0, .. 20] };
@@ -31,18 +49,34 @@ impl from_str::FromStr for OID {
     }
 }
 
+impl OID {
+    /// Write the 40 hexadecimal digits of the id at the start of `buf`, without
+    /// allocating.
+    ///
+    /// Fails if `buf` is shorter than 40 bytes.
+    pub fn fmt_into(&self, buf: &mut [u8]) {
+        assert!(buf.len() >= 40);
+        for self.id.iter().enumerate().advance |(i, &byte)| {
+            let byte = byte as u8;
+            buf[2 * i] = HEX_DIGITS[(byte >> 4) as uint];
+            buf[2 * i + 1] = HEX_DIGITS[(byte & 0xf) as uint];
+        }
+    }
+
+    /// Write the 40 hexadecimal digits of the id to `writer`, without allocating.
+    pub fn write_hex(&self, writer: &io::Writer) {
+        let mut buf = [0u8, ..40];
+        self.fmt_into(buf);
+        writer.write(buf);
+    }
+}
+
 impl to_str::ToStr for OID {
     fn to_str(&self) -> ~str {
-        let mut v: ~[c_char] = vec::with_capacity(41);
-        unsafe {
-            do vec::as_mut_buf(v) |vbuf, _len| {
-                ext::git_oid_fmt(vbuf, self)
-            };
-            vec::raw::set_len(&mut v, 40);
-            v.push(0);
-
-            return cast::transmute(v);
-        }
+        let mut buf = [0u8, ..40];
+        self.fmt_into(buf);
+        // only ASCII hexadecimal digits
+        unsafe { str::raw::from_bytes(buf) }
     }
 }
 
@@ -87,6 +121,14 @@ impl Ord for OID {
     }
 }
 
+/// Allows OIDs as keys of hash maps and sets
+impl IterBytes for OID {
+    fn iter_bytes(&self, _lsb0: bool, f: Cb) -> bool {
+        let bytes: &[i8] = self.id;
+        f(unsafe { cast::transmute::<&[i8], &[u8]>(bytes) })
+    }
+}
+
 impl TotalOrd for OID {
     fn cmp(&self, other: &OID) -> Ordering {
         let cmp = git_oid_cmp(self, other);
@@ -0,0 +1,144 @@
+use std::libc::c_uint;
+use std::{ptr, cast};
+use super::*;
+use ext;
+use trace;
+
+impl<'self> PackBuilder<'self> {
+    /// Set number of threads to spawn
+    ///
+    /// By default, libgit2 won't spawn any threads at all;
+    /// when set to 0, libgit2 will autodetect the number of
+    /// CPUs.
+    ///
+    /// returns the number of threads which will be used
+    pub fn set_threads(&self, n: uint) -> uint
+    {
+        unsafe {
+            ext::git_packbuilder_set_threads(self.pb, n as c_uint) as uint
+        }
+    }
+
+    /// Insert a single object
+    ///
+    /// For an optimal pack it's mandatory to insert objects in recency order,
+    /// commits followed by trees and blobs.
+    ///
+    /// name: The name; might be None
+    ///
+    /// raises git_error on error
+    pub fn insert(&self, id: &OID, name: Option<&str>)
+    {
+        unsafe {
+            let c_name = match name {
+                None => ptr::null(),
+                Some(n) => n.as_c_str(|ptr| {ptr}),
+            };
+            if ext::git_packbuilder_insert(self.pb, id, c_name) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Insert a root tree object
+    ///
+    /// This will add the tree as well as all referenced trees and blobs.
+    ///
+    /// raises git_error on error
+    pub fn insert_tree(&self, id: &OID)
+    {
+        unsafe {
+            if ext::git_packbuilder_insert_tree(self.pb, id) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Insert a commit object
+    ///
+    /// This will add a commit as well as the completed referenced tree.
+    ///
+    /// raises git_error on error
+    pub fn insert_commit(&self, id: &OID)
+    {
+        unsafe {
+            if ext::git_packbuilder_insert_commit(self.pb, id) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Insert objects as given by the walk
+    ///
+    /// Those commits and all objects they reference will be inserted into
+    /// the packbuilder.
+    ///
+    /// raises git_error on error
+    pub fn insert_walk(&self, walk: &Revwalk)
+    {
+        unsafe {
+            if ext::git_packbuilder_insert_walk(self.pb, walk.walk) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Write the new pack and corresponding index file to path.
+    ///
+    /// path: directory where to store the pack and index files
+    pub fn write(&self, path: &str) -> Result<(), (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        do path.as_c_str |c_path| {
+            unsafe {
+                let res = do trace::timed("git_packbuilder_write") {
+                    ext::git_packbuilder_write(self.pb, c_path, 0, ptr::null(), ptr::null())
+                };
+                if res == 0 {
+                    Ok(())
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// Get the packfile's hash
+    ///
+    /// A packfile's name is derived from the sorted hashing of all object
+    /// names. This is only correct after the packfile has been written.
+    pub fn hash<'r>(&self) -> &'r OID
+    {
+        unsafe {
+            cast::transmute(ext::git_packbuilder_hash(self.pb))
+        }
+    }
+
+    /// Get the total number of objects the packbuilder will write out
+    pub fn object_count(&self) -> uint
+    {
+        unsafe {
+            ext::git_packbuilder_object_count(self.pb) as uint
+        }
+    }
+
+    /// Get the number of objects the packbuilder has already written out
+    pub fn written(&self) -> uint
+    {
+        unsafe {
+            ext::git_packbuilder_written(self.pb) as uint
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for PackBuilder<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_packbuilder_free(self.pb);
+        }
+    }
+}
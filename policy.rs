@@ -0,0 +1,193 @@
+use super::*;
+use diff;
+use regex::Regex;
+use revwalk;
+use ext;
+
+/// A rule checked on the commits of a reference update
+pub enum Check {
+    /// blobs added or modified by a commit must not be larger than this many bytes
+    MaxBlobSize(u64),
+    /// the message of every commit must match this POSIX extended regular expression
+    MessageMatches(~str),
+    /// no path matching this shell glob, e.g. "*.pem" or "secrets/*", may be added
+    /// or modified
+    DisallowedPath(~str),
+}
+
+/// A rule broken by a commit of a reference update
+pub struct Violation {
+    commit: OID,
+    /// the path breaking the rule, for the checks on the changes of the commit
+    path: Option<~str>,
+    message: ~str,
+}
+
+/// A set of checks run on proposed reference updates, like a pre-receive hook
+pub struct UpdateValidator {
+    priv checks: ~[Check],
+}
+
+/// a compiled MessageMatches pattern
+struct Pattern {
+    source: ~str,
+    regex: Regex,
+}
+
+impl Pattern {
+    fn new(source: &str) -> Result<Pattern, (~str, GitError)> {
+        match Regex::new(source) {
+            Ok(regex) => Ok( Pattern { source: source.to_owned(), regex: regex } ),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+fn glob_matches(glob: &str, path: &str) -> bool {
+    do glob.as_c_str |c_glob| {
+        do path.as_c_str |c_path| {
+            unsafe { ext::fnmatch(c_glob, c_path, 0) == 0 }
+        }
+    }
+}
+
+impl UpdateValidator {
+    /// A validator without any check
+    pub fn new() -> UpdateValidator {
+        UpdateValidator { checks: ~[] }
+    }
+
+    /// Register a check; all the checks are run on each commit
+    pub fn add(&mut self, check: Check) {
+        self.checks.push(check);
+    }
+
+    /// Run the checks on the commits an update of a reference from `old` to `new`
+    /// introduces: the commits reachable from `new` but not from `old`, oldest first.
+    /// `old` is None when the reference is created, and every commit reachable from
+    /// `new` is checked then.
+    ///
+    /// The changes of a commit are taken against its first parent. Deleting a
+    /// reference introduces no commit, so there is nothing to validate.
+    /// Returns the violations found, an empty vector if the update is acceptable.
+    pub fn validate(&self, repo: &Repository, old: Option<&OID>, new: &OID)
+        -> Result<~[Violation], (~str, GitError)>
+    {
+        let mut patterns = ~[];
+        for self.checks.iter().advance |check| {
+            match *check {
+                MessageMatches(ref source) => match Pattern::new(*source) {
+                    Ok(pattern) => patterns.push(pattern),
+                    Err(e) => return Err(e),
+                },
+                _ => (),
+            }
+        }
+        let check_changes = do self.checks.iter().any_ |check| {
+            match *check {
+                MessageMatches(_) => false,
+                _ => true,
+            }
+        };
+
+        let mut walk = match repo.revwalk() {
+            Ok(walk) => walk,
+            Err(e) => return Err(e),
+        };
+        walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL, revwalk::GIT_SORT_REVERSE]);
+        walk.push(new);
+        match old {
+            Some(old) => walk.hide(old),
+            None => (),
+        }
+
+        let mut violations = ~[];
+        for walk.advance |oid| {
+            let commit = match repo.lookup_commit(&oid) {
+                Some(commit) => commit,
+                None => return Err( (fmt!("commit %s not found", oid.to_str()),
+                                     GITERR_INVALID) ),
+            };
+            let message = commit.message();
+            for patterns.iter().advance |pattern| {
+                if !pattern.is_match(message) {
+                    violations.push(Violation {
+                        commit: oid,
+                        path: None,
+                        message: fmt!("the message does not match %s", pattern.source),
+                    });
+                }
+            }
+            if !check_changes {
+                loop;
+            }
+
+            let deltas = match self.changes(repo, commit) {
+                Ok(deltas) => deltas,
+                Err(e) => return Err(e),
+            };
+            for deltas.iter().advance |delta| {
+                match delta.status {
+                    GIT_DELTA_DELETED => loop,
+                    _ => (),
+                }
+                let path = &delta.new_file.path;
+                for self.checks.iter().advance |check| {
+                    let problem = match *check {
+                        MaxBlobSize(max) => match repo.object_size(&delta.new_file.oid) {
+                            Ok(size) if size > max => Some(fmt!("blob of %? bytes, over %? bytes",
+                                                                size, max)),
+                            Ok(_) => None,
+                            Err(e) => return Err(e),
+                        },
+                        DisallowedPath(ref glob) => if glob_matches(*glob, *path) {
+                            Some(fmt!("path matches %s", *glob))
+                        } else {
+                            None
+                        },
+                        MessageMatches(_) => None,
+                    };
+                    match problem {
+                        Some(message) => violations.push(Violation {
+                            commit: oid,
+                            path: Some(copy *path),
+                            message: message,
+                        }),
+                        None => (),
+                    }
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// the changes of a commit against its first parent, gitlinks excluded
+    fn changes(&self, repo: &Repository, commit: &Commit)
+        -> Result<~[diff::Delta], (~str, GitError)>
+    {
+        let tree = match commit.tree() {
+            Ok(tree) => tree,
+            Err(e) => return Err(e),
+        };
+        let opts = diff::DiffOption::new();
+        let parents = commit.parents();
+        let difflist = if parents.is_empty() {
+            repo.diff_trees(None, Some(tree), &opts)
+        } else {
+            match parents[0].tree() {
+                Ok(parent_tree) => repo.diff_trees(Some(parent_tree), Some(tree), &opts),
+                Err(e) => Err(e),
+            }
+        };
+        match difflist {
+            Ok(difflist) => Ok( difflist.deltas().consume_iter()
+                                .filter(|d| d.new_file.mode != GIT_FILEMODE_COMMIT as u16)
+                                .collect() ),
+            Err(e) => Err(e),
+        }
+    }
+}
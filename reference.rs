@@ -1,19 +1,157 @@
 use std::libc::{c_char, c_int};
 use std::ptr;
 use std::str::raw::from_c_str;
-use super::{Reference, OID, raise};
+use std::to_str::ToStr;
+use super::{Repository, Reference, OID, OType, GitError, LockError, GIT_OBJ_ANY, raise,
+            last_error, git_error, GITERR_REFERENCE, LockFailed};
 use ext;
+use lock;
+
+/// A reference and what it points to, as listed by `ref_summary`
+pub struct RefInfo {
+    /// full name, e.g. "refs/heads/master"
+    name: ~str,
+    /// name without the namespace, e.g. "master" or "origin/master"
+    shorthand: ~str,
+    /// the object the reference points to; None for symbolic references
+    target: Option<OID>,
+    /// for annotated tags, the object the tag points to
+    peeled: Option<OID>,
+    /// the name of the reference a symbolic reference points to
+    symbolic_target: Option<~str>,
+}
+
+/// All the references of a repository, grouped by namespace
+pub struct RefSummary {
+    heads: ~[RefInfo],
+    tags: ~[RefInfo],
+    remotes: ~[RefInfo],
+    notes: ~[RefInfo],
+    /// references outside of the namespaces above, e.g. "refs/stash"
+    others: ~[RefInfo],
+}
+
+/// The hops followed to resolve a reference, see `Reference::resolve_chain`
+pub struct RefChain {
+    /// the name of the reference, then the names of the references it points to in
+    /// turn, e.g. ["HEAD", "refs/heads/master"]
+    names: ~[~str],
+    /// the object the last reference points to, or None if it does not exist,
+    /// e.g. the branch HEAD points to in an empty repository
+    target: Option<OID>,
+}
+
+impl ToStr for RefChain {
+    /// "HEAD -> refs/heads/master -> <target>"
+    fn to_str(&self) -> ~str {
+        let target = match self.target {
+            Some(ref id) => id.to_str(),
+            None => ~"(unborn)",
+        };
+        self.names.connect(" -> ") + " -> " + target
+    }
+}
+
+/// symbolic references nested deeper than this are considered a loop, like libgit2 does
+static MAX_NESTING_LEVEL: uint = 5;
+
+unsafe fn ref_info(c_ref: *ext::git_reference) -> RefInfo {
+    let name = from_c_str(ext::git_reference_name(c_ref));
+    let shorthand = match ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/notes/"]
+                            .iter().find_(|ns| name.starts_with(**ns)) {
+        Some(ns) => name.slice_from(ns.len()).to_owned(),
+        None => copy name,
+    };
+
+    if ext::git_reference_type(c_ref) == ext::GIT_REF_SYMBOLIC {
+        let symbolic = from_c_str(ext::git_reference_symbolic_target(c_ref));
+        return RefInfo { name: name, shorthand: shorthand, target: None, peeled: None,
+                        symbolic_target: Some(symbolic) };
+    }
+
+    let target_ptr = ext::git_reference_target(c_ref);
+    let target = if target_ptr == ptr::null() { None } else { Some(*target_ptr) };
+    let mut peeled = None;
+    if name.starts_with("refs/tags/") {
+        let mut obj: *ext::git_object = ptr::null();
+        if ext::git_reference_peel(&mut obj, c_ref, GIT_OBJ_ANY) == 0 {
+            let peeled_id = *ext::git_object_id(obj);
+            if Some(peeled_id) != target {
+                peeled = Some(peeled_id);
+            }
+            ext::git_object_free(obj);
+        }
+    }
+    RefInfo { name: name, shorthand: shorthand, target: target, peeled: peeled,
+            symbolic_target: None }
+}
+
+/// List all the references of a repository with their targets, grouped by namespace.
+///
+/// The reference database is read in a single pass.
+/// Each group is in the order of the reference database.
+pub fn ref_summary(repo: &Repository) -> Result<RefSummary, (~str, GitError)> {
+    let mut summary = RefSummary { heads: ~[], tags: ~[], remotes: ~[], notes: ~[],
+                                    others: ~[] };
+    unsafe {
+        let mut iter: *ext::git_reference_iterator = ptr::null();
+        if ext::git_reference_iterator_new(&mut iter, repo.repo) != 0 {
+            return Err( last_error() );
+        }
+        loop {
+            let mut c_ref: *ext::git_reference = ptr::null();
+            match ext::git_reference_next(&mut c_ref, iter) {
+                0 => (),
+                ext::GIT_ITEROVER => break,
+                _ => {
+                    let err = last_error();
+                    ext::git_reference_iterator_free(iter);
+                    return Err(err);
+                },
+            }
+            let info = ref_info(c_ref);
+            ext::git_reference_free(c_ref);
+            if info.name.starts_with("refs/heads/") {
+                summary.heads.push(info);
+            } else if info.name.starts_with("refs/tags/") {
+                summary.tags.push(info);
+            } else if info.name.starts_with("refs/remotes/") {
+                summary.remotes.push(info);
+            } else if info.name.starts_with("refs/notes/") {
+                summary.notes.push(info);
+            } else {
+                summary.others.push(info);
+            }
+        }
+        ext::git_reference_iterator_free(iter);
+    }
+    Ok(summary)
+}
 
 /// Delete the branch reference.
 pub fn branch_delete(reference: &Reference) {
+    match reference.owner.check_writable() {
+        Ok(()) => (),
+        Err(e) => { git_error::cond.raise(e); return; },
+    }
+    let name = reference.name();
     unsafe {
         if ext::git_branch_delete(reference.c_ref) != 0 {
             raise();
+            return;
         }
     }
+    reference.owner.notify_ref_deleted(name);
 }
 
 impl<'self> Reference<'self> {
+    /// Get the full name of a reference, e.g. "refs/heads/master"
+    pub fn name(&self) -> ~str {
+        unsafe {
+            from_c_str(ext::git_reference_name(self.c_ref))
+        }
+    }
+
     ///
     /// Return the name of the given local or remote branch.
     ///
@@ -51,13 +189,23 @@ impl<'self> Reference<'self> {
     /// See `git_tag_create()` for rules about valid names.
     pub fn branch_move(&self, new_branch_name: &str, force: bool) -> Option<Reference<'self>>
     {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return None; },
+        }
         let mut ptr: *ext::git_reference = ptr::null();
         let flag = force as c_int;
+        let old_name = self.name();
         unsafe {
             do new_branch_name.as_c_str |c_name| {
                 let res = ext::git_branch_move(&mut ptr, self.c_ref, c_name, flag);
                 match res {
-                    0 => Some( Reference { c_ref: ptr, owner: self.owner } ),
+                    0 => {
+                        let moved = Reference { c_ref: ptr, owner: self.owner };
+                        self.owner.notify_ref_deleted(old_name);
+                        self.owner.notify_ref_updated(moved.name(), &moved.resolve());
+                        Some(moved)
+                    },
                     ext::GIT_EINVALIDSPEC => None,
                     _ => { raise(); None },
                 }
@@ -85,6 +233,10 @@ impl<'self> Reference<'self> {
     ///     upstream. Pass None to unset.
     pub fn set_upstream(&self, upstream_name: Option<&str>)
     {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return; },
+        }
         let c_name =
         match upstream_name {
             None => ptr::null(),
@@ -118,6 +270,158 @@ impl<'self> Reference<'self> {
             return oid;
         }
     }
+
+    /// Resolve the reference like `resolve`, but return every symbolic hop on the way,
+    /// e.g. HEAD -> refs/heads/master -> <id>.
+    ///
+    /// Fails if the symbolic references form a loop or are nested too deeply.
+    pub fn resolve_chain(&self) -> Result<RefChain, (~str, GitError)> {
+        let mut names = ~[self.name()];
+        let mut target = None;
+        unsafe {
+            let mut c_ref = self.c_ref;
+            // whether c_ref was looked up here, and must be freed
+            let mut owned = false;
+            while ext::git_reference_type(c_ref) == ext::GIT_REF_SYMBOLIC {
+                let next = from_c_str(ext::git_reference_symbolic_target(c_ref));
+                if owned {
+                    ext::git_reference_free(c_ref);
+                    owned = false;
+                }
+                if names.contains(&next) || names.len() > MAX_NESTING_LEVEL {
+                    return Err( (fmt!("symbolic reference loop at %s", next), GITERR_REFERENCE) );
+                }
+                let mut next_ref: *ext::git_reference = ptr::null();
+                let res = do next.as_c_str |c_name| {
+                    ext::git_reference_lookup(&mut next_ref, self.owner.repo, c_name)
+                };
+                names.push(next);
+                match res {
+                    0 => {
+                        c_ref = next_ref;
+                        owned = true;
+                    },
+                    ext::GIT_ENOTFOUND => return Ok( RefChain { names: names, target: None } ),
+                    _ => return Err( last_error() ),
+                }
+            }
+            let target_ptr = ext::git_reference_target(c_ref);
+            if target_ptr != ptr::null() {
+                target = Some(*target_ptr);
+            }
+            if owned {
+                ext::git_reference_free(c_ref);
+            }
+        }
+        Ok( RefChain { names: names, target: target } )
+    }
+
+    /// Make the reference point to another target.
+    ///
+    /// The reference must be a direct reference. `log_message` is written to
+    /// its reflog. Returns the updated reference.
+    pub fn set_target(&self, id: &OID, log_message: Option<&str>)
+        -> Result<~Reference<'self>, (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        unsafe {
+            let mut ptr_to_ref: *ext::git_reference = ptr::null();
+            let c_msg = match log_message {
+                None => ptr::null(),
+                Some(msg) => msg.as_c_str(|ptr| {ptr}),
+            };
+            if ext::git_reference_set_target(&mut ptr_to_ref, self.c_ref, id, c_msg) == 0 {
+                self.owner.notify_ref_updated(self.name(), id);
+                Ok( ~Reference { c_ref: ptr_to_ref, owner: self.owner } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Make the reference point to another target like `set_target`, waiting for the
+    /// lock of the reference held by another process to be released, for at most
+    /// `timeout_ms` milliseconds.
+    ///
+    /// See `lock::retry`.
+    pub fn set_target_locked(&self, id: &OID, log_message: Option<&str>, timeout_ms: uint)
+        -> Result<~Reference<'self>, LockError>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err((msg, klass)) => return Err( LockFailed(msg, klass) ),
+        }
+        let c_msg = match log_message {
+            None => ptr::null(),
+            Some(msg) => msg.as_c_str(|ptr| {ptr}),
+        };
+        do lock::retry(timeout_ms) {
+            unsafe {
+                let mut ptr_to_ref: *ext::git_reference = ptr::null();
+                match ext::git_reference_set_target(&mut ptr_to_ref, self.c_ref, id, c_msg) {
+                    0 => {
+                        self.owner.notify_ref_updated(self.name(), id);
+                        Ok( ~Reference { c_ref: ptr_to_ref, owner: self.owner } )
+                    },
+                    res => Err(lock::error(res)),
+                }
+            }
+        }
+    }
+
+    /// Delete the reference from the repository.
+    ///
+    /// raises git_error on error
+    pub fn delete(&self) {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return; },
+        }
+        let name = self.name();
+        unsafe {
+            if ext::git_reference_delete(self.c_ref) != 0 {
+                raise();
+                return;
+            }
+        }
+        self.owner.notify_ref_deleted(name);
+    }
+
+    /// Recursively peel the reference until an object of the specified type is met,
+    /// and return the id of that object.
+    ///
+    /// With GIT_OBJ_ANY, annotated tags are peeled until a non-tag object is met.
+    /// Returns None if the reference cannot be peeled to the requested type.
+    pub fn peel(&self, otype: OType) -> Option<OID> {
+        unsafe {
+            let mut obj: *ext::git_object = ptr::null();
+            if ext::git_reference_peel(&mut obj, self.c_ref, otype) == 0 {
+                let oid = *ext::git_object_id(obj);
+                ext::git_object_free(obj);
+                Some(oid)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl<'self> ToStr for Reference<'self> {
+    /// "<target> <name>" for a direct reference, like `git show-ref`,
+    /// and "<name> -> <target>" for a symbolic reference
+    fn to_str(&self) -> ~str {
+        unsafe {
+            let info = ref_info(self.c_ref);
+            match (info.symbolic_target, info.target) {
+                (Some(target), _) => fmt!("%s -> %s", info.name, target),
+                (None, Some(target)) => fmt!("%s %s", target.to_str(), info.name),
+                (None, None) => info.name,
+            }
+        }
+    }
 }
 
 #[unsafe_destructor]
@@ -0,0 +1,244 @@
+use std::libc::{c_int, size_t};
+use std::ptr;
+use std::str::raw::from_c_str;
+use super::*;
+use signature;
+use ext;
+
+/// An entry of a reflog
+pub struct ReflogEntry {
+    /// the target of the reference before the change
+    id_old: OID,
+    /// the target of the reference after the change
+    id_new: OID,
+    committer: Signature,
+    message: ~str,
+}
+
+impl<'self> Reflog<'self> {
+    /// Get the number of log entries in a reflog
+    pub fn len(&self) -> uint
+    {
+        unsafe {
+            ext::git_reflog_entrycount(self.reflog) as uint
+        }
+    }
+
+    /// Lookup an entry by its index
+    ///
+    /// Requesting the reflog entry with an index of 0 will return the most
+    /// recently created entry.
+    pub fn entry(&self, idx: uint) -> Option<ReflogEntry>
+    {
+        unsafe {
+            let entry = ext::git_reflog_entry_byindex(self.reflog, idx as size_t);
+            if entry == ptr::null() {
+                return None;
+            }
+            let msg = ext::git_reflog_entry_message(entry);
+            Some(ReflogEntry {
+                id_old: *ext::git_reflog_entry_id_old(entry),
+                id_new: *ext::git_reflog_entry_id_new(entry),
+                committer: signature::from_c_sig(ext::git_reflog_entry_committer(entry)),
+                message: if msg == ptr::null() { ~"" } else { from_c_str(msg) },
+            })
+        }
+    }
+
+    /// Get all the entries of the reflog, most recent first
+    pub fn entries(&self) -> ~[ReflogEntry]
+    {
+        let mut entries = ~[];
+        let mut idx = 0u;
+        while idx < self.len() {
+            match self.entry(idx) {
+                Some(entry) => entries.push(entry),
+                None => break,
+            }
+            idx += 1;
+        }
+        entries
+    }
+
+    /// Remove an entry from the reflog by its index
+    ///
+    /// To ensure there's no gap in the log history, set `rewrite_previous_entry`
+    /// to true. When deleting entry `n`, member old_oid of entry `n-1`
+    /// (if any) will be updated with the value of member new_oid of entry `n+1`.
+    ///
+    /// The change is kept in memory until `write` is called.
+    ///
+    /// raises git_error on error
+    pub fn drop(&self, idx: uint, rewrite_previous_entry: bool)
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return; },
+        }
+        unsafe {
+            if ext::git_reflog_drop(self.reflog, idx as size_t,
+                                    rewrite_previous_entry as c_int) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Write an existing in-memory reflog object back to disk
+    /// using an atomic file lock.
+    pub fn write(&self) -> Result<(), (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        unsafe {
+            if ext::git_reflog_write(self.reflog) == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Remove the entries created before `older_than` (seconds since the epoch),
+    /// like `git reflog expire`.
+    ///
+    /// With `unreachable_only`, only the entries whose target is not an ancestor of
+    /// `tip` are removed, as done for gc.reflogExpireUnreachable.
+    /// The most recent entry is always kept. The change is kept in memory until
+    /// `write` is called.
+    ///
+    /// Returns the number of removed entries.
+    pub fn expire(&self, older_than: i64, unreachable_only: bool, tip: Option<&OID>) -> uint
+    {
+        let mut removed = 0u;
+        let mut idx = self.len();
+        while idx > 1 {
+            idx -= 1;
+            let entry = match self.entry(idx) {
+                Some(entry) => entry,
+                None => loop,
+            };
+            if entry.committer.when.time >= older_than {
+                loop;
+            }
+            if unreachable_only && self.reachable(&entry.id_new, tip) {
+                loop;
+            }
+            self.drop(idx, true);
+            removed += 1;
+        }
+        removed
+    }
+
+    fn reachable(&self, id: &OID, tip: Option<&OID>) -> bool
+    {
+        match tip {
+            Some(tip) if tip == id => true,
+            Some(tip) => unsafe {
+                ext::git_graph_descendant_of(self.owner.repo, tip, id) == 1
+            },
+            None => false,
+        }
+    }
+}
+
+/// Reflog message of a commit made on top of the current branch, like `git commit`.
+///
+/// `summary` is the first line of the commit message. The message builders below
+/// produce the same text as git, so tools parsing reflogs cannot tell them apart;
+/// they are meant for the code which moves references itself, and some of them are
+/// not used by the crate.
+pub fn commit_message(summary: &str) -> ~str {
+    fmt!("commit: %s", summary)
+}
+
+/// Reflog message of the first commit of a branch
+pub fn initial_commit_message(summary: &str) -> ~str {
+    fmt!("commit (initial): %s", summary)
+}
+
+/// Reflog message of a merge commit made by `git commit` after a conflicted merge
+pub fn merge_commit_message(summary: &str) -> ~str {
+    fmt!("commit (merge): %s", summary)
+}
+
+/// Reflog message of `git commit --amend`
+pub fn amend_message(summary: &str) -> ~str {
+    fmt!("commit (amend): %s", summary)
+}
+
+/// Reflog message of a merge of `name` which fast-forwarded the branch
+pub fn fast_forward_message(name: &str) -> ~str {
+    fmt!("merge %s: Fast-forward", name)
+}
+
+/// Reflog message of a merge of `name` which created a merge commit with `strategy`,
+/// e.g. "recursive"
+pub fn merge_message(name: &str, strategy: &str) -> ~str {
+    fmt!("merge %s: Merge made by the '%s' strategy.", name, strategy)
+}
+
+/// Reflog message of a pull which fast-forwarded the branch
+pub fn pull_fast_forward_message() -> ~str {
+    ~"pull: Fast-forward"
+}
+
+/// Reflog message of a pull which created a merge commit with `strategy`
+pub fn pull_merge_message(strategy: &str) -> ~str {
+    fmt!("pull: Merge made by the '%s' strategy.", strategy)
+}
+
+/// Reflog message of HEAD when checking out `to` while on `from`; both are branch
+/// names, or commit ids when HEAD is detached
+pub fn checkout_message(from: &str, to: &str) -> ~str {
+    fmt!("checkout: moving from %s to %s", from, to)
+}
+
+/// Reflog message of a branch created from `start`
+pub fn branch_created_message(start: &str) -> ~str {
+    fmt!("branch: Created from %s", start)
+}
+
+/// Reflog message of `git reset` to `target`
+pub fn reset_message(target: &str) -> ~str {
+    fmt!("reset: moving to %s", target)
+}
+
+/// Expire the reflog of the reference `refname`, and write it back to disk.
+///
+/// The reachability of the entries is computed from the current target of the reference;
+/// see `Reflog::expire`. Returns the number of removed entries.
+pub fn expire(repo: &Repository, refname: &str, older_than: i64, unreachable_only: bool)
+    -> Result<uint, (~str, GitError)>
+{
+    match repo.check_writable() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let tip = match repo.lookup(refname) {
+        Some(reference) => reference.peel(GIT_OBJ_COMMIT),
+        None => None,
+    };
+    let reflog = match repo.reflog(refname) {
+        Ok(reflog) => reflog,
+        Err(e) => return Err(e),
+    };
+    let removed = reflog.expire(older_than, unreachable_only, tip.map(|t| t));
+    if removed == 0 {
+        return Ok(0);
+    }
+    match reflog.write() {
+        Ok(()) => Ok(removed),
+        Err(e) => Err(e),
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for Reflog<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_reflog_free(self.reflog);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::libc::{c_char, size_t};
+use std::{cast, ptr, vec};
+use std::str::raw::from_c_str;
+use super::*;
+use ext;
+
+/// A compiled POSIX extended regular expression, freed when dropped
+pub struct Regex {
+    priv regex: ~ext::regex_t,
+}
+
+impl Regex {
+    /// Compile `pattern`; fails with GITERR_INVALID and the message of the C library
+    /// if it is not a valid extended regular expression
+    pub fn new(pattern: &str) -> Result<Regex, (~str, GitError)> {
+        let mut regex = ~([0u64, ..32]);
+        unsafe {
+            let res = do pattern.as_c_str |c_pattern| {
+                ext::regcomp(&mut *regex, c_pattern, ext::REG_EXTENDED)
+            };
+            if res != 0 {
+                let mut buf = vec::from_elem(256, 0 as c_char);
+                do vec::as_mut_buf(buf) |c_buf, len| {
+                    ext::regerror(res, &*regex, c_buf, len as size_t);
+                }
+                let msg = from_c_str(vec::raw::to_ptr(buf));
+                return Err( (fmt!("invalid pattern %s: %s", pattern, msg), GITERR_INVALID) );
+            }
+        }
+        Ok( Regex { regex: regex } )
+    }
+
+    /// Whether the expression matches somewhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        do text.as_c_str |c_text| {
+            unsafe { self.is_match_c(c_text) }
+        }
+    }
+
+    /// Whether the expression matches somewhere in the C string `text`
+    pub unsafe fn is_match_c(&self, text: *c_char) -> bool {
+        ext::regexec(&*self.regex, text, 0, ptr::null(), 0) == 0
+    }
+}
+
+impl Drop for Regex {
+    fn finalize(&self) {
+        unsafe {
+            ext::regfree(cast::transmute(&*self.regex));
+        }
+    }
+}
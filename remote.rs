@@ -0,0 +1,578 @@
+use std::libc::{c_char, c_int, c_void};
+use std::{cast, ptr};
+use std::str::raw::from_c_str;
+use extra::sort::{merge_sort, quick_sort3};
+use extra::time::precise_time_ns;
+use super::*;
+use trace;
+use ext;
+
+/// Progress of a transfer, passed to the callback of `Remote::fetch_with_progress`
+pub struct TransferProgress {
+    total_objects: uint,
+    indexed_objects: uint,
+    received_objects: uint,
+    /// objects which were already in the repository, and were not downloaded
+    local_objects: uint,
+    total_deltas: uint,
+    indexed_deltas: uint,
+    received_bytes: uint,
+}
+
+impl TransferProgress {
+    /// Percentage of the objects received, from 0 to 100
+    pub fn percent(&self) -> uint {
+        if self.total_objects == 0 {
+            0
+        } else {
+            self.received_objects * 100 / self.total_objects
+        }
+    }
+
+    /// Check if all the objects are received and indexed, with their deltas;
+    /// never true while the number of objects is not known yet
+    pub fn is_done(&self) -> bool {
+        self.total_objects > 0 &&
+            self.received_objects == self.total_objects &&
+            self.indexed_objects == self.total_objects &&
+            self.indexed_deltas == self.total_deltas
+    }
+}
+
+/// Limits how often the progress callback of a transfer is called
+///
+/// On fast networks, libgit2 reports the progress for every few kilobytes received;
+/// handling every report can take more time than the transfer itself.
+/// The callback is called once `interval_ms` elapsed or the progress grew by
+/// `percent` since the last call, whichever comes first; a limit of 0 is not used.
+/// While the number of objects is not known yet, only `interval_ms` applies.
+pub struct ProgressThrottle {
+    /// minimum delay between two calls, in milliseconds
+    interval_ms: uint,
+    /// minimum progress between two calls, in percent of the objects
+    percent: uint,
+}
+
+impl ProgressThrottle {
+    /// Call the callback on every report
+    pub fn new() -> ProgressThrottle {
+        ProgressThrottle {
+            interval_ms: 0,
+            percent: 0,
+        }
+    }
+
+    /// Whether a report of `progress` is passed on, `elapsed_ms` after the last call,
+    /// which was made at `last_percent`
+    pub fn is_due(&self, elapsed_ms: uint, last_percent: uint, progress: &TransferProgress)
+        -> bool
+    {
+        let by_interval = elapsed_ms >= self.interval_ms;
+        if self.percent == 0 || progress.total_objects == 0 {
+            return by_interval;
+        }
+        let by_percent = progress.percent() >= last_percent + self.percent;
+        (self.interval_ms > 0 && by_interval) || by_percent
+    }
+}
+
+/// Statistics of a finished transfer
+pub struct TransferSummary {
+    total_objects: uint,
+    received_objects: uint,
+    received_bytes: uint,
+    /// duration of the transfer, in milliseconds
+    elapsed_ms: uint,
+    /// average throughput, in bytes per second
+    throughput: uint,
+}
+
+/// Which tags a fetch downloads, besides the ones matching its refspecs
+pub enum DownloadTags {
+    /// the setting of the remote (`remote.<name>.tagOpt`), auto by default
+    DownloadTagsDefault,
+    /// the tags pointing to the objects downloaded by the fetch, like `git fetch`
+    DownloadTagsAuto,
+    /// no tags, like `git fetch --no-tags`
+    DownloadTagsNone,
+    /// all the tags of the remote, like `git fetch --tags`
+    DownloadTagsAll,
+}
+
+/// A tag created or moved by a fetch
+pub struct TagUpdate {
+    /// full name of the tag, e.g. "refs/tags/v1.0"
+    name: ~str,
+    /// the previous target, or None if the tag was created
+    old: Option<OID>,
+    new: OID,
+}
+
+struct ProgressState<'self> {
+    op: &'self fn(&TransferProgress) -> bool,
+    throttle: &'self ProgressThrottle,
+    last_call_ns: u64,
+    last_percent: uint,
+    last: Option<TransferProgress>,
+}
+
+/// Split the shorthand name of a remote-tracking branch, e.g. "origin/feature/x",
+/// into the name of the remote and the name of the branch on the remote.
+///
+/// The remote is the longest of `remotes` which is a prefix of the name followed by
+/// a slash, so that remotes whose names contain slashes are recognised.
+/// Returns None if the name does not start with any of `remotes`.
+pub fn split_branch_name(remotes: &[~str], name: &str) -> Option<(~str, ~str)>
+{
+    let mut best: Option<&~str> = None;
+    for remotes.iter().advance |remote| {
+        let longer = match best {
+            Some(b) => remote.len() > b.len(),
+            None => true,
+        };
+        if longer && name.len() > remote.len() + 1 && name.starts_with(*remote) &&
+                name.char_at(remote.len()) == '/' {
+            best = Some(remote);
+        }
+    }
+    best.map(|&remote| (copy *remote, name.slice_from(remote.len() + 1).to_owned()))
+}
+
+/// List the remote-tracking branches as (remote, branch) pairs, keeping the names
+/// as they are stored.
+///
+/// Branches which do not belong to a configured remote, e.g. of a removed remote,
+/// are left out. The pairs are sorted by remote, then by branch.
+pub fn remote_branches(repo: &Repository) -> Result<~[(~str, ~str)], (~str, GitError)>
+{
+    let remotes = repo.remote_names();
+    let mut branches = ~[];
+    unsafe {
+        let mut iter: *ext::git_branch_iterator = ptr::null();
+        if ext::git_branch_iterator_new(&mut iter, repo.repo, ext::GIT_BRANCH_REMOTE) != 0 {
+            return Err( last_error() );
+        }
+        let mut err = None;
+        loop {
+            let mut c_ref: *ext::git_reference = ptr::null();
+            let mut branch_type: ext::git_branch_t = 0;
+            match ext::git_branch_next(&mut c_ref, &mut branch_type, iter) {
+                0 => (),
+                ext::GIT_ITEROVER => break,
+                _ => {
+                    err = Some(last_error());
+                    break;
+                },
+            }
+            // the shorthand name, without "refs/remotes/"
+            let mut c_name: *c_char = ptr::null();
+            if ext::git_branch_name(&mut c_name, c_ref) != 0 {
+                err = Some(last_error());
+                ext::git_reference_free(c_ref);
+                break;
+            }
+            match split_branch_name(remotes, from_c_str(c_name)) {
+                Some(pair) => branches.push(pair),
+                None => (),
+            }
+            ext::git_reference_free(c_ref);
+        }
+        ext::git_branch_iterator_free(iter);
+        match err {
+            Some(e) => return Err(e),
+            None => (),
+        }
+    }
+    quick_sort3(branches);
+    Ok(branches)
+}
+
+/// The `push.default` setting: where `git push` sends a branch when no refspec is
+/// given on the command line nor configured for the remote
+#[deriving(Eq)]
+pub enum PushDefault {
+    /// refuse to push
+    PushNothing,
+    /// push to the branch of the same name
+    PushCurrent,
+    /// push to the upstream branch
+    PushUpstream,
+    /// push to the branch of the same name, which must be the upstream branch when
+    /// pushing to the remote of the upstream; the default since git 2.0
+    PushSimple,
+    /// push the branches having a branch of the same name on the remote
+    PushMatching,
+}
+
+/// Read `push.default`, or `PushSimple` if it is not set or not known
+pub fn push_default(cfg: &Config) -> PushDefault
+{
+    match cfg.get_str("push.default") {
+        Some(~"nothing") => PushNothing,
+        Some(~"current") => PushCurrent,
+        Some(~"upstream") | Some(~"tracking") => PushUpstream,
+        Some(~"matching") => PushMatching,
+        _ => PushSimple,
+    }
+}
+
+/// all the values of `remote.<remote>.<key>`, in the order of the configuration
+fn remote_values(cfg: &Config, remote: &str, key: &str) -> ~[~str]
+{
+    let mut values = ~[];
+    let suffix = ~"." + key;
+    for cfg.foreach_match(~"^remote\\..*\\." + key + "$") |name, value| {
+        if name.len() == "remote.".len() + remote.len() + suffix.len() &&
+                name.slice("remote.".len(), name.len() - suffix.len()) == remote {
+            values.push(value.to_owned());
+        }
+    };
+    values
+}
+
+/// The refspecs `git fetch <remote>` uses: the `remote.<remote>.fetch` entries, or
+/// the refspec git writes when adding a remote, "+refs/heads/*:refs/remotes/<remote>/*".
+pub fn default_fetch_refspecs(repo: &Repository, remote: &str)
+    -> Result<~[~str], (~str, GitError)>
+{
+    match repo.config() {
+        Ok(cfg) => {
+            let specs = remote_values(cfg, remote, "fetch");
+            if specs.is_empty() {
+                Ok( ~[fmt!("+refs/heads/*:refs/remotes/%s/*", remote)] )
+            } else {
+                Ok( specs )
+            }
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// The refspecs configured in `remote.<remote>.push`; when there are none,
+/// `push.default` decides, see `push_destination`.
+pub fn default_push_refspecs(repo: &Repository, remote: &str)
+    -> Result<~[~str], (~str, GitError)>
+{
+    match repo.config() {
+        Ok(cfg) => Ok( remote_values(cfg, remote, "push") ),
+        Err(e) => Err(e),
+    }
+}
+
+/// Map `name` through the refspec `spec`, from its source side to its destination side.
+///
+/// The source may contain one "*", which matches any string and is substituted in the
+/// destination. Returns None if the refspec does not match, or has no destination.
+pub fn transform_refspec(spec: &str, name: &str) -> Option<~str>
+{
+    let spec = if spec.starts_with("+") { spec.slice_from(1) } else { spec };
+    let (src, dst) = match spec.find(':') {
+        Some(i) => (spec.slice_to(i), spec.slice_from(i + 1)),
+        None => return None,
+    };
+    if dst.is_empty() {
+        return None;
+    }
+    match src.find('*') {
+        None => if src == name { Some(dst.to_owned()) } else { None },
+        Some(i) => {
+            let (prefix, suffix) = (src.slice_to(i), src.slice_from(i + 1));
+            if name.len() >= prefix.len() + suffix.len() && name.starts_with(prefix) &&
+                    name.ends_with(suffix) {
+                let matched = name.slice(prefix.len(), name.len() - suffix.len());
+                Some(dst.replace("*", matched))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The reference on `remote` which `git push <remote>` updates with the local branch
+/// `branch` (a full name, e.g. "refs/heads/topic").
+///
+/// The `remote.<remote>.push` refspecs are used if there are any, else `push.default`:
+/// like git, an error is returned when pushing with "nothing", when the branch has
+/// no upstream on `remote` for "upstream", and when the upstream has another name
+/// for "simple".
+pub fn push_destination(repo: &Repository, remote: &str, branch: &str)
+    -> Result<~str, (~str, GitError)>
+{
+    let cfg = match repo.config() {
+        Ok(cfg) => cfg,
+        Err(e) => return Err(e),
+    };
+    let specs = remote_values(cfg, remote, "push");
+    if !specs.is_empty() {
+        for specs.iter().advance |spec| {
+            match transform_refspec(*spec, branch) {
+                Some(dst) => return Ok(dst),
+                None => (),
+            }
+        }
+        return Err((fmt!("no push refspec of %s matches %s", remote, branch),
+                    GITERR_INVALID));
+    }
+
+    let short = if branch.starts_with("refs/heads/") {
+        branch.slice_from("refs/heads/".len())
+    } else {
+        return Err((fmt!("%s is not a local branch", branch), GITERR_INVALID));
+    };
+    let upstream_remote = cfg.get_str(fmt!("branch.%s.remote", short));
+    let upstream = cfg.get_str(fmt!("branch.%s.merge", short));
+    let same_remote = upstream_remote == Some(remote.to_owned());
+    match push_default(cfg) {
+        PushNothing => Err((~"push.default is nothing", GITERR_INVALID)),
+        PushCurrent | PushMatching => Ok( branch.to_owned() ),
+        PushUpstream => match upstream {
+            Some(ref merge) if same_remote => Ok(copy *merge),
+            _ => Err((fmt!("%s has no upstream branch on %s", short, remote), GITERR_INVALID)),
+        },
+        PushSimple => match upstream {
+            Some(ref merge) if same_remote => if merge.as_slice() == branch {
+                Ok(copy *merge)
+            } else {
+                Err((fmt!("the upstream branch of %s has another name: %s", short, *merge),
+                     GITERR_INVALID))
+            },
+            _ if same_remote => Err((fmt!("%s has no upstream branch", short), GITERR_INVALID)),
+            _ => Ok( branch.to_owned() ),
+        },
+    }
+}
+
+impl<'self> Remote<'self> {
+    /// Get the remote's name
+    pub fn name(&self) -> Option<~str>
+    {
+        unsafe {
+            let name = ext::git_remote_name(self.remote);
+            if name == ptr::null() { None } else { Some(from_c_str(name)) }
+        }
+    }
+
+    /// Get the remote's url
+    pub fn url(&self) -> ~str
+    {
+        unsafe {
+            from_c_str(ext::git_remote_url(self.remote))
+        }
+    }
+
+    /// Get the remote's url for pushing, if it differs from the url
+    pub fn pushurl(&self) -> Option<~str>
+    {
+        unsafe {
+            let url = ext::git_remote_pushurl(self.remote);
+            if url == ptr::null() { None } else { Some(from_c_str(url)) }
+        }
+    }
+
+    /// Download new data and update the remote-tracking branches,
+    /// like `git fetch <remote>`.
+    ///
+    /// refspecs: the refspecs to use for this fetch; pass an empty slice to use
+    ///  the base refspecs of the remote.
+    /// reflog_message: the message to write to the reflogs of the updated references,
+    ///  or None for the default "fetch".
+    pub fn fetch(&self, refspecs: &[~str], reflog_message: Option<&str>)
+        -> Result<(), (~str, GitError)>
+    {
+        self.fetch_with_opts(refspecs, reflog_message, ptr::null())
+    }
+
+    /// Fetch like `fetch`, reporting the progress of the download to `op`.
+    ///
+    /// `op` is called at most as often as allowed by `throttle`, and always when the
+    /// transfer is done; the fetch is cancelled if it returns false.
+    /// Returns the statistics of the transfer.
+    pub fn fetch_with_progress(&self, refspecs: &[~str], reflog_message: Option<&str>,
+                               throttle: &ProgressThrottle, op: &fn(&TransferProgress) -> bool)
+        -> Result<TransferSummary, (~str, GitError)>
+    {
+        let start = precise_time_ns();
+        let mut state = ProgressState {
+            op: op,
+            throttle: throttle,
+            last_call_ns: start,
+            last_percent: 0,
+            last: None,
+        };
+        let mut opts = fetch_options();
+        opts.callbacks.transfer_progress = transfer_progress_cb;
+        opts.callbacks.payload = unsafe { cast::transmute(&mut state) };
+        match self.fetch_with_opts(refspecs, reflog_message, &opts) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+
+        let elapsed_ms = ((precise_time_ns() - start) / 1000000) as uint;
+        let last = match state.last {
+            Some(last) => last,
+            // nothing was downloaded
+            None => TransferProgress {
+                total_objects: 0,
+                indexed_objects: 0,
+                received_objects: 0,
+                local_objects: 0,
+                total_deltas: 0,
+                indexed_deltas: 0,
+                received_bytes: 0,
+            },
+        };
+        Ok( TransferSummary {
+            total_objects: last.total_objects,
+            received_objects: last.received_objects,
+            received_bytes: last.received_bytes,
+            elapsed_ms: elapsed_ms,
+            throughput: if elapsed_ms == 0 { 0 } else { last.received_bytes * 1000 / elapsed_ms },
+        } )
+    }
+
+    /// Fetch like `fetch`, choosing which tags are downloaded.
+    ///
+    /// Returns the tags which were created or moved, sorted by name.
+    pub fn fetch_with_tags(&self, refspecs: &[~str], reflog_message: Option<&str>,
+                           download: DownloadTags) -> Result<~[TagUpdate], (~str, GitError)>
+    {
+        let mut tags: ~[TagUpdate] = ~[];
+        let mut opts = fetch_options();
+        opts.download_tags = match download {
+            DownloadTagsDefault => ext::GIT_REMOTE_DOWNLOAD_TAGS_UNSET,
+            DownloadTagsAuto => ext::GIT_REMOTE_DOWNLOAD_TAGS_AUTO,
+            DownloadTagsNone => ext::GIT_REMOTE_DOWNLOAD_TAGS_NONE,
+            DownloadTagsAll => ext::GIT_REMOTE_DOWNLOAD_TAGS_ALL,
+        };
+        opts.callbacks.update_tips = update_tips_cb;
+        opts.callbacks.payload = unsafe { cast::transmute(&mut tags) };
+        match self.fetch_with_opts(refspecs, reflog_message, &opts) {
+            Ok(()) => Ok(merge_sort(tags, |a, b| a.name <= b.name)),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn fetch_with_opts(&self, refspecs: &[~str], reflog_message: Option<&str>,
+                       opts: *ext::git_fetch_options) -> Result<(), (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let c_specs = do refspecs.map |spec| {
+            do spec.as_c_str |c_spec| { c_spec }
+        };
+        unsafe {
+            let strarray = ext::git_strarray {
+                strings: std::vec::raw::to_ptr(c_specs),
+                count: c_specs.len() as u64,
+            };
+            let c_msg = match reflog_message {
+                None => ptr::null(),
+                Some(msg) => msg.as_c_str(|ptr| {ptr}),
+            };
+            let res = do trace::timed("git_remote_fetch") {
+                ext::git_remote_fetch(self.remote, &strarray, opts, c_msg)
+            };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+}
+
+/// Default fetch options, without callbacks
+fn fetch_options() -> ext::git_fetch_options
+{
+    ext::git_fetch_options {
+        version: 1,     // GIT_FETCH_OPTIONS_VERSION
+        callbacks: ext::git_remote_callbacks {
+            version: 1,     // GIT_REMOTE_CALLBACKS_VERSION
+            sideband_progress: ptr::null(),
+            completion: ptr::null(),
+            credentials: ptr::null(),
+            certificate_check: ptr::null(),
+            transfer_progress: ptr::null(),
+            update_tips: ptr::null(),
+            pack_progress: ptr::null(),
+            push_transfer_progress: ptr::null(),
+            push_update_reference: ptr::null(),
+            push_negotiation: ptr::null(),
+            transport: ptr::null(),
+            payload: ptr::null(),
+            resolve_url: ptr::null(),
+        },
+        prune: 0,               // GIT_FETCH_PRUNE_UNSPECIFIED
+        update_fetchhead: 1,
+        download_tags: ext::GIT_REMOTE_DOWNLOAD_TAGS_UNSET,
+        proxy_opts: ext::git_proxy_options {
+            version: 1,     // GIT_PROXY_OPTIONS_VERSION
+            proxy_type: 0,  // GIT_PROXY_NONE
+            url: ptr::null(),
+            credentials: ptr::null(),
+            certificate_check: ptr::null(),
+            payload: ptr::null(),
+        },
+        custom_headers: ext::git_strarray { strings: ptr::null(), count: 0 },
+    }
+}
+
+extern fn update_tips_cb(refname: *c_char, old: *OID, new: *OID, payload: *c_void) -> c_int
+{
+    unsafe {
+        let tags: &mut ~[TagUpdate] = cast::transmute(payload);
+        let name = from_c_str(refname);
+        if name.starts_with("refs/tags/") {
+            let zero = OID { id: [0, ..20] };
+            tags.push(TagUpdate {
+                name: name,
+                old: if *old == zero { None } else { Some(*old) },
+                new: *new,
+            });
+        }
+        0
+    }
+}
+
+extern fn transfer_progress_cb(stats: *ext::git_transfer_progress, payload: *c_void) -> c_int
+{
+    unsafe {
+        let state: &mut ProgressState = cast::transmute(payload);
+        let progress = TransferProgress {
+            total_objects: (*stats).total_objects as uint,
+            indexed_objects: (*stats).indexed_objects as uint,
+            received_objects: (*stats).received_objects as uint,
+            local_objects: (*stats).local_objects as uint,
+            total_deltas: (*stats).total_deltas as uint,
+            indexed_deltas: (*stats).indexed_deltas as uint,
+            received_bytes: (*stats).received_bytes as uint,
+        };
+        state.last = Some(progress);
+
+        let now = precise_time_ns();
+        let elapsed_ms = ((now - state.last_call_ns) / 1000000) as uint;
+        if !state.throttle.is_due(elapsed_ms, state.last_percent, &progress) &&
+                !progress.is_done() {
+            return 0;
+        }
+        state.last_call_ns = now;
+        state.last_percent = progress.percent();
+        if (state.op)(&progress) {
+            0
+        } else {
+            // a negative value cancels the transfer
+            -1
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for Remote<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_remote_free(self.remote);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::os;
+use std::from_str::FromStr;
+use super::*;
+
+/// git gives up after this many chained replacements
+static MAX_REPLACE_DEPTH: uint = 5;
+
+/// Whether object replacement is enabled for the repository.
+///
+/// Like git, replacements are disabled when the environment variable
+/// GIT_NO_REPLACE_OBJECTS is set, or when core.useReplaceRefs is false.
+pub fn enabled(repo: &Repository) -> bool
+{
+    if os::getenv("GIT_NO_REPLACE_OBJECTS").is_some() {
+        return false;
+    }
+    match repo.config() {
+        Ok(cfg) => cfg.get_bool("core.useReplaceRefs").get_or_default(true),
+        Err(_) => true,
+    }
+}
+
+/// Enable or disable object replacement for the repository,
+/// by setting core.useReplaceRefs in the repository configuration.
+pub fn set_enabled(repo: &Repository, enable: bool) -> Result<(), (~str, GitError)>
+{
+    match repo.config() {
+        Ok(cfg) => { cfg.set_bool("core.useReplaceRefs", enable); Ok(()) },
+        Err(e) => Err(e),
+    }
+}
+
+/// Replace the object `original` with `replacement`, like `git replace`,
+/// by creating the reference refs/replace/<original>.
+///
+/// Fails if `original` is already replaced, unless `force` is true.
+pub fn create(repo: &Repository, original: &OID, replacement: &OID, force: bool)
+    -> Result<(), (~str, GitError)>
+{
+    if original == replacement {
+        return Err( (~"an object cannot replace itself", GITERR_INVALID) );
+    }
+    let name = ~"refs/replace/" + original.to_str();
+    let msg = fmt!("replace: %s", original.to_str());
+    match repo.reference_create(name, replacement, force, Some(msg.as_slice())) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Remove the replacement of `original`.
+///
+/// Returns false if `original` was not replaced.
+pub fn delete(repo: &Repository, original: &OID) -> bool
+{
+    match repo.lookup(~"refs/replace/" + original.to_str()) {
+        Some(reference) => { reference.delete(); true },
+        None => false,
+    }
+}
+
+/// List the replacements of the repository, as (original, replacement) pairs.
+pub fn list(repo: &Repository) -> ~[(OID, OID)]
+{
+    let mut replacements = ~[];
+    for repo.reference_names().iter().advance |name| {
+        if !name.starts_with("refs/replace/") {
+            loop;
+        }
+        let original: Option<OID> = FromStr::from_str(name.slice_from("refs/replace/".len()));
+        match (original, repo.lookup(*name)) {
+            (Some(original), Some(reference)) => replacements.push((original, reference.resolve())),
+            _ => (),
+        }
+    }
+    replacements
+}
+
+/// The id of the object which replaces `id`, following chained replacements,
+/// or `id` itself if it is not replaced or replacements are disabled.
+pub fn resolve(repo: &Repository, id: &OID) -> OID
+{
+    if !enabled(repo) {
+        return *id;
+    }
+    let mut current = *id;
+    let mut depth = 0u;
+    while depth < MAX_REPLACE_DEPTH {
+        match repo.lookup(~"refs/replace/" + current.to_str()) {
+            Some(reference) => current = reference.resolve(),
+            None => break,
+        }
+        depth += 1;
+    }
+    current
+}
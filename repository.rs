@@ -1,14 +1,30 @@
+use std::libc;
 use std::libc::{c_char, c_int, c_uint, c_void, size_t};
-use std::{ptr, cast};
+use std::{io, os, ptr, cast, rand, uint};
+use std::hashmap::HashMap;
 use std::io::Reader;
 use std::str::raw::{from_c_str, from_c_str_len};
-use std::vec::raw::mut_buf_as_slice;
-use std::vec::{as_mut_buf, as_imm_buf, as_const_buf};
+use std::vec::as_imm_buf;
+use extra::sort::quick_sort3;
 use ext;
+use trace;
 use signature;
 use super::*;
 
-static PATH_BUF_SZ: uint = 1024u;
+/// how much `read_to_end` and `copy_to_new_file` read at once
+static READ_BLOCK_SIZE: uint = 64 * 1024;
+
+impl Observers {
+    /// no callback registered
+    pub fn new() -> Observers {
+        Observers {
+            ref_updated: ~[],
+            ref_deleted: ~[],
+            index_written: ~[],
+            commit_created: ~[],
+        }
+    }
+}
 
 /// Open a git repository.
 ///
@@ -18,14 +34,90 @@ static PATH_BUF_SZ: uint = 1024u;
 /// or bare repository or raise bad_repo if 'path' is neither.
 pub fn open(path: &str) -> Result<Repository, (~str, GitError)>
 {
-    unsafe {
-        let mut ptr_to_repo: *ext::git_repository = ptr::null();
+    do create |ptr_to_repo| {
         do path.as_c_str |c_path| {
-            if ext::git_repository_open(&mut ptr_to_repo, c_path) == 0 {
-                Ok( Repository { repo: ptr_to_repo } )
-            } else {
-                Err( last_error() )
-            }
+            unsafe { ext::git_repository_open(ptr_to_repo, c_path) }
+        }
+    }
+}
+
+/// Initialize libgit2 and create a libgit2 repository with `f`.
+///
+/// The Repository holds on to the initialization, and releases it when it is dropped.
+fn create(f: &fn(&mut *ext::git_repository) -> c_int) -> Result<Repository, (~str, GitError)>
+{
+    match super::init() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let mut ptr_to_repo: *ext::git_repository = ptr::null();
+    if f(&mut ptr_to_repo) == 0 {
+        Ok( Repository::from_raw(ptr_to_repo) )
+    } else {
+        let err = unsafe { last_error() };
+        super::shutdown();
+        Err(err)
+    }
+}
+
+/// Options of `open_ext`
+pub struct OpenOption {
+    /// only open the repository at the given path, without looking for one in the
+    /// parent directories
+    no_search: bool,
+    /// let the search in the parent directories cross filesystem boundaries
+    cross_fs: bool,
+    /// refuse every write to the repository; see `Repository::is_read_only`
+    read_only: bool,
+}
+
+impl OpenOption {
+    /// Like `open`: no search in the parent directories, writes allowed
+    pub fn new() -> OpenOption {
+        OpenOption {
+            no_search: true,
+            cross_fs: false,
+            read_only: false,
+        }
+    }
+}
+
+/// Open a git repository, with more control than `open`.
+///
+/// Without `opts.no_search`, the repository is searched from `path` up through its
+/// parent directories, like git does from the current directory.
+pub fn open_ext(path: &str, opts: &OpenOption) -> Result<Repository, (~str, GitError)>
+{
+    let mut flags = 0 as c_uint;
+    if opts.no_search { flags |= ext::GIT_REPOSITORY_OPEN_NO_SEARCH; }
+    if opts.cross_fs { flags |= ext::GIT_REPOSITORY_OPEN_CROSS_FS; }
+    let repo = do create |ptr_to_repo| {
+        do path.as_c_str |c_path| {
+            unsafe { ext::git_repository_open_ext(ptr_to_repo, c_path, flags, ptr::null()) }
+        }
+    };
+    match repo {
+        Ok(mut repo) => {
+            repo.read_only = opts.read_only;
+            Ok(repo)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Open the repository git would use in the current environment, like the git
+/// commands invoked from hooks and aliases.
+///
+/// The environment variables GIT_DIR, GIT_WORK_TREE, GIT_INDEX_FILE,
+/// GIT_OBJECT_DIRECTORY, GIT_ALTERNATE_OBJECT_DIRECTORIES, GIT_NAMESPACE,
+/// GIT_CEILING_DIRECTORIES and GIT_DISCOVERY_ACROSS_FILESYSTEM are honored; without
+/// GIT_DIR, the repository is discovered from the current directory.
+pub fn open_from_env() -> Result<Repository, (~str, GitError)>
+{
+    do create |ptr_to_repo| {
+        unsafe {
+            ext::git_repository_open_ext(ptr_to_repo, ptr::null(),
+                                         ext::GIT_REPOSITORY_OPEN_FROM_ENV, ptr::null())
         }
     }
 }
@@ -37,14 +129,84 @@ pub fn open(path: &str) -> Result<Repository, (~str, GitError)>
 /// will be created.
 pub fn init(path: &str, is_bare: bool) -> Result<Repository, (~str, GitError)>
 {
-    unsafe {
-        let mut ptr_to_repo: *ext::git_repository = ptr::null();
+    do create |ptr_to_repo| {
         do path.as_c_str |c_path| {
-            if ext::git_repository_init(&mut ptr_to_repo, c_path, is_bare as c_uint) == 0 {
-                Ok( Repository { repo: ptr_to_repo } )
-            } else {
-                Err( last_error() )
-            }
+            unsafe { ext::git_repository_init(ptr_to_repo, c_path, is_bare as c_uint) }
+        }
+    }
+}
+
+/// Options for `init_ext`
+pub struct InitOption {
+    bare: bool,
+    /// fail if the path already holds a repository, instead of reinitializing it
+    no_reinit: bool,
+    /// directory copied into the new repository, e.g. with `hooks`, `info/exclude`
+    /// and `description`; the permissions of the files are kept
+    template: Option<~str>,
+    /// when no template is given, use the templates of the git installation instead of
+    /// the minimal ones built into libgit2
+    system_templates: bool,
+    /// content of the `description` file, replacing the one of the template
+    description: Option<~str>,
+    /// name of the branch HEAD points to, e.g. "main"
+    initial_head: Option<~str>,
+    /// url of a remote named "origin" to add to the repository
+    origin_url: Option<~str>,
+}
+
+impl InitOption {
+    /// Options giving the same repository on every host: no system templates
+    pub fn new() -> InitOption {
+        InitOption {
+            bare: false,
+            no_reinit: false,
+            template: None,
+            system_templates: false,
+            description: None,
+            initial_head: None,
+            origin_url: None,
+        }
+    }
+}
+
+fn opt_c_str(s: &Option<~str>) -> *c_char {
+    match *s {
+        Some(ref s) => s.as_c_str(|ptr| ptr),
+        None => ptr::null(),
+    }
+}
+
+/// Creates a new Git repository in the given folder, with more control than `init`.
+///
+/// The parent directories of `path` are created as needed. Unless
+/// `opts.system_templates` is set, the content of the repository only depends on
+/// `opts`, and not on the git installation of the host.
+pub fn init_ext(path: &str, opts: &InitOption) -> Result<Repository, (~str, GitError)>
+{
+    let mut flags = ext::GIT_REPOSITORY_INIT_MKPATH;
+    if opts.bare {
+        flags |= ext::GIT_REPOSITORY_INIT_BARE;
+    }
+    if opts.no_reinit {
+        flags |= ext::GIT_REPOSITORY_INIT_NO_REINIT;
+    }
+    if opts.template.is_some() || opts.system_templates {
+        flags |= ext::GIT_REPOSITORY_INIT_EXTERNAL_TEMPLATE;
+    }
+    let c_opts = ext::git_repository_init_options {
+        version: 1,     // GIT_REPOSITORY_INIT_OPTIONS_VERSION
+        flags: flags,
+        mode: 0,        // GIT_REPOSITORY_INIT_SHARED_UMASK
+        workdir_path: ptr::null(),
+        description: opt_c_str(&opts.description),
+        template_path: opt_c_str(&opts.template),
+        initial_head: opt_c_str(&opts.initial_head),
+        origin_url: opt_c_str(&opts.origin_url),
+    };
+    do create |ptr_to_repo| {
+        do path.as_c_str |c_path| {
+            unsafe { ext::git_repository_init_ext(ptr_to_repo, c_path, &c_opts) }
         }
     }
 }
@@ -65,35 +227,96 @@ pub fn init(path: &str, is_bare: bool) -> Result<Repository, (~str, GitError)>
 /// might be empty string
 pub fn discover(start_path: &str, across_fs: bool, ceiling_dirs: &str) -> Option<~str>
 {
-    unsafe {
-        let mut buf = std::vec::from_elem(PATH_BUF_SZ, 0u8 as c_char);
-        do as_mut_buf(buf) |c_path, sz| {
-            do start_path.as_c_str |c_start_path| {
-                do ceiling_dirs.as_c_str |c_ceiling_dirs| {
-                    let result = ext::git_repository_discover(c_path, sz as size_t,
-                                            c_start_path, across_fs as c_int, c_ceiling_dirs);
-                    if result == 0 {
-                        Some( std::str::raw::from_buf(c_path as *u8) )
-                    } else {
-                        None
-                    }
-                }
+    match super::init() {
+        Ok(()) => (),
+        Err(_) => return None,
+    }
+    let path = unsafe {
+        let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+        do start_path.as_c_str |c_start_path| {
+            do ceiling_dirs.as_c_str |c_ceiling_dirs| {
+                let result = ext::git_repository_discover(&mut buf, c_start_path,
+                                                          across_fs as c_int, c_ceiling_dirs);
+                let path = if result == 0 {
+                    Some( from_c_str(buf.ptr) )
+                } else {
+                    None
+                };
+                ext::git_buf_dispose(&mut buf);
+                path
             }
         }
+    };
+    super::shutdown();
+    path
+}
+
+/// Create a repository which lives entirely in memory, e.g. for the tests of tools
+/// built on this crate.
+///
+/// Objects are written to a mempack backend and are lost when the Repository is
+/// dropped. The repository has no working directory, index or configuration files,
+/// and its reference database has no backend: libgit2 does not provide an in-memory
+/// one, so looking up or writing references returns an error, and the ids of the
+/// objects written must be kept by the caller. It has no path either: `path` returns
+/// None, and the methods working on the files of the repository return an error.
+pub fn new_in_memory() -> Result<Repository, (~str, GitError)>
+{
+    // from here on the Repository frees the pointer, and with it the odb and refdb
+    let repo = match create(|ptr_to_repo| unsafe { ext::git_repository_new(ptr_to_repo) }) {
+        Ok(repo) => repo,
+        Err(e) => return Err(e),
+    };
+    unsafe {
+
+        let mut odb: *ext::git_odb = ptr::null();
+        if ext::git_odb_new(&mut odb) != 0 {
+            return Err( last_error() );
+        }
+        let mut backend: *ext::git_odb_backend = ptr::null();
+        if ext::git_mempack_new(&mut backend) != 0 {
+            ext::git_odb_free(odb);
+            return Err( last_error() );
+        }
+        if ext::git_odb_add_backend(odb, backend, 1) != 0 {
+            ext::git_odb_free(odb);
+            return Err( last_error() );
+        }
+        // the repository takes its own reference
+        ext::git_repository_set_odb(repo.repo, odb);
+        ext::git_odb_free(odb);
+
+        let mut refdb: *ext::git_refdb = ptr::null();
+        if ext::git_refdb_new(&mut refdb, repo.repo) != 0 {
+            return Err( last_error() );
+        }
+        ext::git_repository_set_refdb(repo.repo, refdb);
+        ext::git_refdb_free(refdb);
+        Ok(repo)
     }
 }
 
 /// Clone a remote repository, and checkout the branch pointed to by the remote
 /// this function do not receive options for now
+///
+/// The URL is rewritten with the `url.<base>.insteadOf` rules of the global configuration.
 pub fn clone(url: &str, local_path: &str) -> Result<Repository, (~str, GitError)> {
-    unsafe {
-        let mut ptr_to_repo: *ext::git_repository = ptr::null();
+    // reading the global configuration needs libgit2, which `create` only
+    // initializes for the clone itself
+    match super::init() {
+        Ok(()) => (),
+        Err(e) => return Err(e),
+    }
+    let url = match config::open_default() {
+        Ok(cfg) => cfg.rewrite_url(url, false),
+        Err(_) => url.to_owned(),
+    };
+    super::shutdown();
+    do create |ptr_to_repo| {
         do url.as_c_str |c_url| {
             do local_path.as_c_str |c_path| {
-                if ext::git_clone(&mut ptr_to_repo, c_url, c_path, ptr::null()) == 0 {
-                    Ok( Repository { repo: ptr_to_repo } )
-                } else {
-                    Err( last_error() )
+                do trace::timed("git_clone") {
+                    unsafe { ext::git_clone(ptr_to_repo, c_url, c_path, ptr::null()) }
                 }
             }
         }
@@ -101,14 +324,201 @@ pub fn clone(url: &str, local_path: &str) -> Result<Repository, (~str, GitError)
 }
 
 impl Repository {
+    /// wrap a pointer to a repository, which the Repository frees
+    fn from_raw(repo: *ext::git_repository) -> Repository {
+        Repository {
+            repo: repo,
+            observers: Observers::new(),
+            read_only: false,
+            signer: None,
+        }
+    }
+
+    /// Whether the repository was opened with `OpenOption::read_only`.
+    ///
+    /// The writing methods of a read-only Repository, and of the index, object
+    /// database, references, remotes and tree builders obtained from it, fail with
+    /// the error of `check_writable` before touching the repository, so no lock file
+    /// is created either. The configuration is a read-only snapshot. Reading the
+    /// repository writes nothing, except for the index, which libgit2 may refresh
+    /// when computing a status.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fail with ("the repository is opened read-only", GITERR_REPOSITORY) if the
+    /// repository is read-only; the writing methods of the crate call this first.
+    ///
+    /// Code writing to the repository through the `ext` bindings can call it as well.
+    pub fn check_writable(&self) -> Result<(), (~str, GitError)> {
+        if self.read_only {
+            Err( (~"the repository is opened read-only", GITERR_REPOSITORY) )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// raise the error of `check_writable`, for the writing methods reporting errors
+    /// with git_error; true if the write must not happen
+    fn refuse_write(&self) -> bool {
+        match self.check_writable() {
+            Ok(()) => false,
+            Err(e) => {
+                git_error::cond.raise(e);
+                true
+            },
+        }
+    }
+
+    /// Register a callback run after a reference is created or moved through this
+    /// Repository, with the name of the reference and its new target.
+    ///
+    /// Only the writes made by this crate are reported; use `watch` to be told about
+    /// the changes made by other processes.
+    pub fn on_ref_updated(&mut self, op: ~fn(name: &str, id: &OID)) {
+        self.observers.ref_updated.push(op);
+    }
+
+    /// Register a callback run after a reference is deleted through this Repository,
+    /// or renamed away from its name, with the full name of the reference
+    pub fn on_ref_deleted(&mut self, op: ~fn(name: &str)) {
+        self.observers.ref_deleted.push(op);
+    }
+
+    /// Register a callback run after an index of this Repository is written to disk
+    pub fn on_index_written(&mut self, op: ~fn()) {
+        self.observers.index_written.push(op);
+    }
+
+    /// Register a callback run after a commit is created through this Repository,
+    /// with the id of the new commit
+    pub fn on_commit_created(&mut self, op: ~fn(id: &OID)) {
+        self.observers.commit_created.push(op);
+    }
+
+    /// Set the function signing the commits made by `commit` when `commit.gpgsign` is
+    /// set, like `git commit -S`.
+    ///
+    /// It is given the content of the unsigned commit object, and returns the armored
+    /// signature, e.g. the output of `gpg --detach-sign --armor`, which is stored in
+    /// the gpgsig header of the commit. An error aborts the commit.
+    pub fn set_commit_signer(&mut self, signer: ~fn(content: &str)
+                                                   -> Result<~str, (~str, GitError)>) {
+        self.signer = Some(signer);
+    }
+
+    /// Run the callbacks registered with `on_ref_updated`.
+    ///
+    /// The write paths of the crate call this; code writing references through
+    /// the `ext` bindings can call it as well.
+    pub fn notify_ref_updated(&self, name: &str, id: &OID) {
+        for self.observers.ref_updated.iter().advance |op| {
+            (*op)(name, id);
+        }
+    }
+
+    /// Run the callbacks registered with `on_ref_deleted`.
+    pub fn notify_ref_deleted(&self, name: &str) {
+        for self.observers.ref_deleted.iter().advance |op| {
+            (*op)(name);
+        }
+    }
+
+    /// Run the callbacks registered with `on_index_written`.
+    pub fn notify_index_written(&self) {
+        for self.observers.index_written.iter().advance |op| {
+            (*op)();
+        }
+    }
+
+    /// Run the callbacks registered with `on_commit_created`.
+    pub fn notify_commit_created(&self, id: &OID) {
+        for self.observers.commit_created.iter().advance |op| {
+            (*op)(id);
+        }
+    }
+
+    /// Start watching HEAD, the references and the index for changes made by other
+    /// processes, checking every `interval_ms` milliseconds.
+    ///
+    /// See `watch::watch`.
+    pub fn watch(&self, interval_ms: uint) -> Result<watch::Watcher, (~str, GitError)> {
+        match self.disk_path() {
+            Ok(path) => Ok( watch::watch(path, interval_ms) ),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Get the path of this repository
     ///
     /// This is the path of the `.git` folder for normal repositories,
     /// or of the repository itself for bare repositories.
-    pub fn path(&self) -> ~str {
+    /// Returns None for a repository created with `new_in_memory`.
+    pub fn path(&self) -> Option<~str> {
         unsafe {
             let c_path = ext::git_repository_path(self.repo);
-            from_c_str(c_path)
+            if ptr::is_null(c_path) {
+                None
+            } else {
+                Some(from_c_str(c_path))
+            }
+        }
+    }
+
+    /// Get the path of this repository like `path`, failing with GITERR_REPOSITORY
+    /// for a repository created with `new_in_memory`; the methods working on the files
+    /// of the repository call this first.
+    pub fn disk_path(&self) -> Result<~str, (~str, GitError)> {
+        match self.path() {
+            Some(path) => Ok(path),
+            None => Err( (~"the repository lives in memory and has no path",
+                          GITERR_REPOSITORY) ),
+        }
+    }
+
+    /// Open a new handle on the same repository on disk.
+    ///
+    /// The new handle has its own git_repository, with its own caches, and shares
+    /// nothing with this one, not even the observers; the working directory of this
+    /// handle is kept, even when it was set with `set_workdir`, and so is the
+    /// read-only mode.
+    /// A Repository must not be shared between tasks: reopening it and moving the
+    /// new handle is the supported way to hand work on the repository to another task.
+    pub fn reopen(&self) -> Result<Repository, (~str, GitError)> {
+        let path = match self.disk_path() {
+            Ok(path) => path,
+            Err(e) => return Err(e),
+        };
+        let mut repo = match open(path) {
+            Ok(repo) => repo,
+            Err(e) => return Err(e),
+        };
+        repo.read_only = self.read_only;
+        match self.workdir() {
+            Some(workdir) => if repo.workdir() != Some(copy workdir) {
+                unsafe {
+                    let res = do workdir.as_c_str |c_path| {
+                        ext::git_repository_set_workdir(repo.repo, c_path, 0)
+                    };
+                    if res != 0 {
+                        return Err( last_error() );
+                    }
+                }
+            },
+            None => (),
+        }
+        Ok(repo)
+    }
+
+    /// Take a read-only snapshot of this repository.
+    ///
+    /// The snapshot is a separate handle on the same repository, which can be
+    /// moved to another task for concurrent reads; the references and configuration
+    /// are captured now. See `Snapshot` and `reopen`.
+    pub fn snapshot(&self) -> Result<Snapshot, (~str, GitError)> {
+        match self.reopen() {
+            Ok(repo) => snapshot::capture(repo),
+            Err(e) => Err(e),
         }
     }
 
@@ -126,20 +536,86 @@ impl Repository {
         }
     }
 
+    /// Set the path to the working directory for this repository
+    ///
+    /// The working directory doesn't need to be the same one
+    /// that contains the `.git` folder for this repository.
+    ///
+    /// If this repository is bare, setting its working directory
+    /// will turn it into a normal repository, capable of performing
+    /// all the common workdir operations (checkout, status, index
+    /// manipulation, etc).
+    ///
+    /// If `update_gitlink` is true, a `.git` file is created in the new working
+    /// directory pointing to the repository, and `core.worktree` is set in its
+    /// config, so that git finds the repository from the new location.
+    ///
+    /// raises git_error on error
+    pub fn set_workdir(&self, path: &str, update_gitlink: bool) {
+        if self.refuse_write() {
+            return;
+        }
+        unsafe {
+            do path.as_c_str |c_path| {
+                if ext::git_repository_set_workdir(self.repo, c_path,
+                                                    update_gitlink as c_int) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
     /// Retrieve and resolve the reference pointed at by HEAD.
+    ///
+    /// Returns None when HEAD is unborn or missing; raises git_error on other errors.
     pub fn head<'r>(&'r self) -> Option<~Reference<'r>> {
+        match self.try_head() {
+            Ok(head) => head,
+            Err(e) => {
+                git_error::cond.raise(e);
+                None
+            },
+        }
+    }
+
+    /// Retrieve and resolve the reference pointed at by HEAD, like `head`
+    ///
+    /// Unlike `head`, the errors, e.g. a corrupted branch file, are returned;
+    /// Ok(None) means that HEAD is unborn or missing.
+    pub fn try_head<'r>(&'r self) -> Result<Option<~Reference<'r>>, (~str, GitError)> {
         unsafe {
             let mut ptr_to_ref: *ext::git_reference = ptr::null();
 
             match ext::git_repository_head(&mut ptr_to_ref, self.repo) {
-                0 => Some( ~Reference { c_ref: ptr_to_ref, owner: self } ),
-                ext::GIT_EORPHANEDHEAD => None,
-                ext::GIT_ENOTFOUND => None,
-                _ => {
-                    raise();
-                    None
-                },
+                0 => Ok( Some( ~Reference { c_ref: ptr_to_ref, owner: self } ) ),
+                ext::GIT_EUNBORNBRANCH => Ok(None),
+                ext::GIT_ENOTFOUND => Ok(None),
+                _ => Err( last_error() ),
+            }
+        }
+    }
+
+    /// Get the name of the branch HEAD points to, e.g. "refs/heads/master", without
+    /// resolving it: unlike `head`, this works when the branch is unborn, e.g. in a
+    /// freshly initialized repository.
+    ///
+    /// Returns None when HEAD is detached or missing.
+    pub fn head_name(&self) -> Option<~str> {
+        unsafe {
+            let mut c_ref: *ext::git_reference = ptr::null();
+            let res = do "HEAD".as_c_str |c_name| {
+                ext::git_reference_lookup(&mut c_ref, self.repo, c_name)
+            };
+            if res != 0 {
+                return None;
             }
+            let name = if ext::git_reference_type(c_ref) == ext::GIT_REF_SYMBOLIC {
+                Some(from_c_str(ext::git_reference_symbolic_target(c_ref)))
+            } else {
+                None
+            };
+            ext::git_reference_free(c_ref);
+            name
         }
     }
 
@@ -159,6 +635,138 @@ impl Repository {
         }
     }
 
+    /// Create a new direct reference pointing to `id`.
+    ///
+    /// If `force` is true and a reference already exists with the given name,
+    /// it'll be replaced. `log_message` is written to the reflog of the reference.
+    /// The name will be checked for validity.
+    pub fn reference_create<'r>(&'r self, name: &str, id: &OID, force: bool,
+        log_message: Option<&str>) -> Result<~Reference<'r>, (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        unsafe {
+            let mut ptr_to_ref: *ext::git_reference = ptr::null();
+            let c_msg = match log_message {
+                None => ptr::null(),
+                Some(msg) => msg.as_c_str(|ptr| {ptr}),
+            };
+            do name.as_c_str |c_name| {
+                if ext::git_reference_create(&mut ptr_to_ref, self.repo, c_name, id,
+                                                force as c_int, c_msg) == 0 {
+                    self.notify_ref_updated(name, id);
+                    Ok( ~Reference { c_ref: ptr_to_ref, owner: self } )
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// Create a new direct reference like `reference_create`, waiting for the lock of
+    /// the reference held by another process to be released, for at most `timeout_ms`
+    /// milliseconds.
+    ///
+    /// See `lock::retry`.
+    pub fn reference_create_locked<'r>(&'r self, name: &str, id: &OID, force: bool,
+        log_message: Option<&str>, timeout_ms: uint) -> Result<~Reference<'r>, LockError>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err((msg, klass)) => return Err( LockFailed(msg, klass) ),
+        }
+        let c_msg = match log_message {
+            None => ptr::null(),
+            Some(msg) => msg.as_c_str(|ptr| {ptr}),
+        };
+        do name.as_c_str |c_name| {
+            do lock::retry(timeout_ms) {
+                unsafe {
+                    let mut ptr_to_ref: *ext::git_reference = ptr::null();
+                    match ext::git_reference_create(&mut ptr_to_ref, self.repo, c_name, id,
+                                                    force as c_int, c_msg) {
+                        0 => {
+                            self.notify_ref_updated(name, id);
+                            Ok( ~Reference { c_ref: ptr_to_ref, owner: self } )
+                        },
+                        res => Err(lock::error(res)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the names of the remotes configured in the repository
+    pub fn remote_names(&self) -> ~[~str] {
+        unsafe {
+            let mut arr = ext::git_strarray { strings: ptr::null(), count: 0 };
+            if ext::git_remote_list(&mut arr, self.repo) == 0 {
+                let names = from_c_strarray(&arr);
+                ext::git_strarray_free(&mut arr);
+                names
+            } else {
+                raise();
+                ~[]
+            }
+        }
+    }
+
+    /// Split the shorthand name of a remote-tracking branch into the remote and
+    /// the branch, using the configured remotes.
+    ///
+    /// See `remote::split_branch_name`.
+    pub fn split_remote_branch(&self, name: &str) -> Option<(~str, ~str)> {
+        remote::split_branch_name(self.remote_names(), name)
+    }
+
+    /// List the remote-tracking branches as (remote, branch) pairs.
+    ///
+    /// See `remote::remote_branches`.
+    pub fn remote_branches(&self) -> Result<~[(~str, ~str)], (~str, GitError)> {
+        remote::remote_branches(self)
+    }
+
+    /// The refspecs `git fetch <remote>` uses.
+    ///
+    /// See `remote::default_fetch_refspecs`.
+    pub fn default_fetch_refspecs(&self, remote: &str) -> Result<~[~str], (~str, GitError)> {
+        remote::default_fetch_refspecs(self, remote)
+    }
+
+    /// The push refspecs configured for `remote`.
+    ///
+    /// See `remote::default_push_refspecs`.
+    pub fn default_push_refspecs(&self, remote: &str) -> Result<~[~str], (~str, GitError)> {
+        remote::default_push_refspecs(self, remote)
+    }
+
+    /// The reference on `remote` which `git push <remote>` updates with `branch`.
+    ///
+    /// See `remote::push_destination`.
+    pub fn push_destination(&self, remote: &str, branch: &str)
+        -> Result<~str, (~str, GitError)>
+    {
+        remote::push_destination(self, remote, branch)
+    }
+
+    /// Get the information for a particular remote
+    ///
+    /// Returns None if there is no remote with this name.
+    pub fn remote_lookup<'r>(&'r self, name: &str) -> Option<~Remote<'r>> {
+        unsafe {
+            let mut remote: *ext::git_remote = ptr::null();
+            do name.as_c_str |c_name| {
+                match ext::git_remote_lookup(&mut remote, self.repo, c_name) {
+                    0 => Some( ~Remote { remote: remote, owner: self } ),
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
+                }
+            }
+        }
+    }
+
     /// Lookup a branch by its name in a repository.
     ///
     /// The generated reference must be freed by the user.
@@ -199,29 +807,190 @@ impl Repository {
         }
     }
 
-    /// Lookup a tree object from repository
-    pub fn lookup_tree<'r>(&'r self, id: &OID) -> Option<~Tree<'r>> {
-        unsafe {
-            let mut tree: *ext::git_tree = ptr::null();
-            if ext::git_tree_lookup(&mut tree, self.repo, id) == 0 {
-                Some( ~Tree { tree: tree, owner: self } )
-            } else {
-                None
-            }
+    /// Lookup a commit object from repository, honoring the replacements
+    /// of refs/replace/ when they are enabled.
+    ///
+    /// See `replace::resolve`.
+    pub fn lookup_commit_replaced<'r>(&'r self, id: &OID) -> Option<~Commit<'r>> {
+        self.lookup_commit(&replace::resolve(self, id))
+    }
+
+    /// Lookup a commit as an AnnotatedCommit, found by its id
+    pub fn annotated_commit_lookup<'r>(&'r self, id: &OID)
+        -> Result<~AnnotatedCommit<'r>, (~str, GitError)>
+    {
+        do self.annotated_commit() |out| {
+            unsafe { ext::git_annotated_commit_lookup(out, self.repo, id) }
         }
     }
 
-    /// Updates files in the index and the working tree to match the content of
-    /// the commit pointed at by HEAD.
-    /// This function does not accept options for now
-    ///
-    /// returns true when successful, false if HEAD points to an non-existing branch
-    /// raise on other errors
-    pub fn checkout_head(&self) -> bool {
-        unsafe {
-            match ext::git_checkout_head(self.repo, ptr::null()) {
+    /// Get the commit a reference points to as an AnnotatedCommit, which remembers
+    /// the name of the reference
+    pub fn annotated_commit_from_ref<'r>(&'r self, reference: &Reference)
+        -> Result<~AnnotatedCommit<'r>, (~str, GitError)>
+    {
+        do self.annotated_commit() |out| {
+            unsafe { ext::git_annotated_commit_from_ref(out, self.repo, reference.c_ref) }
+        }
+    }
+
+    /// Get a commit fetched from `branch_name` of the remote at `remote_url` as an
+    /// AnnotatedCommit, like the entries of FETCH_HEAD
+    pub fn annotated_commit_from_fetchhead<'r>(&'r self, branch_name: &str, remote_url: &str,
+                                               id: &OID)
+        -> Result<~AnnotatedCommit<'r>, (~str, GitError)>
+    {
+        do self.annotated_commit() |out| {
+            do branch_name.as_c_str |c_branch| {
+                do remote_url.as_c_str |c_url| {
+                    unsafe {
+                        ext::git_annotated_commit_from_fetchhead(out, self.repo, c_branch, c_url,
+                                                                 id)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the commit a revision string like "origin/master~2" designates as an
+    /// AnnotatedCommit, which remembers the revision string
+    pub fn annotated_commit_from_revspec<'r>(&'r self, spec: &str)
+        -> Result<~AnnotatedCommit<'r>, (~str, GitError)>
+    {
+        do self.annotated_commit() |out| {
+            do spec.as_c_str |c_spec| {
+                unsafe { ext::git_annotated_commit_from_revspec(out, self.repo, c_spec) }
+            }
+        }
+    }
+
+    /// create an AnnotatedCommit with `f`
+    fn annotated_commit<'r>(&'r self, f: &fn(&mut *ext::git_annotated_commit) -> c_int)
+        -> Result<~AnnotatedCommit<'r>, (~str, GitError)>
+    {
+        let mut commit: *ext::git_annotated_commit = ptr::null();
+        if f(&mut commit) == 0 {
+            Ok( ~AnnotatedCommit { commit: commit, owner: self } )
+        } else {
+            Err( unsafe { last_error() } )
+        }
+    }
+
+    /// Read the note attached to `target` in `notes_ref`, or in the default notes
+    /// reference if it is None.
+    ///
+    /// See `notes::read`.
+    pub fn note(&self, notes_ref: Option<&str>, target: &OID) -> Option<notes::Note> {
+        notes::read(self, notes_ref, target)
+    }
+
+    /// Attach a note to any object, like `git notes add`.
+    ///
+    /// See `notes::create`.
+    pub fn note_create(&self, notes_ref: Option<&str>, author: &Signature,
+                       committer: &Signature, target: &OID, message: &str, force: bool)
+        -> Result<OID, (~str, GitError)>
+    {
+        notes::create(self, notes_ref, author, committer, target, message, force)
+    }
+
+    /// All the notes attached to `target` in the notes references matching `glob`.
+    ///
+    /// See `notes::notes_for`.
+    pub fn notes_for(&self, target: &OID, glob: &str) -> ~[notes::Note] {
+        notes::notes_for(self, target, glob)
+    }
+
+    /// Lookup a tree object from repository
+    pub fn lookup_tree<'r>(&'r self, id: &OID) -> Option<~Tree<'r>> {
+        unsafe {
+            let mut tree: *ext::git_tree = ptr::null();
+            if ext::git_tree_lookup(&mut tree, self.repo, id) == 0 {
+                Some( ~Tree { tree: tree, owner: self } )
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Lookup a commit object from repository
+    ///
+    /// Unlike `lookup_commit`, the reason of a failure is reported.
+    pub fn try_lookup_commit<'r>(&'r self, id: &OID) -> Result<~Commit<'r>, LookupError> {
+        unsafe {
+            let mut commit: *ext::git_commit = ptr::null();
+            let res = ext::git_commit_lookup(&mut commit, self.repo, id);
+            if res == 0 {
+                Ok( ~Commit { commit: commit, owner: self } )
+            } else {
+                Err( self.lookup_error(id, res) )
+            }
+        }
+    }
+
+    /// Lookup a tree object from repository
+    ///
+    /// Unlike `lookup_tree`, the reason of a failure is reported.
+    pub fn try_lookup_tree<'r>(&'r self, id: &OID) -> Result<~Tree<'r>, LookupError> {
+        unsafe {
+            let mut tree: *ext::git_tree = ptr::null();
+            let res = ext::git_tree_lookup(&mut tree, self.repo, id);
+            if res == 0 {
+                Ok( ~Tree { tree: tree, owner: self } )
+            } else {
+                Err( self.lookup_error(id, res) )
+            }
+        }
+    }
+
+    /// Lookup a blob object from repository
+    ///
+    /// Unlike `blob_lookup`, the reason of a failure is reported.
+    pub fn try_lookup_blob<'r>(&'r self, id: &OID) -> Result<~Blob<'r>, LookupError> {
+        unsafe {
+            let mut blob: *ext::git_blob = ptr::null();
+            let res = ext::git_blob_lookup(&mut blob, self.repo, id);
+            if res == 0 {
+                Ok( ~Blob { blob: blob, owner: self } )
+            } else {
+                Err( self.lookup_error(id, res) )
+            }
+        }
+    }
+
+    /// libgit2 reports a type mismatch as GIT_ENOTFOUND;
+    /// tell it apart by looking for the object in the database
+    unsafe fn lookup_error(&self, id: &OID, res: c_int) -> LookupError {
+        if res != ext::GIT_ENOTFOUND {
+            let (msg, klass) = last_error();
+            return LookupFailed(msg, klass);
+        }
+        match self.odb() {
+            Ok(odb) => match odb.read(id) {
+                Ok(obj) => WrongType(obj.otype()),
+                Err(_) => NotFound,
+            },
+            Err((msg, klass)) => LookupFailed(msg, klass),
+        }
+    }
+
+    /// Updates files in the index and the working tree to match the content of
+    /// the commit pointed at by HEAD.
+    /// This function does not accept options for now
+    ///
+    /// returns true when successful, false if HEAD points to an non-existing branch
+    /// raise on other errors
+    pub fn checkout_head(&self) -> bool {
+        if self.refuse_write() {
+            return false;
+        }
+        unsafe {
+            let res = do trace::timed("git_checkout_head") {
+                ext::git_checkout_head(self.repo, ptr::null())
+            };
+            match res {
                 0 => true,
-                ext::GIT_EORPHANEDHEAD => false,
+                ext::GIT_EUNBORNBRANCH => false,
                 _ => {
                     raise();
                     false
@@ -230,6 +999,168 @@ impl Repository {
         }
     }
 
+    /// Make HEAD point to the reference `refname`.
+    ///
+    /// If the reference is a branch, HEAD will point to it, even if the branch
+    /// doesn't exist yet. Otherwise, HEAD will be detached and will point to the commit
+    /// the reference points to. The working directory is not updated.
+    /// libgit2 logs "checkout: moving from <old> to <new>" in the reflog of HEAD, like git.
+    pub fn set_head(&self, refname: &str) -> Result<(), (~str, GitError)> {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let res = do refname.as_c_str |c_name| {
+            unsafe { ext::git_repository_set_head(self.repo, c_name) }
+        };
+        if res != 0 {
+            return unsafe { Err( last_error() ) };
+        }
+        // an unborn branch has no commit to report yet
+        match self.head() {
+            Some(head) => self.notify_ref_updated("HEAD", &head.resolve()),
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// Switch to the local branch tracking the remote-tracking branch `remote_branch`,
+    /// e.g. "origin/feature", like `git checkout feature` does.
+    ///
+    /// If the local branch does not exist, it is created at the commit of the remote branch,
+    /// with the remote branch as upstream. The working directory is updated safely:
+    /// the checkout fails instead of overwriting local changes.
+    /// Returns the local branch.
+    pub fn checkout_remote_branch<'r>(&'r self, remote_branch: &str)
+        -> Result<~Reference<'r>, (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let tracking = match self.lookup_branch(remote_branch, true) {
+            Some(tracking) => tracking,
+            None => return Err( (fmt!("no remote-tracking branch %s", remote_branch),
+                                GITERR_REFERENCE) ),
+        };
+        // remote names may contain slashes: "team/origin/feature" is the branch
+        // "feature" of the remote "team/origin"
+        let local_name = match remote::split_branch_name(self.remote_names(), remote_branch) {
+            Some((_, branch)) => branch,
+            None => return Err( (fmt!("%s does not belong to a configured remote",
+                                      remote_branch), GITERR_INVALID) ),
+        };
+
+        let local = match self.lookup_branch(local_name, false) {
+            Some(local) => local,
+            None => {
+                let msg = reflog::branch_created_message(remote_branch);
+                let local = match self.reference_create(~"refs/heads/" + local_name,
+                                                        &tracking.resolve(), false,
+                                                        Some(msg.as_slice())) {
+                    Ok(local) => local,
+                    Err(e) => return Err(e),
+                };
+                local.set_upstream(Some(remote_branch));
+                local
+            },
+        };
+
+        let commit = match self.lookup_commit(&local.resolve()) {
+            Some(commit) => commit,
+            None => return Err( (fmt!("%s does not point to a commit", local.name()),
+                                GITERR_REFERENCE) ),
+        };
+        let tree = match commit.tree() {
+            Ok(tree) => tree,
+            Err(e) => return Err(e),
+        };
+        let mut opts = checkout::CheckoutOption::new();
+        opts.strategy = ~[checkout::GIT_CHECKOUT_SAFE];
+        match self.try_checkout_tree(tree, &opts) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        match self.set_head(local.name()) {
+            Ok(()) => Ok(local),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Updates files in the index and working tree to match the content of the
+    /// tree pointed at by the treeish.
+    ///
+    /// raises git_error on error
+    pub fn checkout_tree(&self, tree: &Tree, opts: &checkout::CheckoutOption) {
+        match self.try_checkout_tree(tree, opts) {
+            Ok(()) => (),
+            Err(e) => git_error::cond.raise(e),
+        }
+    }
+
+    /// `checkout_tree`, returning the error
+    fn try_checkout_tree(&self, tree: &Tree, opts: &checkout::CheckoutOption)
+        -> Result<(), (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        unsafe {
+            do checkout::with_c_opts(opts) |c_opts| {
+                let res = do trace::timed("git_checkout_tree") {
+                    ext::git_checkout_tree(self.repo, tree.tree as *ext::git_object, c_opts)
+                };
+                if res == 0 {
+                    Ok(())
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// Updates files in the working tree to match the content of an index.
+    ///
+    /// With None, the index of the repository is used. Another index can be given,
+    /// e.g. the result of a merge made in memory.
+    ///
+    /// raises git_error on error
+    pub fn checkout_index(&self, index: Option<&GitIndex>, opts: &checkout::CheckoutOption) {
+        if self.refuse_write() {
+            return;
+        }
+        let c_index = match index {
+            Some(index) => index.index,
+            None => ptr::null(),
+        };
+        unsafe {
+            do checkout::with_c_opts(opts) |c_opts| {
+                let res = do trace::timed("git_checkout_index") {
+                    ext::git_checkout_index(self.repo, c_index, c_opts)
+                };
+                if res != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Restore only the given paths from a tree into the index and the working tree,
+    /// like `git checkout <tree> -- <paths>`.
+    ///
+    /// The paths are matched exactly, not as fnmatch patterns, and files
+    /// which differ from the tree are overwritten.
+    ///
+    /// raises git_error on error
+    pub fn checkout_paths(&self, tree: &Tree, paths: &[~str], opts: &checkout::CheckoutOption) {
+        let mut path_opts = copy *opts;
+        path_opts.paths = paths.to_owned();
+        path_opts.strategy.push(checkout::GIT_CHECKOUT_FORCE);
+        path_opts.strategy.push(checkout::GIT_CHECKOUT_DISABLE_PATHSPEC_MATCH);
+        self.checkout_tree(tree, &path_opts)
+    }
+
     /// Get the Index file for this repository.
     ///
     /// If a custom index has not been set, the default
@@ -247,6 +1178,660 @@ impl Repository {
         }
     }
 
+    /// Get the Object Database for this repository.
+    ///
+    /// If a custom ODB has not been set, the default
+    /// database for the repository will be returned (the one
+    /// located in `.git/objects`).
+    pub fn odb<'r>(&'r self) -> Result<~Odb<'r>, (~str, GitError)> {
+        unsafe {
+            let mut ptr_to_odb: *ext::git_odb = ptr::null();
+
+            if ext::git_repository_odb(&mut ptr_to_odb, self.repo) == 0 {
+                Ok( ~Odb { odb: ptr_to_odb, owner: self } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Get the size of an object, in bytes, without reading its content,
+    /// e.g. to reject large blobs before loading them.
+    ///
+    /// See `Odb::read_header`.
+    pub fn object_size(&self, id: &OID) -> Result<u64, (~str, GitError)> {
+        match self.odb() {
+            Ok(odb) => match odb.read_header(id) {
+                Ok((size, _)) => Ok(size as u64),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Allocate a new revision walker to iterate through this repository.
+    ///
+    /// The walker is created without any starting point;
+    /// at least one commit must be pushed before starting the walk.
+    pub fn revwalk<'r>(&'r self) -> Result<~Revwalk<'r>, (~str, GitError)> {
+        unsafe {
+            let mut walk: *ext::git_revwalk = ptr::null();
+
+            if ext::git_revwalk_new(&mut walk, self.repo) == 0 {
+                let bounds = ~revwalk::TimeBounds { repo: self.repo, since: None, until: None };
+                Ok( ~Revwalk { walk: walk, owner: self, bounds: bounds } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Create a new packbuilder, to write a set of objects of this repository into a pack.
+    pub fn packbuilder<'r>(&'r self) -> Result<~PackBuilder<'r>, (~str, GitError)> {
+        unsafe {
+            let mut pb: *ext::git_packbuilder = ptr::null();
+
+            if ext::git_packbuilder_new(&mut pb, self.repo) == 0 {
+                Ok( ~PackBuilder { pb: pb, owner: self } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Write all the reachable objects into a single new pack,
+    /// optionally removing the superseded packs and loose objects.
+    ///
+    /// See `maintenance::repack`.
+    pub fn repack(&self, opts: &maintenance::RepackOption)
+        -> Result<maintenance::RepackStats, (~str, GitError)>
+    {
+        maintenance::repack(self, opts)
+    }
+
+    /// Remove the unreachable loose objects older than `older_than` (seconds since the epoch).
+    ///
+    /// See `maintenance::prune_loose`.
+    pub fn prune_loose(&self, older_than: i64, dry_run: bool) -> Result<~[OID], (~str, GitError)>
+    {
+        maintenance::prune_loose(self, older_than, dry_run)
+    }
+
+    /// Delete the local branches whose upstream branch was deleted on the remote.
+    ///
+    /// See `maintenance::prune_gone_branches`.
+    pub fn prune_gone_branches(&self, dry_run: bool) -> ~[maintenance::GoneBranch]
+    {
+        maintenance::prune_gone_branches(self, dry_run)
+    }
+
+    /// Read the reflog for the given reference
+    ///
+    /// If there is no reflog file for the given reference yet, an empty reflog
+    /// object will be returned.
+    pub fn reflog<'r>(&'r self, name: &str) -> Result<~Reflog<'r>, (~str, GitError)> {
+        do name.as_c_str |c_name| {
+            unsafe {
+                let mut reflog: *ext::git_reflog = ptr::null();
+
+                if ext::git_reflog_read(&mut reflog, self.repo, c_name) == 0 {
+                    Ok( ~Reflog { reflog: reflog, owner: self } )
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// Remove the entries of the reflog of `refname` created before `older_than`
+    /// (seconds since the epoch), and write the reflog back to disk.
+    ///
+    /// See `reflog::expire`.
+    pub fn reflog_expire(&self, refname: &str, older_than: i64, unreachable_only: bool)
+        -> Result<uint, (~str, GitError)>
+    {
+        reflog::expire(self, refname, older_than, unreachable_only)
+    }
+
+    /// Iterate over the commits reachable from `start` which modified `path`,
+    /// following renames of the file.
+    ///
+    /// See `history::file_history`.
+    pub fn file_history<'r>(&'r self, path: &str, start: &OID)
+        -> Result<history::FileHistory<'r>, (~str, GitError)>
+    {
+        history::file_history(self, path, start)
+    }
+
+    /// Iterate over the commits reachable from `start` which modified the lines
+    /// `start_line` to `end_line` of `path`.
+    ///
+    /// See `history::line_history`.
+    pub fn line_history<'r>(&'r self, path: &str, start_line: uint, end_line: uint,
+        start: &OID) -> Result<history::LineHistory<'r>, (~str, GitError)>
+    {
+        history::line_history(self, path, start_line, end_line, start)
+    }
+
+    /// Find the commits of `head` which are not in `upstream`, and mark those
+    /// already applied upstream, like `git cherry`.
+    ///
+    /// See `cherry::cherry`.
+    pub fn cherry(&self, upstream: &OID, head: &OID)
+        -> Result<~[cherry::CherryCommit], (~str, GitError)>
+    {
+        cherry::cherry(self, upstream, head)
+    }
+
+    /// Match up the commits of two versions of a branch, like `git range-diff`.
+    ///
+    /// See `cherry::range_diff`.
+    pub fn range_diff(&self, old_range: (&OID, &OID), new_range: (&OID, &OID))
+        -> Result<~[cherry::RangeDiffEntry], (~str, GitError)>
+    {
+        cherry::range_diff(self, old_range, new_range)
+    }
+
+    /// Count the number of unique commits between two commit objects
+    ///
+    /// There is no need for branches containing the commits to have any
+    /// upstream relationship, but it helps to think of one as a branch and
+    /// the other as its upstream, the `ahead` and `behind` values will be
+    /// what git would report for the branches.
+    ///
+    /// returns (ahead, behind)
+    pub fn ahead_behind(&self, local: &OID, upstream: &OID)
+        -> Result<(uint, uint), (~str, GitError)>
+    {
+        let mut ahead: size_t = 0;
+        let mut behind: size_t = 0;
+        unsafe {
+            if ext::git_graph_ahead_behind(&mut ahead, &mut behind, self.repo,
+                                            local, upstream) == 0 {
+                Ok( (ahead as uint, behind as uint) )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Count ahead/behind commits for many (local, upstream) pairs in a single walk.
+    ///
+    /// See `dag::ahead_behind_matrix`.
+    pub fn ahead_behind_matrix(&self, pairs: &[(OID, OID)])
+        -> Result<~[(uint, uint)], (~str, GitError)>
+    {
+        dag::ahead_behind_matrix(self, pairs)
+    }
+
+    /// Build a reachability index of the commits of this repository, for fast
+    /// ancestry queries.
+    ///
+    /// See `dag::reachability`.
+    pub fn reachability<'r>(&'r self) -> Result<dag::Reachability<'r>, (~str, GitError)>
+    {
+        dag::reachability(self)
+    }
+
+    /// Build an index mapping the commits produced by `walk` to their children.
+    ///
+    /// See `dag::children_index`.
+    pub fn children_index(&self, walk: &mut Revwalk) -> dag::ChildrenIndex
+    {
+        dag::children_index(self, walk)
+    }
+
+    /// Fill a list with all the references that can be found in a repository.
+    ///
+    /// The listed references may be direct or symbolic.
+    pub fn reference_names(&self) -> ~[~str] {
+        unsafe {
+            let mut arr = ext::git_strarray { strings: ptr::null(), count: 0 };
+            if ext::git_reference_list(&mut arr, self.repo) == 0 {
+                let names = from_c_strarray(&arr);
+                ext::git_strarray_free(&mut arr);
+                names
+            } else {
+                raise();
+                ~[]
+            }
+        }
+    }
+
+    /// List all the references with their targets, grouped by namespace.
+    ///
+    /// See `reference::ref_summary`.
+    pub fn ref_summary(&self) -> Result<reference::RefSummary, (~str, GitError)> {
+        reference::ref_summary(self)
+    }
+
+    /// List the shorthand names of the branches, tags and remote-tracking branches
+    /// which start with `prefix`, e.g. for shell completion.
+    ///
+    /// Local branches are listed first, then tags, then remote-tracking branches.
+    /// Each group is sorted by name.
+    pub fn refnames_matching(&self, prefix: &str) -> ~[~str] {
+        let namespaces = ["refs/heads/", "refs/tags/", "refs/remotes/"];
+        let mut groups: ~[~[~str]] = ~[~[], ~[], ~[]];
+        for self.reference_names().iter().advance |name| {
+            for namespaces.iter().enumerate().advance |(i, ns)| {
+                if name.starts_with(*ns) {
+                    let short = name.slice_from(ns.len());
+                    if short.starts_with(prefix) {
+                        groups[i].push(short.to_owned());
+                    }
+                    break;
+                }
+            }
+        }
+
+        let mut names: ~[~str] = ~[];
+        for groups.mut_iter().advance |group| {
+            quick_sort3(*group);
+            names.push_all(*group);
+        }
+        names
+    }
+
+    /// Get the configuration file for this repository.
+    ///
+    /// If a configuration file has not been set, the default
+    /// config set for the repository will be returned, including
+    /// global and system configurations (if they are available).
+    ///
+    /// For a read-only repository, this is a snapshot which cannot be written.
+    pub fn config(&self) -> Result<~Config, (~str, GitError)> {
+        unsafe {
+            let mut cfg: *ext::git_config = ptr::null();
+
+            if ext::git_repository_config(&mut cfg, self.repo) != 0 {
+                return Err( last_error() );
+            }
+            let cfg = ~Config { cfg: cfg, tmpdir: None };
+            if self.read_only { cfg.snapshot() } else { Ok(cfg) }
+        }
+    }
+
+    /// Create a new action signature with default user and now timestamp.
+    ///
+    /// This looks up the user.name and user.email from the configuration and
+    /// uses the current time as the timestamp, and creates a new signature
+    /// based on that information.
+    pub fn default_signature(&self) -> Result<Signature, (~str, GitError)> {
+        unsafe {
+            let mut c_sig: *ext::git_signature = ptr::null();
+
+            if ext::git_signature_default(&mut c_sig, self.repo) == 0 {
+                let sig = signature::from_c_sig(c_sig);
+                ext::git_signature_free(c_sig);
+                Ok(sig)
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Get a cache for the default signature of this repository.
+    ///
+    /// Use this instead of `default_signature` when creating many commits:
+    /// the configuration stays open, and only the files which changed are read again.
+    pub fn signature_cache<'r>(&'r self) -> SignatureCache<'r> {
+        SignatureCache { owner: self, config: None }
+    }
+
+    /// The URL git would actually connect to for `url`, after applying the
+    /// `url.<base>.insteadOf` (and for pushes `url.<base>.pushInsteadOf`) rules
+    /// of the repository configuration.
+    ///
+    /// See `Config::rewrite_url`.
+    pub fn effective_url(&self, url: &str, push: bool) -> Result<~str, (~str, GitError)> {
+        match self.config() {
+            Ok(cfg) => Ok( cfg.rewrite_url(url, push) ),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Compute commit counts per author, and churn of the commits reachable from `to`
+    /// but not from `from`.
+    ///
+    /// See `stats::range_stats`.
+    pub fn range_stats(&self, from: Option<&OID>, to: &OID,
+        canonicalize: &fn(&Signature) -> (~str, ~str))
+        -> Result<stats::RangeStats, (~str, GitError)>
+    {
+        stats::range_stats(self, from, to, canonicalize)
+    }
+
+    /// Move the branch `branch_ref` forward to `target`, refusing non fast-forward updates.
+    ///
+    /// See `merge::fast_forward`.
+    pub fn fast_forward<'r>(&'r self, branch_ref: &str, target: &OID)
+        -> Result<~Reference<'r>, merge::FastForwardError>
+    {
+        merge::fast_forward(self, branch_ref, target)
+    }
+
+    /// Analyze what merging `their_heads` into HEAD would take.
+    ///
+    /// See `merge::merge_analysis`.
+    pub fn merge_analysis(&self, their_heads: &[OID])
+        -> Result<merge::MergeAnalysis, (~str, GitError)>
+    {
+        merge::merge_analysis(self, their_heads)
+    }
+
+    /// Merge `theirs` into `ours` in memory, and return the resulting index.
+    ///
+    /// See `merge::merge_commits`.
+    pub fn merge_commits<'r>(&'r self, ours: &Commit, theirs: &Commit,
+                             opts: &merge::MergeOption) -> Result<~GitIndex<'r>, (~str, GitError)>
+    {
+        merge::merge_commits(self, ours, theirs, opts)
+    }
+
+    /// Merge `their_heads` into HEAD in the index and the working directory, leaving
+    /// the merge to be committed.
+    ///
+    /// See `merge::merge`.
+    pub fn merge(&self, their_heads: &[~AnnotatedCommit], opts: &merge::MergeOption,
+                 checkout_opts: &checkout::CheckoutOption) -> Result<(), (~str, GitError)>
+    {
+        merge::merge(self, their_heads, opts, checkout_opts)
+    }
+
+    /// The commits being merged, read from MERGE_HEAD.
+    ///
+    /// See `merge::merge_heads`.
+    pub fn merge_heads(&self) -> Result<~[OID], (~str, GitError)> {
+        merge::merge_heads(self)
+    }
+
+    /// The message prepared for the merge commit, read from MERGE_MSG.
+    ///
+    /// See `merge::merge_message`.
+    pub fn merge_message(&self) -> Option<~str> {
+        merge::merge_message(self)
+    }
+
+    /// Remove the files recording the merge, or other operation, in progress.
+    ///
+    /// See `merge::cleanup_state`.
+    pub fn cleanup_state(&self) -> Result<(), (~str, GitError)> {
+        merge::cleanup_state(self)
+    }
+
+    /// Cherry-pick `commit` onto `ours` in memory, and return the resulting index.
+    ///
+    /// See `merge::cherrypick_commit`.
+    pub fn cherrypick_commit<'r>(&'r self, commit: &Commit, ours: &Commit, mainline: uint,
+                                 opts: &merge::MergeOption)
+        -> Result<~GitIndex<'r>, (~str, GitError)>
+    {
+        merge::cherrypick_commit(self, commit, ours, mainline, opts)
+    }
+
+    /// Revert `commit` on top of `ours` in memory, and return the resulting index.
+    ///
+    /// See `merge::revert_commit`.
+    pub fn revert_commit<'r>(&'r self, commit: &Commit, ours: &Commit, mainline: uint,
+                             opts: &merge::MergeOption)
+        -> Result<~GitIndex<'r>, (~str, GitError)>
+    {
+        merge::revert_commit(self, commit, ours, mainline, opts)
+    }
+
+    /// Fetch `branch` from `remote` and integrate it into the current branch.
+    ///
+    /// See `merge::pull`.
+    pub fn pull(&self, remote: &str, branch: &str, strategy: merge::PullStrategy,
+                opts: &merge::MergeOption) -> Result<merge::PullOutcome, StepError>
+    {
+        merge::pull(self, remote, branch, strategy, opts)
+    }
+
+    /// Find the object specified by a revision string, e.g. "HEAD~2" or "master:README.md".
+    ///
+    /// See `man gitrevisions` for the supported syntax.
+    pub fn revparse(&self, spec: &str) -> Result<OID, (~str, GitError)> {
+        do spec.as_c_str |c_spec| {
+            unsafe {
+                let mut obj: *ext::git_object = ptr::null();
+                if ext::git_revparse_single(&mut obj, self.repo, c_spec) == 0 {
+                    let oid = *ext::git_object_id(obj);
+                    ext::git_object_free(obj);
+                    Ok(oid)
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// Find the commit specified by a revision string, e.g. "HEAD~2", "v1.0" or an
+    /// abbreviated id, peeling tags.
+    ///
+    /// Returns an error of class GITERR_OBJECT if the revision names another kind of
+    /// object, e.g. a tree or a blob.
+    pub fn find_commit<'r>(&'r self, spec: &str) -> Result<~Commit<'r>, (~str, GitError)> {
+        do spec.as_c_str |c_spec| {
+            unsafe {
+                let mut obj: *ext::git_object = ptr::null();
+                if ext::git_revparse_single(&mut obj, self.repo, c_spec) != 0 {
+                    return Err( last_error() );
+                }
+                let mut commit: *ext::git_object = ptr::null();
+                let res = ext::git_object_peel(&mut commit, obj, GIT_OBJ_COMMIT);
+                ext::git_object_free(obj);
+                match res {
+                    0 => Ok( ~Commit { commit: commit, owner: self } ),
+                    ext::GIT_ENOTFOUND | ext::GIT_EINVALIDSPEC | ext::GIT_EAMBIGUOUS =>
+                        Err( (fmt!("%s is not a commit", spec), GITERR_OBJECT) ),
+                    _ => Err( last_error() ),
+                }
+            }
+        }
+    }
+
+    /// Get the type and the content of the object specified by a revision string,
+    /// like `git cat-file -p`.
+    ///
+    /// Blobs, commits and tags are returned as they are stored: the headers of commits
+    /// and tags are already in the format printed by git. Trees are rendered as one
+    /// "<mode> <type> <id>\t<name>" line per entry.
+    pub fn cat_file(&self, spec: &str) -> Result<(OType, ~[u8]), (~str, GitError)> {
+        let oid = match self.revparse(spec) {
+            Ok(oid) => oid,
+            Err(e) => return Err(e),
+        };
+        match self.lookup_tree(&oid) {
+            Some(tree) => {
+                let lines = match tree.ls(false) {
+                    Ok(lines) => lines,
+                    Err(e) => return Err(e),
+                };
+                let mut out = ~"";
+                for lines.iter().advance |line| {
+                    out.push_str(*line);
+                    out.push_char('\n');
+                }
+                return Ok((GIT_OBJ_TREE, out.as_bytes().to_owned()));
+            },
+            None => (),
+        }
+        let odb = match self.odb() {
+            Ok(odb) => odb,
+            Err(e) => return Err(e),
+        };
+        match odb.read(&oid) {
+            Ok(obj) => Ok((obj.otype(), obj.data_as_slice(|data| data.to_owned()))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read a file and write it to the object database as a blob, like
+    /// `git hash-object -w <path>`.
+    ///
+    /// The path is relative to the current directory, and the content is written
+    /// as is, without applying filters.
+    pub fn hash_and_write(&self, path: &str) -> Result<OID, (~str, GitError)> {
+        let data = match io::read_whole_file(&Path(path)) {
+            Ok(data) => data,
+            Err(msg) => return Err( (msg, GITERR_OS) ),
+        };
+        match self.odb() {
+            Ok(odb) => odb.write(data, GIT_OBJ_BLOB),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find whether a blob with this content is already stored in the repository.
+    ///
+    /// The content is hashed without being written; returns the id of the blob
+    /// if the object database already contains it, so that importers can skip
+    /// writing unchanged files.
+    pub fn blob_exists_for_buffer(&self, data: &[u8]) -> Option<OID> {
+        let oid = match odb::hash(data, GIT_OBJ_BLOB) {
+            Ok(oid) => oid,
+            Err(_) => return None,
+        };
+        match self.odb() {
+            Ok(odb) => if odb.exists(&oid) { Some(oid) } else { None },
+            Err(_) => None,
+        }
+    }
+
+    /// Get the empty tree, writing it to the object database if it is missing.
+    ///
+    /// The empty tree is the "old" side of the changes of a root commit,
+    /// and the tree of an initial empty commit.
+    pub fn empty_tree<'r>(&'r self) -> Result<~Tree<'r>, (~str, GitError)> {
+        let oid = match self.odb() {
+            Ok(odb) => match odb.write([], GIT_OBJ_TREE) {
+                Ok(oid) => oid,
+                Err(e) => return Err(e),
+            },
+            Err(e) => return Err(e),
+        };
+        match self.try_lookup_tree(&oid) {
+            Ok(tree) => Ok(tree),
+            Err(e) => Err(e.to_error(&oid)),
+        }
+    }
+
+    /// Get the empty blob, writing it to the object database if it is missing.
+    pub fn empty_blob<'r>(&'r self) -> Result<~Blob<'r>, (~str, GitError)> {
+        let oid = match self.odb() {
+            Ok(odb) => match odb.write([], GIT_OBJ_BLOB) {
+                Ok(oid) => oid,
+                Err(e) => return Err(e),
+            },
+            Err(e) => return Err(e),
+        };
+        match self.try_lookup_blob(&oid) {
+            Ok(blob) => Ok(blob),
+            Err(e) => Err(e.to_error(&oid)),
+        }
+    }
+
+    /// Check whether commits are signed: a signer is set with `set_commit_signer`, and
+    /// the configuration asks for signatures (`commit.gpgsign`).
+    ///
+    /// `commit` then signs the commits with the signer. Without a signer, the
+    /// configuration is not read; with one, it is read from a snapshot of the
+    /// configuration libgit2 keeps loaded for the repository.
+    pub fn commit_signing_requested(&self) -> bool {
+        if self.signer.is_none() {
+            return false;
+        }
+        unsafe {
+            let mut cfg: *ext::git_config = ptr::null();
+            if ext::git_repository_config_snapshot(&mut cfg, self.repo) != 0 {
+                return false;
+            }
+            let cfg = ~Config { cfg: cfg, tmpdir: None };
+            cfg.get_bool("commit.gpgsign").get_or_default(false)
+        }
+    }
+
+    /// Load objects in bulk, and keep them in memory as long as the returned
+    /// Prefetched lives.
+    ///
+    /// For commits, their trees, their parents and the trees of their parents are
+    /// loaded as well, so that a pipeline which walks commits and then diffs each of
+    /// them against its parent finds all the objects it needs already parsed: looking
+    /// them up again is served from the object cache of libgit2, which holds the
+    /// objects for as long as they are referenced.
+    /// Ids of missing objects are ignored.
+    pub fn prefetch<'r>(&'r self, ids: &[OID]) -> ~Prefetched<'r> {
+        let mut prefetched = ~Prefetched { objects: HashMap::new(), owner: self };
+        for ids.iter().advance |id| {
+            let obj = match prefetched.load(id, GIT_OBJ_ANY) {
+                Some(obj) => obj,
+                None => loop,
+            };
+            unsafe {
+                if ext::git_object_type(obj) as int != GIT_OBJ_COMMIT as int {
+                    loop;
+                }
+                prefetched.load(&*ext::git_commit_tree_id(obj), GIT_OBJ_TREE);
+                for uint::range(0, ext::git_commit_parentcount(obj) as uint) |n| {
+                    let parent_id = *ext::git_commit_parent_id(obj, n as c_uint);
+                    match prefetched.load(&parent_id, GIT_OBJ_COMMIT) {
+                        Some(parent) => {
+                            prefetched.load(&*ext::git_commit_tree_id(parent), GIT_OBJ_TREE);
+                        },
+                        None => (),
+                    }
+                }
+            }
+        }
+        prefetched
+    }
+
+    /// Diff each commit against its first parent, using `n_tasks` tasks.
+    ///
+    /// See `diff::diff_commits_parallel`.
+    pub fn diff_commits_parallel(&self, commits: &[OID], opts: &diff::DiffOption,
+                                 n_tasks: uint) -> Result<~[diff::CommitDeltas], (~str, GitError)>
+    {
+        diff::diff_commits_parallel(self, commits, opts, n_tasks)
+    }
+
+    /// Get the shortest abbreviation of `id` which identifies a single object,
+    /// and is at least as long as `core.abbrev` (7 by default).
+    ///
+    /// `core.abbrev` set to "no" gives the full id.
+    pub fn abbreviate(&self, id: &OID) -> Result<~str, (~str, GitError)> {
+        let hex = id.to_str();
+        let min_len = match self.config() {
+            Ok(cfg) => match cfg.get_str("core.abbrev") {
+                Some(value) => if value == ~"no" {
+                    hex.len()
+                } else {
+                    match uint::from_str(value) {
+                        Some(len) => uint::max(4, uint::min(len, hex.len())),
+                        None => 7,
+                    }
+                },
+                None => 7,
+            },
+            Err(e) => return Err(e),
+        };
+        let odb = match self.odb() {
+            Ok(odb) => odb,
+            Err(e) => return Err(e),
+        };
+        let mut len = min_len;
+        while len < hex.len() {
+            match odb.is_unique_prefix(id, len) {
+                Ok(true) => break,
+                Ok(false) => len += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok( hex.slice(0, len).to_owned() )
+    }
+
     /// Check if a repository is empty
     pub fn is_empty(&self) -> bool {
         unsafe {
@@ -267,6 +1852,69 @@ impl Repository {
         }
     }
 
+    /// Check if a repository is a shallow clone
+    pub fn is_shallow(&self) -> bool {
+        unsafe {
+            ext::git_repository_is_shallow(self.repo) == 1
+        }
+    }
+
+    /// Check if a repository is a linked working tree, created by `git worktree add`
+    pub fn is_worktree(&self) -> bool {
+        unsafe {
+            ext::git_repository_is_worktree(self.repo) == 1
+        }
+    }
+
+    /// Check that the repository belongs to the current user, or is listed in the
+    /// `safe.directory` settings, like git does before trusting the configuration and
+    /// hooks of a repository.
+    ///
+    /// Only the global and system configurations are read, as the repository could
+    /// list itself otherwise. `safe.directory = *` trusts every repository.
+    /// Services scanning repositories of other users should call this right after
+    /// opening them, and refuse to go on if it fails.
+    pub fn check_ownership(&self) -> Result<(), (~str, GitError)> {
+        let path = match self.disk_path() {
+            Ok(path) => path,
+            Err(e) => return Err(e),
+        };
+        let dir = match self.workdir() {
+            Some(workdir) => workdir,
+            None => copy path,
+        };
+        let trimmed = dir.trim_right_chars(&'/').to_owned();
+        let mut safe = false;
+        match config::open_default() {
+            Ok(cfg) => {
+                for cfg.foreach_match("^safe\\.directory$") |_, value| {
+                    // an empty value resets the list
+                    if value.is_empty() {
+                        safe = false;
+                    } else if value == "*" || value.trim_right_chars(&'/') == trimmed {
+                        safe = true;
+                    }
+                }
+            },
+            Err(e) => return Err(e),
+        }
+        if safe {
+            return Ok(());
+        }
+
+        let uid = unsafe { libc::funcs::posix88::unistd::getuid() };
+        for [Path(dir), Path(path)].iter().advance |path| {
+            match path.stat() {
+                Some(st) if st.st_uid == uid => (),
+                Some(_) => return Err( (fmt!("%s is owned by someone else; add it to \
+                                            safe.directory to trust it", path.to_str()),
+                                        GITERR_REPOSITORY) ),
+                None => return Err( (fmt!("cannot stat %s", path.to_str()), GITERR_OS) ),
+            }
+        }
+        Ok(())
+    }
+
     /// Gather file statuses and run a callback for each one.
     /// The callback is passed the path of the file and the status (Status)
     /// If the callback returns false, this function will stop looping
@@ -281,39 +1929,328 @@ impl Repository {
                             op: &fn(path: ~str, status_flags: c_uint) -> bool)
                             -> bool
     {
-        let fptr: *c_void = cast::transmute(&op);
-        let res = ext::git_status_foreach(self.repo, git_status_cb, fptr);
-        if res == 0 {
-            true
-        } else if res == ext::GIT_EUSER {
-            false
-        } else {
-            raise();
-            false
+        let fptr: *c_void = cast::transmute(&op);
+        let res = do trace::timed("git_status_foreach") {
+            ext::git_status_foreach(self.repo, git_status_cb, fptr)
+        };
+        if res == 0 {
+            true
+        } else if res > 0 {
+            // the callback stopped the loop
+            false
+        } else {
+            raise();
+            false
+        }
+    }
+
+    /// Safer variant of each_status
+    pub fn status(&self) -> ~[(~str, ~Status)] {
+        let mut status_list:~[(~str, ~Status)] = ~[];
+        unsafe {
+            for self.each_status |path, status_flags| {
+                status_list.push((path, status_from_flags(status_flags)));
+            };
+        }
+        status_list
+    }
+
+    /// Gather file statuses like `status`, choosing which comparisons are made and
+    /// which files are reported.
+    ///
+    /// With `include_unmodified`, every tracked file is listed, which gives a full
+    /// manifest of the working directory in a single pass.
+    pub fn status_with(&self, opts: &StatusOption)
+        -> Result<~[(~str, ~Status)], (~str, GitError)>
+    {
+        let mut status_list:~[(~str, ~Status)] = ~[];
+        let res = do self.each_status_with(opts) |path, status_flags| {
+            status_list.push((lossy_utf8(path), status_from_flags(status_flags)));
+            true
+        };
+        match res {
+            Ok(_) => Ok(status_list),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gather file statuses like `status_with`, with the paths as stored by git,
+    /// which need not be valid UTF-8.
+    pub fn status_with_bytes(&self, opts: &StatusOption)
+        -> Result<~[(~[u8], ~Status)], (~str, GitError)>
+    {
+        let mut status_list:~[(~[u8], ~Status)] = ~[];
+        let res = do self.each_status_with(opts) |path, status_flags| {
+            status_list.push((path, status_from_flags(status_flags)));
+            true
+        };
+        match res {
+            Ok(_) => Ok(status_list),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Gather file statuses like `status_with`, and classify the modifications, so
+    /// that the trivial ones, like line ending churn, can be told from the others.
+    ///
+    /// For a file modified in the working directory, the content of the file is
+    /// compared with the content in the index; for a file modified in the index only,
+    /// the content in the index is compared with the content in HEAD. The other
+    /// entries, e.g. added or deleted files, have no classification.
+    pub fn status_classified(&self, opts: &StatusOption)
+        -> Result<~[(~str, ~Status, Option<ChangeKind>)], (~str, GitError)>
+    {
+        let status_list = match self.status_with(opts) {
+            Ok(status_list) => status_list,
+            Err(e) => return Err(e),
+        };
+        let index = match self.index() {
+            Ok(index) => index,
+            Err(e) => return Err(e),
+        };
+        let head = self.head_tree();
+        let mut classified = ~[];
+        for status_list.consume_iter().advance |(path, status)| {
+            let kind = if status.wt_modified {
+                self.classify_workdir_change(index, path)
+            } else if status.index_modified {
+                self.classify_index_change(index, &head, path)
+            } else {
+                Ok(None)
+            };
+            match kind {
+                Ok(kind) => classified.push((path, status, kind)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(classified)
+    }
+
+    /// classify the change of `path` between the index and the working directory
+    fn classify_workdir_change(&self, index: &GitIndex, path: &str)
+        -> Result<Option<ChangeKind>, (~str, GitError)>
+    {
+        let workdir = match self.workdir() {
+            Some(workdir) => workdir,
+            None => return Ok(None),
+        };
+        let blob = match index.get_bypath(path, 0) {
+            Some(entry) => match self.try_lookup_blob(&entry.id) {
+                Ok(blob) => blob,
+                Err(e) => return Err(e.to_error(&entry.id)),
+            },
+            None => return Ok(None),
+        };
+        let data = match io::read_whole_file(&Path(workdir).push_rel(&Path(path))) {
+            Ok(data) => data,
+            Err(msg) => return Err( (msg, GITERR_OS) ),
+        };
+        Ok( Some(blob.rawcontent_as_slice(|old| change_kind(old, data))) )
+    }
+
+    /// classify the change of `path` between HEAD and the index
+    fn classify_index_change(&self, index: &GitIndex, head: &Option<~Tree>, path: &str)
+        -> Result<Option<ChangeKind>, (~str, GitError)>
+    {
+        let old_id = match *head {
+            Some(ref tree) => match tree.entry_bypath(path) {
+                Some(entry) => *entry.id(),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let new_id = match index.get_bypath(path, 0) {
+            Some(entry) => entry.id,
+            None => return Ok(None),
+        };
+        let old_blob = match self.try_lookup_blob(&old_id) {
+            Ok(blob) => blob,
+            Err(e) => return Err(e.to_error(&old_id)),
+        };
+        let new_blob = match self.try_lookup_blob(&new_id) {
+            Ok(blob) => blob,
+            Err(e) => return Err(e.to_error(&new_id)),
+        };
+        do old_blob.rawcontent_as_slice |old| {
+            do new_blob.rawcontent_as_slice |new| {
+                Ok( Some(change_kind(old, new)) )
+            }
+        }
+    }
+
+    /// Whether the index and the working directory match HEAD, ignoring untracked
+    /// files, like an empty `git status --porcelain --untracked-files=no`.
+    ///
+    /// This stops at the first difference instead of gathering the whole status.
+    pub fn is_clean(&self) -> Result<bool, (~str, GitError)>
+    {
+        let mut opts = StatusOption::new();
+        opts.include_untracked = false;
+        self.each_status_with(&opts, |_, _| false)
+    }
+
+    /// run `op` for each status entry selected by `opts`, until it returns false;
+    /// returns Ok(false) if it did
+    fn each_status_with(&self, opts: &StatusOption, op: &fn(~[u8], c_uint) -> bool)
+        -> Result<bool, (~str, GitError)>
+    {
+        if opts.ignore_filemode {
+            let index = match self.index() {
+                Ok(index) => index,
+                Err(e) => return Err(e),
+            };
+            let head = self.head_tree();
+            let mut plain = copy *opts;
+            plain.ignore_filemode = false;
+            return do self.each_status_with(&plain) |path, status_flags| {
+                let status_flags = self.without_mode_changes(index, &head, lossy_utf8(path),
+                                                             status_flags);
+                if status_flags == 0 && !opts.include_unmodified {
+                    true
+                } else {
+                    op(path, status_flags)
+                }
+            };
+        }
+
+        let mut flags = 0 as c_uint;
+        if opts.include_untracked { flags |= ext::GIT_STATUS_OPT_INCLUDE_UNTRACKED; }
+        if opts.include_ignored { flags |= ext::GIT_STATUS_OPT_INCLUDE_IGNORED; }
+        if opts.include_unmodified { flags |= ext::GIT_STATUS_OPT_INCLUDE_UNMODIFIED; }
+        if opts.recurse_untracked_dirs { flags |= ext::GIT_STATUS_OPT_RECURSE_UNTRACKED_DIRS; }
+        let pathspec = do opts.pathspec.map |path| {
+            do path.as_c_str |c_path| { c_path }
+        };
+        let c_opts = ext::git_status_options {
+            version: 1,     // GIT_STATUS_OPTIONS_VERSION
+            show: opts.show as c_int,
+            flags: flags,
+            pathspec: ext::git_strarray {
+                strings: std::vec::raw::to_ptr(pathspec),
+                count: pathspec.len() as u64,
+            },
+            baseline: ptr::null(),
+        };
+
+        unsafe {
+            let payload: *c_void = cast::transmute(&op);
+            let res = do trace::timed("git_status_foreach_ext") {
+                ext::git_status_foreach_ext(self.repo, &c_opts, git_status_bytes_cb, payload)
+            };
+            match res {
+                0 => Ok(true),
+                // the callback stopped the loop
+                res if res > 0 => Ok(false),
+                _ => Err( last_error() ),
+            }
+        }
+    }
+
+    /// clear the modified flags of `path` when its content is the same on both sides,
+    /// i.e. when only its mode changed
+    fn without_mode_changes(&self, index: &GitIndex, head: &Option<~Tree>, path: &str,
+                            status_flags: c_uint) -> c_uint
+    {
+        let mut status_flags = status_flags;
+        let staged = match index.get_bypath(path, 0) {
+            Some(entry) => entry,
+            None => return status_flags,
+        };
+        if status_flags & ext::GIT_STATUS_INDEX_MODIFIED != 0 {
+            let head_entry = match *head {
+                Some(ref tree) => tree.entry_bypath(path),
+                None => None,
+            };
+            match head_entry {
+                Some(entry) => if *entry.id() == staged.id {
+                    status_flags &= !ext::GIT_STATUS_INDEX_MODIFIED;
+                },
+                None => (),
+            }
+        }
+        if status_flags & ext::GIT_STATUS_WT_MODIFIED != 0 {
+            let mut oid = OID { id: [0, .. 20] };
+            let res = do path.as_c_str |c_path| {
+                unsafe {
+                    ext::git_repository_hashfile(&mut oid, self.repo, c_path, GIT_OBJ_BLOB,
+                                                 c_path)
+                }
+            };
+            if res == 0 && oid == staged.id {
+                status_flags &= !ext::GIT_STATUS_WT_MODIFIED;
+            }
+        }
+        status_flags
+    }
+
+    /// Status of a bare repository: compare the tree of HEAD with `tree`, as if
+    /// `tree` was the content of the index.
+    ///
+    /// `status` needs a working directory; this works on any repository, which lets
+    /// server-side tools process the changes of a push with the same code.
+    /// Only the `index_*` flags are set; an unborn HEAD is compared as an empty tree.
+    pub fn status_against_tree(&self, tree: &Tree) -> Result<~[(~str, ~Status)], (~str, GitError)>
+    {
+        let head = self.head_tree();
+        let old_t = match head {
+            Some(ref t) => t.tree,
+            None => ptr::null(),
+        };
+        do diff::with_c_opts(&diff::DiffOption::default()) |c_opts| {
+            unsafe {
+                let mut diff_list: *ext::git_diff = ptr::null();
+                let res = do trace::timed("git_diff_tree_to_tree") {
+                    ext::git_diff_tree_to_tree(&mut diff_list, self.repo, old_t, tree.tree, c_opts)
+                };
+                if res == 0 {
+                    Ok( status_from_diff(&DiffList { difflist: diff_list }) )
+                } else {
+                    Err( last_error() )
+                }
+            }
         }
     }
 
-    /// Safer variant of each_status
-    pub fn status(&self) -> ~[(~str, ~Status)] {
-        let mut status_list:~[(~str, ~Status)] = ~[];
-        unsafe {
-            for self.each_status |path, status_flags| {
-                let status = ~Status {
-                    index_new: status_flags & ext::GIT_STATUS_INDEX_NEW != 0,
-                    index_modified: status_flags & ext::GIT_STATUS_INDEX_MODIFIED != 0,
-                    index_deleted: status_flags & ext::GIT_STATUS_INDEX_DELETED != 0,
-                    index_renamed: status_flags & ext::GIT_STATUS_INDEX_RENAMED != 0,
-                    index_typechange: status_flags & ext::GIT_STATUS_INDEX_TYPECHANGE != 0,
-                    wt_new: status_flags & ext::GIT_STATUS_WT_NEW != 0,
-                    wt_modified: status_flags & ext::GIT_STATUS_WT_MODIFIED != 0,
-                    wt_deleted: status_flags & ext::GIT_STATUS_WT_DELETED != 0,
-                    wt_typechange: status_flags & ext::GIT_STATUS_WT_TYPECHANGE != 0,
-                    ignored: status_flags & ext::GIT_STATUS_IGNORED != 0,
+    /// Status of a bare repository: compare the tree of HEAD with `index`, which may
+    /// be an index built in memory.
+    ///
+    /// See `status_against_tree`.
+    pub fn status_against_index(&self, index: &GitIndex)
+        -> Result<~[(~str, ~Status)], (~str, GitError)>
+    {
+        let head = self.head_tree();
+        let old_t = match head {
+            Some(ref t) => t.tree,
+            None => ptr::null(),
+        };
+        do diff::with_c_opts(&diff::DiffOption::default()) |c_opts| {
+            unsafe {
+                let mut diff_list: *ext::git_diff = ptr::null();
+                let res = do trace::timed("git_diff_tree_to_index") {
+                    ext::git_diff_tree_to_index(&mut diff_list, self.repo, old_t, index.index,
+                                                c_opts)
                 };
-                status_list.push((path, status));
-            };
+                if res == 0 {
+                    Ok( status_from_diff(&DiffList { difflist: diff_list }) )
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+
+    /// tree of the commit HEAD points to, None if HEAD is unborn
+    fn head_tree<'r>(&'r self) -> Option<~Tree<'r>> {
+        match self.head() {
+            Some(head) => match self.lookup_commit(&head.resolve()) {
+                Some(commit) => match commit.tree() {
+                    Ok(tree) => self.lookup_tree(tree.id()),
+                    Err(_) => None,
+                },
+                None => None,
+            },
+            None => None,
         }
-        status_list
     }
 
 
@@ -330,13 +2267,19 @@ impl Repository {
     pub fn branch_create<'r>(&'r mut self, branch_name: &str, target: &Commit, force: bool)
         -> Option<~Reference<'r>>
     {
+        if self.refuse_write() {
+            return None;
+        }
         let mut ptr: *ext::git_reference = ptr::null();
         let flag = force as c_int;
         unsafe {
             do branch_name.as_c_str |c_name| {
                 let res = ext::git_branch_create(&mut ptr, self.repo, c_name, target.commit, flag);
                 match res {
-                    0 => Some( ~Reference { c_ref: ptr, owner: self } ),
+                    0 => {
+                        self.notify_ref_updated(~"refs/heads/" + branch_name, target.id());
+                        Some( ~Reference { c_ref: ptr, owner: self } )
+                    },
                     ext::GIT_EINVALIDSPEC => None,
                     _ => { raise(); None },
                 }
@@ -344,21 +2287,85 @@ impl Repository {
         }
     }
 
-    /// Loop over all the branches and issue a callback for each one.
+    /// Create a new branch pointing at an annotated commit, like `branch_create`.
+    ///
+    /// The reflog of the new branch tells where the commit came from, e.g.
+    /// "branch: Created from origin/master" when the commit was found with
+    /// `annotated_commit_from_revspec("origin/master")`, as `git branch` writes it.
+    pub fn branch_create_from_annotated<'r>(&'r self, branch_name: &str,
+                                            target: &AnnotatedCommit, force: bool)
+        -> Result<~Reference<'r>, (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let mut ptr: *ext::git_reference = ptr::null();
+        unsafe {
+            let res = do branch_name.as_c_str |c_name| {
+                ext::git_branch_create_from_annotated(&mut ptr, self.repo, c_name, target.commit,
+                                                      force as c_int)
+            };
+            if res == 0 {
+                self.notify_ref_updated(~"refs/heads/" + branch_name, target.id());
+                Ok( ~Reference { c_ref: ptr, owner: self } )
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// Loop over all the branches and issue a callback for each one, with the short
+    /// name of the branch, e.g. "master" or "origin/master".
+    ///
+    /// Returns false if `op` returned false to stop the iteration.
     pub fn branch_foreach(&self, local: bool, remote: bool,
         op: &fn(name: &str, is_remote: bool) -> bool) -> bool
     {
         let flocal = if local { ext::GIT_BRANCH_LOCAL } else { 0 };
         let fremote = if remote { ext::GIT_BRANCH_REMOTE } else { 0 };
-        let flags = flocal & fremote;
+        let flags = flocal | fremote;
+        if flags == 0 {
+            return true;
+        }
         unsafe {
-            let payload: *c_void = cast::transmute(&op);
-            let res = ext::git_branch_foreach(self.repo, flags, git_branch_foreach_cb, payload);
-            match res {
-                0 => true,
-                ext::GIT_EUSER => false,
-                _ => { raise(); false },
+            let mut iter: *ext::git_branch_iterator = ptr::null();
+            if ext::git_branch_iterator_new(&mut iter, self.repo, flags) != 0 {
+                raise();
+                return false;
+            }
+            let mut finished = true;
+            loop {
+                let mut c_ref: *ext::git_reference = ptr::null();
+                let mut branch_type: ext::git_branch_t = 0;
+                match ext::git_branch_next(&mut c_ref, &mut branch_type, iter) {
+                    0 => (),
+                    ext::GIT_ITEROVER => break,
+                    _ => {
+                        raise();
+                        finished = false;
+                        break;
+                    },
+                }
+                let mut c_name: *c_char = ptr::null();
+                let res = ext::git_branch_name(&mut c_name, c_ref);
+                let name = if res == 0 { Some(from_c_str(c_name)) } else { None };
+                ext::git_reference_free(c_ref);
+                let name = match name {
+                    Some(name) => name,
+                    None => {
+                        raise();
+                        finished = false;
+                        break;
+                    },
+                };
+                if !op(name, branch_type == ext::GIT_BRANCH_REMOTE) {
+                    finished = false;
+                    break;
+                }
             }
+            ext::git_branch_iterator_free(iter);
+            finished
         }
     }
 
@@ -366,20 +2373,17 @@ impl Repository {
     /// given the name of a local branch reference.
     pub fn upstream_name(&self, canonical_branch_name: &str) -> Option<~str>
     {
-        let mut buf: [c_char, ..1024] = [0, ..1024];
         do canonical_branch_name.as_c_str |c_name| {
-            do as_mut_buf(buf) |v, _len| {
-                unsafe {
-                    let res = ext::git_branch_upstream_name(v, 1024, self.repo, c_name);
-                    if res >= 0 {
-                        let ptr: *c_char = cast::transmute(v);
-                        Some( from_c_str_len(ptr, res as uint) )
-                    } else if res == ext::GIT_ENOTFOUND {
-                        None
-                    } else {
-                        raise();
-                        None
-                    }
+            unsafe {
+                let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+                match ext::git_branch_upstream_name(&mut buf, self.repo, c_name) {
+                    0 => {
+                        let name = from_c_str_len(buf.ptr, buf.size as uint);
+                        ext::git_buf_dispose(&mut buf);
+                        Some(name)
+                    },
+                    ext::GIT_ENOTFOUND => None,
+                    _ => { raise(); None },
                 }
             }
         }
@@ -391,17 +2395,15 @@ impl Repository {
     pub fn git_branch_remote_name(&self, canonical_branch_name: &str)
         -> Result<~str, (~str, GitError)>
     {
-        let mut buf: [c_char, ..1024] = [0, ..1024];
         do canonical_branch_name.as_c_str |c_name| {
-            do as_mut_buf(buf) |v, _len| {
-                unsafe {
-                    let res = ext::git_branch_remote_name(v, 1024, self.repo, c_name);
-                    if res >= 0 {
-                        let ptr: *c_char = cast::transmute(v);
-                        Ok( from_c_str_len(ptr, res as uint) )
-                    } else {
-                        Err( last_error() )
-                    }
+            unsafe {
+                let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+                if ext::git_branch_remote_name(&mut buf, self.repo, c_name) == 0 {
+                    let name = from_c_str_len(buf.ptr, buf.size as uint);
+                    ext::git_buf_dispose(&mut buf);
+                    Ok(name)
+                } else {
+                    Err( last_error() )
                 }
             }
         }
@@ -425,6 +2427,10 @@ impl Repository {
     pub fn blob_create_fromworkdir<'r>(&'r self, relative_path: &str)
         -> Result<~Blob<'r>, (~str, GitError)>
     {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
         let mut oid = OID { id: [0, ..20] };
         let mut ptr: *ext::git_blob = ptr::null();
         do relative_path.as_c_str |c_path| {
@@ -446,6 +2452,10 @@ impl Repository {
     pub fn blob_create_fromdisk<'r>(&'r self, relative_path: &str)
         -> Result<~Blob<'r>, (~str, GitError)>
     {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
         let mut oid = OID { id: [0, ..20] };
         let mut ptr: *ext::git_blob = ptr::null();
         do relative_path.as_c_str |c_path| {
@@ -462,8 +2472,13 @@ impl Repository {
         }
     }
 
-    /// Write a loose blob to the Object Database from a
-    /// provider of chunks of data.
+    /// Write a loose blob to the Object Database with the content of `reader`,
+    /// which is read to its end.
+    ///
+    /// The content is not held in memory: like `git_blob_create_fromstream`, it is
+    /// copied block by block to a temporary file in the objects directory, which
+    /// libgit2 then hashes and compresses. Only the filters which need the whole
+    /// content, like the end of line conversions, load it.
     ///
     /// Provided the `hintpath` parameter is not None, its value
     /// will help to determine what git filters should be applied
@@ -471,24 +2486,66 @@ impl Repository {
     pub fn blob_create_fromreader<'r>(&'r self, reader: &Reader, hintpath: Option<&str>)
         -> Result<~Blob<'r>, (~str, GitError)>
     {
-        let mut oid = OID { id: [0, ..20] };
-        unsafe {
-            let c_path =
-            match hintpath {
-                None => ptr::null(),
-                Some(pathref) => pathref.as_c_str(|ptr| {ptr}),
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let name = fmt!("tmp_blob_%u", rand::random::<uint>());
+        let tmp = match self.path() {
+            Some(path) => Path(path).push_many(["objects", name]),
+            // a repository in memory has no objects directory
+            None => os::tmpdir().push(name),
+        };
+        let result = match copy_to_new_file(reader, &tmp) {
+            Ok(()) => match hintpath {
+                None => self.blob_create_fromdisk(tmp.to_str()),
+                Some(path) => self.blob_create_filtered(&tmp, path),
+            },
+            Err(e) => Err(e),
+        };
+        os::remove_file(&tmp);
+        result
+    }
+
+    /// Write the content of `file` as a blob after applying the filters configured
+    /// for `path`, e.g. the end of line conversions of core.autocrlf
+    fn blob_create_filtered<'r>(&'r self, file: &Path, path: &str)
+        -> Result<~Blob<'r>, (~str, GitError)>
+    {
+        let oid = unsafe {
+            let mut filters: *ext::git_filter_list = ptr::null();
+            let res = do path.as_c_str |c_path| {
+                ext::git_filter_list_load(&mut filters, self.repo, ptr::null(), c_path,
+                                          ext::GIT_FILTER_TO_ODB, ext::GIT_FILTER_DEFAULT)
+            };
+            if res != 0 {
+                return Err( last_error() );
+            }
+            if filters == ptr::null() {
+                // no filter applies to the path
+                return self.blob_create_fromdisk(file.to_str());
+            }
+            let mut output = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+            let res = do file.to_str().as_c_str |c_file| {
+                ext::git_filter_list_apply_to_file(&mut output, filters, self.repo, c_file)
             };
-            let payload: *c_void = cast::transmute(&reader);
-            if (ext::git_blob_create_fromchunks(&mut oid, self.repo, c_path, git_blob_chunk_cb,
-                    payload) == 0) {
-                let mut ptr: *ext::git_blob = ptr::null();
-                if ext::git_blob_lookup(&mut ptr, self.repo, &oid) != 0 {
-                    fail!(~"blob lookup failure");
-                }
-                Ok( ~Blob { blob: ptr, owner: self } )
+            ext::git_filter_list_free(filters);
+            let oid = if res == 0 {
+                do std::vec::raw::buf_as_slice(output.ptr as *u8, output.size as uint) |data| {
+                    self.blob_write(data)
+                }
             } else {
                 Err( last_error() )
-            }
+            };
+            ext::git_buf_dispose(&mut output);
+            oid
+        };
+        match oid {
+            Ok(oid) => match self.try_lookup_blob(&oid) {
+                Ok(blob) => Ok(blob),
+                Err(e) => Err(e.to_error(&oid)),
+            },
+            Err(e) => Err(e),
         }
     }
 
@@ -496,16 +2553,35 @@ impl Repository {
     pub fn blob_create_frombuffer<'r>(&'r self, buffer: &[u8])
         -> Result<~Blob<'r>, (~str, GitError)>
     {
+        let oid = match self.blob_write(buffer) {
+            Ok(oid) => oid,
+            Err(e) => return Err(e),
+        };
+        let mut ptr: *ext::git_blob = ptr::null();
+        unsafe {
+            if ext::git_blob_lookup(&mut ptr, self.repo, &oid) != 0 {
+                return Err( last_error() );
+            }
+        }
+        Ok( ~Blob { blob: ptr, owner: self } )
+    }
+
+    /// Write an in-memory buffer to the ODB as a blob, returning only its id.
+    ///
+    /// Unlike `blob_create_frombuffer`, the blob is not looked up again after
+    /// the write.
+    pub fn blob_write(&self, buffer: &[u8]) -> Result<OID, (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
         let mut oid = OID { id: [0, ..20] };
         do as_imm_buf(buffer) |v, len| {
             unsafe {
                 let buf:*c_void = cast::transmute(v);
                 if ext::git_blob_create_frombuffer(&mut oid, self.repo, buf, len as u64) == 0 {
-                    let mut ptr: *ext::git_blob = ptr::null();
-                    if ext::git_blob_lookup(&mut ptr, self.repo, &oid) != 0 {
-                        fail!(~"blob lookup failure");
-                    }
-                    Ok( ~Blob { blob: ptr, owner: self } )
+                    Ok(oid)
                 } else {
                     Err( last_error() )
                 }
@@ -513,6 +2589,101 @@ impl Repository {
         }
     }
 
+    /// Read `reader` to its end and write the content to the ODB as a blob,
+    /// returning its id.
+    ///
+    /// The content is read into `scratch`, whose previous content is dropped;
+    /// passing the same buffer to successive calls reuses its allocation, so that
+    /// importing many files does not allocate a buffer for each of them.
+    pub fn blob_write_from_reader(&self, reader: &Reader, scratch: &mut ~[u8])
+        -> Result<OID, (~str, GitError)>
+    {
+        scratch.truncate(0);
+        read_to_end(reader, scratch);
+        self.blob_write(*scratch)
+    }
+
+    /// Compress the objects written to the repository from now on with the zlib
+    /// level `compression_level`.
+    ///
+    /// The object database of the repository is replaced by a new one, made like the
+    /// default one, from the packs and the alternates of the repository, but whose
+    /// only loose backend is one at this level (see `Odb::add_loose_backend`), so that
+    /// calling this again changes the level. The `Odb` handles taken before, and the
+    /// mempacks added to them, keep the previous database.
+    pub fn set_compression_level(&self, compression_level: int) -> Result<(), (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let objects_dir = match self.disk_path() {
+            Ok(path) => Path(path).push("objects"),
+            Err(e) => return Err(e),
+        };
+        let odb = unsafe {
+            let mut c_odb: *ext::git_odb = ptr::null();
+            if ext::git_odb_new(&mut c_odb) != 0 {
+                return Err( last_error() );
+            }
+            // frees this reference when dropped
+            Odb { odb: c_odb, owner: self }
+        };
+        match odb.add_loose_backend(compression_level) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        match odb.add_pack_backend(objects_dir.to_str()) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        // like libgit2, relative alternates are relative to the objects directory
+        match io::read_whole_file_str(&objects_dir.push_many(["info", "alternates"])) {
+            Ok(alternates) => {
+                for alternates.line_iter().advance |line| {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with("#") {
+                        loop;
+                    }
+                    let path = if line.starts_with("/") {
+                        Path(line)
+                    } else {
+                        objects_dir.push_rel(&Path(line))
+                    };
+                    match odb.add_disk_alternate(path.to_str()) {
+                        Ok(()) => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+            },
+            Err(_) => (),
+        }
+        unsafe {
+            // the repository takes its own reference
+            ext::git_repository_set_odb(self.repo, odb.odb);
+        }
+        Ok(())
+    }
+
+    /// Write the content of `reader` as chunk blobs listed by a manifest tree.
+    ///
+    /// See `chunked::write`, which also points a reference to the manifest so that
+    /// the chunks are not pruned.
+    pub fn blob_write_chunked(&self, reader: &Reader, opts: &chunked::ChunkOption)
+        -> Result<chunked::ChunkedBlob, (~str, GitError)>
+    {
+        chunked::write(self, reader, opts)
+    }
+
+    /// Write the content stored by `blob_write_chunked` to `writer`.
+    ///
+    /// See `chunked::read`.
+    pub fn blob_read_chunked(&self, manifest: &OID, writer: &io::Writer)
+        -> Result<(), (~str, GitError)>
+    {
+        chunked::read(self, manifest, writer)
+    }
+
     /// Create new commit in the repository from a list of Commit pointers
     ///
     /// Returns the created commit. The commit will be written to the Object Database and
@@ -544,10 +2715,48 @@ impl Repository {
     ///
     /// parents: Vector of Commit objects that will be used as the parents for this commit.
     ///  All the given commits must be owned by `self`.
+    ///
+    /// When `commit.gpgsign` is set and a signer is set with `set_commit_signer`, the
+    /// commit is signed with it; see `commit_signing_requested`. Files staged with
+    /// `GitIndex::add_bypath` already went through the `core.autocrlf` and
+    /// `.gitattributes` filters.
+    ///
+    /// raises git_error with GITERR_INVALID, without writing anything, when the tree
+    /// or a parent belongs to another Repository handle or is missing from the object
+    /// database; see `check_commit_parts`.
     pub fn commit<'r>(&'r self, update_ref: Option<&str>, author: &Signature,
             committer: &Signature, message_encoding: Option<&str>, message: &str, tree: &Tree,
             parents: &[~Commit<'r>]) -> OID
     {
+        match self.try_commit(update_ref, author, committer, message_encoding, message, tree,
+                              parents) {
+            Ok(oid) => oid,
+            Err(e) => {
+                git_error::cond.raise(e);
+                OID { id: [0, .. 20] }
+            },
+        }
+    }
+
+    /// Create a new commit like `commit`
+    ///
+    /// Unlike `commit`, the errors are returned instead of raising git_error.
+    pub fn try_commit<'r>(&'r self, update_ref: Option<&str>, author: &Signature,
+            committer: &Signature, message_encoding: Option<&str>, message: &str, tree: &Tree,
+            parents: &[~Commit<'r>]) -> Result<OID, (~str, GitError)>
+    {
+        match self.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        match self.check_commit_parts(tree, parents) {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        if self.commit_signing_requested() {
+            return self.commit_signed(update_ref, author, committer, message_encoding,
+                                      message, tree, parents);
+        }
         unsafe {
             let c_ref = 
             match update_ref {
@@ -564,14 +2773,187 @@ impl Repository {
             let c_message = message.as_c_str(|ptr| {ptr});
             let mut oid = OID { id: [0, .. 20] };
             let c_parents = do parents.map |p| { p.commit };
-            do as_const_buf(c_parents) |parent_ptr, len| {
+            do as_imm_buf(c_parents) |parent_ptr, len| {
                 let res = ext::git_commit_create(&mut oid, self.repo, c_ref,
                             &c_author, &c_committer, c_encoding, c_message, tree.tree,
                             len as c_int, parent_ptr);
                 if res != 0 {
-                    raise()
+                    Err( last_error() )
+                } else {
+                    self.notify_commit_created(&oid);
+                    match update_ref {
+                        Some(uref) => self.notify_ref_updated(uref, &oid),
+                        None => (),
+                    }
+                    Ok(oid)
+                }
+            }
+        }
+    }
+
+    /// `commit` with a signature: libgit2 formats the commit, `signer` signs it, and
+    /// the branch is then updated like git_commit_create does
+    fn commit_signed(&self, update_ref: Option<&str>, author: &Signature,
+            committer: &Signature, message_encoding: Option<&str>, message: &str, tree: &Tree,
+            parents: &[~Commit]) -> Result<OID, (~str, GitError)>
+    {
+        let signer = match self.signer {
+            Some(ref signer) => signer,
+            None => return Err( (~"commit.gpgsign is set, but no commit signer is set",
+                                 GITERR_INVALID) ),
+        };
+        // HEAD is followed to the branch it points to, even an unborn one
+        let refname = match update_ref {
+            Some(uref) if uref == "HEAD" => Some(self.head_name().get_or_default(~"HEAD")),
+            Some(uref) => Some(uref.to_owned()),
+            None => None,
+        };
+        // like git_commit_create, only move a branch which is at the first parent; the
+        // branch is then updated from this very value, so that a commit another writer
+        // makes in the meantime is not lost
+        let current = match refname {
+            Some(ref name) => self.lookup(*name),
+            None => None,
+        };
+        match current {
+            Some(ref branch) if parents.is_empty() || *parents[0].id() != branch.resolve() =>
+                return Err( (~"failed to create commit: current tip is not the first parent",
+                             GITERR_OBJECT) ),
+            _ => (),
+        }
+        let content = unsafe {
+            let c_author = signature::to_c_sig(author);
+            let c_committer = signature::to_c_sig(committer);
+            let c_encoding = match message_encoding {
+                None => ptr::null(),
+                Some(enc) => enc.as_c_str(|ptr| {ptr}),
+            };
+            let c_parents = do parents.map |p| { p.commit };
+            let mut buf = ext::git_buf { ptr: ptr::null(), asize: 0, size: 0 };
+            let res = do message.as_c_str |c_message| {
+                do as_imm_buf(c_parents) |parent_ptr, len| {
+                    ext::git_commit_create_buffer(&mut buf, self.repo, &c_author, &c_committer,
+                                                  c_encoding, c_message, tree.tree,
+                                                  len as size_t, parent_ptr)
+                }
+            };
+            if res != 0 {
+                return Err( last_error() );
+            }
+            let content = from_c_str_len(buf.ptr, buf.size as uint);
+            ext::git_buf_dispose(&mut buf);
+            content
+        };
+        let signature = match (*signer)(content) {
+            Ok(signature) => signature,
+            Err(e) => return Err(e),
+        };
+
+        let mut oid = OID { id: [0, .. 20] };
+        let res = do content.as_c_str |c_content| {
+            do signature.as_c_str |c_signature| {
+                unsafe {
+                    // a null field is "gpgsig"
+                    ext::git_commit_create_with_signature(&mut oid, self.repo, c_content,
+                                                          c_signature, ptr::null())
+                }
+            }
+        };
+        if res != 0 {
+            return unsafe { Err( last_error() ) };
+        }
+        self.notify_commit_created(&oid);
+
+        let name = match refname {
+            Some(name) => name,
+            None => return Ok(oid),
+        };
+        let summary = match message.find('\n') {
+            Some(end) => message.slice_to(end),
+            None => message,
+        };
+        let msg = if parents.is_empty() {
+            reflog::initial_commit_message(summary)
+        } else {
+            reflog::commit_message(summary)
+        };
+        // set_target fails with GIT_EMODIFIED if the branch moved since it was read,
+        // and reference_create without force if the branch was created since then
+        let res = match current {
+            Some(branch) => branch.set_target(&oid, Some(msg.as_slice())),
+            None => self.reference_create(name, &oid, false, Some(msg.as_slice())),
+        };
+        match res {
+            Ok(_) => Ok(oid),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check that a commit of `tree` with `parents` can be created in this repository:
+    /// the tree and the parents must have been looked up through this very handle,
+    /// not through another Repository, even one opened on the same path, and they
+    /// must exist in the object database.
+    ///
+    /// Returns an error of class GITERR_INVALID naming the faulty object otherwise.
+    pub fn check_commit_parts(&self, tree: &Tree, parents: &[~Commit])
+        -> Result<(), (~str, GitError)>
+    {
+        if tree.owner.repo != self.repo {
+            return Err( (fmt!("tree %s belongs to another repository", tree.id().to_str()),
+                         GITERR_INVALID) );
+        }
+        for parents.iter().advance |parent| {
+            if parent.owner.repo != self.repo {
+                return Err( (fmt!("parent %s belongs to another repository",
+                                  parent.id().to_str()), GITERR_INVALID) );
+            }
+        }
+        let odb = match self.odb() {
+            Ok(odb) => odb,
+            Err(e) => return Err(e),
+        };
+        if !odb.exists(tree.id()) {
+            return Err( (fmt!("tree %s is missing from the object database",
+                              tree.id().to_str()), GITERR_INVALID) );
+        }
+        for parents.iter().advance |parent| {
+            if !odb.exists(parent.id()) {
+                return Err( (fmt!("parent %s is missing from the object database",
+                                  parent.id().to_str()), GITERR_INVALID) );
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a diff list with the difference between two tree objects,
+    /// without a notify callback.
+    ///
+    /// Pass None to indicate an empty tree, although it is an error to pass
+    /// None for both the `old_tree` and `new_tree`.
+    pub fn diff_trees(&self, old_tree: Option<&Tree>, new_tree: Option<&Tree>,
+            opts: &diff::DiffOption) -> Result<~DiffList, (~str, GitError)>
+    {
+        let old_t = match old_tree {
+            None => ptr::null(),
+            Some(t) => t.tree,
+        };
+
+        let new_t = match new_tree {
+            None => ptr::null(),
+            Some(t) => t.tree,
+        };
+
+        do diff::with_c_opts(opts) |c_opts| {
+            unsafe {
+                let mut diff_list: *ext::git_diff = ptr::null();
+                let res = do trace::timed("git_diff_tree_to_tree") {
+                    ext::git_diff_tree_to_tree(&mut diff_list, self.repo, old_t, new_t, c_opts)
+                };
+                if res == 0 {
+                    Ok( ~DiffList { difflist: diff_list } )
+                } else {
+                    Err( last_error() )
                 }
-                oid
             }
         }
     }
@@ -586,7 +2968,7 @@ impl Repository {
     /// pass None to indicate an empty tree, although it is an error to pass
     /// None for both the `old_tree` and `new_tree`.
     ///
-    /// @param diff Output pointer to a git_diff_list pointer to be allocated.
+    /// @param diff Output pointer to a git_diff pointer to be allocated.
     /// @param repo The repository containing the trees.
     /// @param old_tree A git_tree object to diff from, or NULL for empty tree.
     /// @param new_tree A git_tree object to diff to, or NULL for empty tree.
@@ -623,19 +3005,25 @@ impl Repository {
             let c_opts = ext::git_diff_options {
                 version: 1,     // GIT_DIFF_OPTIONS_VERSION
                 flags: flags,
-                context_lines: opts.context_lines,
-                interhunk_lines: opts.interhunk_lines,
-                old_prefix: do opts.old_prefix.as_c_str |c_pref| { c_pref },
-                new_prefix: do opts.new_prefix.as_c_str |c_pref| { c_pref },
+                ignore_submodules: 0,   // GIT_SUBMODULE_IGNORE_UNSPECIFIED
                 pathspec: c_pathspec,
-                max_size: opts.max_size,
                 notify_cb: git_diff_notify_cb,
-                notify_payload: cast::transmute(&notify_cb),
+                progress_cb: ptr::null(),
+                payload: cast::transmute(&notify_cb),
+                context_lines: opts.context_lines as u32,
+                interhunk_lines: opts.interhunk_lines as u32,
+                id_abbrev: 0,
+                max_size: opts.max_size,
+                old_prefix: do opts.old_prefix.as_c_str |c_pref| { c_pref },
+                new_prefix: do opts.new_prefix.as_c_str |c_pref| { c_pref },
             };
 
-            let mut diff_list: *ext::git_diff_list = ptr::null();
+            let mut diff_list: *ext::git_diff = ptr::null();
 
-            if ext::git_diff_tree_to_tree(&mut diff_list, self.repo, old_t, new_t, &c_opts) == 0 {
+            let res = do trace::timed("git_diff_tree_to_tree") {
+                ext::git_diff_tree_to_tree(&mut diff_list, self.repo, old_t, new_t, &c_opts)
+            };
+            if res == 0 {
                 Ok( ~DiffList { difflist: diff_list } )
             } else {
                 Err( last_error() )
@@ -644,12 +3032,86 @@ impl Repository {
     }
 }
 
+fn status_from_flags(status_flags: c_uint) -> ~Status
+{
+    ~Status {
+        index_new: status_flags & ext::GIT_STATUS_INDEX_NEW != 0,
+        index_modified: status_flags & ext::GIT_STATUS_INDEX_MODIFIED != 0,
+        index_deleted: status_flags & ext::GIT_STATUS_INDEX_DELETED != 0,
+        index_renamed: status_flags & ext::GIT_STATUS_INDEX_RENAMED != 0,
+        index_typechange: status_flags & ext::GIT_STATUS_INDEX_TYPECHANGE != 0,
+        wt_new: status_flags & ext::GIT_STATUS_WT_NEW != 0,
+        wt_modified: status_flags & ext::GIT_STATUS_WT_MODIFIED != 0,
+        wt_deleted: status_flags & ext::GIT_STATUS_WT_DELETED != 0,
+        wt_typechange: status_flags & ext::GIT_STATUS_WT_TYPECHANGE != 0,
+        wt_renamed: status_flags & ext::GIT_STATUS_WT_RENAMED != 0,
+        wt_unreadable: status_flags & ext::GIT_STATUS_WT_UNREADABLE != 0,
+        ignored: status_flags & ext::GIT_STATUS_IGNORED != 0,
+        conflicted: status_flags & ext::GIT_STATUS_CONFLICTED != 0,
+    }
+}
+
+/// how the content changed from `old` to `new`
+fn change_kind(old: &[u8], new: &[u8]) -> ChangeKind
+{
+    if old == new {
+        ChangeModeOnly
+    } else if without_cr(old) == without_cr(new) {
+        ChangeEolOnly
+    } else if without_whitespace(old) == without_whitespace(new) {
+        ChangeWhitespaceOnly
+    } else {
+        ChangeContent
+    }
+}
+
+/// `data` with its CRLF line endings turned into LF
+fn without_cr(data: &[u8]) -> ~[u8]
+{
+    let mut out = std::vec::with_capacity(data.len());
+    for data.iter().enumerate().advance |(i, &b)| {
+        if b == '\r' as u8 && i + 1 < data.len() && data[i + 1] == '\n' as u8 {
+            loop;
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// `data` without spaces, tabs and line endings
+fn without_whitespace(data: &[u8]) -> ~[u8]
+{
+    data.iter().filter(|&&b| {
+        b != ' ' as u8 && b != '\t' as u8 && b != '\n' as u8 && b != '\r' as u8 &&
+            b != '\x0b' as u8 && b != '\x0c' as u8
+    }).transform(|&b| b).collect()
+}
+
+/// index flags of the status of each delta of a diff between HEAD and the index
+fn status_from_diff(diff: &DiffList) -> ~[(~str, ~Status)]
+{
+    let mut status_list = ~[];
+    for diff.deltas().iter().advance |delta| {
+        let mut status = ~Status::new();
+        let path = match delta.status {
+            GIT_DELTA_ADDED => { status.index_new = true; copy delta.new_file.path },
+            GIT_DELTA_DELETED => { status.index_deleted = true; copy delta.old_file.path },
+            GIT_DELTA_MODIFIED => { status.index_modified = true; copy delta.new_file.path },
+            GIT_DELTA_RENAMED => { status.index_renamed = true; copy delta.new_file.path },
+            GIT_DELTA_TYPECHANGE => { status.index_typechange = true; copy delta.new_file.path },
+            _ => loop,
+        };
+        status_list.push((path, status));
+    }
+    status_list
+}
+
 extern fn git_status_cb(path: *c_char, status_flags: c_uint, payload: *c_void) -> c_int
 {
     unsafe {
         let op_ptr: *&fn(~str, c_uint) -> bool = cast::transmute(payload);
         let op = *op_ptr;
-        let path_str = from_c_str(path);
+        let path_str = lossy_utf8(from_c_bytes(path));
         if op(path_str, status_flags) {
             0
         } else {
@@ -658,38 +3120,73 @@ extern fn git_status_cb(path: *c_char, status_flags: c_uint, payload: *c_void) -
     }
 }
 
-extern fn git_blob_chunk_cb(content: *mut u8, max_length: size_t, payload: *&Reader) -> c_int
+extern fn git_status_bytes_cb(path: *c_char, status_flags: c_uint, payload: *c_void) -> c_int
 {
-    let len = max_length as uint;
     unsafe {
-        let reader = *payload;
-        do mut_buf_as_slice(content, len) |v| {
-            if reader.eof() {
-                0
-            } else {
-                reader.read(v, len) as c_int
-            }
+        let op_ptr: *&fn(~[u8], c_uint) -> bool = cast::transmute(payload);
+        let op = *op_ptr;
+        if op(from_c_bytes(path), status_flags) {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Append the content of `reader`, up to its end, to `buf`
+fn read_to_end(reader: &Reader, buf: &mut ~[u8])
+{
+    while !reader.eof() {
+        let len = buf.len();
+        buf.grow(READ_BLOCK_SIZE, &0u8);
+        let read = reader.read(buf.mut_slice(len, len + READ_BLOCK_SIZE), READ_BLOCK_SIZE);
+        buf.truncate(len + read);
+        if read == 0 {
+            break;
         }
     }
 }
 
-extern fn git_branch_foreach_cb(branch_name: *c_char, branch_type: ext::git_branch_t,
-    payload: *c_void) -> c_int
+/// Copy `reader`, up to its end, to `path`, which must not exist yet, readable by
+/// the user only
+fn copy_to_new_file(reader: &Reader, path: &Path) -> Result<(), (~str, GitError)>
 {
     unsafe {
-        let op_ptr: *&fn(name: &str, is_remote: bool) -> bool = cast::transmute(payload);
-        let op = *op_ptr;
-        let branch_str = from_c_str(branch_name);
-        let is_remote = (branch_type == ext::GIT_BRANCH_REMOTE);
-        if op(branch_str, is_remote) {
-            0
-        } else {
-            1
+        let fd = do path.to_str().as_c_str |c_path| {
+            libc::open(c_path, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL, 0o600)
+        };
+        if fd < 0 {
+            return Err( (fmt!("cannot create %s: %s", path.to_str(), os::last_os_error()),
+                         GITERR_OS) );
         }
+        let mut buf = std::vec::from_elem(READ_BLOCK_SIZE, 0u8);
+        while !reader.eof() {
+            let read = reader.read(buf, READ_BLOCK_SIZE);
+            if read == 0 {
+                break;
+            }
+            let mut written = 0u;
+            while written < read {
+                let res = do as_imm_buf(buf.slice(written, read)) |data, len| {
+                    libc::write(fd, data as *c_void, len as size_t)
+                };
+                if res <= 0 {
+                    let err = os::last_os_error();
+                    libc::close(fd);
+                    return Err( (fmt!("cannot write %s: %s", path.to_str(), err), GITERR_OS) );
+                }
+                written += res as uint;
+            }
+        }
+        if libc::close(fd) != 0 {
+            return Err( (fmt!("cannot write %s: %s", path.to_str(), os::last_os_error()),
+                         GITERR_OS) );
+        }
+        Ok(())
     }
 }
 
-extern fn git_diff_notify_cb(diff_so_far: *ext::git_diff_list, delta_to_add: *DiffDelta,
+extern fn git_diff_notify_cb(diff_so_far: *ext::git_diff, delta_to_add: *DiffDelta,
     matched_pathspec: *c_char, payload: *c_void) -> c_int
 {
     unsafe {
@@ -701,10 +3198,51 @@ extern fn git_diff_notify_cb(diff_so_far: *ext::git_diff_list, delta_to_add: *Di
     }
 }
 
+impl<'self> Prefetched<'self> {
+    /// Look `id` up and keep it, unless it already is; returns the object, or None if
+    /// it is missing
+    fn load(&mut self, id: &OID, otype: OType) -> Option<*ext::git_object> {
+        match self.objects.find(id) {
+            Some(&obj) => return Some(obj),
+            None => (),
+        }
+        unsafe {
+            let mut obj: *ext::git_object = ptr::null();
+            if ext::git_object_lookup(&mut obj, self.owner.repo, id, otype) != 0 {
+                return None;
+            }
+            self.objects.insert(*id, obj);
+            Some(obj)
+        }
+    }
+
+    /// The number of objects loaded
+    pub fn len(&self) -> uint {
+        self.objects.len()
+    }
+
+    /// Check if the object `id` was loaded
+    pub fn contains(&self, id: &OID) -> bool {
+        self.objects.contains_key(id)
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for Prefetched<'self> {
+    fn finalize(&self) {
+        for self.objects.iter().advance |(_, obj)| {
+            unsafe {
+                ext::git_object_free(*obj);
+            }
+        }
+    }
+}
+
 impl Drop for Repository {
     fn finalize(&self) {
         unsafe {
             ext::git_repository_free(self.repo);
         }
+        super::shutdown();
     }
 }
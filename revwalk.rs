@@ -0,0 +1,427 @@
+use std::libc::{c_int, c_uint, c_void};
+use std::{cast, ptr, vec};
+use std::str::raw::from_c_str;
+use std::iterator::Iterator;
+use std::hashmap::{HashMap, HashSet};
+use extra::time::precise_time_ns;
+use super::*;
+use ext;
+use regex::Regex;
+
+/// Sort modes for the revision walker.
+/// These can be combined to get the reverse of a topological or time sort.
+pub enum SortMode {
+    /** Sort the repository contents in no particular ordering */
+    GIT_SORT_NONE = 0,
+    /** Sort the repository contents in topological order (parents before children) */
+    GIT_SORT_TOPOLOGICAL = (1 << 0),
+    /** Sort the repository contents by commit time */
+    GIT_SORT_TIME = (1 << 1),
+    /** Iterate through the repository contents in reverse order */
+    GIT_SORT_REVERSE = (1 << 2),
+}
+
+/// The commit dates a walk is restricted to, see `Revwalk::since` and `Revwalk::until`
+pub struct TimeBounds {
+    priv repo: *ext::git_repository,
+    priv since: Option<i64>,
+    priv until: Option<i64>,
+}
+
+/// the commit time of `id`, or None if it cannot be read
+unsafe fn commit_time(repo: *ext::git_repository, id: &OID) -> Option<i64> {
+    let mut commit: *ext::git_commit = ptr::null();
+    if ext::git_commit_lookup(&mut commit, repo, id) != 0 {
+        return None;
+    }
+    let time = ext::git_commit_time(commit);
+    ext::git_commit_free(commit);
+    Some(time)
+}
+
+extern fn hide_older_cb(id: *OID, payload: *c_void) -> c_int
+{
+    unsafe {
+        let bounds: &TimeBounds = cast::transmute(payload);
+        match (bounds.since, commit_time(bounds.repo, &*id)) {
+            (Some(since), Some(time)) if time < since => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// The part of the commits matched by `Revwalk::matching`
+pub enum MatchField {
+    /// the whole commit message
+    MatchMessage,
+    /// the author, as "Name <email>"
+    MatchAuthor,
+    /// the committer, as "Name <email>"
+    MatchCommitter,
+}
+
+/// A revision walk listing only the commits matching a regular expression,
+/// created by `Revwalk::matching`
+pub struct MatchingWalk<'self> {
+    priv walk: ~Revwalk<'self>,
+    priv regex: Regex,
+    priv field: MatchField,
+}
+
+impl<'self> Revwalk<'self> {
+    /// Reset the revision walker for reuse.
+    ///
+    /// This will clear all the pushed and hidden commits, and
+    /// leave the walker in a blank state (just like at creation)
+    /// ready to receive new commit pushes and start a new walk.
+    ///
+    /// The revision walk is automatically reset when a walk is over.
+    pub fn reset(&self)
+    {
+        unsafe {
+            ext::git_revwalk_reset(self.walk);
+        }
+    }
+
+    /// Mark a commit to start traversal from.
+    ///
+    /// The given OID must belong to a commit on the walked repository.
+    ///
+    /// The given commit will be used as one of the roots when starting the revision walk.
+    /// At least one commit must be pushed onto the walker before a walk can be started.
+    ///
+    /// raises git_error on error
+    pub fn push(&self, id: &OID)
+    {
+        unsafe {
+            if ext::git_revwalk_push(self.walk, id) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Push the repository's HEAD
+    ///
+    /// raises git_error on error
+    pub fn push_head(&self)
+    {
+        unsafe {
+            if ext::git_revwalk_push_head(self.walk) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Push the OID pointed to by a reference
+    ///
+    /// The reference must point to a commit.
+    ///
+    /// raises git_error on error
+    pub fn push_ref(&self, refname: &str)
+    {
+        do refname.as_c_str |c_name| {
+            unsafe {
+                if ext::git_revwalk_push_ref(self.walk, c_name) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Push matching references
+    ///
+    /// The OIDs pointed to by the references that match the given glob
+    /// pattern will be pushed to the revision walker.
+    ///
+    /// A leading 'refs/' is implied if not present as well as a trailing
+    /// '/\*' if the glob lacks '?', '\*' or '['.
+    ///
+    /// raises git_error on error
+    pub fn push_glob(&self, glob: &str)
+    {
+        do glob.as_c_str |c_glob| {
+            unsafe {
+                if ext::git_revwalk_push_glob(self.walk, c_glob) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Mark a commit (and its ancestors) uninteresting for the output.
+    ///
+    /// The given OID must belong to a commit on the walked repository.
+    ///
+    /// The resolved commit and all its parents will be hidden from the
+    /// output on the revision walk.
+    ///
+    /// raises git_error on error
+    pub fn hide(&self, id: &OID)
+    {
+        unsafe {
+            if ext::git_revwalk_hide(self.walk, id) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Hide the OID pointed to by a reference
+    ///
+    /// The reference must point to a commit.
+    ///
+    /// raises git_error on error
+    pub fn hide_ref(&self, refname: &str)
+    {
+        do refname.as_c_str |c_name| {
+            unsafe {
+                if ext::git_revwalk_hide_ref(self.walk, c_name) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Change the sorting mode when iterating through the
+    /// repository's contents.
+    ///
+    /// Changing the sorting mode resets the walker.
+    pub fn sorting(&self, modes: &[SortMode])
+    {
+        let mode = do modes.iter().fold(0u32) |mode, &m| {
+            mode | (m as u32)
+        };
+        unsafe {
+            ext::git_revwalk_sorting(self.walk, mode as c_uint);
+        }
+    }
+
+    /// Follow only the first parent of each commit, like `git log --first-parent`.
+    ///
+    /// On a mainline where feature branches are merged, this lists the merges but not
+    /// the commits of the feature branches. The setting lasts until `reset`.
+    ///
+    /// raises git_error on error
+    pub fn simplify_first_parent(&self)
+    {
+        unsafe {
+            if ext::git_revwalk_simplify_first_parent(self.walk) != 0 {
+                raise()
+            }
+        }
+    }
+
+    /// Leave out the commits committed before `time`, in seconds since the epoch,
+    /// like `git log --since`.
+    ///
+    /// The walk does not go past older commits: their ancestors are hidden too, even
+    /// the ones with a later date, e.g. because of a wrong clock. This keeps walks
+    /// of recent history short on large repositories. Must be called before the walk
+    /// starts.
+    ///
+    /// raises git_error on error
+    pub fn since(&mut self, time: i64)
+    {
+        let first = self.bounds.since.is_none();
+        self.bounds.since = Some(time);
+        if first {
+            unsafe {
+                let payload: *c_void = cast::transmute(&*self.bounds);
+                if ext::git_revwalk_add_hide_cb(self.walk, hide_older_cb, payload) != 0 {
+                    raise()
+                }
+            }
+        }
+    }
+
+    /// Leave out the commits committed after `time`, in seconds since the epoch,
+    /// like `git log --until`.
+    ///
+    /// Newer commits are still walked through, to reach their ancestors.
+    pub fn until(&mut self, time: i64)
+    {
+        self.bounds.until = Some(time);
+    }
+
+    /// Hide `bottom`, run the walk to its end and keep only the commits on the ancestry
+    /// path between `bottom` and the pushed commits, like `git rev-list --ancestry-path`:
+    /// the commits which are descendants of `bottom`.
+    ///
+    /// For example pushing a release branch and passing a fix commit lists the merges
+    /// which brought the fix into the release. The commits are in the order of the walk.
+    ///
+    /// raises git_error on error
+    pub fn ancestry_path(&mut self, bottom: &OID) -> ~[OID]
+    {
+        self.hide(bottom);
+        let ids: ~[OID] = self.collect();
+        let mut index = HashMap::new();
+        for ids.iter().enumerate().advance |(i, id)| {
+            index.insert(copy *id, i);
+        }
+        let parents = do ids.map |id| {
+            match self.owner.lookup_commit(id) {
+                Some(commit) => commit.parents_oid(),
+                None => ~[],
+            }
+        };
+        // the walk may not list parents before children: visit the commits in
+        // reverse topological order, each once all its walked parents are visited
+        let mut children = vec::from_fn(ids.len(), |_| ~[]);
+        let mut pending = vec::from_elem(ids.len(), 0u);
+        for parents.iter().enumerate().advance |(i, ps)| {
+            for ps.iter().advance |p| {
+                match index.find(p) {
+                    Some(&j) => { children[j].push(i); pending[i] += 1; },
+                    None => (),
+                }
+            }
+        }
+        let mut ready: ~[uint] = ~[];
+        for pending.iter().enumerate().advance |(i, &count)| {
+            if count == 0 {
+                ready.push(i);
+            }
+        }
+        let mut on_path = HashSet::new();
+        while !ready.is_empty() {
+            let i = ready.pop();
+            if parents[i].iter().any(|p| *p == *bottom || on_path.contains(p)) {
+                on_path.insert(copy ids[i]);
+            }
+            for children[i].iter().advance |&c| {
+                pending[c] -= 1;
+                if pending[c] == 0 {
+                    ready.push(c);
+                }
+            }
+        }
+        ids.consume_iter().filter(|id| on_path.contains(id)).collect()
+    }
+
+    /// Filter the walk with the POSIX extended regular expression `pattern`, like
+    /// `git log --grep`, `--author` or `--committer`.
+    ///
+    /// The commits are matched as they are walked, on the text stored by libgit2,
+    /// so the commits which do not match are never converted to Rust values.
+    /// The filtered walk takes over this one.
+    pub fn matching(~self, pattern: &str, field: MatchField)
+        -> Result<MatchingWalk<'self>, (~str, GitError)>
+    {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok( MatchingWalk { walk: self, regex: regex, field: field } ),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the next `count` commits from the revision walk.
+    ///
+    /// Fewer commits are returned when the walk is over; the next call
+    /// resumes the walk where this one stopped.
+    pub fn next_n(&mut self, count: uint) -> ~[OID]
+    {
+        let mut oids = std::vec::with_capacity(count);
+        while oids.len() < count {
+            match self.next() {
+                Some(oid) => oids.push(oid),
+                None => break,
+            }
+        }
+        oids
+    }
+
+    /// Get at most `count` commits from the revision walk, stopping early
+    /// when `budget_ms` milliseconds have elapsed.
+    ///
+    /// This allows to walk a large history from a UI task in steps of bounded duration.
+    /// At least one commit is returned unless the walk is over; an empty result
+    /// means the walk is over.
+    pub fn next_within(&mut self, count: uint, budget_ms: u64) -> ~[OID]
+    {
+        let deadline = precise_time_ns() + budget_ms * 1000000;
+        let mut oids = ~[];
+        while oids.len() < count {
+            match self.next() {
+                Some(oid) => oids.push(oid),
+                None => break,
+            }
+            if precise_time_ns() >= deadline {
+                break;
+            }
+        }
+        oids
+    }
+}
+
+impl<'self> Iterator<OID> for Revwalk<'self> {
+    /// Get the next commit from the revision walk.
+    ///
+    /// Returns None when the walk is over; raises git_error on other errors
+    fn next(&mut self) -> Option<OID>
+    {
+        let mut oid = OID { id: [0, ..20] };
+        loop {
+            unsafe {
+                match ext::git_revwalk_next(&mut oid, self.walk) {
+                    0 => (),
+                    ext::GIT_ITEROVER => return None,
+                    _ => { raise(); return None; },
+                }
+                match self.bounds.until {
+                    Some(until) => match commit_time(self.bounds.repo, &oid) {
+                        Some(time) if time > until => loop,
+                        _ => (),
+                    },
+                    None => (),
+                }
+            }
+            return Some(oid);
+        }
+    }
+}
+
+impl<'self> MatchingWalk<'self> {
+    unsafe fn signature_matches(&self, sig: *ext::git_signature) -> bool {
+        let text = fmt!("%s <%s>", from_c_str((*sig).name), from_c_str((*sig).email));
+        self.regex.is_match(text)
+    }
+}
+
+impl<'self> Iterator<OID> for MatchingWalk<'self> {
+    /// Get the next matching commit from the revision walk.
+    ///
+    /// Returns None when the walk is over; raises git_error on other errors
+    fn next(&mut self) -> Option<OID>
+    {
+        loop {
+            let oid = match self.walk.next() {
+                Some(oid) => oid,
+                None => return None,
+            };
+            unsafe {
+                let mut commit: *ext::git_commit = ptr::null();
+                if ext::git_commit_lookup(&mut commit, self.walk.owner.repo, &oid) != 0 {
+                    raise();
+                    return None;
+                }
+                let matched = match self.field {
+                    MatchMessage => self.regex.is_match_c(ext::git_commit_message(commit)),
+                    MatchAuthor => self.signature_matches(ext::git_commit_author(commit)),
+                    MatchCommitter => self.signature_matches(ext::git_commit_committer(commit)),
+                };
+                ext::git_commit_free(commit);
+                if matched {
+                    return Some(oid);
+                }
+            }
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'self> Drop for Revwalk<'self> {
+    fn finalize(&self) {
+        unsafe {
+            ext::git_revwalk_free(self.walk);
+        }
+    }
+}
@@ -23,7 +23,13 @@ fn main() {
     let cmd = copy args[1];
     let cmd_args = args.slice(2, args.len());
 
-    git2::threads_init();
+    match git2::init() {
+        Ok(()) => (),
+        Err((msg, _)) => {
+            println(msg);
+            return;
+        },
+    }
 
     if cmd == ~"init" {
         cmd_init(cmd_args);
@@ -38,6 +44,8 @@ fn main() {
     } else {
         main_usage(program);
     }
+
+    git2::shutdown();
 }
 
 fn get_current_repo() -> git2::Repository {
@@ -102,7 +110,9 @@ fn cmd_status(_: &str, _: &[~str]) {
         {
             staged.push((copy path, copy stat))
         }
-        if stat.wt_new || stat.wt_modified || stat.wt_deleted || stat.wt_typechange {
+        if stat.wt_new || stat.wt_modified || stat.wt_deleted || stat.wt_typechange
+            || stat.wt_renamed || stat.conflicted
+        {
             not_staged.push((copy path, copy stat))
         }
     }
@@ -137,7 +147,9 @@ fn cmd_status(_: &str, _: &[~str]) {
     }
     for not_staged.each() |&tup| {
         let (path, stat) = tup;
-        if stat.wt_new {
+        if stat.conflicted {
+            print("both modified: ")
+        } else if stat.wt_new {
             print("new: ")
         } else if stat.wt_modified {
             print("modified: ")
@@ -145,6 +157,8 @@ fn cmd_status(_: &str, _: &[~str]) {
             print("deleted: ")
         } else if stat.wt_typechange {
             print("typechange: ")
+        } else if stat.wt_renamed {
+            print("renamed: ")
         }
 
         println(path)
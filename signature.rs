@@ -1,7 +1,52 @@
-use std::libc::c_int;
+use std::libc::{c_char, c_int};
+use std::ptr;
 use std::str::raw::from_c_str;
+use std::to_str::ToStr;
 use ext;
-use super::{Signature, Time};
+use super::{Signature, Time, SignatureCache, GitError, last_error};
+
+static DAYS: [&'static str, ..7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+static MONTHS: [&'static str, ..12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// (year, month, day) of a number of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64)
+{
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl ToStr for Time {
+    /// Format the time in its own timezone, like git's default date format:
+    /// e.g. "Tue Jun 11 19:14:48 2013 +0900"
+    fn to_str(&self) -> ~str {
+        let local = self.time + (self.offset as i64) * 60;
+        let days = if local >= 0 { local / 86400 } else { (local - 86399) / 86400 };
+        let secs = local - days * 86400;
+        let (year, month, day) = civil_from_days(days);
+        let weekday = ((days % 7 + 11) % 7) as uint;
+        let offset = if self.offset < 0 { -self.offset } else { self.offset };
+        let secs = secs as int;
+        fmt!("%s %s %d %02d:%02d:%02d %d %c%02d%02d", DAYS[weekday], MONTHS[(month - 1) as uint],
+            day as int, secs / 3600, secs % 3600 / 60, secs % 60, year as int,
+            if self.offset < 0 { '-' } else { '+' }, offset / 60, offset % 60)
+    }
+}
+
+impl ToStr for Signature {
+    /// Format the signature like git: "Name <email> date"
+    fn to_str(&self) -> ~str {
+        fmt!("%s <%s> %s", self.name, self.email, self.when.to_str())
+    }
+}
 
 pub fn to_c_sig(sig: &Signature) -> ext::git_signature {
     do sig.name.as_c_str |c_name| {
@@ -12,6 +57,7 @@ pub fn to_c_sig(sig: &Signature) -> ext::git_signature {
                 when: ext::git_time {
                     time: sig.when.time,
                     offset: sig.when.offset as c_int,
+                    sign: if sig.when.offset < 0 { '-' as c_char } else { '+' as c_char },
                 }
             }
         }
@@ -26,6 +72,58 @@ pub unsafe fn from_c_sig(c_sig: *ext::git_signature) -> Signature {
     }
 }
 
+/// Create a new signature with a timestamp of 'now'.
+pub fn now(name: &str, email: &str) -> Result<Signature, (~str, GitError)> {
+    do name.as_c_str |c_name| {
+        do email.as_c_str |c_email| {
+            unsafe {
+                let mut c_sig: *ext::git_signature = ptr::null();
+                if ext::git_signature_now(&mut c_sig, c_name, c_email) == 0 {
+                    let sig = from_c_sig(c_sig);
+                    ext::git_signature_free(c_sig);
+                    Ok(sig)
+                } else {
+                    Err( last_error() )
+                }
+            }
+        }
+    }
+}
+
+impl<'self> SignatureCache<'self> {
+    /// Create a signature with the default user and now timestamp.
+    ///
+    /// The user name and email are read from a snapshot of the configuration,
+    /// which is kept open between calls. libgit2 reloads the configuration files
+    /// which changed when the snapshot is taken, including the system file, the
+    /// included files and `GIT_CONFIG`, so the identity is never stale.
+    pub fn signature(&mut self) -> Result<Signature, (~str, GitError)> {
+        if self.config.is_none() {
+            match self.owner.config() {
+                Ok(cfg) => self.config = Some(cfg),
+                Err(e) => return Err(e),
+            }
+        }
+        let snapshot = match self.config {
+            Some(ref cfg) => match cfg.snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(e) => return Err(e),
+            },
+            None => fail!(~"configuration should be open"),
+        };
+        match (snapshot.get_str("user.name"), snapshot.get_str("user.email")) {
+            (Some(name), Some(email)) => now(name, email),
+            // let libgit2 report the missing identity
+            _ => self.owner.default_signature(),
+        }
+    }
+
+    /// Close the configuration; it will be opened again on next use.
+    pub fn invalidate(&mut self) {
+        self.config = None;
+    }
+}
+
 #[inline]
 fn time_cmp(a: &Time, b: &Time) -> i64 {
     let a_utc = a.time + (a.offset as i64) * 60;
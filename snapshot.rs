@@ -0,0 +1,141 @@
+use extra::sort::quick_sort3;
+use super::*;
+use diff;
+use reference;
+use repository;
+
+impl Snapshot {
+    /// Get the path of the snapshotted repository
+    pub fn path(&self) -> ~str {
+        // snapshots are reopened from disk
+        self.repo.path().unwrap()
+    }
+
+    /// Get the path of the working directory of the snapshotted repository
+    ///
+    /// If the repository is bare, this function will always return None.
+    pub fn workdir(&self) -> Option<~str> {
+        self.repo.workdir()
+    }
+
+    /// Check if the repository is empty
+    pub fn is_empty(&self) -> bool {
+        self.repo.is_empty()
+    }
+
+    /// Check if the repository is bare
+    pub fn is_bare(&self) -> bool {
+        self.repo.is_bare()
+    }
+
+    /// The name of the branch HEAD pointed to when the snapshot was taken, or "HEAD"
+    /// if it was detached, and the commit it pointed to; None if HEAD was unborn.
+    pub fn head(&self) -> Option<(~str, OID)> {
+        copy self.head
+    }
+
+    /// The object the reference `name` pointed to when the snapshot was taken,
+    /// after resolving symbolic references.
+    pub fn lookup(&self, name: &str) -> Option<OID> {
+        for self.refs.iter().advance |&(ref n, oid)| {
+            if n.as_slice() == name {
+                return Some(oid);
+            }
+        }
+        None
+    }
+
+    /// The object the branch `branch_name` pointed to when the snapshot was taken.
+    pub fn lookup_branch(&self, branch_name: &str, remote: bool) -> Option<OID> {
+        let prefix = if remote { "refs/remotes/" } else { "refs/heads/" };
+        self.lookup(prefix + branch_name)
+    }
+
+    /// The configuration of the repository when the snapshot was taken.
+    pub fn config<'r>(&'r self) -> &'r Config {
+        &*self.config
+    }
+
+    /// Lookup a commit object
+    pub fn lookup_commit<'r>(&'r self, id: &OID) -> Option<~Commit<'r>> {
+        self.repo.lookup_commit(id)
+    }
+
+    /// Lookup a tree object
+    pub fn lookup_tree<'r>(&'r self, id: &OID) -> Option<~Tree<'r>> {
+        self.repo.lookup_tree(id)
+    }
+
+    /// Lookup a blob object
+    pub fn blob_lookup<'r>(&'r self, id: &OID) -> Option<~Blob<'r>> {
+        self.repo.blob_lookup(id)
+    }
+
+    /// Allocate a new revision walker on the repository.
+    ///
+    /// Push the ids given by `head` and `lookup`: reference names pushed with
+    /// `push_ref` are read from the repository as it is now.
+    pub fn revwalk<'r>(&'r self) -> Result<~Revwalk<'r>, (~str, GitError)> {
+        self.repo.revwalk()
+    }
+
+    /// List the names of all the references, when the snapshot was taken.
+    pub fn reference_names(&self) -> ~[~str] {
+        self.refs.map(|&(ref name, _)| copy *name)
+    }
+
+    /// List all the references with their targets, grouped by namespace, when the
+    /// snapshot was taken.
+    pub fn ref_summary(&self) -> reference::RefSummary {
+        copy self.summary
+    }
+
+    /// Create a diff list with the difference between two tree objects.
+    pub fn diff_trees(&self, old_tree: Option<&Tree>, new_tree: Option<&Tree>,
+            opts: &diff::DiffOption) -> Result<~DiffList, (~str, GitError)>
+    {
+        self.repo.diff_trees(old_tree, new_tree, opts)
+    }
+}
+
+/// Take a snapshot of `repo`, which must be a handle of its own: it is made read-only,
+/// and the references and configuration are captured now.
+pub fn capture(mut repo: Repository) -> Result<Snapshot, (~str, GitError)> {
+    repo.read_only = true;
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(e) => return Err(e),
+    };
+    let summary = match repo.ref_summary() {
+        Ok(summary) => summary,
+        Err(e) => return Err(e),
+    };
+    let mut refs = ~[];
+    for repo.reference_names().iter().advance |name| {
+        let target = match repo.lookup(*name) {
+            Some(reference) => match reference.resolve_chain() {
+                Ok(chain) => chain.target,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+        match target {
+            Some(oid) => refs.push((copy *name, oid)),
+            None => (),
+        }
+    }
+    quick_sort3(refs);
+    let head = match repo.head() {
+        Some(head) => Some((head.name(), head.resolve())),
+        None => None,
+    };
+    Ok( Snapshot { repo: repo, config: config, head: head, refs: refs, summary: summary } )
+}
+
+/// Open a read-only snapshot of the repository at the given path
+pub fn open(path: &str) -> Result<Snapshot, (~str, GitError)> {
+    match repository::open(path) {
+        Ok(repo) => capture(repo),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,132 @@
+use std::hashmap::HashMap;
+use extra::sort::merge_sort;
+use super::*;
+use diff;
+use revwalk;
+
+/// Commit counts and churn of one author
+pub struct AuthorStats {
+    name: ~str,
+    email: ~str,
+    commits: uint,
+    insertions: uint,
+    deletions: uint,
+}
+
+/// Statistics of a range of commits
+pub struct RangeStats {
+    /// number of commits in the range, merges included
+    commits: uint,
+    insertions: uint,
+    deletions: uint,
+    /// the authors, most commits first, like `git shortlog -sn`
+    authors: ~[AuthorStats],
+    /// the paths and the number of commits which modified them, most modified first
+    files: ~[(~str, uint)],
+}
+
+/// The canonical name and email of an author: the signature itself, without mailmap.
+pub fn identity(sig: &Signature) -> (~str, ~str) {
+    (copy sig.name, copy sig.email)
+}
+
+/// Compute the statistics of the commits reachable from `to` but not from `from`,
+/// like `git shortlog -sn from..to` and `git log --numstat`.
+///
+/// Authors are grouped by the (name, email) pair returned by `canonicalize`, which allows
+/// to plug a mailmap; pass `identity` to use the signatures as they are.
+/// Merge commits are counted, but their changes are not: insertions, deletions and
+/// touched files are computed against the first parent of the other commits.
+pub fn range_stats(repo: &Repository, from: Option<&OID>, to: &OID,
+    canonicalize: &fn(&Signature) -> (~str, ~str)) -> Result<RangeStats, (~str, GitError)>
+{
+    let mut walk = match repo.revwalk() {
+        Ok(walk) => walk,
+        Err(e) => return Err(e),
+    };
+    walk.sorting([revwalk::GIT_SORT_TOPOLOGICAL]);
+    walk.push(to);
+    match from {
+        Some(oid) => walk.hide(oid),
+        None => (),
+    }
+
+    let mut stats = RangeStats { commits: 0, insertions: 0, deletions: 0, authors: ~[],
+                                files: ~[] };
+    let mut author_idx: HashMap<(~str, ~str), uint> = HashMap::new();
+    let mut files: HashMap<~str, uint> = HashMap::new();
+    let opts = diff::DiffOption::new();
+
+    for walk.advance |oid| {
+        let commit = match repo.lookup_commit(&oid) {
+            Some(commit) => commit,
+            None => loop,
+        };
+        stats.commits += 1;
+        let key = canonicalize(&commit.author());
+        let idx = match author_idx.find(&key) {
+            Some(&idx) => idx,
+            None => {
+                let (ref name, ref email) = key;
+                stats.authors.push(AuthorStats { name: copy *name, email: copy *email,
+                                                commits: 0, insertions: 0, deletions: 0 });
+                stats.authors.len() - 1
+            },
+        };
+        author_idx.insert(key, idx);
+        stats.authors[idx].commits += 1;
+
+        let parents = commit.parents();
+        if parents.len() > 1 {
+            loop;
+        }
+        let tree = match commit.tree() {
+            Ok(tree) => tree,
+            Err(e) => return Err(e),
+        };
+        let difflist = if parents.is_empty() {
+            repo.diff_trees(None, Some(tree), &opts)
+        } else {
+            match parents[0].tree() {
+                Ok(parent_tree) => repo.diff_trees(Some(parent_tree), Some(tree), &opts),
+                Err(e) => Err(e),
+            }
+        };
+        let difflist = match difflist {
+            Ok(difflist) => difflist,
+            Err(e) => return Err(e),
+        };
+        for difflist.deltas().iter().advance |delta| {
+            let path = match delta.status {
+                GIT_DELTA_DELETED => copy delta.old_file.path,
+                _ => copy delta.new_file.path,
+            };
+            let count = files.find_or_insert(path, 0);
+            *count += 1;
+        }
+        let mut insertions = 0u;
+        let mut deletions = 0u;
+        for difflist.each_line |_, origin, _| {
+            match origin {
+                '+' => insertions += 1,
+                '-' => deletions += 1,
+                _ => (),
+            }
+        };
+        stats.insertions += insertions;
+        stats.deletions += deletions;
+        stats.authors[idx].insertions += insertions;
+        stats.authors[idx].deletions += deletions;
+    }
+
+    // stable sorts: ties keep the order of first appearance
+    stats.authors = merge_sort(stats.authors, |a, b| a.commits >= b.commits);
+    let mut file_counts = ~[];
+    for files.consume().advance |(path, count)| {
+        file_counts.push((path, count));
+    }
+    stats.files = merge_sort(file_counts, |&(ref pa, ca), &(ref pb, cb)| {
+        ca > cb || (ca == cb && *pa <= *pb)
+    });
+    Ok(stats)
+}
@@ -0,0 +1,125 @@
+use std::{io, os, rand};
+use super::*;
+use repository;
+
+/// A scratch repository in a temporary directory, for tests.
+///
+/// The repository is seeded through the public API, and its directory is removed
+/// when the TempRepo is dropped. Every commit is made with the same fixed signature,
+/// so that commit ids are reproducible.
+pub struct TempRepo {
+    repo: Repository,
+    /// path of the working directory
+    path: ~str,
+}
+
+/// The signature used for all the commits of a TempRepo
+pub fn signature() -> Signature {
+    Signature {
+        name: ~"Test User",
+        email: ~"test@example.com",
+        when: Time { time: 1370945688, offset: 540 },
+    }
+}
+
+/// A new unique path in the temporary directory; nothing is created
+pub fn temp_path(prefix: &str) -> Path {
+    os::tmpdir().push(fmt!("%s-%u", prefix, rand::random::<uint>()))
+}
+
+/// the file type bits of a mode, and the type of the symbolic links
+static S_IFMT: uint = 0o170000;
+static S_IFLNK: uint = 0o120000;
+
+/// true if `path` is a symbolic link, which is not followed
+fn is_symlink(path: &Path) -> bool {
+    match path.lstat() {
+        Some(st) => (st.st_mode as uint) & S_IFMT == S_IFLNK,
+        None => false,
+    }
+}
+
+/// Remove a directory and all its content; the symbolic links it contains are
+/// removed, not followed
+pub fn remove_dir_all(path: &Path) {
+    for os::list_dir(path).iter().advance |name| {
+        if *name == ~"." || *name == ~".." {
+            loop;
+        }
+        let child = path.push(*name);
+        if !is_symlink(&child) && os::path_is_dir(&child) {
+            remove_dir_all(&child);
+        } else {
+            os::remove_file(&child);
+        }
+    }
+    os::remove_dir(path);
+}
+
+impl TempRepo {
+    /// Create an empty repository with a working directory
+    pub fn new() -> TempRepo {
+        let path = temp_path("git2-rs-test").to_str();
+        match repository::init(path, false) {
+            Ok(repo) => TempRepo { repo: repo, path: path },
+            Err((msg, _)) => fail!(fmt!("cannot create test repository %s: %s", path, msg)),
+        }
+    }
+
+    /// Write a file in the working directory, creating the parent directories
+    pub fn write_file(&self, path: &str, content: &str) {
+        let full = Path(self.path).push_rel(&Path(path));
+        os::mkdir_recursive(&full.dir_path(), 0o755);
+        match io::file_writer(&full, [io::Create, io::Truncate]) {
+            Ok(writer) => writer.write(content.as_bytes()),
+            Err(msg) => fail!(fmt!("cannot write %s: %s", full.to_str(), msg)),
+        }
+    }
+
+    /// Write a file, add it to the index, and commit the index on HEAD
+    pub fn commit_file(&self, path: &str, content: &str, message: &str) -> OID {
+        self.write_file(path, content);
+        let index = self.repo.index().unwrap();
+        index.add_bypath(path);
+        index.write();
+        self.commit_index(message)
+    }
+
+    /// Commit the current index on HEAD, completing the merge in progress if any
+    pub fn commit_index(&self, message: &str) -> OID {
+        let index = self.repo.index().unwrap();
+        let tree = index.write_tree().unwrap();
+        let mut parents = match self.repo.head() {
+            Some(head) => match self.repo.lookup_commit(&head.resolve()) {
+                Some(commit) => ~[commit],
+                None => ~[],
+            },
+            None => ~[],
+        };
+        for self.repo.merge_heads().unwrap().iter().advance |id| {
+            parents.push(self.repo.lookup_commit(id).unwrap());
+        }
+        let sig = signature();
+        let id = self.repo.commit(Some("HEAD"), &sig, &sig, None, message, tree, parents);
+        self.repo.cleanup_state().unwrap();
+        id
+    }
+
+    /// Create a branch pointing to a commit
+    pub fn branch(&self, name: &str, target: &OID) {
+        let commit = match self.repo.annotated_commit_lookup(target) {
+            Ok(commit) => commit,
+            Err((msg, _)) => fail!(fmt!("no commit %s: %s", target.to_str(), msg)),
+        };
+        match self.repo.branch_create_from_annotated(name, commit, false) {
+            Ok(_) => (),
+            Err((msg, _)) => fail!(fmt!("cannot create branch %s: %s", name, msg)),
+        }
+    }
+}
+
+impl Drop for TempRepo {
+    fn finalize(&self) {
+        remove_dir_all(&Path(self.path));
+    }
+}
@@ -1,6 +1,8 @@
 extern mod extra;
 extern mod git2;
 
+use extra::json::ToJson;
+
 #[test]
 fn repo_head() {
     let repo = git2::repository::open("fixture").unwrap();
@@ -45,6 +47,17 @@ fn repo_oid() {
     assert_eq!(oid.to_str(), oid_str)
 }
 
+#[test]
+fn oid_fmt_into() {
+    let oid = git2::oid::from_str(&"93d8ad7e3f5a300d2b4e18e9f31247a95e5cc37f");
+    let mut buf = [0u8, ..41];
+    oid.fmt_into(buf);
+    assert_eq!(buf.slice(0, 40), bytes!("93d8ad7e3f5a300d2b4e18e9f31247a95e5cc37f"));
+    assert_eq!(buf[40], 0);
+    let written = do std::io::with_bytes_writer |writer| { oid.write_hex(writer) };
+    assert_eq!(written, bytes!("93d8ad7e3f5a300d2b4e18e9f31247a95e5cc37f").to_owned());
+}
+
 #[test]
 fn repo_lookup_commit() {
     let repo = git2::repository::open("fixture").unwrap();
@@ -112,7 +125,7 @@ fn commit() {
     let text = "blob text\n";
     let blob = repo.blob_create_frombuffer(text.as_bytes()).unwrap();
 
-    let treebuilder = git2::TreeBuilder::from_tree(parent.tree());
+    let treebuilder = git2::TreeBuilder::from_tree(parent.tree().unwrap()).unwrap();
     treebuilder.insert(&"test_blob.txt", blob.id(), git2::GIT_FILEMODE_BLOB);
     let tree_id = treebuilder.write(&repo);
     let tree = match repo.lookup_tree(&tree_id) {
@@ -129,3 +142,2690 @@ fn commit() {
         }
     };
 }
+
+#[test]
+fn odb_read() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let odb = repo.odb().unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    assert!(odb.exists(&oid));
+    let obj = odb.read(&oid).unwrap();
+    assert_eq!(obj.id(), &oid);
+    match obj.otype() {
+        git2::GIT_OBJ_COMMIT => (),
+        _ => fail!(~"object should be a commit"),
+    }
+}
+
+#[test]
+fn odb_reader() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let odb = repo.odb().unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    let (reader, size, otype) = odb.reader(&oid).unwrap();
+    assert_eq!(otype as int, git2::GIT_OBJ_COMMIT as int);
+    let data = reader.read_whole_stream();
+    assert_eq!(data.len(), size);
+    assert!(reader.eof());
+    do odb.read(&oid).unwrap().data_as_slice |v| {
+        assert_eq!(v, data.as_slice());
+    }
+}
+
+#[test]
+fn blob_exists_for_buffer() {
+    let temp = git2::test::TempRepo::new();
+    assert!(temp.repo.blob_exists_for_buffer("hello\n".as_bytes()).is_none());
+    temp.commit_file("a.txt", "hello\n", "first");
+    let oid = temp.repo.blob_exists_for_buffer("hello\n".as_bytes()).unwrap();
+    assert_eq!(oid.to_str(), ~"ce013625030ba8dba906f756967f9e9ca394464a");
+}
+
+#[test]
+fn revwalk_since_until() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("a.txt", "a\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+    let tree = index.write_tree().unwrap();
+    let mut ids: ~[git2::OID] = ~[];
+    for [1000i64, 2000, 3000].iter().advance |&time| {
+        let mut sig = git2::test::signature();
+        sig.when.time = time;
+        let parents = match ids.last_opt() {
+            Some(id) => ~[temp.repo.lookup_commit(id).unwrap()],
+            None => ~[],
+        };
+        ids.push(temp.repo.commit(None, &sig, &sig, None, "commit", tree, parents));
+    }
+
+    let mut walk = temp.repo.revwalk().unwrap();
+    walk.push(&ids[2]);
+    walk.since(1500);
+    let walked: ~[git2::OID] = walk.collect();
+    assert_eq!(walked, ~[ids[2], ids[1]]);
+
+    let mut walk = temp.repo.revwalk().unwrap();
+    walk.push(&ids[2]);
+    walk.until(2500);
+    let walked: ~[git2::OID] = walk.collect();
+    assert_eq!(walked, ~[ids[1], ids[0]]);
+}
+
+#[test]
+fn revwalk_matching() {
+    let temp = git2::test::TempRepo::new();
+    let fix = temp.commit_file("a.txt", "a\n", "fix: crash on start");
+    temp.commit_file("b.txt", "b\n", "feat: add b");
+    let head = temp.commit_file("a.txt", "a, fixed\n", "fix: typo");
+
+    let walk = temp.repo.revwalk().unwrap();
+    walk.push(&head);
+    let mut matching = walk.matching("^fix", git2::revwalk::MatchMessage).unwrap();
+    let ids: ~[git2::OID] = matching.collect();
+    assert_eq!(ids, ~[head, fix]);
+
+    let walk = temp.repo.revwalk().unwrap();
+    walk.push(&head);
+    let mut matching = walk.matching("Nobody", git2::revwalk::MatchAuthor).unwrap();
+    assert!(matching.next().is_none());
+
+    let walk = temp.repo.revwalk().unwrap();
+    assert!(walk.matching("(", git2::revwalk::MatchMessage).is_err());
+}
+
+#[test]
+fn revwalk_ancestry_path() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    let fix = temp.commit_file("b.txt", "b\n", "fix");
+    let after = temp.commit_file("c.txt", "c\n", "after the fix");
+
+    // a side branch forked before the fix, then merged
+    let sig = git2::test::signature();
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    let base_tree = temp.repo.lookup_tree(base_commit.tree().unwrap().id()).unwrap();
+    let side = temp.repo.commit(None, &sig, &sig, None, "side", base_tree, [base_commit]);
+    let after_commit = temp.repo.lookup_commit(&after).unwrap();
+    let after_tree = temp.repo.lookup_tree(after_commit.tree().unwrap().id()).unwrap();
+    let side_commit = temp.repo.lookup_commit(&side).unwrap();
+    let merge = temp.repo.commit(Some("HEAD"), &sig, &sig, None, "merge side", after_tree,
+                                [after_commit, side_commit]);
+
+    let mut walk = temp.repo.revwalk().unwrap();
+    walk.sorting([git2::revwalk::GIT_SORT_TOPOLOGICAL]);
+    walk.push(&merge);
+    assert_eq!(walk.ancestry_path(&fix), ~[merge, after]);
+}
+
+#[test]
+fn revwalk_first_parent() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    let sig = git2::test::signature();
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    let base_tree = temp.repo.lookup_tree(base_commit.tree().unwrap().id()).unwrap();
+    let side = temp.repo.commit(None, &sig, &sig, None, "side", base_tree, [base_commit]);
+    let head = temp.repo.lookup_commit(&base).unwrap();
+    let head_tree = temp.repo.lookup_tree(head.tree().unwrap().id()).unwrap();
+    let side_commit = temp.repo.lookup_commit(&side).unwrap();
+    let merge = temp.repo.commit(Some("HEAD"), &sig, &sig, None, "merge side", head_tree,
+                                [head, side_commit]);
+
+    let mut walk = temp.repo.revwalk().unwrap();
+    walk.sorting([git2::revwalk::GIT_SORT_TOPOLOGICAL]);
+    walk.push(&merge);
+    walk.simplify_first_parent();
+    let ids: ~[git2::OID] = walk.collect();
+    assert_eq!(ids, ~[merge, base]);
+}
+
+#[test]
+fn graph_export() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let mut walk = repo.revwalk().unwrap();
+    walk.push_ref("refs/heads/master");
+    let nodes = git2::graph::commit_graph(&repo, &mut *walk);
+    assert_eq!(nodes.len(), 1);
+    assert!(nodes[0].parents.is_empty());
+    assert!(nodes[0].refs.contains(&~"refs/heads/master"));
+
+    let dot = git2::graph::to_dot(nodes);
+    assert!(dot.starts_with("digraph"));
+    let json = git2::graph::to_json(nodes);
+    assert!(json.contains("\"id\":\"21002f5d3f411fe990e13604273a51cd598a4a51\""));
+
+    let node = git2::graph::CommitNode {
+        id: nodes[0].id,
+        parents: ~[],
+        refs: ~[~"a\"b\\c\nd\te\x01f\x1fg"],
+    };
+    let dot = git2::graph::to_dot([copy node]);
+    assert!(dot.contains("\\na\\\"b\\\\c\\nd\te\x01f\x1fg\"];"));
+    let json = git2::graph::to_json([node]);
+    match extra::json::from_str(json).unwrap() {
+        extra::json::List(list) => {
+            assert_eq!(list.len(), 1);
+            match list[0] {
+                extra::json::Object(ref obj) => {
+                    assert_eq!(obj.find(&~"refs"),
+                               Some(&extra::json::List(~[extra::json::String(
+                                   ~"a\"b\\c\nd\te\x01f\x1fg")])));
+                },
+                _ => fail!(),
+            }
+        },
+        _ => fail!(),
+    }
+}
+
+#[test]
+fn refnames_completion() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let names = repo.refnames_matching("ma");
+    assert!(names.contains(&~"master"));
+    assert!(repo.refnames_matching("no-such-ref").is_empty());
+}
+
+#[test]
+fn file_history() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    let mut history = repo.file_history("README.md", &oid).unwrap();
+    match history.next() {
+        Some((id, path)) => {
+            assert_eq!(id, oid);
+            assert_eq!(path, ~"README.md");
+        },
+        None => fail!(~"README.md should have history"),
+    }
+    assert!(history.next().is_none());
+}
+
+#[test]
+fn file_history_deletion() {
+    let temp = git2::test::TempRepo::new();
+    let added = temp.commit_file("a.txt", "hello\n", "add a");
+    temp.commit_file("b.txt", "hello\n", "add b");
+    let index = temp.repo.index().unwrap();
+    index.remove_bypath("a.txt");
+    index.write();
+    let deleted = temp.commit_index("delete a");
+    temp.commit_file("b.txt", "hello world\n", "change b");
+    let head = temp.repo.head().unwrap().resolve();
+
+    let mut history = temp.repo.file_history("a.txt", &head).unwrap();
+    let mut ids = ~[];
+    for history.advance |item| {
+        ids.push(item);
+    }
+    assert_eq!(ids, ~[(deleted, ~"a.txt"), (added, ~"a.txt")]);
+    assert!(history.error().is_none());
+}
+
+#[test]
+fn file_history_error() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "one\n", "first");
+    temp.commit_file("a.txt", "two\n", "second");
+    let third = temp.commit_file("a.txt", "three\n", "third");
+    let tree = temp.repo.lookup_commit(&first).unwrap().tree().unwrap().id().to_str();
+    let object = Path(temp.path).push_many([~".git", ~"objects", tree.slice(0, 2).to_owned(),
+                                            tree.slice(2, 40).to_owned()]);
+    assert!(std::os::remove_file(&object));
+    let repo = git2::repository::open(temp.path).unwrap();
+
+    // the second commit cannot be compared with its parent
+    let mut history = repo.file_history("a.txt", &third).unwrap();
+    let ids: ~[(git2::OID, ~str)] = history.collect();
+    assert_eq!(ids, ~[(third, ~"a.txt")]);
+    match history.error() {
+        Some((msg, _)) => assert!(msg.contains(tree.as_slice())),
+        None => fail!(~"the missing tree should be reported"),
+    }
+    assert!(history.next().is_none());
+
+    let mut lines = repo.line_history("a.txt", 1, 1, &third).unwrap();
+    let mut changes = ~[];
+    for lines.advance |change| {
+        changes.push(change.id);
+    }
+    assert_eq!(changes, ~[third]);
+    assert!(lines.error().is_some());
+}
+
+#[test]
+fn line_history() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "a\nb\nc\n", "first");
+    let second = temp.commit_file("a.txt", "a\nB\nc\n", "change b");
+    let third = temp.commit_file("a.txt", "x\ny\na\nB\nc\n", "insert above");
+
+    // line 4 of the last version is the line 2 changed in the second commit
+    let mut history = temp.repo.line_history("a.txt", 4, 4, &third).unwrap();
+    let mut changes = ~[];
+    for history.advance |change| {
+        changes.push((change.id, change.start, change.end));
+    }
+    assert_eq!(changes, ~[(second, 2u, 2u), (first, 2u, 2u)]);
+}
+
+#[test]
+fn mailbox_parse() {
+    let mail = "From 21002f5d3f411fe990e13604273a51cd598a4a51 Mon Sep 17 00:00:00 2001\n\
+From: \"Some One\" <someone@example.com>\n\
+Date: Tue, 11 Jun 2013 19:14:48 +0900\n\
+Subject: [PATCH 1/2] Update\n README\n\
+\n\
+Longer description.\n\
+---\n\
+ README.md | 1 +\n\
+\n\
+diff --git a/README.md b/README.md\n\
+--- a/README.md\n\
++++ b/README.md\n\
+@@ -1 +1,2 @@\n\
+ git2-rs-test\n\
++more\n\
+-- \n\
+1.8.3\n";
+    let mails = git2::mailbox::split_mbox(mail);
+    assert_eq!(mails.len(), 1);
+    let patch = git2::mailbox::parse(mails[0]).unwrap();
+    assert_eq!(patch.author.name, ~"Some One");
+    assert_eq!(patch.author.email, ~"someone@example.com");
+    assert_eq!(patch.subject, ~"Update README");
+    assert_eq!(patch.message, ~"Update README\n\nLonger description.\n");
+    assert!(patch.diff.starts_with("diff --git a/README.md b/README.md\n"));
+    assert!(patch.diff.ends_with("+more\n"));
+    assert_eq!(patch.author.when.offset, 9 * 60);
+    assert_eq!(patch.author.when.time, 1370945688);
+}
+
+#[test]
+fn mailbox_apply_commit_error() {
+    let mut temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("user.name", "Test User");
+    cfg.set_str("user.email", "test@example.com");
+    cfg.set_bool("commit.gpgsign", true);
+    do temp.repo.set_commit_signer |_| {
+        Err( (~"no secret key", git2::GITERR_INVALID) )
+    }
+    let mail = "From: Some One <someone@example.com>\n\
+Date: Tue, 11 Jun 2013 19:14:48 +0900\n\
+Subject: [PATCH] Update\n\
+\n\
+---\n\
+diff --git a/a.txt b/a.txt\n\
+--- a/a.txt\n\
++++ b/a.txt\n\
+@@ -1 +1 @@\n\
+-hello\n\
++hello mail\n";
+    let patch = git2::mailbox::parse(mail).unwrap();
+    match git2::mailbox::apply(&temp.repo, &patch) {
+        Err((msg, _)) => assert_eq!(msg, ~"no secret key"),
+        Ok(_) => fail!(~"the failed commit should be reported"),
+    }
+    assert_eq!(temp.repo.head().unwrap().resolve(), first);
+}
+
+#[test]
+fn mailbox_parse_date_invalid() {
+    assert!(git2::mailbox::parse_date("Tue, 11 Jun 2013 19:14:48 +0900").is_some());
+    // too many time components
+    assert!(git2::mailbox::parse_date("Tue, 11 Jun 2013 19:14:48:12 +0900").is_none());
+    // a multi-byte character in the zone
+    assert!(git2::mailbox::parse_date("Tue, 11 Jun 2013 19:14:48 +\xe900").is_none());
+    assert!(git2::mailbox::parse_date("Tue, 11 Jun 2013 19:14:48 +09a0").is_none());
+}
+
+#[test]
+fn mailbox_split_separators() {
+    let mbox = "From 21002f5d3f411fe990e13604273a51cd598a4a51 Mon Sep 17 00:00:00 2001\n\
+Subject: first\n\
+\n\
+From the start, this is a body line.\n\
+>From here too, quoted.\n\
+>>From twice quoted.\n\
+From someone@example.com Tue Jun 11 19:14:48 2013\n\
+Subject: second\n";
+    let mails = git2::mailbox::split_mbox(mbox);
+    assert_eq!(mails.len(), 2);
+    assert_eq!(mails[0], ~"Subject: first\n\nFrom the start, this is a body line.\n\
+From here too, quoted.\n>From twice quoted.\n");
+    assert_eq!(mails[1], ~"Subject: second\n");
+}
+
+#[test]
+fn ahead_behind_matrix() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    assert_eq!(repo.ahead_behind(&oid, &oid).unwrap(), (0u, 0u));
+    let counts = repo.ahead_behind_matrix([(oid, oid)]).unwrap();
+    assert_eq!(counts, ~[(0u, 0u)]);
+}
+
+#[test]
+fn ahead_behind_matrix_diverged() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    temp.commit_file("a.txt", "m1\n", "m1");
+    let master = temp.commit_file("a.txt", "m2\n", "m2");
+    temp.branch("topic", &base);
+    temp.repo.set_head("refs/heads/topic").unwrap();
+    let index = temp.repo.index().unwrap();
+    index.read_tree(temp.repo.lookup_commit(&base).unwrap().tree().unwrap());
+    index.write();
+    let topic = temp.commit_file("b.txt", "t1\n", "t1");
+
+    let pairs = [(master, topic), (topic, master), (master, base), (base, topic)];
+    let counts = temp.repo.ahead_behind_matrix(pairs).unwrap();
+    assert_eq!(counts, ~[(2u, 1u), (1u, 2u), (2u, 0u), (0u, 1u)]);
+    for pairs.iter().zip(counts.iter()).advance |(&(local, upstream), count)| {
+        assert_eq!(temp.repo.ahead_behind(&local, &upstream).unwrap(), *count);
+    }
+}
+
+#[test]
+fn ref_summary() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let summary = repo.ref_summary().unwrap();
+    let master = summary.heads.iter().find_(|r| r.shorthand == ~"master").unwrap();
+    assert_eq!(master.name, ~"refs/heads/master");
+    assert_eq!(master.target.unwrap().to_str(), ~"21002f5d3f411fe990e13604273a51cd598a4a51");
+    assert!(master.symbolic_target.is_none());
+}
+
+#[test]
+fn revwalk_chunks() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let mut walk = repo.revwalk().unwrap();
+    walk.push_ref("refs/heads/master");
+    let chunk = walk.next_n(10);
+    assert_eq!(chunk.len(), 1);
+    assert!(walk.next_within(10, 100).is_empty());
+}
+
+#[test]
+fn reachability_index() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    let mut reach = repo.reachability().unwrap();
+    assert_eq!(reach.generation(&oid), Some(1u));
+    assert!(reach.is_ancestor(&oid, &oid));
+
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    let second = temp.commit_file("a.txt", "b\n", "second");
+    let third = temp.commit_file("a.txt", "c\n", "third");
+    temp.branch("side", &second);
+    let mut reach = temp.repo.reachability().unwrap();
+    assert_eq!(reach.generation(&third), Some(3u));
+    // repeated queries reuse the visited set
+    assert!(reach.is_ancestor(&base, &third));
+    assert!(reach.is_ancestor(&second, &third));
+    assert!(!reach.is_ancestor(&third, &base));
+    assert!(reach.is_ancestor(&base, &third));
+}
+
+#[test]
+fn children_index() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    let second = temp.commit_file("b.txt", "b\n", "second");
+    let third = temp.commit_file("c.txt", "c\n", "third");
+
+    let mut walk = temp.repo.revwalk().unwrap();
+    walk.push(&third);
+    let index = temp.repo.children_index(walk);
+    assert_eq!(index.len(), 3);
+    assert_eq!(index.children(&base), ~[second]);
+    assert!(index.children(&third).is_empty());
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    assert_eq!(base_commit.descendants(&index), ~[second, third]);
+}
+
+#[test]
+fn prune_loose_dry_run() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let reachable = git2::maintenance::reachable_objects(&repo).unwrap();
+    assert!(reachable.contains(&git2::oid::from_str("21002f5d3f411fe990e13604273a51cd598a4a51")));
+    assert!(repo.prune_loose(0, true).unwrap().is_empty());
+}
+
+#[test]
+fn reachable_objects_tag_chain() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "a\n", "first");
+    let odb = temp.repo.odb().unwrap();
+    let blob = temp.repo.blob_write("tagged\n".as_bytes()).unwrap();
+    let tag_of = |target: &git2::OID, otype: &str, name: &str| {
+        let content = fmt!("object %s\ntype %s\ntag %s\ntagger A U Thor <author@example.com> 0 +0000\n\n%s\n",
+                           target.to_str(), otype, name, name);
+        odb.write(content.as_bytes(), git2::GIT_OBJ_TAG).unwrap()
+    };
+    let inner = tag_of(&blob, "blob", "inner");
+    let outer = tag_of(&inner, "tag", "outer");
+    temp.repo.reference_create("refs/tags/outer", &outer, false, None).unwrap();
+
+    let reachable = git2::maintenance::reachable_objects(&temp.repo).unwrap();
+    assert!(reachable.contains(&outer));
+    assert!(reachable.contains(&inner));
+    assert!(reachable.contains(&blob));
+    assert!(temp.repo.prune_loose(0, false).unwrap().is_empty());
+    assert!(odb.exists(&blob));
+}
+
+#[test]
+fn odb_reader_packed() {
+    let temp = git2::test::TempRepo::new();
+    let content = std::vec::from_fn(200000, |i| (i * 7 % 251) as u8);
+    let id = temp.repo.blob_write(content).unwrap();
+    temp.repo.reference_create("refs/tags/big", &id, false, None).unwrap();
+    let mut opts = git2::maintenance::RepackOption::new();
+    opts.delete_loose = true;
+    temp.repo.repack(&opts).unwrap();
+
+    let odb = temp.repo.odb().unwrap();
+    match odb.locate(&id) {
+        Some(git2::odb::Packed(_, _)) => (),
+        _ => fail!(~"the blob should be packed"),
+    }
+    let (reader, size, otype) = odb.reader(&id).unwrap();
+    assert_eq!(otype as int, git2::GIT_OBJ_BLOB as int);
+    assert_eq!(size, content.len());
+    // small reads, across the blocks read from the packfile
+    let mut data = ~[];
+    let mut buf = [0u8, ..1000];
+    while !reader.eof() {
+        let read = reader.read(buf, 1000);
+        assert!(read > 0);
+        data.push_all(buf.slice(0, read));
+    }
+    assert!(data == content);
+
+    let (reader, _, _) = odb.reader(&id).unwrap();
+    reader.seek(100000, std::io::SeekSet);
+    assert_eq!(reader.tell(), 100000);
+    assert_eq!(reader.read_byte(), content[100000] as int);
+    let mut failed = false;
+    do git2::git_error::cond.trap(|_| failed = true).in {
+        reader.seek(0, std::io::SeekSet);
+    }
+    assert!(failed);
+}
+
+#[test]
+fn commit_gpgsign() {
+    let mut temp = git2::test::TempRepo::new();
+    temp.repo.config().unwrap().set_bool("commit.gpgsign", true);
+    temp.write_file("a.txt", "a\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+    index.write();
+
+    // no signer: the configuration is not looked at
+    assert!(!temp.repo.commit_signing_requested());
+    let unsigned = temp.commit_index("unsigned");
+    let raw = do temp.repo.odb().unwrap().read(&unsigned).unwrap().data_as_slice |v| {
+        std::str::from_bytes(v)
+    };
+    assert!(!raw.contains("\ngpgsig "));
+
+    do temp.repo.set_commit_signer |content| {
+        assert!(content.starts_with("tree "));
+        Ok( ~"-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----" )
+    }
+    assert!(temp.repo.commit_signing_requested());
+    temp.repo.config().unwrap().set_bool("commit.gpgsign", false);
+    assert!(!temp.repo.commit_signing_requested());
+    temp.repo.config().unwrap().set_bool("commit.gpgsign", true);
+    let id = temp.commit_index("signed");
+    assert_eq!(temp.repo.head().unwrap().resolve(), id);
+    let raw = do temp.repo.odb().unwrap().read(&id).unwrap().data_as_slice |v| {
+        std::str::from_bytes(v)
+    };
+    assert!(raw.contains("\ngpgsig -----BEGIN PGP SIGNATURE-----\n \n c2lnbmVk\n"));
+    assert!(raw.ends_with("\n\nsigned\n") || raw.ends_with("\n\nsigned"));
+
+    // another writer moves the branch while the commit is signed: it is kept
+    let second = temp.commit_index("second");
+    let path = copy temp.path;
+    let branch = temp.repo.head_name().unwrap();
+    do temp.repo.set_commit_signer |_| {
+        let other = git2::repository::open(path).unwrap();
+        other.reference_create(branch, &id, true, None).unwrap();
+        Ok( ~"-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----" )
+    }
+    let tree = index.write_tree().unwrap();
+    let parents = ~[temp.repo.lookup_commit(&second).unwrap()];
+    let sig = git2::test::signature();
+    assert!(temp.repo.try_commit(Some("HEAD"), &sig, &sig, None, "third", tree, parents).is_err());
+    assert_eq!(temp.repo.head().unwrap().resolve(), id);
+}
+
+#[test]
+fn add_bypath_autocrlf() {
+    let temp = git2::test::TempRepo::new();
+    temp.repo.config().unwrap().set_bool("core.autocrlf", true);
+    temp.write_file("a.txt", "one\r\ntwo\r\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+    let entry = index.get_bypath("a.txt", 0).unwrap();
+    let blob = temp.repo.blob_lookup(&entry.id).unwrap();
+    do blob.rawcontent_as_slice |content| {
+        assert_eq!(content, bytes!("one\ntwo\n"));
+    }
+}
+
+#[test]
+fn prefetch_parents() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "a\n", "first");
+    let second = temp.commit_file("a.txt", "b\n", "second");
+    let first_tree = *temp.repo.lookup_commit(&first).unwrap().tree().unwrap().id();
+    let second_tree = *temp.repo.lookup_commit(&second).unwrap().tree().unwrap().id();
+
+    // the parent and its tree are loaded with the commit, once
+    let prefetched = temp.repo.prefetch([second, first]);
+    assert_eq!(prefetched.len(), 4);
+    for [first, second, first_tree, second_tree].iter().advance |id| {
+        assert!(prefetched.contains(id));
+    }
+
+    let missing = git2::oid::from_str("0123456789012345678901234567890123456789");
+    assert_eq!(temp.repo.prefetch([missing]).len(), 0);
+    // a tree is loaded alone
+    assert_eq!(temp.repo.prefetch([first_tree]).len(), 1);
+}
+
+#[test]
+fn repack_keeps_reflog_commits() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    // second is now only reachable from the reflogs
+    temp.repo.reference_create("refs/heads/master", &first, true, None).unwrap();
+
+    let stats = temp.repo.repack(&git2::maintenance::RepackOption::new()).unwrap();
+    assert_eq!(stats.removed_packs, 0);
+    assert_eq!(stats.removed_loose, 0);
+
+    // a new commit, so that the next pack differs from the first one
+    temp.commit_file("b.txt", "b\n", "third");
+    let mut opts = git2::maintenance::RepackOption::new();
+    opts.delete_old_packs = true;
+    opts.delete_loose = true;
+    let stats = temp.repo.repack(&opts).unwrap();
+    assert_eq!(stats.removed_packs, 1);
+    assert!(stats.removed_loose > 0);
+    let commit = temp.repo.lookup_commit(&second).unwrap();
+    assert!(temp.repo.lookup_tree(commit.tree().unwrap().id()).is_some());
+    match temp.repo.odb().unwrap().locate(&second) {
+        Some(git2::odb::Packed(path, _)) => assert_eq!(path, stats.pack),
+        _ => fail!(~"the reflog commit is not in the new pack"),
+    }
+}
+
+#[test]
+fn repack_order() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    let mut opts = git2::maintenance::RepackOption::new();
+    opts.delete_loose = true;
+    let stats = temp.repo.repack(&opts).unwrap();
+
+    let odb = temp.repo.odb().unwrap();
+    let offset = |id: &git2::OID| {
+        match odb.locate(id) {
+            Some(git2::odb::Packed(path, offset)) => {
+                assert_eq!(path, stats.pack);
+                offset
+            },
+            _ => fail!(fmt!("%s is not in the new pack", id.to_str())),
+        }
+    };
+    // the commits go first, newest first, then the objects they point to
+    let blob = git2::odb::hash(bytes!("hello\n"), git2::GIT_OBJ_BLOB).unwrap();
+    assert!(offset(&second) < offset(&first));
+    assert!(offset(&first) < offset(&blob));
+}
+
+#[test]
+fn prune_gone_branches() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", "https://example.com/a.git");
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+    // a commit which is not merged into HEAD
+    let sig = git2::test::signature();
+    let first_commit = temp.repo.lookup_commit(&first).unwrap();
+    let side = temp.repo.commit(None, &sig, &sig, None, "side", first_commit.tree().unwrap(),
+                                [first_commit]);
+    for [("gone", first), ("kept", first), ("unmerged", side)].iter().advance
+            |&(name, target)| {
+        temp.branch(name, &target);
+        cfg.set_str(fmt!("branch.%s.remote", name), "origin");
+        cfg.set_str(fmt!("branch.%s.merge", name), ~"refs/heads/" + name);
+    }
+    temp.repo.reference_create("refs/remotes/origin/kept", &first, false, None).unwrap();
+
+    let gone = temp.repo.prune_gone_branches(true);
+    assert_eq!(gone.map(|b| copy b.name), ~[~"gone", ~"unmerged"]);
+    assert_eq!(gone[0].upstream, ~"refs/remotes/origin/gone");
+    assert_eq!(gone[0].action, git2::maintenance::PruneDryRun);
+    assert_eq!(gone[1].action, git2::maintenance::PruneKeptUnmerged);
+    assert!(temp.repo.lookup_branch("gone", false).is_some());
+
+    let gone = temp.repo.prune_gone_branches(false);
+    assert_eq!(gone[0].action, git2::maintenance::PruneDeleted);
+    assert_eq!(gone[1].action, git2::maintenance::PruneKeptUnmerged);
+    assert!(temp.repo.lookup_branch("gone", false).is_none());
+    assert!(temp.repo.lookup_branch("kept", false).is_some());
+    assert!(temp.repo.lookup_branch("unmerged", false).is_some());
+}
+
+#[test]
+fn diff_option_builder() {
+    let opts = git2::diff::DiffOption::default().context_lines(5).pathspec(["src/*"])
+                    .ignore_whitespace().build().unwrap();
+    assert_eq!(opts.context_lines, 5);
+    assert_eq!(opts.pathspec, ~[~"src/*"]);
+    assert!(git2::diff::DiffOption::default().max_size(0).build().is_err());
+    assert!(git2::diff::DiffOption::default().prefixes("a\n", "b").build().is_err());
+}
+
+#[test]
+fn status_to_str() {
+    let mut status = git2::Status::new();
+    status.wt_renamed = true;
+    assert_eq!(status.to_str(), ~" R");
+    status.conflicted = true;
+    assert_eq!(status.to_str(), ~"UU");
+}
+
+#[test]
+fn signature_cache() {
+    let temp = git2::test::TempRepo::new();
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("user.name", "First Name");
+    cfg.set_str("user.email", "first@example.com");
+    let mut cache = temp.repo.signature_cache();
+    assert_eq!(cache.signature().unwrap().name, ~"First Name");
+
+    // an included file is followed, and read again when it changes
+    temp.write_file("identity.cfg", "[user]\n\tname = Included\n");
+    cfg.set_str("include.path", Path(temp.path).push("identity.cfg").to_str());
+    let sig = cache.signature().unwrap();
+    assert_eq!(sig.name, ~"Included");
+    assert_eq!(sig.email, ~"first@example.com");
+    temp.write_file("identity.cfg", "[user]\n\tname = Included Again\n");
+    assert_eq!(cache.signature().unwrap().name, ~"Included Again");
+}
+
+#[test]
+fn signature_to_str() {
+    let when = git2::Time { time: 1370945688, offset: 540 };
+    let sig = git2::Signature { name: ~"Test User", email: ~"test@example.com", when: when };
+    assert_eq!(sig.to_str(), ~"Test User <test@example.com> Tue Jun 11 19:14:48 2013 +0900");
+
+    let mut status = git2::Status::new();
+    status.wt_new = true;
+    assert_eq!(status.to_str(), ~"??");
+    status.wt_new = false;
+    status.index_modified = true;
+    assert_eq!(status.to_str(), ~"M ");
+}
+
+#[test]
+fn commit_to_json() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    let commit = repo.lookup_commit(&oid).unwrap();
+    match commit.to_json() {
+        extra::json::Object(obj) => {
+            assert_eq!(obj.find(&~"id"),
+                Some(&extra::json::String(~"21002f5d3f411fe990e13604273a51cd598a4a51")));
+            assert_eq!(obj.find(&~"parents"), Some(&extra::json::List(~[])));
+        },
+        _ => fail!(~"commit should be a JSON object"),
+    }
+}
+
+#[test]
+fn try_lookup() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    assert!(repo.try_lookup_commit(&oid).is_ok());
+    match repo.try_lookup_tree(&oid) {
+        Err(git2::WrongType(git2::GIT_OBJ_COMMIT)) => (),
+        _ => fail!(~"a commit should not be found as a tree"),
+    }
+    let missing = git2::oid::from_str(&"0000000000000000000000000000000000000001");
+    match repo.try_lookup_blob(&missing) {
+        Err(git2::NotFound) => (),
+        _ => fail!(~"the blob should not be found"),
+    }
+    let (msg, klass) = git2::NotFound.to_error(&missing);
+    assert!(msg.contains("0000000000000000000000000000000000000001"));
+    assert_eq!(klass as int, git2::GITERR_ODB as int);
+}
+
+#[test]
+fn index_entries() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let index = repo.index().unwrap();
+    let entry = index.get_bypath("README.md", 0).unwrap();
+    assert_eq!(entry.path, ~"README.md");
+    assert_eq!(entry.stage, 0);
+    assert_eq!(index.entries().len(), index.len());
+    assert!(index.get_bypath("missing", 0).is_none());
+}
+
+#[test]
+fn temp_repo_commit_and_status() {
+    let temp = git2::test::TempRepo::new();
+    assert!(temp.repo.is_empty());
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let commit = temp.repo.lookup_commit(&first).unwrap();
+    assert_eq!(commit.message(), ~"first");
+    assert_eq!(commit.author(), git2::test::signature());
+
+    temp.write_file("a.txt", "hello world\n");
+    temp.write_file("b.txt", "new\n");
+    let status = temp.repo.status();
+    let codes: ~[(~str, ~str)] = status.iter().transform(|&(ref p, ref s)| (copy *p, s.to_str()))
+                                    .collect();
+    assert_eq!(codes, ~[(~"a.txt", ~" M"), (~"b.txt", ~"??")]);
+}
+
+#[test]
+fn temp_repo_symlink_not_followed() {
+    let outside = git2::test::temp_path("git2-rs-outside");
+    std::os::make_dir(&outside, 0o755);
+    let kept = outside.push("kept.txt");
+    std::io::file_writer(&kept, [std::io::Create]).unwrap().write_str("kept\n");
+    {
+        let temp = git2::test::TempRepo::new();
+        let link = Path(temp.path).push("link");
+        assert_eq!(std::run::process_status("ln", [~"-s", outside.to_str(), link.to_str()]), 0);
+    }
+    // dropping the repository removed the link, not the directory it points to
+    assert!(std::os::path_exists(&kept));
+    git2::test::remove_dir_all(&outside);
+    assert!(!std::os::path_exists(&outside));
+}
+
+#[test]
+fn status_with_options() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("b.txt", "world\n", "second");
+    temp.write_file("a.txt", "hello world\n");
+    temp.write_file("c.txt", "new\n");
+
+    let mut opts = git2::StatusOption::new();
+    opts.include_unmodified = true;
+    let status = temp.repo.status_with(&opts).unwrap();
+    let codes: ~[(~str, ~str)] = status.iter().transform(|&(ref p, ref s)| (copy *p, s.to_str()))
+                                    .collect();
+    assert_eq!(codes, ~[(~"a.txt", ~" M"), (~"b.txt", ~"  "), (~"c.txt", ~"??")]);
+    assert!(status[1].second_ref().is_unmodified());
+
+    opts.show = git2::StatusShowIndexOnly;
+    opts.include_unmodified = false;
+    assert!(temp.repo.status_with(&opts).unwrap().is_empty());
+}
+
+#[test]
+fn status_ignore_filemode() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("run.sh", "echo hi\n", "first");
+    temp.repo.config().unwrap().set_bool("core.filemode", true);
+    let path = Path(temp.path).push("run.sh").to_str();
+    do path.as_c_str |c_path| {
+        unsafe { std::libc::chmod(c_path, 0o755); }
+    }
+
+    let mut opts = git2::StatusOption::new();
+    assert_eq!(temp.repo.status_with(&opts).unwrap().len(), 1);
+    opts.ignore_filemode = true;
+    assert!(temp.repo.status_with(&opts).unwrap().is_empty());
+    temp.write_file("run.sh", "echo hello\n");
+    let status = temp.repo.status_with(&opts).unwrap();
+    assert_eq!(status.len(), 1);
+    assert!(status[0].second_ref().wt_modified);
+}
+
+#[test]
+fn status_classified() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "one\ntwo\n", "first");
+    temp.commit_file("b.txt", "one two\n", "second");
+    temp.commit_file("c.txt", "one\n", "third");
+    temp.write_file("a.txt", "one\r\ntwo\r\n");
+    temp.write_file("b.txt", "one  two \n");
+    temp.write_file("c.txt", "two\n");
+    temp.write_file("d.txt", "new\n");
+
+    let status = temp.repo.status_classified(&git2::StatusOption::new()).unwrap();
+    let kinds: ~[(~str, Option<git2::ChangeKind>)] = do status.map |&(ref path, _, kind)| {
+        (copy *path, kind)
+    };
+    assert_eq!(kinds, ~[(~"a.txt", Some(git2::ChangeEolOnly)),
+                        (~"b.txt", Some(git2::ChangeWhitespaceOnly)),
+                        (~"c.txt", Some(git2::ChangeContent)),
+                        (~"d.txt", None)]);
+}
+
+#[test]
+fn status_classified_missing_blob() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "one\n", "first");
+    temp.write_file("a.txt", "one\ntwo\n");
+    let id = git2::odb::hash(bytes!("one\n"), git2::GIT_OBJ_BLOB).unwrap().to_str();
+    let object = Path(temp.path).push_many([~".git", ~"objects", id.slice(0, 2).to_owned(),
+                                            id.slice(2, 40).to_owned()]);
+    assert!(std::os::remove_file(&object));
+
+    let repo = git2::repository::open(temp.path).unwrap();
+    match repo.status_classified(&git2::StatusOption::new()) {
+        Err((msg, _)) => assert!(msg.contains(id.as_slice())),
+        Ok(_) => fail!(~"the missing blob should be reported"),
+    }
+}
+
+#[test]
+fn racily_clean_index() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "aaaa\n", "first");
+    let set_mtime = |path: ~str| {
+        assert_eq!(std::run::process_status("touch", [~"-d", ~"@1500000000", path]), 0);
+    };
+    let file = Path(temp.path).push("a.txt").to_str();
+    // the entry gets the time of the file, then the index file the same time
+    set_mtime(copy file);
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+    index.write();
+    set_mtime(Path(temp.repo.path().unwrap()).push("index").to_str());
+    // same size and time as recorded in the entry
+    temp.write_file("a.txt", "bbbb\n");
+    set_mtime(file);
+    let status = temp.repo.status();
+    assert_eq!(status.len(), 1);
+    assert!(status[0].second_ref().wt_modified);
+}
+
+#[test]
+fn is_clean() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    assert!(temp.repo.is_clean().unwrap());
+    temp.write_file("b.txt", "untracked\n");
+    assert!(temp.repo.is_clean().unwrap());
+    temp.write_file("a.txt", "hello world\n");
+    assert!(!temp.repo.is_clean().unwrap());
+}
+
+#[test]
+fn foreach_stop() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "a\n", "first");
+    temp.commit_file("b.txt", "b\n", "second");
+    let second = temp.commit_file("c.txt", "c\n", "third");
+    let mut count = 0;
+    assert!(!temp.repo.odb().unwrap().foreach(|_| { count += 1; false }));
+    assert_eq!(count, 1);
+
+    let tree = temp.repo.lookup_commit(&second).unwrap().tree().unwrap();
+    count = 0;
+    assert!(!tree.walk_preorder(|_, _| { count += 1; git2::WalkStop }));
+    assert_eq!(count, 1);
+    count = 0;
+    assert!(!tree.walk_postorder(|_, _| { count += 1; false }));
+    assert_eq!(count, 1);
+
+    let old_tree = temp.repo.lookup_commit(&first).unwrap().tree().unwrap();
+    let opts = git2::diff::DiffOption::new();
+    let diff = temp.repo.diff_trees(Some(old_tree), Some(tree), &opts).unwrap();
+    count = 0;
+    assert!(!diff.each_line(|_, _, _| { count += 1; false }));
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn byte_paths() {
+    assert_eq!(git2::lossy_utf8(bytes!("caf\xc3\xa9")), ~"caf\u00e9");
+    assert_eq!(git2::lossy_utf8([0x61, 0xff, 0x62, 0xc3]), ~"a\ufffdb\ufffd");
+
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("dir/a.txt", "hello\n", "first");
+    temp.write_file("b.txt", "new\n");
+    let index = temp.repo.index().unwrap();
+    assert_eq!(index.get(0).unwrap().path_bytes, bytes!("dir/a.txt").to_owned());
+    let status = temp.repo.status_with_bytes(&git2::StatusOption::new()).unwrap();
+    assert_eq!(status[0].first_ref(), &bytes!("b.txt").to_owned());
+
+    // a file name which is not valid UTF-8
+    let name = ~[0x63u8, 0xff, 0x2e, 0x74, 0x78, 0x74];     // "c\xff.txt"
+    let mut full = (temp.path + "/").as_bytes().to_owned();
+    full.push_all(name);
+    full.push(0);
+    unsafe {
+        let fd = std::libc::funcs::posix88::fcntl::open(
+            std::cast::transmute(std::vec::raw::to_ptr(full)),
+            std::libc::consts::os::posix88::O_CREAT | std::libc::consts::os::posix88::O_WRONLY,
+            0o644);
+        assert!(fd >= 0);
+        std::libc::funcs::posix88::unistd::close(fd);
+    }
+    let status = temp.repo.status_with_bytes(&git2::StatusOption::new()).unwrap();
+    assert!(status.iter().any(|&(ref path, _)| *path == name));
+    index.add_bypath_bytes(name);
+    index.write();
+    temp.commit_index("add c\\xff.txt");
+    let index = temp.repo.index().unwrap();
+    let entry = index.entries().consume_iter().find_(|e| e.path_bytes == name).unwrap();
+    assert_eq!(entry.path, ~"c\ufffd.txt");
+    assert!(temp.repo.status_with_bytes(&git2::StatusOption::new()).unwrap().iter()
+                .all(|&(ref path, _)| *path != name));
+}
+
+#[test]
+fn status_against_tree() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("b.txt", "new\n", "second");
+    let commit = temp.repo.lookup_commit(&first).unwrap();
+    let status = temp.repo.status_against_tree(commit.tree().unwrap()).unwrap();
+    let codes: ~[(~str, ~str)] = status.iter().transform(|&(ref p, ref s)| (copy *p, s.to_str()))
+                                    .collect();
+    assert_eq!(codes, ~[(~"b.txt", ~"D ")]);
+}
+
+#[test]
+fn set_workdir() {
+    let temp = git2::test::TempRepo::new();
+    let other = git2::test::temp_path("workdir");
+    std::os::make_dir(&other, 0x1c0);
+    temp.repo.set_workdir(other.to_str(), false);
+    assert_eq!(Path(temp.repo.workdir().unwrap()), other);
+    git2::test::remove_dir_all(&other);
+}
+
+#[test]
+fn tree_collect_paths() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("dir/b.txt", "world\n", "first");
+    let head = temp.repo.lookup_commit(&temp.repo.head().unwrap().resolve()).unwrap();
+    let tree = head.tree().unwrap();
+    let paths: ~[~str] = tree.collect_paths().iter().transform(|&(ref p, _, _)| copy *p).collect();
+    assert_eq!(paths, ~[~"dir", ~"dir/b.txt"]);
+}
+
+#[test]
+fn tree_walk_gitlinks() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let head = temp.repo.lookup_commit(&first).unwrap();
+    let builder = git2::TreeBuilder::from_tree(head.tree().unwrap()).unwrap();
+    builder.insert("sub", &first, git2::GIT_FILEMODE_COMMIT).unwrap();
+    let tree = temp.repo.lookup_tree(&builder.write(&temp.repo)).unwrap();
+
+    let mut reported = ~[];
+    do tree.walk_preorder_gitlinks(git2::GitlinkReport) |path, _, gitlink| {
+        reported.push((path.to_owned(), gitlink.map(|&oid| *oid)));
+        git2::WalkPass
+    };
+    assert_eq!(reported, ~[(~"a.txt", None), (~"sub", Some(first))]);
+
+    let mut skipped = ~[];
+    do tree.walk_preorder_gitlinks(git2::GitlinkSkip) |path, _, _| {
+        skipped.push(path.to_owned());
+        git2::WalkPass
+    };
+    assert_eq!(skipped, ~[~"a.txt"]);
+}
+
+#[test]
+fn empty_objects() {
+    let temp = git2::test::TempRepo::new();
+    let tree = temp.repo.empty_tree().unwrap();
+    assert_eq!(tree.id(), &git2::oid::empty_tree());
+    assert!(tree.is_empty());
+    assert_eq!(tree.id().to_str(), git2::oid::EMPTY_TREE_ID.to_owned());
+    let blob = temp.repo.empty_blob().unwrap();
+    assert_eq!(blob.id(), &git2::oid::empty_blob());
+    assert!(temp.repo.odb().unwrap().exists(&git2::oid::empty_blob()));
+}
+
+#[test]
+fn observers() {
+    let mut temp = git2::test::TempRepo::new();
+    let (port, chan) = std::comm::stream();
+    let chan = std::comm::SharedChan::new(chan);
+    let refs = chan.clone();
+    temp.repo.on_ref_updated(|name, id| refs.send(fmt!("ref %s %s", name, id.to_str())));
+    let index = chan.clone();
+    temp.repo.on_index_written(|| index.send(~"index"));
+    temp.repo.on_commit_created(|id| chan.send(fmt!("commit %s", id.to_str())));
+
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    assert_eq!(port.recv(), ~"index");
+    assert_eq!(port.recv(), fmt!("commit %s", first.to_str()));
+    assert_eq!(port.recv(), fmt!("ref HEAD %s", first.to_str()));
+    assert!(!port.peek());
+}
+
+#[test]
+fn observers_deletions() {
+    let mut temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let (port, chan) = std::comm::stream();
+    let chan = std::comm::SharedChan::new(chan);
+    let refs = chan.clone();
+    temp.repo.on_ref_updated(|name, id| refs.send(fmt!("ref %s %s", name, id.to_str())));
+    temp.repo.on_ref_deleted(|name| chan.send(fmt!("deleted %s", name)));
+
+    temp.repo.reference_create("refs/tags/old", &first, false, None).unwrap();
+    assert_eq!(port.recv(), fmt!("ref refs/tags/old %s", first.to_str()));
+    temp.repo.lookup("refs/tags/old").unwrap().delete();
+    assert_eq!(port.recv(), ~"deleted refs/tags/old");
+
+    temp.branch("topic", &first);
+    let topic = temp.repo.lookup("refs/heads/topic").unwrap();
+    topic.branch_move("renamed", false).unwrap();
+    assert_eq!(port.recv(), ~"deleted refs/heads/topic");
+    assert_eq!(port.recv(), fmt!("ref refs/heads/renamed %s", first.to_str()));
+    temp.repo.set_head("refs/heads/renamed").unwrap();
+    assert_eq!(port.recv(), fmt!("ref HEAD %s", first.to_str()));
+    git2::reference::branch_delete(temp.repo.lookup("refs/heads/master").unwrap());
+    assert_eq!(port.recv(), ~"deleted refs/heads/master");
+    assert!(!port.peek());
+}
+
+#[test]
+fn reflog_expire() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("a.txt", "hello world\n", "second");
+    temp.commit_file("a.txt", "hello again\n", "third");
+    // the last two commits are now only reachable from the reflog
+    temp.repo.reference_create("refs/heads/master", &first, true, None).unwrap();
+    let len = temp.repo.reflog("refs/heads/master").unwrap().len();
+    assert_eq!(len, 4);
+
+    // nothing is old enough
+    assert_eq!(temp.repo.reflog_expire("refs/heads/master", 0, false).unwrap(), 0);
+
+    let removed = temp.repo.reflog_expire("refs/heads/master", std::i64::max_value, true).unwrap();
+    assert_eq!(removed, 2);
+    let reflog = temp.repo.reflog("refs/heads/master").unwrap();
+    assert_eq!(reflog.entries().map(|e| e.id_new), ~[first, first]);
+
+    // the most recent entry is always kept
+    assert_eq!(temp.repo.reflog_expire("refs/heads/master", std::i64::max_value, false).unwrap(), 1);
+    assert_eq!(temp.repo.reflog("refs/heads/master").unwrap().len(), 1);
+}
+
+#[test]
+fn reflog_messages() {
+    use git2::reflog;
+    assert_eq!(reflog::commit_message("fix typo"), ~"commit: fix typo");
+    assert_eq!(reflog::initial_commit_message("first"), ~"commit (initial): first");
+    assert_eq!(reflog::fast_forward_message("topic"), ~"merge topic: Fast-forward");
+    assert_eq!(reflog::merge_message("topic", "recursive"),
+                ~"merge topic: Merge made by the 'recursive' strategy.");
+    assert_eq!(reflog::checkout_message("master", "topic"),
+                ~"checkout: moving from master to topic");
+    assert_eq!(reflog::reset_message("HEAD~1"), ~"reset: moving to HEAD~1");
+}
+
+#[test]
+fn merge_analysis() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("a.txt", "hello world\n", "second");
+
+    let analysis = temp.repo.merge_analysis([first]).unwrap();
+    assert!(analysis.up_to_date);
+    assert!(!analysis.fast_forward && !analysis.normal && !analysis.unborn);
+    assert_eq!(analysis.preference, git2::merge::MergePreferenceNone);
+
+    temp.repo.config().unwrap().set_str("merge.ff", "only");
+    let analysis = temp.repo.merge_analysis([first]).unwrap();
+    assert_eq!(analysis.preference, git2::merge::MergePreferenceFastForwardOnly);
+}
+
+#[test]
+fn merge_commits_favor() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "base\n", "base");
+
+    // their side, committed without moving HEAD
+    temp.write_file("a.txt", "theirs\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+    let sig = git2::test::signature();
+    let their_tree = index.write_tree().unwrap();
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    let theirs = temp.repo.commit(None, &sig, &sig, None, "theirs", their_tree, [base_commit]);
+    let ours = temp.commit_file("a.txt", "ours\n", "ours");
+
+    let our_commit = temp.repo.lookup_commit(&ours).unwrap();
+    let their_commit = temp.repo.lookup_commit(&theirs).unwrap();
+    let mut opts = git2::merge::MergeOption::new();
+    let merged = temp.repo.merge_commits(our_commit, their_commit, &opts).unwrap();
+    assert!(merged.has_conflicts());
+
+    opts.favor = git2::merge::FavorOurs;
+    let merged = temp.repo.merge_commits(our_commit, their_commit, &opts).unwrap();
+    assert!(!merged.has_conflicts());
+    let entry = merged.get_bypath("a.txt", 0).unwrap();
+    assert_eq!(entry.id, *our_commit.tree().unwrap().entry_byname("a.txt").unwrap().id());
+}
+
+#[test]
+fn open_from_env() {
+    let temp = git2::test::TempRepo::new();
+    let worktree = git2::test::temp_path("git2-rs-worktree");
+    std::os::mkdir_recursive(&worktree, 0o755);
+    std::os::setenv("GIT_DIR", temp.repo.path().unwrap());
+    std::os::setenv("GIT_WORK_TREE", worktree.to_str());
+    let repo = git2::repository::open_from_env();
+    std::os::unsetenv("GIT_DIR");
+    std::os::unsetenv("GIT_WORK_TREE");
+    git2::test::remove_dir_all(&worktree);
+
+    let repo = repo.unwrap();
+    assert_eq!(repo.path(), temp.repo.path());
+    let name = worktree.filename().unwrap();
+    assert!(repo.workdir().unwrap().ends_with(fmt!("%s/", name)));
+}
+
+#[test]
+fn reopen() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let repo = temp.repo.reopen().unwrap();
+    assert_eq!(repo.path(), temp.repo.path());
+    assert_eq!(repo.workdir(), temp.repo.workdir());
+
+    let (port, chan) = std::comm::stream();
+    do std::task::spawn {
+        chan.send(repo.head().unwrap().resolve());
+    }
+    assert_eq!(port.recv(), first);
+}
+
+fn push_be32(out: &mut ~[u8], v: u32) {
+    out.push_all([(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+}
+
+/// a pack index of the given version listing `entries`, without the trailing checksums
+fn pack_index(version: uint, entries: &[(git2::OID, u64)]) -> ~[u8] {
+    let mut sorted = entries.to_owned();
+    extra::sort::quick_sort3(sorted);
+    let mut out = ~[];
+    if version == 2 {
+        out.push_all([0xff, 't' as u8, 'O' as u8, 'c' as u8]);
+        push_be32(&mut out, 2);
+    }
+    for std::uint::range(0, 256) |b| {
+        let mut count = 0;
+        for sorted.iter().advance |&(id, _)| {
+            if (id.id[0] as u8 as uint) <= b {
+                count += 1;
+            }
+        }
+        push_be32(&mut out, count);
+    }
+    let name = |id: &git2::OID| id.id.map(|&b| b as u8);
+    if version == 1 {
+        for sorted.iter().advance |&(id, offset)| {
+            push_be32(&mut out, offset as u32);
+            out.push_all(name(&id));
+        }
+        return out;
+    }
+    for sorted.iter().advance |&(id, _)| {
+        out.push_all(name(&id));
+    }
+    for sorted.iter().advance |_| {
+        push_be32(&mut out, 0);
+    }
+    let mut large = ~[];
+    for sorted.iter().advance |&(_, offset)| {
+        if offset < 0x80000000 {
+            push_be32(&mut out, offset as u32);
+        } else {
+            push_be32(&mut out, 0x80000000 | (large.len() as u32));
+            large.push(offset);
+        }
+    }
+    for large.iter().advance |&offset| {
+        push_be32(&mut out, (offset >> 32) as u32);
+        push_be32(&mut out, offset as u32);
+    }
+    out
+}
+
+#[test]
+fn pack_index_offsets() {
+    let id = |content: &str| {
+        git2::odb::hash(content.as_bytes(), git2::GIT_OBJ_BLOB).unwrap()
+    };
+    let (a, b, c) = (id("a"), id("b"), id("c"));
+    let missing = id("missing");
+
+    let v1 = pack_index(1, [(a, 12), (b, 345), (c, 6789)]);
+    assert_eq!(git2::odb::pack_index_offset(v1, &a), Some(12));
+    assert_eq!(git2::odb::pack_index_offset(v1, &b), Some(345));
+    assert_eq!(git2::odb::pack_index_offset(v1, &c), Some(6789));
+    assert!(git2::odb::pack_index_offset(v1, &missing).is_none());
+
+    // c is beyond 4GB, and stored in the table of large offsets
+    let v2 = pack_index(2, [(a, 12), (b, 345), (c, 0x100000010)]);
+    assert_eq!(git2::odb::pack_index_offset(v2, &a), Some(12));
+    assert_eq!(git2::odb::pack_index_offset(v2, &b), Some(345));
+    assert_eq!(git2::odb::pack_index_offset(v2, &c), Some(0x100000010));
+    assert!(git2::odb::pack_index_offset(v2, &missing).is_none());
+
+    // truncated or of an unknown version
+    assert!(git2::odb::pack_index_offset(v2.slice(0, 100), &a).is_none());
+    let mut v3 = copy v2;
+    v3[7] = 3;
+    assert!(git2::odb::pack_index_offset(v3, &a).is_none());
+}
+
+#[test]
+fn blob_write_compression_level() {
+    let temp = git2::test::TempRepo::new();
+    let content = std::vec::from_elem(10000, 'a' as u8);
+    // the last level set is used
+    temp.repo.set_compression_level(9).unwrap();
+    temp.repo.set_compression_level(0).unwrap();
+    assert!(temp.repo.set_compression_level(10).is_err());
+    let id = temp.repo.blob_write(content).unwrap();
+    assert_eq!(id, git2::odb::hash(content, git2::GIT_OBJ_BLOB).unwrap());
+
+    // stored without compression, the loose object is larger than the content
+    let odb = temp.repo.odb().unwrap();
+    match odb.locate(&id) {
+        Some(git2::odb::Loose(path)) => assert!(Path(path).get_size().unwrap() > 10000),
+        _ => fail!(~"the blob was not written as a loose object"),
+    }
+
+    // the objects of the alternates are still read
+    let other = git2::test::TempRepo::new();
+    let borrowed = other.commit_file("b.txt", "borrowed\n", "other");
+    let info = Path(temp.path).push_many([".git", "objects", "info"]);
+    std::os::mkdir_recursive(&info, 0o755);
+    std::io::file_writer(&info.push("alternates"), [std::io::Create]).unwrap()
+        .write_str(Path(other.path).push_many([".git", "objects"]).to_str() + "\n");
+    temp.repo.set_compression_level(1).unwrap();
+    assert!(temp.repo.lookup_commit(&borrowed).is_some());
+    assert!(temp.repo.blob_lookup(&id).is_some());
+
+    let mut opts = git2::repository::OpenOption::new();
+    opts.read_only = true;
+    let repo = git2::repository::open_ext(temp.repo.path().unwrap(), &opts).unwrap();
+    assert!(repo.set_compression_level(9).is_err());
+    assert!(repo.odb().unwrap().add_loose_backend(9).is_err());
+
+    let mut scratch = ~[];
+    let first = do std::io::with_bytes_reader(bytes!("first\n")) |reader| {
+        temp.repo.blob_write_from_reader(reader, &mut scratch).unwrap()
+    };
+    let second = do std::io::with_bytes_reader(bytes!("2\n")) |reader| {
+        temp.repo.blob_write_from_reader(reader, &mut scratch).unwrap()
+    };
+    assert_eq!(scratch, bytes!("2\n").to_owned());
+    let blob = temp.repo.blob_lookup(&first).unwrap();
+    do blob.rawcontent_as_slice |v| { assert_eq!(v, bytes!("first\n")); }
+    assert_eq!(second, git2::odb::hash(bytes!("2\n"), git2::GIT_OBJ_BLOB).unwrap());
+}
+
+#[test]
+fn blob_create_fromreader() {
+    let temp = git2::test::TempRepo::new();
+    // larger than a block, so that it is copied in several
+    let content = do std::vec::from_fn(200000) |i| { (i % 251) as u8 };
+    let blob = do std::io::with_bytes_reader(content) |reader| {
+        temp.repo.blob_create_fromreader(reader, None).unwrap()
+    };
+    assert_eq!(*blob.id(), git2::odb::hash(content, git2::GIT_OBJ_BLOB).unwrap());
+    do blob.rawcontent_as_slice |v| { assert_eq!(v, content.as_slice()); }
+
+    temp.repo.config().unwrap().set_bool("core.autocrlf", true);
+    let blob = do std::io::with_bytes_reader(bytes!("one\r\ntwo\r\n")) |reader| {
+        temp.repo.blob_create_fromreader(reader, Some("a.txt")).unwrap()
+    };
+    do blob.rawcontent_as_slice |v| { assert_eq!(v, bytes!("one\ntwo\n")); }
+
+    // the temporary files are removed
+    let objects = Path(temp.repo.path().unwrap()).push("objects");
+    assert!(!std::os::list_dir(&objects).iter().any(|name| name.starts_with("tmp_blob_")));
+}
+
+#[test]
+fn blob_write_chunked() {
+    let temp = git2::test::TempRepo::new();
+    let mut state = 1u32;
+    let content = do std::vec::from_fn(100000) |_| {
+        state = state * 1103515245 + 12345;
+        (state >> 16) as u8
+    };
+    let mut opts = git2::chunked::ChunkOption::new();
+    opts.chunk_size = 4096;
+    let first = do std::io::with_bytes_reader(content) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts).unwrap()
+    };
+    assert_eq!(first.size, 100000);
+    assert!(first.chunks > 1);
+    assert_eq!(first.new_chunks, first.chunks);
+    let read = do std::io::with_bytes_writer |writer| {
+        temp.repo.blob_read_chunked(&first.manifest, writer).unwrap();
+    };
+    assert_eq!(read, content.clone());
+    assert_eq!(first.refname, ~"refs/chunks/" + first.manifest.to_str());
+    assert_eq!(temp.repo.lookup(first.refname).unwrap().resolve(), first.manifest);
+
+    // an insertion only changes the chunks around it
+    let edited = content.slice(0, 50000) + bytes!("inserted") + content.slice(50000, 100000);
+    let second = do std::io::with_bytes_reader(edited) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts).unwrap()
+    };
+    assert!(second.new_chunks < 4 && second.new_chunks < second.chunks / 4);
+
+    // a given reference is moved to the new manifest
+    opts.refname = Some(~"refs/chunks/content");
+    let named = do std::io::with_bytes_reader(edited) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts).unwrap()
+    };
+    assert_eq!(named.manifest, second.manifest);
+    assert_eq!(named.refname, ~"refs/chunks/content");
+    let moved = do std::io::with_bytes_reader(content) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts).unwrap()
+    };
+    assert_eq!(temp.repo.lookup("refs/chunks/content").unwrap().resolve(), moved.manifest);
+    opts.refname = None;
+
+    // fixed chunks: the two first chunks are identical
+    opts.mode = git2::chunked::FixedChunks;
+    let zeros = std::vec::from_elem(10000, 0u8);
+    let fixed = do std::io::with_bytes_reader(zeros) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts).unwrap()
+    };
+    assert_eq!(fixed.chunks, 3);
+    assert_eq!(fixed.new_chunks, 2);
+
+    opts.chunk_size = 0;
+    let res = do std::io::with_bytes_reader(zeros) |reader| {
+        temp.repo.blob_write_chunked(reader, &opts)
+    };
+    assert!(res.is_err());
+    let head = temp.commit_file("a.txt", "hello\n", "first");
+    let tree = temp.repo.lookup_commit(&head).unwrap().tree().unwrap();
+    let res = do std::io::with_bytes_writer |writer| {
+        assert!(temp.repo.blob_read_chunked(tree.id(), writer).is_err());
+    };
+    assert!(res.is_empty());
+}
+
+#[test]
+fn open_read_only() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let mut opts = git2::repository::OpenOption::new();
+    opts.read_only = true;
+    let repo = git2::repository::open_ext(temp.repo.path().unwrap(), &opts).unwrap();
+    assert!(repo.is_read_only());
+    assert!(!temp.repo.is_read_only());
+
+    // reads still work
+    assert_eq!(repo.head().unwrap().resolve(), first);
+    assert!(repo.lookup_commit(&first).is_some());
+
+    match repo.blob_create_frombuffer(bytes!("new\n")) {
+        Err((_, class)) => assert_eq!(class as int, git2::GITERR_REPOSITORY as int),
+        Ok(_) => fail!(~"a blob was written to a read-only repository"),
+    }
+    assert!(repo.reference_create("refs/heads/other", &first, false, None).is_err());
+    assert!(repo.lookup("refs/heads/other").is_none());
+    assert!(repo.reopen().unwrap().is_read_only());
+}
+
+#[test]
+fn read_only_leaves_worktree() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    // check out a branch at the first commit
+    temp.branch("topic", &first);
+    temp.repo.set_head("refs/heads/topic").unwrap();
+    let index = temp.repo.index().unwrap();
+    index.read_tree(temp.repo.lookup_commit(&first).unwrap().tree().unwrap());
+    index.write();
+    temp.write_file("a.txt", "hello\n");
+
+    let mut opts = git2::repository::OpenOption::new();
+    opts.read_only = true;
+    let repo = git2::repository::open_ext(temp.repo.path().unwrap(), &opts).unwrap();
+    let path = Path(temp.path).push("a.txt");
+    let unchanged = || {
+        assert_eq!(std::io::read_whole_file_str(&path).unwrap(), ~"hello\n");
+        // a new handle reads the index from the disk
+        let fresh = git2::repository::open(temp.repo.path().unwrap()).unwrap();
+        let index = fresh.index().unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get_bypath("a.txt", 0).unwrap().id,
+                   *temp.repo.lookup_commit(&first).unwrap().tree().unwrap()
+                        .entry_byname("a.txt").unwrap().id());
+    };
+
+    match repo.fast_forward("HEAD", &second) {
+        Err(git2::merge::FastForwardFailed(_, class)) =>
+            assert_eq!(class as int, git2::GITERR_REPOSITORY as int),
+        _ => fail!(~"a read-only repository was fast-forwarded"),
+    }
+    assert_eq!(temp.repo.lookup("refs/heads/topic").unwrap().resolve(), first);
+    unchanged();
+
+    let mail = "From: Some One <someone@example.com>\n\
+Date: Tue, 11 Jun 2013 19:14:48 +0900\n\
+Subject: [PATCH] Update\n\
+\n\
+---\n\
+diff --git a/a.txt b/a.txt\n\
+--- a/a.txt\n\
++++ b/a.txt\n\
+@@ -1 +1 @@\n\
+-hello\n\
++hello mail\n";
+    let patch = git2::mailbox::parse(mail).unwrap();
+    assert!(git2::mailbox::apply(&repo, &patch).is_err());
+    unchanged();
+    assert_eq!(temp.repo.head().unwrap().resolve(), first);
+
+    assert!(repo.index().unwrap().write_tree().is_err());
+    let tree = repo.lookup_commit(&first).unwrap().tree().unwrap();
+    assert!(tree.update([git2::Remove(~"a.txt")]).is_err());
+}
+
+#[test]
+fn snapshot_point_in_time() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("user.name", "Before");
+    let snap = temp.repo.snapshot().unwrap();
+
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    temp.repo.reference_create("refs/heads/later", &second, false, None).unwrap();
+    cfg.set_str("user.name", "After");
+
+    assert_eq!(snap.head(), Some((~"refs/heads/master", first)));
+    assert_eq!(snap.lookup("refs/heads/master"), Some(first));
+    assert_eq!(snap.lookup_branch("master", false), Some(first));
+    assert!(snap.lookup("refs/heads/later").is_none());
+    assert!(!snap.reference_names().contains(&~"refs/heads/later"));
+    assert_eq!(snap.config().get_str("user.name"), Some(~"Before"));
+    // the objects written later can still be read
+    assert!(snap.lookup_commit(&second).is_some());
+    assert_eq!(temp.repo.head().unwrap().resolve(), second);
+}
+
+#[test]
+fn check_commit_parts() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let parent = temp.repo.lookup_commit(&first).unwrap();
+    let tree = parent.tree().unwrap();
+    assert!(temp.repo.check_commit_parts(tree, [parent]).is_ok());
+
+    let other = temp.repo.reopen().unwrap();
+    let foreign_tree = other.lookup_tree(tree.id()).unwrap();
+    let parent = temp.repo.lookup_commit(&first).unwrap();
+    match temp.repo.check_commit_parts(foreign_tree, [parent]) {
+        Err((msg, class)) => {
+            assert!(msg.ends_with("belongs to another repository"));
+            assert_eq!(class as int, git2::GITERR_INVALID as int);
+        },
+        Ok(()) => fail!(~"a tree of another handle was accepted"),
+    }
+}
+
+#[test]
+fn branch_create_from_annotated() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("a.txt", "hello world\n", "second");
+    let target = temp.repo.annotated_commit_from_revspec("HEAD~1").unwrap();
+    assert_eq!(*target.id(), first);
+    let branch = temp.repo.branch_create_from_annotated("topic", target, false).unwrap();
+    assert_eq!(branch.resolve(), first);
+    let reflog = temp.repo.reflog("refs/heads/topic").unwrap();
+    assert_eq!(reflog.entry(0).unwrap().message, ~"branch: Created from HEAD~1");
+}
+
+#[test]
+fn merge_into_workdir() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "hello\n", "base");
+    temp.branch("side", &base);
+    let ours = temp.commit_file("b.txt", "ours\n", "ours");
+
+    let sig = git2::test::signature();
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    let builder = git2::TreeBuilder::from_tree(base_commit.tree().unwrap()).unwrap();
+    let blob = temp.repo.blob_create_frombuffer(bytes!("theirs\n")).unwrap();
+    builder.insert("c.txt", blob.id(), git2::GIT_FILEMODE_BLOB);
+    let their_tree = temp.repo.lookup_tree(&builder.write(&temp.repo)).unwrap();
+    let theirs = temp.repo.commit(Some("refs/heads/side"), &sig, &sig, None, "theirs",
+                                  their_tree, [base_commit]);
+
+    let head = temp.repo.annotated_commit_from_revspec("side").unwrap();
+    temp.repo.merge([head], &git2::merge::MergeOption::new(),
+                    &git2::checkout::CheckoutOption::new()).unwrap();
+    assert!(!temp.repo.index().unwrap().has_conflicts());
+    assert_eq!(temp.repo.merge_heads().unwrap(), ~[theirs]);
+    assert!(temp.repo.merge_message().unwrap().starts_with("Merge branch 'side'"));
+    let merged = Path(temp.path).push("c.txt");
+    assert_eq!(std::io::read_whole_file_str(&merged).unwrap(), ~"theirs\n");
+
+    let merge = temp.commit_index("merge side");
+    let parents: ~[git2::OID] = do temp.repo.lookup_commit(&merge).unwrap().parents().map |p| {
+        *p.id()
+    };
+    assert_eq!(parents, ~[ours, theirs]);
+    assert!(temp.repo.merge_heads().unwrap().is_empty());
+}
+
+#[test]
+fn merge_into_workdir_conflict_style() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "hello\n", "base");
+    temp.branch("side", &base);
+    temp.commit_file("a.txt", "ours\n", "ours");
+
+    let sig = git2::test::signature();
+    let base_commit = temp.repo.lookup_commit(&base).unwrap();
+    let builder = git2::TreeBuilder::from_tree(base_commit.tree().unwrap()).unwrap();
+    let blob = temp.repo.blob_create_frombuffer(bytes!("theirs\n")).unwrap();
+    builder.insert("a.txt", blob.id(), git2::GIT_FILEMODE_BLOB);
+    let their_tree = temp.repo.lookup_tree(&builder.write(&temp.repo)).unwrap();
+    temp.repo.commit(Some("refs/heads/side"), &sig, &sig, None, "theirs", their_tree,
+                     [base_commit]);
+
+    let head = temp.repo.annotated_commit_from_revspec("side").unwrap();
+    let mut opts = git2::merge::MergeOption::new();
+    opts.conflict_style = git2::merge::ConflictDiff3;
+    temp.repo.merge([head], &opts, &git2::checkout::CheckoutOption::new()).unwrap();
+    assert!(temp.repo.index().unwrap().has_conflicts());
+    let path = Path(temp.path).push("a.txt");
+    let content = std::io::read_whole_file_str(&path).unwrap();
+    assert!(content.contains("||||||| "));
+    assert!(content.contains("hello\n"));
+}
+
+#[test]
+fn odb_refresh() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    let watcher = temp.repo.reopen().unwrap();
+    let watcher_odb = watcher.odb().unwrap();
+    assert!(watcher_odb.exists(&temp.repo.head().unwrap().resolve()));
+
+    // another handle writes a pack after the watcher listed the packs
+    let odb = temp.repo.odb().unwrap();
+    let mempack = odb.add_mempack().unwrap();
+    let id = temp.repo.blob_create_frombuffer(bytes!("packed\n")).unwrap().id().clone();
+    mempack.flush().unwrap();
+    let loose = Path(temp.repo.path().unwrap()).push("objects")
+                    .push(id.to_str().slice_to(2)).push(id.to_str().slice_from(2));
+    assert!(!loose.exists());
+
+    // lookups refresh the packs by themselves when they miss
+    assert_eq!(watcher.blob_lookup(&id).unwrap().id().clone(), id);
+    watcher_odb.refresh().unwrap();
+    assert!(watcher_odb.exists(&id));
+}
+
+#[test]
+fn find_commit() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("a.txt", "hello world\n", "second");
+    assert_eq!(*temp.repo.find_commit("HEAD~1").unwrap().id(), first);
+    assert_eq!(temp.repo.find_commit("HEAD").unwrap().message(), ~"second");
+    match temp.repo.find_commit("HEAD:a.txt") {
+        Err((msg, class)) => {
+            assert_eq!(msg, ~"HEAD:a.txt is not a commit");
+            assert_eq!(class as int, git2::GITERR_OBJECT as int);
+        },
+        Ok(_) => fail!(~"a blob was returned as a commit"),
+    }
+    assert!(temp.repo.find_commit("no-such-branch").is_err());
+}
+
+#[test]
+fn trace_hook() {
+    // the hook is global and the other tests trace their own diffs in parallel, so
+    // only the messages carrying this marker are checked
+    let marker = fmt!("trace_hook %u", std::rand::random::<uint>());
+    let (port, chan) = std::comm::stream();
+    let chan = std::comm::SharedChan::new(chan);
+    let wanted = copy marker;
+    // libgit2 may be built without tracing; the messages of the crate are sent anyway
+    git2::trace::set_hook(git2::trace::TraceTrace, |level, msg| {
+        if level == git2::trace::TraceTrace &&
+                (msg.starts_with("git_diff_tree_to_tree: ") || msg.starts_with(wanted.as_slice())) {
+            chan.send(msg.to_owned());
+        }
+    });
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    let old = temp.repo.lookup_commit(&first).unwrap();
+    let new = temp.repo.lookup_commit(&second).unwrap();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let opts = git2::diff::DiffOption::new();
+    temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    assert!(port.recv().ends_with(" us"));
+    git2::trace::emit(git2::trace::TraceTrace, marker + " first");
+    loop {
+        let msg = port.recv();
+        if msg.starts_with(marker.as_slice()) {
+            assert_eq!(msg, marker + " first");
+            break;
+        }
+    }
+
+    // the replaced hook gets the messages
+    let (other_port, other_chan) = std::comm::stream();
+    let other_chan = std::comm::SharedChan::new(other_chan);
+    let wanted = copy marker;
+    git2::trace::set_hook(git2::trace::TraceTrace, |_, msg| {
+        if msg.starts_with(wanted.as_slice()) {
+            other_chan.send(msg.to_owned());
+        }
+    });
+    git2::trace::emit(git2::trace::TraceTrace, marker + " second");
+    git2::trace::clear_hook();
+    assert_eq!(other_port.recv(), marker + " second");
+}
+
+#[test]
+fn new_in_memory() {
+    let repo = git2::repository::new_in_memory().unwrap();
+    let id = repo.blob_create_frombuffer(bytes!("hello\n")).unwrap().id().clone();
+    let obj = repo.odb().unwrap().read(&id).unwrap();
+    assert_eq!(obj.size(), 6);
+    assert_eq!(obj.otype() as int, git2::GIT_OBJ_BLOB as int);
+    assert!(repo.lookup("refs/heads/master").is_none());
+
+    // no path, and the methods working on the files fail
+    assert_eq!(repo.path(), None);
+    assert!(repo.disk_path().is_err());
+    assert!(repo.watch(10).is_err());
+    assert!(repo.reopen().is_err());
+    assert!(repo.snapshot().is_err());
+    assert!(repo.check_ownership().is_err());
+    assert!(repo.odb().unwrap().add_loose_backend(1).is_err());
+    assert!(repo.odb().unwrap().locate(&id).is_none());
+    assert_eq!(repo.merge_heads().unwrap(), ~[]);
+}
+
+#[test]
+fn watch_refs() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let watcher = temp.repo.watch(10).unwrap();
+    assert!(watcher.try_recv().is_none());
+    temp.branch("topic", &first);
+    assert_eq!(watcher.recv(), git2::watch::RefsChanged);
+}
+
+#[test]
+fn watch_change_right_after_start() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    // a long interval: the change is made before the task ran, and must still be seen
+    let watcher = temp.repo.watch(200).unwrap();
+    temp.branch("topic", &first);
+    assert_eq!(watcher.recv(), git2::watch::RefsChanged);
+}
+
+#[test]
+fn temp_repo_diff() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    temp.branch("topic", &first);
+    assert!(temp.repo.lookup_branch("topic", false).is_some());
+
+    let old = temp.repo.lookup_commit(&first).unwrap();
+    let new = temp.repo.lookup_commit(&second).unwrap();
+    let opts = git2::diff::DiffOption::new();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let difflist = temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    let deltas = difflist.deltas();
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].to_str(), ~"M\ta.txt");
+}
+
+#[test]
+fn diff_commits_parallel() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("b.txt", "world\n", "second");
+    let third = temp.commit_file("a.txt", "hello world\n", "third");
+    let opts = git2::diff::DiffOption::new();
+    let results = temp.repo.diff_commits_parallel([first, second, third], &opts, 2).unwrap();
+    let summary: ~[(git2::OID, ~[~str])] = do results.map |r| {
+        (r.commit, r.deltas.map(|d| d.to_str()))
+    };
+    assert_eq!(summary, ~[(first, ~[~"A\ta.txt"]), (second, ~[~"A\tb.txt"]),
+                          (third, ~[~"M\ta.txt"])]);
+}
+
+#[test]
+fn abbreviate() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    assert_eq!(temp.repo.abbreviate(&first).unwrap(), first.to_str().slice(0, 7).to_owned());
+    temp.repo.config().unwrap().set_str("core.abbrev", "12");
+    assert_eq!(temp.repo.abbreviate(&first).unwrap(), first.to_str().slice(0, 12).to_owned());
+}
+
+#[test]
+fn init_with_template() {
+    let template = git2::test::temp_path("git2-rs-template");
+    std::os::mkdir_recursive(&template.push("hooks"), 0o755);
+    let hook = template.push_many(["hooks", "pre-commit"]);
+    std::io::file_writer(&hook, [std::io::Create]).unwrap().write_str("#!/bin/sh\n");
+
+    let path = git2::test::temp_path("git2-rs-init");
+    let mut opts = git2::repository::InitOption::new();
+    opts.template = Some(template.to_str());
+    opts.description = Some(~"test repository");
+    opts.initial_head = Some(~"main");
+    let repo = git2::repository::init_ext(path.to_str(), &opts).unwrap();
+    let copied = Path(repo.path().unwrap()).push_many(["hooks", "pre-commit"]);
+    assert!(std::os::path_exists(&copied));
+    let description = Path(repo.path().unwrap()).push("description");
+    assert!(std::io::read_whole_file_str(&description).unwrap().starts_with("test repository"));
+    assert!(repo.head_name().unwrap().ends_with("main"));
+
+    git2::test::remove_dir_all(&template);
+    git2::test::remove_dir_all(&path);
+}
+
+#[test]
+fn init_template_modes() {
+    let template = git2::test::temp_path("git2-rs-template");
+    std::os::mkdir_recursive(&template.push("hooks"), 0o755);
+    std::os::mkdir_recursive(&template.push("info"), 0o755);
+    let hook = template.push_many(["hooks", "pre-commit"]);
+    let exclude = template.push_many(["info", "exclude"]);
+    std::io::file_writer(&hook, [std::io::Create]).unwrap().write_str("#!/bin/sh\n");
+    std::io::file_writer(&exclude, [std::io::Create]).unwrap().write_str("*.o\n");
+    for [(&hook, 0o755), (&exclude, 0o644)].iter().advance |&(path, mode)| {
+        let res = do path.to_str().as_c_str |c_path| {
+            unsafe { std::libc::funcs::posix88::stat_::chmod(c_path, mode as std::libc::mode_t) }
+        };
+        assert_eq!(res, 0);
+    }
+
+    let path = git2::test::temp_path("git2-rs-init");
+    let mut opts = git2::repository::InitOption::new();
+    opts.template = Some(template.to_str());
+    let repo = git2::repository::init_ext(path.to_str(), &opts).unwrap();
+    let mode = |name: &[~str]| {
+        Path(repo.path().unwrap()).push_many(name).stat().unwrap().st_mode as uint & 0o777
+    };
+    // the hook stays executable, the other files do not become so
+    assert!(mode([~"hooks", ~"pre-commit"]) & 0o111 != 0);
+    assert_eq!(mode([~"info", ~"exclude"]) & 0o111, 0);
+    assert!(mode([~"info", ~"exclude"]) & 0o600 == 0o600);
+
+    git2::test::remove_dir_all(&template);
+    git2::test::remove_dir_all(&path);
+}
+
+#[test]
+fn index_write_locked() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("a.txt", "hello\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("a.txt");
+
+    let lock = Path(temp.repo.path().unwrap()).push("index.lock");
+    std::io::file_writer(&lock, [std::io::Create]).unwrap();
+    match index.write_locked(50) {
+        Err(git2::Busy) => (),
+        _ => fail!(~"the index should be locked"),
+    }
+    std::os::remove_file(&lock);
+    assert!(index.write_locked(50).is_ok());
+}
+
+#[test]
+fn temp_repo_clone() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let path = git2::test::temp_path("git2-rs-clone").to_str();
+    {
+        let clone = git2::repository::clone(temp.path, path).unwrap();
+        assert_eq!(clone.head().unwrap().resolve(), first);
+    }
+    git2::test::remove_dir_all(&Path(path));
+}
+
+#[test]
+fn libgit2_version() {
+    let (major, minor, _) = git2::version();
+    assert!(git2::require_version(major, minor, "anything").is_ok());
+    assert!(git2::require_version(major + 1, 0, "anything").is_err());
+    assert_eq!((major, minor), git2::LIBGIT2_VERSION);
+    assert!(git2::init().is_ok());
+    git2::shutdown();
+}
+
+#[test]
+fn url_instead_of() {
+    let temp = git2::test::TempRepo::new();
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("url.https://mirror.example.com/.insteadOf", "https://github.com/");
+    cfg.set_str("url.ssh://git@mirror.example.com/.pushInsteadOf", "https://github.com/");
+    assert_eq!(temp.repo.effective_url("https://github.com/a/b", false).unwrap(),
+                ~"https://mirror.example.com/a/b");
+    assert_eq!(temp.repo.effective_url("https://github.com/a/b", true).unwrap(),
+                ~"ssh://git@mirror.example.com/a/b");
+    assert_eq!(temp.repo.effective_url("https://example.org/c", false).unwrap(),
+                ~"https://example.org/c");
+}
+
+#[test]
+fn config_foreach_match_stop() {
+    let temp = git2::test::TempRepo::new();
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("url.https://a.example.com/.insteadOf", "a:");
+    cfg.set_str("url.https://b.example.com/.insteadOf", "b:");
+    let mut seen = 0;
+    assert!(cfg.foreach_match("^url\\.", |_, _| { seen += 1; true }));
+    assert_eq!(seen, 2);
+    let mut seen = 0;
+    assert!(!cfg.foreach_match("^url\\.", |_, _| { seen += 1; false }));
+    assert_eq!(seen, 1);
+}
+
+#[test]
+fn config_buffer() {
+    let cfg = git2::config::from_buffer("[core]\n\tbare = true\n[remote \"origin\"]\n\t\
+                                         url = https://example.com/a.git ; comment\n").unwrap();
+    assert_eq!(cfg.get_bool("core.bare"), Some(true));
+    assert_eq!(cfg.get_str("remote.origin.url"), Some(~"https://example.com/a.git"));
+    cfg.set_str("user.name", " Test User");
+    assert_eq!(cfg.to_buffer(), ~"[core]\n\tbare = true\n[remote \"origin\"]\n\t\
+                                  url = https://example.com/a.git\n[user]\n\tname = \" Test User\"\n");
+}
+
+#[test]
+fn split_remote_branch() {
+    let remotes = ~[~"origin", ~"team", ~"team/upstream"];
+    assert_eq!(git2::remote::split_branch_name(remotes, "origin/feature/x"),
+                Some((~"origin", ~"feature/x")));
+    assert_eq!(git2::remote::split_branch_name(remotes, "team/upstream/master"),
+                Some((~"team/upstream", ~"master")));
+    assert_eq!(git2::remote::split_branch_name(remotes, "team/master"),
+                Some((~"team", ~"master")));
+    assert_eq!(git2::remote::split_branch_name(remotes, "gone/master"), None);
+}
+
+#[test]
+fn remote_branches() {
+    let temp = git2::test::TempRepo::new();
+    let id = temp.commit_file("a.txt", "a\n", "first");
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("remote.team.url", "https://example.com/a.git");
+    cfg.set_str("remote.team/upstream.url", "https://example.com/b.git");
+    for ["team/master", "team/upstream/feature/x", "gone/master"].iter().advance |name| {
+        temp.repo.reference_create(~"refs/remotes/" + *name, &id, false, None).unwrap();
+    }
+    assert_eq!(temp.repo.remote_branches().unwrap(),
+               ~[(~"team", ~"master"), (~"team/upstream", ~"feature/x")]);
+}
+
+#[test]
+fn push_destination() {
+    let temp = git2::test::TempRepo::new();
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", "https://example.com/a.git");
+    assert_eq!(temp.repo.default_fetch_refspecs("origin").unwrap(),
+                ~[~"+refs/heads/*:refs/remotes/origin/*"]);
+
+    cfg.set_str("branch.topic.remote", "origin");
+    cfg.set_str("branch.topic.merge", "refs/heads/master");
+    assert!(temp.repo.push_destination("origin", "refs/heads/topic").is_err());
+    assert_eq!(temp.repo.push_destination("fork", "refs/heads/topic").unwrap(),
+                ~"refs/heads/topic");
+    cfg.set_str("push.default", "upstream");
+    assert_eq!(temp.repo.push_destination("origin", "refs/heads/topic").unwrap(),
+                ~"refs/heads/master");
+
+    cfg.set_str("remote.origin.push", "refs/heads/*:refs/heads/review/*");
+    assert_eq!(temp.repo.push_destination("origin", "refs/heads/topic").unwrap(),
+                ~"refs/heads/review/topic");
+}
+
+#[test]
+fn notes_for_object() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let sig = git2::test::signature();
+    assert!(temp.repo.note(None, &first).is_none());
+    temp.repo.note_create(None, &sig, &sig, &first, "default\n", false).unwrap();
+    temp.repo.note_create(Some("refs/notes/review/ci"), &sig, &sig, &first, "ci passed\n",
+                          false).unwrap();
+    temp.repo.note_create(Some("refs/notes/review/lint"), &sig, &sig, &first, "clean\n",
+                          false).unwrap();
+    assert!(temp.repo.note_create(None, &sig, &sig, &first, "again\n", false).is_err());
+
+    assert_eq!(temp.repo.note(None, &first).unwrap().message, ~"default\n");
+    let notes = temp.repo.notes_for(&first, "refs/notes/review/*");
+    assert_eq!(notes.map(|n| copy n.notes_ref),
+                ~[~"refs/notes/review/ci", ~"refs/notes/review/lint"]);
+    assert_eq!(notes[0].message, ~"ci passed\n");
+    assert_eq!(temp.repo.notes_for(&first, "refs/notes/*").len(), 3);
+}
+
+#[test]
+fn replace_refs() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    git2::replace::create(&temp.repo, &first, &second, false).unwrap();
+    assert_eq!(git2::replace::list(&temp.repo), ~[(first, second)]);
+    assert_eq!(temp.repo.lookup_commit_replaced(&first).unwrap().message(), ~"second");
+
+    git2::replace::set_enabled(&temp.repo, false).unwrap();
+    assert_eq!(temp.repo.lookup_commit_replaced(&first).unwrap().message(), ~"first");
+    assert!(git2::replace::delete(&temp.repo, &first));
+    assert!(git2::replace::list(&temp.repo).is_empty());
+}
+
+#[test]
+fn range_stats() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("b.txt", "one\ntwo\n", "second");
+    let head = temp.commit_file("a.txt", "hello world\n", "third");
+
+    let stats = temp.repo.range_stats(None, &head, git2::stats::identity).unwrap();
+    assert_eq!(stats.commits, 3);
+    assert_eq!(stats.insertions, 4);
+    assert_eq!(stats.deletions, 1);
+    assert_eq!(stats.authors.len(), 1);
+    assert_eq!(stats.authors[0].name, ~"Test User");
+    assert_eq!(stats.files, ~[(~"a.txt", 2u), (~"b.txt", 1u)]);
+}
+
+#[test]
+fn checkout_tree_and_paths() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("b.txt", "one\n");
+    temp.repo.index().unwrap().add_bypath("b.txt");
+    let first = temp.commit_file("a.txt", "one\n", "first");
+    temp.write_file("b.txt", "two\n");
+    temp.repo.index().unwrap().add_bypath("b.txt");
+    temp.commit_file("a.txt", "two\n", "second");
+    let tree = temp.repo.lookup_commit(&first).unwrap().tree().unwrap();
+    fn read(temp: &git2::test::TempRepo, name: &str) -> ~str {
+        std::io::read_whole_file_str(&Path(temp.path).push(name)).unwrap()
+    }
+
+    let mut opts = git2::checkout::CheckoutOption::new();
+    opts.strategy = ~[git2::checkout::GIT_CHECKOUT_SAFE];
+    temp.repo.checkout_paths(tree, [~"a.txt"], &opts);
+    assert_eq!(read(&temp, "a.txt"), ~"one\n");
+    assert_eq!(read(&temp, "b.txt"), ~"two\n");
+
+    opts.strategy = ~[git2::checkout::GIT_CHECKOUT_FORCE];
+    temp.repo.checkout_tree(tree, &opts);
+    assert_eq!(read(&temp, "a.txt"), ~"one\n");
+    assert_eq!(read(&temp, "b.txt"), ~"one\n");
+}
+
+#[test]
+fn checkout_index() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "one\n", "first");
+    temp.write_file("b.txt", "staged\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("b.txt");
+    index.write();
+    std::os::remove_file(&Path(temp.path).push("b.txt"));
+    temp.write_file("a.txt", "local\n");
+    fn read(temp: &git2::test::TempRepo, name: &str) -> ~str {
+        std::io::read_whole_file_str(&Path(temp.path).push(name)).unwrap()
+    }
+
+    // the missing staged file is recreated, and the local change is kept
+    let mut opts = git2::checkout::CheckoutOption::new();
+    temp.repo.checkout_index(None, &opts);
+    assert_eq!(read(&temp, "b.txt"), ~"staged\n");
+    assert_eq!(read(&temp, "a.txt"), ~"local\n");
+
+    opts.strategy = ~[git2::checkout::GIT_CHECKOUT_FORCE];
+    temp.repo.checkout_index(Some(index), &opts);
+    assert_eq!(read(&temp, "a.txt"), ~"one\n");
+    assert_eq!(read(&temp, "b.txt"), ~"staged\n");
+}
+
+#[test]
+fn checkout_remote_branch() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    let cfg = temp.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", "https://example.com/a.git");
+    cfg.set_str("remote.team/upstream.url", "https://example.com/b.git");
+    temp.repo.reference_create("refs/remotes/origin/feature", &second, false, None).unwrap();
+    temp.repo.reference_create("refs/remotes/team/upstream/fix/typo", &second, false, None)
+        .unwrap();
+    temp.repo.reference_create("refs/remotes/gone/topic", &second, false, None).unwrap();
+
+    let local = temp.repo.checkout_remote_branch("origin/feature").unwrap();
+    assert_eq!(local.name(), ~"refs/heads/feature");
+    assert_eq!(local.resolve(), second);
+    assert_eq!(temp.repo.head().unwrap().name(), ~"refs/heads/feature");
+    assert!(temp.repo.checkout_remote_branch("origin/missing").is_err());
+
+    // the remote name contains a slash
+    let local = temp.repo.checkout_remote_branch("team/upstream/fix/typo").unwrap();
+    assert_eq!(local.name(), ~"refs/heads/fix/typo");
+    assert_eq!(temp.repo.upstream_name(local.name()), Some(~"refs/remotes/team/upstream/fix/typo"));
+    // no remote "gone" is configured
+    assert!(temp.repo.checkout_remote_branch("gone/topic").is_err());
+}
+
+#[test]
+fn fast_forward() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    temp.branch("topic", &first);
+
+    let topic = temp.repo.fast_forward("refs/heads/topic", &second).unwrap();
+    assert_eq!(topic.resolve(), second);
+    match temp.repo.fast_forward("refs/heads/topic", &first) {
+        Err(git2::merge::NonFastForward) => (),
+        _ => fail!(~"moving a branch backwards is not a fast-forward"),
+    }
+}
+
+#[test]
+fn pull_fast_forward() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+    let second = upstream.commit_file("a.txt", "hello world\n", "second");
+    let branch = upstream.repo.head().unwrap().name().slice_from("refs/heads/".len()).to_owned();
+
+    let local = git2::test::TempRepo::new();
+    // same content, signature and date: same commit as upstream
+    assert_eq!(local.commit_file("a.txt", "hello\n", "first"), first);
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+
+    match local.repo.pull("origin", branch, git2::merge::PullMerge,
+                          &git2::merge::MergeOption::new()).unwrap() {
+        git2::merge::FastForwarded(id) => assert_eq!(id, second),
+        _ => fail!(~"pull should fast-forward"),
+    }
+    let head = local.repo.head().unwrap().name();
+    let entry = local.repo.reflog(head).unwrap().entry(0).unwrap();
+    assert_eq!(entry.message, ~"pull: Fast-forward");
+    match local.repo.pull("origin", branch, git2::merge::PullMerge,
+                          &git2::merge::MergeOption::new()).unwrap() {
+        git2::merge::UpToDate => (),
+        _ => fail!(~"second pull should be up to date"),
+    }
+}
+
+#[test]
+fn pull_follows_fetch_refspec() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+    let second = upstream.commit_file("a.txt", "hello world\n", "second");
+    let branch = upstream.repo.head().unwrap().name().slice_from("refs/heads/".len()).to_owned();
+
+    let local = git2::test::TempRepo::new();
+    assert_eq!(local.commit_file("a.txt", "hello\n", "first"), first);
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    // the remote-tracking branches are not under refs/remotes/origin
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/upstream/*");
+
+    match local.repo.pull("origin", branch, git2::merge::PullFastForwardOnly,
+                          &git2::merge::MergeOption::new()).unwrap() {
+        git2::merge::FastForwarded(id) => assert_eq!(id, second),
+        _ => fail!(~"pull should fast-forward"),
+    }
+    assert!(local.repo.lookup(~"refs/remotes/upstream/" + branch).is_some());
+    assert!(local.repo.lookup(~"refs/remotes/origin/" + branch).is_none());
+}
+
+#[test]
+fn pull_merge_options() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+    upstream.commit_file("a.txt", "upstream\n", "upstream");
+    let branch = upstream.repo.head().unwrap().name().slice_from("refs/heads/".len()).to_owned();
+
+    let local = git2::test::TempRepo::new();
+    assert_eq!(local.commit_file("a.txt", "hello\n", "first"), first);
+    local.commit_file("a.txt", "local\n", "local");
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+
+    let mut opts = git2::merge::MergeOption::new();
+    match local.repo.pull("origin", branch, git2::merge::PullMerge, &opts).unwrap() {
+        git2::merge::Conflicts(paths) => assert_eq!(paths, ~[~"a.txt"]),
+        _ => fail!(~"pull should report the conflict"),
+    }
+    opts.favor = git2::merge::FavorTheirs;
+    match local.repo.pull("origin", branch, git2::merge::PullMerge, &opts).unwrap() {
+        git2::merge::Merged(_) => (),
+        _ => fail!(~"pull should merge with their side of the conflict"),
+    }
+    let path = Path(local.path).push("a.txt");
+    assert_eq!(std::io::read_whole_file_str(&path).unwrap(), ~"upstream\n");
+}
+
+#[test]
+fn pull_commit_error_step() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+    upstream.commit_file("b.txt", "upstream\n", "upstream");
+    let branch = upstream.repo.head().unwrap().name().slice_from("refs/heads/".len()).to_owned();
+
+    let mut local = git2::test::TempRepo::new();
+    assert_eq!(local.commit_file("a.txt", "hello\n", "first"), first);
+    let head = local.commit_file("c.txt", "local\n", "local");
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+    cfg.set_bool("commit.gpgsign", true);
+    do local.repo.set_commit_signer |_| {
+        Err( (~"no secret key", git2::GITERR_INVALID) )
+    }
+
+    match local.repo.pull("origin", branch, git2::merge::PullMerge,
+                          &git2::merge::MergeOption::new()) {
+        Err(e) => {
+            assert_eq!(e.step as int, git2::StepCommit as int);
+            assert_eq!(e.to_str(), ~"commit failed: no secret key");
+        },
+        Ok(_) => fail!(~"the merge commit should fail"),
+    }
+    assert_eq!(local.repo.head().unwrap().resolve(), head);
+}
+
+#[test]
+fn tree_binary_paths() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "hello\n", "first");
+    temp.commit_file("dir/data.bin", "\x00\x01\x02", "second");
+    let head = temp.commit_file("big.txt", "some long text\n", "third");
+    let tree = temp.repo.lookup_commit(&head).unwrap().tree().unwrap();
+    assert_eq!(tree.binary_paths(None).unwrap(), ~[~"dir/data.bin"]);
+    assert_eq!(tree.binary_paths(Some(10)).unwrap(), ~[~"big.txt", ~"dir/data.bin"]);
+}
+
+#[test]
+fn object_size() {
+    let temp = git2::test::TempRepo::new();
+    let id = temp.repo.blob_create_frombuffer(bytes!("hello\n")).unwrap().id().clone();
+    assert_eq!(temp.repo.object_size(&id).unwrap(), 6u64);
+    let missing = git2::oid::from_str("0123456789012345678901234567890123456789");
+    assert!(temp.repo.object_size(&missing).is_err());
+}
+
+#[test]
+fn validate_update() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "hello\n", "JIRA-1: first");
+    temp.commit_file("keys/id.pem", "secret\n", "add key");
+    let head = temp.commit_file("big.txt", "0123456789abcdef\n", "JIRA-2: big file");
+
+    let mut validator = git2::policy::UpdateValidator::new();
+    validator.add(git2::policy::MaxBlobSize(10));
+    validator.add(git2::policy::MessageMatches(~"^JIRA-[0-9]+: "));
+    validator.add(git2::policy::DisallowedPath(~"*.pem"));
+    let violations = validator.validate(&temp.repo, Some(&base), &head).unwrap();
+    let found: ~[(Option<~str>, ~str)] = violations.iter()
+        .transform(|v| (copy v.path, copy v.message)).collect();
+    assert_eq!(found, ~[(None, ~"the message does not match ^JIRA-[0-9]+: "),
+                        (Some(~"keys/id.pem"), ~"path matches *.pem"),
+                        (Some(~"big.txt"), ~"blob of 17 bytes, over 10 bytes")]);
+
+    assert!(validator.validate(&temp.repo, None, &base).unwrap().is_empty());
+    validator.add(git2::policy::MessageMatches(~"("));
+    assert!(validator.validate(&temp.repo, None, &base).is_err());
+}
+
+#[test]
+fn tree_len() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "a\n", "first");
+    let head = temp.commit_file("dir/b.txt", "b\n", "second");
+    let tree = temp.repo.lookup_commit(&head).unwrap().tree().unwrap();
+    assert_eq!(tree.len(), 2);
+    assert!(!tree.is_empty());
+}
+
+#[test]
+fn tree_update() {
+    let temp = git2::test::TempRepo::new();
+    temp.commit_file("a.txt", "a\n", "first");
+    let head = temp.commit_file("dir/b.txt", "b\n", "second");
+    let commit = temp.repo.lookup_commit(&head).unwrap();
+    let tree = commit.tree().unwrap();
+    let blob = temp.repo.blob_create_frombuffer(bytes!("c\n")).unwrap().id().clone();
+
+    let updates = [git2::Upsert(~"dir/sub/c.txt", blob, git2::GIT_FILEMODE_BLOB),
+                   git2::Remove(~"a.txt")];
+    let id = tree.update(updates).unwrap();
+    let updated = temp.repo.lookup_tree(&id).unwrap();
+    assert!(updated.entry_bypath("a.txt").is_none());
+    assert!(updated.entry_bypath("dir/b.txt").is_some());
+    assert_eq!(updated.entry_bypath("dir/sub/c.txt").unwrap().id(), &blob);
+    // the original tree is unchanged
+    assert!(tree.entry_bypath("a.txt").is_some());
+}
+
+#[test]
+fn diff_file_flags() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("b.bin", "\x00\x01", "second");
+    let old = temp.repo.lookup_commit(&first).unwrap();
+    let new = temp.repo.lookup_commit(&second).unwrap();
+    let opts = git2::diff::DiffOption::new();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let difflist = temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    // produce the patch, so that the content is inspected
+    difflist.to_patch().unwrap();
+    let delta = difflist.delta(0).unwrap();
+    assert!(!delta.old_file.exists());
+    assert!(delta.new_file.exists());
+    assert!(delta.new_file.is_valid_oid());
+    assert!(delta.new_file.is_binary());
+}
+
+#[test]
+fn diff_reverse() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let second = temp.commit_file("a.txt", "hello world\n", "second");
+    let old = temp.repo.lookup_commit(&first).unwrap();
+    let new = temp.repo.lookup_commit(&second).unwrap();
+    let opts = git2::diff::DiffOption::new();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let forward = temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    let backward = temp.repo.diff_trees(Some(new_tree), Some(old_tree), &opts).unwrap();
+    let reversed = forward.reverse().unwrap();
+    assert_eq!(reversed.to_patch().unwrap(), backward.to_patch().unwrap());
+
+    // several lines changed in one block, and the last line losing its newline
+    let third = temp.commit_file("a.txt", "a\nb\nc\n", "third");
+    let fourth = temp.commit_file("a.txt", "a\nB\nC\nd", "fourth");
+    let old = temp.repo.lookup_commit(&third).unwrap();
+    let new = temp.repo.lookup_commit(&fourth).unwrap();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let forward = temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    let backward = temp.repo.diff_trees(Some(new_tree), Some(old_tree), &opts).unwrap();
+    let reversed = forward.reverse().unwrap().to_patch().unwrap();
+    assert_eq!(reversed.clone(), backward.to_patch().unwrap());
+    assert!(reversed.contains("-B\n-C\n-d\n\\ No newline at end of file\n+b\n+c\n"));
+}
+
+#[test]
+fn cherry_patch_ids() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    temp.commit_file("x.txt", "x\n", "add x");
+    let upstream = temp.commit_file("a.txt", "a changed\n", "change a");
+
+    // the same change to a.txt on another base
+    temp.branch("topic", &base);
+    temp.repo.set_head("refs/heads/topic").unwrap();
+    let index = temp.repo.index().unwrap();
+    index.read_tree(temp.repo.lookup_commit(&base).unwrap().tree().unwrap());
+    index.write();
+    let picked = temp.commit_file("a.txt", "a changed\n", "change a again");
+    let own = temp.commit_file("y.txt", "y\n", "add y");
+
+    let patch_id = |id: &git2::OID| {
+        git2::cherry::patch_id(&temp.repo, temp.repo.lookup_commit(id).unwrap()).unwrap()
+    };
+    assert_eq!(patch_id(&upstream), patch_id(&picked));
+    assert!(patch_id(&own) != patch_id(&picked));
+
+    let commits = temp.repo.cherry(&upstream, &own).unwrap();
+    let summary = do commits.map |c| { (c.id, c.upstream) };
+    assert_eq!(summary, ~[(picked, true), (own, false)]);
+
+    // a commit whose tree is missing is an error, not a commit without a patch id
+    let tree = temp.repo.lookup_commit(&own).unwrap().tree().unwrap().id().to_str();
+    let object = Path(temp.path).push_many([~".git", ~"objects", tree.slice(0, 2).to_owned(),
+                                            tree.slice(2, 40).to_owned()]);
+    assert!(std::os::remove_file(&object));
+    let repo = git2::repository::open(temp.path).unwrap();
+    assert!(git2::cherry::patch_id(&repo, repo.lookup_commit(&own).unwrap()).is_err());
+    assert!(repo.cherry(&upstream, &own).is_err());
+}
+
+#[test]
+fn range_diff() {
+    let temp = git2::test::TempRepo::new();
+    let base = temp.commit_file("a.txt", "a\n", "base");
+    let one = temp.commit_file("b.txt", "b\n", "add b");
+    let two = temp.commit_file("c.txt", "c\n", "add c");
+    let dropped = temp.commit_file("d.txt", "d\n", "add d");
+
+    // the new version of the branch: "add b" as is, "add c" amended, "add d" dropped
+    // and "add e" added
+    temp.branch("v2", &base);
+    temp.repo.set_head("refs/heads/v2").unwrap();
+    let index = temp.repo.index().unwrap();
+    index.read_tree(temp.repo.lookup_commit(&base).unwrap().tree().unwrap());
+    index.write();
+    let new_one = temp.commit_file("b.txt", "b\n", "add b");
+    let new_two = temp.commit_file("c.txt", "c, amended\n", "add c");
+    let added = temp.commit_file("e.txt", "e\n", "add e");
+
+    let entries = temp.repo.range_diff((&base, &dropped), (&base, &added)).unwrap();
+    let summary = do entries.map |e| { (e.status, e.old, e.new) };
+    assert_eq!(summary, ~[(git2::cherry::RangeUnchanged, Some(one), Some(new_one)),
+                            (git2::cherry::RangeModified, Some(two), Some(new_two)),
+                            (git2::cherry::RangeAdded, None, Some(added)),
+                            (git2::cherry::RangeDropped, Some(dropped), None)]);
+}
+
+#[test]
+fn find_similar_with_metric() {
+    let temp = git2::test::TempRepo::new();
+    let first = temp.commit_file("a.txt", "one two three\n", "first");
+    temp.write_file("b.txt", "three\ntwo\none\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("b.txt");
+    index.remove_bypath("a.txt");
+    index.write();
+    let second = temp.commit_index("second");
+
+    let old = temp.repo.lookup_commit(&first).unwrap();
+    let new = temp.repo.lookup_commit(&second).unwrap();
+    let opts = git2::diff::DiffOption::new();
+    let (old_tree, new_tree) = (old.tree().unwrap(), new.tree().unwrap());
+    let difflist = temp.repo.diff_trees(Some(old_tree), Some(new_tree), &opts).unwrap();
+    difflist.find_similar(&git2::diff::FindOption::new());
+    assert_eq!(difflist.deltas().len(), 2);
+
+    // compare the sets of words, ignoring the layout
+    let signature: &fn(&str, &[u8]) -> Option<~[u64]> = |_, data| {
+        let mut words: ~[u64] = std::str::from_bytes(data).word_iter().transform(|w| {
+            w.iter().fold(0u64, |h, c| h * 31 + c as u64)
+        }).collect();
+        extra::sort::quick_sort3(words);
+        Some(words)
+    };
+    let similarity: &fn(&[u64], &[u64]) -> uint = |a, b| if a == b { 100 } else { 0 };
+    let metric = git2::diff::SimilarityMetric { signature: signature, similarity: similarity };
+    difflist.find_similar_with(&git2::diff::FindOption::new(), &metric);
+    let deltas = difflist.deltas();
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].status as int, git2::GIT_DELTA_RENAMED as int);
+}
+
+#[test]
+fn repository_kind() {
+    let temp = git2::test::TempRepo::new();
+    assert!(!temp.repo.is_shallow());
+    assert!(!temp.repo.is_worktree());
+    assert!(temp.repo.check_ownership().is_ok());
+}
+
+#[test]
+fn resolve_chain() {
+    let temp = git2::test::TempRepo::new();
+    let head = temp.repo.lookup("HEAD").unwrap();
+    let chain = head.resolve_chain().unwrap();
+    assert_eq!(copy chain.names, ~[~"HEAD", ~"refs/heads/master"]);
+    assert_eq!(chain.target, None);
+
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    let chain = head.resolve_chain().unwrap();
+    assert_eq!(chain.target, Some(first));
+    assert_eq!(chain.to_str(), ~"HEAD -> refs/heads/master -> " + first.to_str());
+}
+
+#[test]
+fn fetch_with_tags() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+    upstream.repo.reference_create("refs/tags/v1", &first, false, None).unwrap();
+
+    let local = git2::test::TempRepo::new();
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+    let remote = local.repo.remote_lookup("origin").unwrap();
+
+    let tags = remote.fetch_with_tags([], None, git2::remote::DownloadTagsNone).unwrap();
+    assert!(tags.is_empty());
+    assert!(local.repo.lookup("refs/tags/v1").is_none());
+
+    let tags = remote.fetch_with_tags([], None, git2::remote::DownloadTagsAll).unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(copy tags[0].name, ~"refs/tags/v1");
+    assert_eq!(tags[0].old, None);
+    assert_eq!(tags[0].new, first);
+}
+
+#[test]
+fn fetch_with_progress() {
+    let upstream = git2::test::TempRepo::new();
+    let first = upstream.commit_file("a.txt", "hello\n", "first");
+
+    let local = git2::test::TempRepo::new();
+    let cfg = local.repo.config().unwrap();
+    cfg.set_str("remote.origin.url", upstream.path);
+    cfg.set_str("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*");
+    let remote = local.repo.remote_lookup("origin").unwrap();
+
+    let mut throttle = git2::remote::ProgressThrottle::new();
+    throttle.interval_ms = 60000;
+    throttle.percent = 50;
+    let mut calls = 0u;
+    let summary = do remote.fetch_with_progress([], None, &throttle) |progress| {
+        assert!(progress.received_objects <= progress.total_objects);
+        calls += 1;
+        true
+    }.unwrap();
+    assert!(calls >= 1 && calls <= 4);
+    assert!(summary.received_objects <= summary.total_objects);
+    assert_eq!(local.repo.remote_branches().unwrap().map(|&(ref r, _)| copy *r), ~[~"origin"]);
+    assert!(local.repo.lookup_commit(&first).is_some());
+    // the options after the callbacks are read where they are set
+    assert!(std::os::path_exists(&Path(local.path).push_many([".git", "FETCH_HEAD"])));
+}
+
+#[test]
+fn progress_throttle() {
+    let progress = |received: uint, total: uint| git2::remote::TransferProgress {
+        total_objects: total,
+        indexed_objects: 0,
+        received_objects: received,
+        local_objects: 0,
+        total_deltas: 0,
+        indexed_deltas: 0,
+        received_bytes: received * 100,
+    };
+    let mut throttle = git2::remote::ProgressThrottle::new();
+    assert!(throttle.is_due(0, 0, &progress(1, 100)));
+
+    throttle.interval_ms = 1000;
+    throttle.percent = 10;
+    // either limit is enough
+    assert!(!throttle.is_due(10, 0, &progress(5, 100)));
+    assert!(throttle.is_due(10, 0, &progress(10, 100)));
+    assert!(throttle.is_due(1000, 0, &progress(5, 100)));
+    // an unknown total only counts the interval
+    assert!(!throttle.is_due(10, 0, &progress(50, 0)));
+    assert!(throttle.is_due(1000, 0, &progress(50, 0)));
+
+    // the percent alone
+    throttle.interval_ms = 0;
+    assert!(!throttle.is_due(10, 20, &progress(25, 100)));
+    assert!(throttle.is_due(10, 20, &progress(30, 100)));
+
+    // the reports made before the total is known are throttled like the others
+    assert!(!progress(0, 0).is_done());
+    assert!(!progress(100, 100).is_done());
+    let mut done = progress(100, 100);
+    done.indexed_objects = 100;
+    assert!(done.is_done());
+}
+
+#[test]
+fn try_head() {
+    let temp = git2::test::TempRepo::new();
+    assert!(temp.repo.try_head().unwrap().is_none());
+    let first = temp.commit_file("a.txt", "hello\n", "first");
+    assert_eq!(temp.repo.try_head().unwrap().unwrap().resolve(), first);
+
+    // a corrupted branch is an error, not an unborn HEAD
+    let name = temp.repo.head_name().unwrap();
+    let path = Path(temp.path).push(".git").push_rel(&Path(name));
+    std::io::file_writer(&path, [std::io::Create, std::io::Truncate]).unwrap().write_str("garbage\n");
+    let repo = git2::repository::open(temp.path).unwrap();
+    assert!(repo.try_head().is_err());
+}
+
+#[test]
+fn pull_error_step() {
+    let local = git2::test::TempRepo::new();
+    local.commit_file("a.txt", "hello\n", "first");
+    match local.repo.pull("origin", "master", git2::merge::PullMerge,
+                          &git2::merge::MergeOption::new()) {
+        Err(e) => {
+            assert_eq!(e.step as int, git2::StepFetch as int);
+            assert_eq!(e.to_str(), ~"fetch failed: no remote origin");
+        },
+        Ok(_) => fail!(~"pull without remote should fail"),
+    }
+}
+
+#[test]
+fn commit_header_field() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let oid = git2::oid::from_str(&"21002f5d3f411fe990e13604273a51cd598a4a51");
+    let commit = repo.lookup_commit(&oid).unwrap();
+    assert_eq!(commit.header_field("tree"), Some(commit.tree().unwrap().id().to_str()));
+    assert!(commit.header_field("author").unwrap().starts_with(commit.author().name.as_slice()));
+    assert_eq!(commit.header_field("gpgsig"), None);
+}
+
+#[test]
+fn tree_ls() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("src/main.rs", "fn main() {}\n");
+    temp.write_file("say \"hi\".txt", "hi\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("src/main.rs");
+    index.add_bypath("say \"hi\".txt");
+    let tree = index.write_tree().unwrap();
+    let main_id = git2::odb::hash(bytes!("fn main() {}\n"), git2::GIT_OBJ_BLOB).unwrap();
+    let hi_id = git2::odb::hash(bytes!("hi\n"), git2::GIT_OBJ_BLOB).unwrap();
+    let src_id = *tree.entry_byname("src").unwrap().id();
+
+    let hi_line = fmt!("100644 blob %s\t\"say \\\"hi\\\".txt\"", hi_id.to_str());
+    assert_eq!(tree.ls(false).unwrap(),
+               ~[copy hi_line, fmt!("040000 tree %s\tsrc", src_id.to_str())]);
+    assert_eq!(tree.ls(true).unwrap(),
+               ~[hi_line, fmt!("100644 blob %s\tsrc/main.rs", main_id.to_str())]);
+
+    let blob = temp.repo.blob_lookup(&main_id).unwrap();
+    let printed = do std::io::with_bytes_writer |writer| { blob.print(writer) };
+    assert_eq!(printed, bytes!("fn main() {}\n").to_owned());
+}
+
+#[test]
+fn tree_ls_missing_subtree() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("src/main.rs", "fn main() {}\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("src/main.rs");
+    let written = index.write_tree().unwrap();
+    let tree_id = *written.id();
+    let src_id = written.entry_byname("src").unwrap().id().to_str();
+    let object = Path(temp.path).push_many([~".git", ~"objects", src_id.slice(0, 2).to_owned(),
+                                            src_id.slice(2, 40).to_owned()]);
+    assert!(std::os::remove_file(&object));
+
+    let repo = git2::repository::open(temp.path).unwrap();
+    let tree = repo.lookup_tree(&tree_id).unwrap();
+    assert!(tree.ls(false).is_ok());
+    match tree.ls(true) {
+        Err((msg, _)) => assert!(msg.contains(src_id.as_slice())),
+        Ok(_) => fail!(~"the missing subtree should be reported"),
+    }
+}
+
+#[test]
+fn cat_file() {
+    let repo = git2::repository::open("fixture").unwrap();
+    let (otype, data) = repo.cat_file("HEAD").unwrap();
+    assert_eq!(otype as int, git2::GIT_OBJ_COMMIT as int);
+    assert!(std::str::from_bytes(data).starts_with("tree "));
+
+    let (otype, data) = repo.cat_file("HEAD^{tree}").unwrap();
+    assert_eq!(otype as int, git2::GIT_OBJ_TREE as int);
+    let listing = std::str::from_bytes(data);
+    assert!(listing.starts_with("100644 blob "));
+    assert!(listing.ends_with("\tREADME.md\n"));
+}
+
+#[test]
+fn head_name_unborn() {
+    let temp = git2::test::TempRepo::new();
+    assert!(temp.repo.head().is_none());
+    let name = temp.repo.head_name().unwrap();
+    assert!(name.starts_with("refs/heads/"));
+}
+
+#[test]
+fn index_read_tree_filtered() {
+    let temp = git2::test::TempRepo::new();
+    temp.write_file("src/main.rs", "fn main() {}\n");
+    temp.write_file("doc/index.md", "doc\n");
+    let index = temp.repo.index().unwrap();
+    index.add_bypath("src/main.rs");
+    index.add_bypath("doc/index.md");
+    let tree = index.write_tree().unwrap();
+
+    index.read_tree_filtered(tree, |path, _| path == "src" || path.starts_with("src/"));
+    let paths: ~[~str] = index.entries().map(|e| copy e.path);
+    assert_eq!(paths, ~[~"src/main.rs"]);
+}
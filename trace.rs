@@ -0,0 +1,146 @@
+use std::libc::{c_char, c_int};
+use std::{cast, ptr, task};
+use std::str::raw::from_c_str;
+use std::unstable::atomics::{atomic_add, atomic_load, atomic_sub, atomic_swap, SeqCst};
+use extra::time::precise_time_ns;
+use super::*;
+use ext;
+
+/// Verbosity of the trace messages, from the least to the most verbose
+#[deriving(Eq)]
+pub enum TraceLevel {
+    TraceNone = 0,
+    TraceFatal = 1,
+    TraceError = 2,
+    TraceWarn = 3,
+    TraceInfo = 4,
+    TraceDebug = 5,
+    /// the most verbose level, which includes the duration of the libgit2 calls
+    /// made by this crate
+    TraceTrace = 6,
+}
+
+struct Hook {
+    level: TraceLevel,
+    callback: ~fn(TraceLevel, &str),
+}
+
+/// the installed Hook, as a pointer; 0 when there is none
+///
+/// libgit2 gives no payload to the trace callback, so the hook has to be global.
+/// It is swapped atomically, so that tasks on other threads always see either the
+/// previous hook or the new one.
+static mut HOOK: uint = 0;
+
+/// number of tasks using the hook they loaded from HOOK; a replaced hook is freed
+/// once this drops to 0
+static mut USERS: uint = 0;
+
+/// decrements USERS when dropped, even if the callback fails
+struct HookUse;
+
+impl Drop for HookUse {
+    fn finalize(&self) {
+        unsafe {
+            atomic_sub(&mut USERS, 1, SeqCst);
+        }
+    }
+}
+
+/// Install `hook` in place of the current one, and free the current one once no
+/// task is using it
+fn replace_hook(hook: uint) {
+    unsafe {
+        let previous = atomic_swap(&mut HOOK, hook, SeqCst);
+        if previous != 0 {
+            while atomic_load(&USERS, SeqCst) != 0 {
+                task::yield();
+            }
+            let _previous: ~Hook = cast::transmute(previous);
+        }
+    }
+}
+
+/// Send the trace messages of level `level` and below to `callback`: the messages of
+/// libgit2, and the duration of the long-running libgit2 calls made by this crate,
+/// like fetches, diffs and status, at TraceTrace.
+///
+/// The callback can be called from any task which uses the crate, so it must not
+/// rely on task-local state. It replaces the previous one, which is freed once the
+/// tasks running it are done: the callbacks must not call `set_hook` or `clear_hook`.
+///
+/// Returns an error if libgit2 was built without tracing support (GIT_TRACE); the
+/// messages of this crate are delivered anyway.
+pub fn set_hook(level: TraceLevel, callback: ~fn(TraceLevel, &str))
+    -> Result<(), (~str, GitError)>
+{
+    let hook = ~Hook { level: level, callback: callback };
+    replace_hook(unsafe { cast::transmute::<~Hook, uint>(hook) });
+    unsafe {
+        if ext::git_trace_set(level as c_int, trace_cb) == 0 {
+            Ok(())
+        } else {
+            Err( last_error() )
+        }
+    }
+}
+
+/// Stop sending trace messages
+pub fn clear_hook() {
+    unsafe {
+        ext::git_trace_set(TraceNone as c_int, ptr::null());
+    }
+    replace_hook(0);
+}
+
+/// Run `f` with the installed hook, if messages of `level` are wanted; the hook is
+/// not freed while `f` runs
+fn with_hook<T>(level: TraceLevel, f: &fn(Option<&Hook>) -> T) -> T {
+    unsafe {
+        atomic_add(&mut USERS, 1, SeqCst);
+        let _use = HookUse;
+        let hook = atomic_load(&HOOK, SeqCst);
+        if hook == 0 {
+            return f(None);
+        }
+        let hook: &Hook = cast::transmute(hook);
+        if (level as int) <= (hook.level as int) { f(Some(hook)) } else { f(None) }
+    }
+}
+
+/// Send a message to the trace hook, if one is installed for `level`
+pub fn emit(level: TraceLevel, msg: &str) {
+    do with_hook(level) |hook| {
+        match hook {
+            Some(hook) => (hook.callback)(level, msg),
+            None => (),
+        }
+    }
+}
+
+/// Run `f`, which calls the libgit2 function `name`, and report its duration at
+/// TraceTrace. Nothing is measured when no hook wants these messages.
+pub fn timed<T>(name: &str, f: &fn() -> T) -> T {
+    if !with_hook(TraceTrace, |hook| hook.is_some()) {
+        return f();
+    }
+    let start = precise_time_ns();
+    let res = f();
+    let elapsed_us = (precise_time_ns() - start) / 1000;
+    emit(TraceTrace, fmt!("%s: %? us", name, elapsed_us));
+    res
+}
+
+extern fn trace_cb(level: c_int, msg: *c_char) {
+    let level = match level {
+        1 => TraceFatal,
+        2 => TraceError,
+        3 => TraceWarn,
+        4 => TraceInfo,
+        5 => TraceDebug,
+        _ => TraceTrace,
+    };
+    unsafe {
+        emit(level, from_c_str(msg));
+    }
+}
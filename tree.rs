@@ -1,6 +1,5 @@
 use std::libc::{size_t, c_void, c_char, c_int};
-use std::{ptr, cast};
-use std::str::raw::from_c_str;
+use std::{os, ptr, cast, vec};
 use super::*;
 use ext;
 
@@ -13,6 +12,20 @@ impl<'self> Tree<'self> {
         }
     }
 
+    /// Get the number of entries of the tree, not counting the entries of its subtrees
+    pub fn len(&self) -> uint
+    {
+        unsafe {
+            ext::git_tree_entrycount(self.tree) as uint
+        }
+    }
+
+    /// Check if the tree has no entries, like the empty tree
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
     /// Lookup a tree entry by its filename
     pub fn entry_byname(&self, filename: &str) -> Option<~TreeEntry>
     {
@@ -108,6 +121,260 @@ impl<'self> Tree<'self> {
             }
         }
     }
+
+    /// Traverse the entries in a tree and its subtrees in pre order, handling gitlink
+    /// (submodule) entries according to `mode`.
+    ///
+    /// The callback is called with the full path of each entry, the entry itself, and
+    /// for gitlink entries the id of the submodule commit they point to.
+    /// With GitlinkDescend, the submodule repository is looked up in the working directory,
+    /// then in `.git/modules`; the entries of the submodule tree are passed with their path
+    /// in the superproject. Submodules which are not available are not descended into.
+    /// Returning WalkSkip for a gitlink entry prevents descending into it.
+    ///
+    /// The function returns false if the loop is stopped by WalkStop
+    pub fn walk_preorder_gitlinks(&self, mode: GitlinkMode,
+            callback: &fn(path: &str, entry: &TreeEntry, gitlink: Option<&OID>) -> WalkMode)
+        -> bool
+    {
+        walk_gitlinks(self.owner, self, "", mode, callback)
+    }
+
+    /// Apply `updates` to this tree, and write the new trees to the database.
+    ///
+    /// Only the trees on the paths of the updates are rewritten; the other subtrees
+    /// are shared with this tree. Returns the id of the new root tree.
+    pub fn update(&self, updates: &[TreeUpdate]) -> Result<OID, (~str, GitError)>
+    {
+        match self.owner.check_writable() {
+            Ok(()) => (),
+            Err(e) => return Err(e),
+        }
+        let zero = OID { id: [0, ..20] };
+        let c_updates = do updates.map |update| {
+            match *update {
+                Upsert(ref path, id, mode) => ext::git_tree_update {
+                    action: ext::GIT_TREE_UPDATE_UPSERT,
+                    id: id,
+                    filemode: mode as c_int,
+                    path: do path.as_c_str |c_path| { c_path },
+                },
+                Remove(ref path) => ext::git_tree_update {
+                    action: ext::GIT_TREE_UPDATE_REMOVE,
+                    id: zero,
+                    filemode: 0,
+                    path: do path.as_c_str |c_path| { c_path },
+                },
+            }
+        };
+        let mut oid = OID { id: [0, ..20] };
+        unsafe {
+            if ext::git_tree_create_updated(&mut oid, self.owner.repo, self.tree,
+                                            c_updates.len() as size_t,
+                                            vec::raw::to_ptr(c_updates)) == 0 {
+                Ok(oid)
+            } else {
+                Err( last_error() )
+            }
+        }
+    }
+
+    /// List the paths of the blobs of the tree and its subtrees which look binary,
+    /// according to the heuristic of `Blob::is_binary`, in pre order.
+    ///
+    /// Blobs larger than `max_size` bytes are reported as binary without being read,
+    /// like git does for files above `core.bigFileThreshold`. Symbolic links and
+    /// submodules are left out.
+    pub fn binary_paths(&self, max_size: Option<uint>) -> Result<~[~str], (~str, GitError)>
+    {
+        let odb = match self.owner.odb() {
+            Ok(odb) => odb,
+            Err(e) => return Err(e),
+        };
+        let mut paths = ~[];
+        let mut error = None;
+        do self.walk_preorder |root, entry| {
+            match entry.filemode() {
+                GIT_FILEMODE_BLOB | GIT_FILEMODE_BLOB_EXECUTABLE => {
+                    let too_big = match max_size {
+                        Some(max) => match odb.read_header(entry.id()) {
+                            Ok((size, _)) => size > max,
+                            Err(e) => { error = Some(e); false },
+                        },
+                        None => false,
+                    };
+                    let binary = too_big || match self.owner.blob_lookup(entry.id()) {
+                        Some(blob) => blob.is_binary(),
+                        None => {
+                            error = Some( (fmt!("cannot read blob %s", entry.id().to_str()),
+                                            GITERR_ODB) );
+                            false
+                        },
+                    };
+                    if binary {
+                        paths.push(entry.path_in(root));
+                    }
+                    if error.is_some() { WalkStop } else { WalkPass }
+                },
+                _ => WalkPass,
+            }
+        };
+        match error {
+            Some(e) => Err(e),
+            None => Ok(paths),
+        }
+    }
+
+    /// Collect the full path, relative to the root of the tree, the id and the file
+    /// mode of every entry of the tree and its subtrees, in pre order.
+    ///
+    /// Subtrees are listed before their entries.
+    pub fn collect_paths(&self) -> ~[(~str, OID, FileMode)]
+    {
+        let mut paths = ~[];
+        do self.walk_preorder |root, entry| {
+            paths.push((entry.path_in(root), *entry.id(), entry.filemode()));
+            WalkPass
+        };
+        paths
+    }
+
+    /// List the entries of the tree like `git ls-tree`, as "mode type id\tpath" lines
+    /// without the line break, e.g. "100644 blob 3b18e512dba79e4c8300dd08aeb37f8e728b8dad\tREADME".
+    ///
+    /// With `recursive`, the entries of the subtrees are listed in place of the
+    /// subtrees, with their full path, like `git ls-tree -r`. Paths with special or
+    /// non-ASCII characters are quoted the way git quotes them by default.
+    pub fn ls(&self, recursive: bool) -> Result<~[~str], (~str, GitError)>
+    {
+        let mut lines = ~[];
+        match self.ls_into([], recursive, &mut lines) {
+            Ok(()) => Ok(lines),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn ls_into(&self, prefix: &[u8], recursive: bool, lines: &mut ~[~str])
+        -> Result<(), (~str, GitError)>
+    {
+        for self.each |entry| {
+            let mut path = prefix.to_owned();
+            path.push_all(entry.name_bytes());
+            let otype = match entry.otype() {
+                GIT_OBJ_TREE => "tree",
+                GIT_OBJ_COMMIT => "commit",
+                _ => "blob",
+            };
+            if recursive && otype == "tree" {
+                let subtree = match self.owner.try_lookup_tree(entry.id()) {
+                    Ok(subtree) => subtree,
+                    Err(e) => return Err(e.to_error(entry.id())),
+                };
+                path.push('/' as u8);
+                match subtree.ls_into(path, true, lines) {
+                    Ok(()) => (),
+                    Err(e) => return Err(e),
+                }
+            } else {
+                lines.push(fmt!("%06o %s %s\t%s", entry.filemode() as uint, otype,
+                                entry.id().to_str(), quote_path(path)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// quote a path like git does with core.quotePath, the default: paths with control
+/// characters, double quotes, backslashes or non-ASCII bytes are put in double quotes,
+/// with C-style escapes and octal escapes for the bytes
+fn quote_path(path: &[u8]) -> ~str
+{
+    let needs_quotes = do path.iter().any |&b| {
+        b < 0x20 || b == '"' as u8 || b == '\\' as u8 || b >= 0x7f
+    };
+    if !needs_quotes {
+        return lossy_utf8(path);
+    }
+    let mut quoted = ~"\"";
+    for path.iter().advance |&b| {
+        match b as char {
+            '\x07' => quoted.push_str("\\a"),
+            '\x08' => quoted.push_str("\\b"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            '\x0b' => quoted.push_str("\\v"),
+            '\x0c' => quoted.push_str("\\f"),
+            '\r' => quoted.push_str("\\r"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            _ if b < 0x20 || b >= 0x7f => quoted.push_str(fmt!("\\%03o", b as uint)),
+            c => quoted.push_char(c),
+        }
+    }
+    quoted.push_char('"');
+    quoted
+}
+
+fn walk_gitlinks(repo: &Repository, tree: &Tree, prefix: &str, mode: GitlinkMode,
+                 callback: &fn(&str, &TreeEntry, Option<&OID>) -> WalkMode) -> bool
+{
+    let mut stopped = false;
+    let finished = do tree.walk_preorder |root, entry| {
+        let path = entry.path_in(root);
+        let full_path = prefix.to_owned() + path;
+        match (entry.filemode(), mode) {
+            (GIT_FILEMODE_COMMIT, GitlinkSkip) => WalkPass,
+            (GIT_FILEMODE_COMMIT, _) => match callback(full_path, entry, Some(entry.id())) {
+                WalkPass => match mode {
+                    GitlinkDescend => {
+                        if descend_gitlink(repo, path, entry.id(), full_path + "/", mode,
+                                            callback) {
+                            WalkPass
+                        } else {
+                            stopped = true;
+                            WalkStop
+                        }
+                    },
+                    _ => WalkPass,
+                },
+                WalkSkip => WalkPass,
+                WalkStop => WalkStop,
+            },
+            _ => callback(full_path, entry, None),
+        }
+    };
+    finished && !stopped
+}
+
+/// walk the tree of the commit `id` of the submodule at `path`, if it can be found
+fn descend_gitlink(repo: &Repository, path: &str, id: &OID, prefix: &str, mode: GitlinkMode,
+                   callback: &fn(&str, &TreeEntry, Option<&OID>) -> WalkMode) -> bool
+{
+    let mut candidates = ~[];
+    match repo.workdir() {
+        Some(workdir) => candidates.push(Path(workdir).push_rel(&Path(path))),
+        None => (),
+    }
+    match repo.path() {
+        Some(git_dir) => candidates.push(Path(git_dir).push("modules").push_rel(&Path(path))),
+        None => (),
+    }
+    for candidates.iter().advance |candidate| {
+        if !os::path_exists(candidate) {
+            loop;
+        }
+        match repository::open(candidate.to_str()) {
+            Ok(sub) => match sub.lookup_commit(id) {
+                Some(commit) => match commit.tree() {
+                    Ok(tree) => return walk_gitlinks(&sub, tree, prefix, mode, callback),
+                    Err(_) => (),
+                },
+                None => (),
+            },
+            Err(_) => (),
+        }
+    }
+    true
 }
 
 extern fn pre_walk_cb(root: *c_char, entry: *ext::git_tree_entry, payload: *c_void) -> c_int
@@ -115,9 +382,13 @@ extern fn pre_walk_cb(root: *c_char, entry: *ext::git_tree_entry, payload: *c_vo
     unsafe {
         let op_ptr: *&fn(&str, &TreeEntry) -> WalkMode = cast::transmute(payload);
         let op: &fn(&str, &TreeEntry) -> WalkMode = *op_ptr;
-        let root_str = from_c_str(root);
+        let root_str = lossy_utf8(from_c_bytes(root));
         let entry = TreeEntry { tree_entry: entry, owned: false };
-        op(root_str, &entry) as c_int
+        match op(root_str, &entry) {
+            // a negative value stops the walk, and is returned by git_tree_walk
+            WalkStop => ext::GIT_EUSER,
+            mode => mode as c_int,
+        }
     }
 }
 
@@ -126,14 +397,14 @@ extern fn post_walk_cb(root: *c_char, entry: *ext::git_tree_entry, payload: *c_v
     unsafe {
         let op_ptr: *&fn(&str, &TreeEntry) -> bool = cast::transmute(payload);
         let op: &fn(&str, &TreeEntry) -> bool = *op_ptr;
-        let root_str = from_c_str(root);
+        let root_str = lossy_utf8(from_c_bytes(root));
         let entry = TreeEntry { tree_entry: entry, owned: false };
         if op(root_str, &entry) {
             // continue
             0
         } else {
-            // negative value stops the walk
-            -1
+            // a negative value stops the walk, and is returned by git_tree_walk
+            ext::GIT_EUSER
         }
     }
 }
@@ -160,9 +431,7 @@ impl<'self> BaseIter<TreeEntry> for Tree<'self> {
     }
 
     fn size_hint(&self) -> Option<uint> {
-        unsafe {
-            Some(ext::git_tree_entrycount(self.tree) as uint)
-        }
+        Some(self.len())
     }
 }
 
@@ -176,11 +445,17 @@ impl<'self> Drop for Tree<'self> {
 }
 
 impl TreeEntry {
-    /// Get the filename of a tree entry
+    /// Get the filename of a tree entry; invalid UTF-8 sequences are replaced with U+FFFD
     pub fn name(&self) -> ~str
+    {
+        lossy_utf8(self.name_bytes())
+    }
+
+    /// Get the filename of a tree entry, as stored in the tree
+    pub fn name_bytes(&self) -> ~[u8]
     {
         unsafe {
-            from_c_str(ext::git_tree_entry_name(self.tree_entry))
+            from_c_bytes(ext::git_tree_entry_name(self.tree_entry))
         }
     }
 
@@ -192,6 +467,13 @@ impl TreeEntry {
         }
     }
 
+    /// Get the full path of the entry, given the root passed with it to the
+    /// callbacks of `walk_preorder` and `walk_postorder`
+    pub fn path_in(&self, root: &str) -> ~str
+    {
+        root.to_owned() + self.name()
+    }
+
     pub fn otype(&self) -> OType
     {
         unsafe {
@@ -351,15 +633,19 @@ impl TreeBuilder {
 
     /// Write the contents of the tree builder as a tree object
     ///
-    /// The tree builder will be written to the given `repo`, and its
-    /// identifying SHA1 hash will be returned
+    /// The tree will be written to the repository the builder was created for, and
+    /// its identifying SHA1 hash will be returned
     ///
-    /// repo: Repository in which to store the object
+    /// repo: Repository the builder was created for
     pub fn write(&self, repo: &Repository) -> OID
     {
+        match repo.check_writable() {
+            Ok(()) => (),
+            Err(e) => { git_error::cond.raise(e); return OID { id: [0, ..20] }; },
+        }
         let mut oid = OID { id: [0, ..20] };
         unsafe {
-            if ext::git_treebuilder_write(&mut oid, repo.repo, self.bld) != 0 {
+            if ext::git_treebuilder_write(&mut oid, self.bld) != 0 {
                 raise()
             }
         }
@@ -0,0 +1,130 @@
+use std::{comm, os, task};
+use std::comm::{Port, Chan};
+use extra::timer::sleep;
+use extra::uv::global_loop;
+
+/// A change of the repository made by another process, reported by a Watcher
+#[deriving(Eq)]
+pub enum Change {
+    /// HEAD points to another branch or commit
+    HeadChanged,
+    /// a reference was created, moved or deleted
+    RefsChanged,
+    /// the index was written
+    IndexChanged,
+}
+
+/// Reports the changes made to HEAD, the references and the index of a repository.
+///
+/// Changes are found by polling the modification time and size of the files, as there
+/// is no portable way to be notified by the filesystem; changes made less than one
+/// polling interval apart are reported once. The polling task stops when the Watcher
+/// is dropped.
+pub struct Watcher {
+    priv port: Port<Change>,
+    priv stop: Chan<()>,
+}
+
+/// modification time and size of a file, or None if it does not exist
+type Stamp = Option<(i64, int, i64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    match (path.get_mtime(), path.get_size()) {
+        (Some((secs, nsecs)), Some(size)) => Some((secs, nsecs, size)),
+        _ => None,
+    }
+}
+
+/// stamps of the loose references and of packed-refs
+fn refs_stamps(git_dir: &Path) -> ~[(~str, Stamp)] {
+    let mut stamps = ~[(~"packed-refs", stamp(&git_dir.push("packed-refs")))];
+    let mut dirs = ~[~"refs"];
+    while !dirs.is_empty() {
+        let dir = dirs.pop();
+        for os::list_dir(&git_dir.push_rel(&Path(dir))).iter().advance |name| {
+            let rel = dir + "/" + *name;
+            let path = git_dir.push_rel(&Path(rel));
+            if os::path_is_dir(&path) {
+                dirs.push(rel);
+            } else {
+                stamps.push((rel, stamp(&path)));
+            }
+        }
+    }
+    stamps
+}
+
+/// Start watching the repository whose `.git` directory is `git_dir`, e.g. the
+/// result of `Repository::path`, checking for changes every `interval_ms` milliseconds.
+pub fn watch(git_dir: &str, interval_ms: uint) -> Watcher
+{
+    let (port, chan) = comm::stream();
+    let (stop_port, stop) = comm::stream();
+    let git_dir = Path(git_dir);
+    let head = git_dir.push("HEAD");
+    let index = git_dir.push("index");
+    // taken before returning, so that every change made after `watch` is reported
+    let head_stamp = stamp(&head);
+    let index_stamp = stamp(&index);
+    let refs = refs_stamps(&git_dir);
+    do task::spawn_unlinked {
+        let mut head_stamp = head_stamp;
+        let mut index_stamp = index_stamp;
+        let mut refs = refs;
+        loop {
+            sleep(&global_loop::get(), interval_ms);
+            if stop_port.peek() {
+                break;
+            }
+            let mut changes = ~[];
+            let new_head = stamp(&head);
+            if new_head != head_stamp {
+                changes.push(HeadChanged);
+                head_stamp = new_head;
+            }
+            let new_refs = refs_stamps(&git_dir);
+            if new_refs != refs {
+                changes.push(RefsChanged);
+                refs = new_refs;
+            }
+            let new_index = stamp(&index);
+            if new_index != index_stamp {
+                changes.push(IndexChanged);
+                index_stamp = new_index;
+            }
+            // the Watcher was dropped
+            if !changes.consume_iter().all(|change| chan.try_send(change)) {
+                break;
+            }
+        }
+    }
+    Watcher { port: port, stop: stop }
+}
+
+impl Watcher {
+    /// Wait for the next change
+    pub fn recv(&self) -> Change {
+        self.port.recv()
+    }
+
+    /// Get the next change if there is one, without waiting
+    pub fn try_recv(&self) -> Option<Change> {
+        if self.port.peek() {
+            Some(self.port.recv())
+        } else {
+            None
+        }
+    }
+
+    /// The port the changes are sent to, e.g. to select on it with other ports
+    pub fn port<'r>(&'r self) -> &'r Port<Change> {
+        &self.port
+    }
+}
+
+impl Drop for Watcher {
+    fn finalize(&self) {
+        // the polling task may be gone already, e.g. after a failure
+        self.stop.try_send(());
+    }
+}